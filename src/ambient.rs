@@ -0,0 +1,249 @@
+//! Lights the headset in the average color of the screen, like the ambient lighting of TVs.
+//!
+//! The screen is sampled through the X11 protocol, which also works in XWayland sessions as
+//! long as the sampled windows are X11 clients. Wayland's screencopy protocol is not supported.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use x11rb::{
+    connection::Connection as _,
+    protocol::xproto::{ConnectionExt as _, ImageFormat, ImageOrder, Window},
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    config::RunConfig,
+    lights::{Color, Effect, Light},
+    plugin::Plugin,
+    Headset,
+};
+
+/// The number of columns of the grid of blocks that are sampled.
+const GRID_COLUMNS: u16 = 16;
+
+/// The number of rows of the grid of blocks that are sampled.
+const GRID_ROWS: u16 = 9;
+
+/// The width and height in pixels of a sampled block.
+const BLOCK_SIZE: u16 = 8;
+
+/// How much a color channel needs to change before the light is updated, to avoid flooding the
+/// headset with requests for changes that cannot be seen.
+const THRESHOLD: u8 = 4;
+
+/// The default time between two samples of the screen.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How a pixel of the root window is laid out.
+#[derive(Debug, Clone, Copy)]
+struct PixelFormat {
+    /// The number of bytes per pixel.
+    bytes_per_pixel: usize,
+    /// Whether the bytes of a pixel are ordered from the most significant one.
+    big_endian: bool,
+    /// The masks of the red, green and blue channels.
+    masks: [u32; 3],
+}
+
+impl PixelFormat {
+    /// Returns the color of the pixel.
+    fn color(&self, bytes: &[u8]) -> Color {
+        let bytes = &bytes[..self.bytes_per_pixel];
+        let pixel = if self.big_endian {
+            bytes
+                .iter()
+                .fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+        } else {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+        };
+
+        let [r, g, b] = self.masks.map(|mask| {
+            let value = (pixel & mask) >> mask.trailing_zeros();
+            let max = mask >> mask.trailing_zeros();
+
+            (u64::from(value) * 255 / u64::from(max.max(1))) as u8
+        });
+
+        Color::new(r, g, b)
+    }
+}
+
+/// Reads the average color of the screen.
+#[derive(Debug)]
+pub struct ScreenSampler {
+    /// The connection to the X server.
+    connection: RustConnection,
+    /// The root window of the screen.
+    root: Window,
+    /// The width of the screen in pixels.
+    width: u16,
+    /// The height of the screen in pixels.
+    height: u16,
+    /// How the pixels of the root window are laid out.
+    format: PixelFormat,
+}
+
+impl ScreenSampler {
+    /// Connects to the X server given by `$DISPLAY`.
+    pub fn connect() -> anyhow::Result<Self> {
+        let (connection, screen_num) =
+            x11rb::connect(None).context("could not connect to the X server")?;
+
+        let setup = connection.setup();
+        let screen = &setup.roots[screen_num];
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == screen.root_depth)
+            .map(|format| format.bits_per_pixel)
+            .context("the X server has no pixmap format for the root window")?;
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.visual_id == screen.root_visual)
+            .context("the X server has no visual for the root window")?;
+
+        anyhow::ensure!(
+            bits_per_pixel % 8 == 0 && (8..=32).contains(&bits_per_pixel),
+            "unsupported root window format with {bits_per_pixel} bits per pixel"
+        );
+
+        let format = PixelFormat {
+            bytes_per_pixel: usize::from(bits_per_pixel / 8),
+            big_endian: setup.image_byte_order == ImageOrder::MSB_FIRST,
+            masks: [visual.red_mask, visual.green_mask, visual.blue_mask],
+        };
+        let (root, width, height) = (screen.root, screen.width_in_pixels, screen.height_in_pixels);
+
+        Ok(Self {
+            connection,
+            root,
+            width,
+            height,
+            format,
+        })
+    }
+
+    /// Returns the average color of a grid of blocks spread over the screen.
+    ///
+    /// Only small blocks are read instead of the whole screen, as that would transfer megabytes
+    /// with every sample.
+    pub fn average_color(&self) -> anyhow::Result<Color> {
+        let block_width = BLOCK_SIZE.min(self.width);
+        let block_height = BLOCK_SIZE.min(self.height);
+
+        // All requests are sent before waiting for the first reply
+        let mut cookies = Vec::new();
+        for row in 0..GRID_ROWS {
+            for column in 0..GRID_COLUMNS {
+                let x = (self.width - block_width) / (GRID_COLUMNS - 1) * column;
+                let y = (self.height - block_height) / (GRID_ROWS - 1) * row;
+
+                cookies.push(self.connection.get_image(
+                    ImageFormat::Z_PIXMAP,
+                    self.root,
+                    x as i16,
+                    y as i16,
+                    block_width,
+                    block_height,
+                    !0,
+                )?);
+            }
+        }
+        self.connection.flush()?;
+
+        let mut sums = [0u64; 3];
+        let mut count = 0u64;
+        for cookie in cookies {
+            let image = cookie.reply()?;
+
+            for pixel in image.data.chunks_exact(self.format.bytes_per_pixel) {
+                let color = self.format.color(pixel);
+                sums[0] += u64::from(color.r);
+                sums[1] += u64::from(color.g);
+                sums[2] += u64::from(color.b);
+                count += 1;
+            }
+        }
+
+        anyhow::ensure!(count > 0, "no pixels were sampled");
+        let [r, g, b] = sums.map(|sum| (sum / count) as u8);
+
+        Ok(Color::new(r, g, b))
+    }
+}
+
+/// Sets a light to the average color of the screen periodically.
+///
+/// The color is only sent while the headset runs with the config, using the temporary profile,
+/// so it is gone after the headset is turned off.
+#[derive(Debug)]
+pub struct AmbientLight {
+    /// The sampler reading the screen.
+    sampler: ScreenSampler,
+    /// The light showing the screen color.
+    light: Light,
+    /// The time between two samples.
+    interval: Duration,
+    /// When the screen was last sampled.
+    last_sample: Option<Instant>,
+    /// The color last shown.
+    color: Option<Color>,
+}
+
+impl AmbientLight {
+    /// Connects to the X server to show its screen color on the light.
+    pub fn new(light: Light) -> anyhow::Result<Self> {
+        Ok(Self {
+            sampler: ScreenSampler::connect()?,
+            light,
+            interval: DEFAULT_INTERVAL,
+            last_sample: None,
+            color: None,
+        })
+    }
+
+    /// Sets the time between two samples of the screen, which defaults to 250 ms.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Returns `true` if the color differs visibly from the one shown.
+    fn differs(&self, color: Color) -> bool {
+        self.color.is_none_or(|shown| {
+            [(shown.r, color.r), (shown.g, color.g), (shown.b, color.b)]
+                .iter()
+                .any(|(shown, new)| shown.abs_diff(*new) >= THRESHOLD)
+        })
+    }
+}
+
+impl Plugin for AmbientLight {
+    fn on_tick(&mut self, config: &mut RunConfig, _: &mut Headset) {
+        if self
+            .last_sample
+            .is_some_and(|last_sample| last_sample.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_sample = Some(Instant::now());
+
+        match self.sampler.average_color() {
+            Ok(color) if self.differs(color) => {
+                self.color = Some(color);
+                config.set_light_effect(self.light, Effect::static_color(color));
+            }
+            Ok(_) => (),
+            Err(err) => log::debug!("could not sample the screen: {err:#}"),
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.interval)
+    }
+}
@@ -0,0 +1,363 @@
+//! Controls the sink and source of the headset through PulseAudio (or PipeWire's PulseAudio server).
+
+use std::{cell::RefCell, rc::Rc};
+
+use libpulse_binding::{
+    callbacks::ListResult,
+    context::{self, Context},
+    mainloop::standard::{IterateResult, Mainloop},
+    operation::{self, Operation},
+    proplist::{properties, Proplist},
+    volume::{ChannelVolumes, Volume},
+};
+use regex::Regex;
+
+use crate::{
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonState, Event, EventKind, Headset,
+};
+
+/// The vendor ID of the headset as reported by the sound server.
+const VENDOR_ID: &str = "046d";
+
+/// The product ID of the headset as reported by the sound server.
+const PRODUCT_ID: &str = "0a87";
+
+/// Returns `true` if the properties of a sink or source belong to the headset.
+fn is_headset(proplist: &Proplist, description: Option<&str>) -> bool {
+    // PipeWire reports the IDs with a `0x` prefix, PulseAudio without
+    let id_matches = |key: &str, id: &str| {
+        proplist
+            .get_str(key)
+            .is_some_and(|value| value.trim_start_matches("0x").eq_ignore_ascii_case(id))
+    };
+
+    (id_matches(properties::DEVICE_VENDOR_ID, VENDOR_ID)
+        && id_matches(properties::DEVICE_PRODUCT_ID, PRODUCT_ID))
+        || description.is_some_and(|description| description.contains("G935"))
+}
+
+/// Selects the sink or source of the sound server that is controlled.
+#[derive(Debug, Clone, Default)]
+pub enum DeviceSelector {
+    /// The headset's own device, recognized by its USB IDs or its description.
+    #[default]
+    Auto,
+    /// The device with exactly this name, like `alsa_output.usb-Logitech_G935-00.analog-stereo`.
+    Name(String),
+    /// The first device whose name or description matches the regular expression.
+    Pattern(Regex),
+}
+
+impl DeviceSelector {
+    /// Selects the first device whose name or description matches the regular expression.
+    pub fn pattern(pattern: &str) -> anyhow::Result<Self> {
+        Ok(DeviceSelector::Pattern(Regex::new(pattern)?))
+    }
+
+    /// Returns `true` if the sink or source with the given properties is selected.
+    fn matches(&self, name: Option<&str>, proplist: &Proplist, description: Option<&str>) -> bool {
+        match self {
+            DeviceSelector::Auto => is_headset(proplist, description),
+            DeviceSelector::Name(expected) => name == Some(expected.as_str()),
+            DeviceSelector::Pattern(pattern) => name
+                .into_iter()
+                .chain(description)
+                .any(|text| pattern.is_match(text)),
+        }
+    }
+}
+
+/// A device of the sound server.
+#[derive(Debug, Clone)]
+struct AudioDevice {
+    /// The index of the device.
+    index: u32,
+    /// The volume of the device.
+    volume: ChannelVolumes,
+    /// Whether the device is muted.
+    mute: bool,
+}
+
+/// A connection to the sound server.
+pub struct Audio {
+    /// The main loop driving the connection.
+    mainloop: Mainloop,
+    /// The connection context.
+    context: Context,
+    /// Selects the sink whose volume is changed.
+    sink: DeviceSelector,
+    /// Selects the source whose mute state is changed.
+    source: DeviceSelector,
+}
+
+impl std::fmt::Debug for Audio {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Audio").finish_non_exhaustive()
+    }
+}
+
+impl Audio {
+    /// Connects to the sound server.
+    pub fn connect() -> anyhow::Result<Self> {
+        let mut proplist =
+            Proplist::new().ok_or_else(|| anyhow::anyhow!("could not create a proplist"))?;
+        proplist
+            .set_str(properties::APPLICATION_NAME, "g935")
+            .map_err(|()| anyhow::anyhow!("could not set the application name"))?;
+
+        let mut mainloop =
+            Mainloop::new().ok_or_else(|| anyhow::anyhow!("could not create a main loop"))?;
+        let mut context = Context::new_with_proplist(&mainloop, "g935", &proplist)
+            .ok_or_else(|| anyhow::anyhow!("could not create a sound server context"))?;
+
+        context.connect(None, context::FlagSet::NOFLAGS, None)?;
+
+        loop {
+            Self::iterate(&mut mainloop)?;
+
+            match context.get_state() {
+                context::State::Ready => break,
+                context::State::Failed | context::State::Terminated => {
+                    anyhow::bail!("could not connect to the sound server")
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            mainloop,
+            context,
+            sink: DeviceSelector::Auto,
+            source: DeviceSelector::Auto,
+        })
+    }
+
+    /// Selects the sink whose volume is changed, which is the headset's own sink by default.
+    pub fn select_sink(&mut self, sink: DeviceSelector) {
+        self.sink = sink;
+    }
+
+    /// Selects the source whose mute state is changed, which is the headset's own source by
+    /// default.
+    pub fn select_source(&mut self, source: DeviceSelector) {
+        self.source = source;
+    }
+
+    /// Runs one iteration of the main loop.
+    fn iterate(mainloop: &mut Mainloop) -> anyhow::Result<()> {
+        match mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => Err(anyhow::anyhow!("the sound server main loop quit")),
+            IterateResult::Err(err) => Err(anyhow::anyhow!("sound server error: {err}")),
+        }
+    }
+
+    /// Runs the main loop until the operation is finished.
+    fn wait<T: ?Sized>(&mut self, operation: Operation<T>) -> anyhow::Result<()> {
+        while operation.get_state() == operation::State::Running {
+            Self::iterate(&mut self.mainloop)?;
+        }
+
+        anyhow::ensure!(
+            operation.get_state() == operation::State::Done,
+            "sound server operation was cancelled"
+        );
+
+        Ok(())
+    }
+
+    /// Returns the selected sink.
+    fn headset_sink(&mut self) -> anyhow::Result<AudioDevice> {
+        let found = Rc::new(RefCell::new(None));
+        let found_in_callback = Rc::clone(&found);
+        let selector = self.sink.clone();
+
+        let operation = self.context.introspect().get_sink_info_list(move |result| {
+            if let ListResult::Item(sink) = result {
+                if found_in_callback.borrow().is_none()
+                    && selector.matches(
+                        sink.name.as_deref(),
+                        &sink.proplist,
+                        sink.description.as_deref(),
+                    )
+                {
+                    found_in_callback.replace(Some(AudioDevice {
+                        index: sink.index,
+                        volume: sink.volume,
+                        mute: sink.mute,
+                    }));
+                }
+            }
+        });
+        self.wait(operation)?;
+
+        let sink = found.borrow_mut().take();
+        sink.ok_or_else(|| anyhow::anyhow!("no sink matches {:?}", self.sink))
+    }
+
+    /// Returns the selected source, ignoring the monitors of sinks.
+    fn headset_source(&mut self) -> anyhow::Result<AudioDevice> {
+        let found = Rc::new(RefCell::new(None));
+        let found_in_callback = Rc::clone(&found);
+        let selector = self.source.clone();
+
+        let operation = self
+            .context
+            .introspect()
+            .get_source_info_list(move |result| {
+                if let ListResult::Item(source) = result {
+                    if found_in_callback.borrow().is_none()
+                        && source.monitor_of_sink.is_none()
+                        && selector.matches(
+                            source.name.as_deref(),
+                            &source.proplist,
+                            source.description.as_deref(),
+                        )
+                    {
+                        found_in_callback.replace(Some(AudioDevice {
+                            index: source.index,
+                            volume: source.volume,
+                            mute: source.mute,
+                        }));
+                    }
+                }
+            });
+        self.wait(operation)?;
+
+        let source = found.borrow_mut().take();
+        source.ok_or_else(|| anyhow::anyhow!("no source matches {:?}", self.source))
+    }
+
+    /// Changes the volume of the headset's sink by the given percentage.
+    ///
+    /// The volume is never raised above 100%.
+    pub fn change_sink_volume(&mut self, percent: i32) -> anyhow::Result<()> {
+        let sink = self.headset_sink()?;
+        let step = Volume(Volume::NORMAL.0 / 100 * percent.unsigned_abs());
+
+        let mut volume = sink.volume;
+        if percent >= 0 {
+            volume.inc_clamp(step, Volume::NORMAL);
+        } else {
+            volume.decrease(step);
+        }
+
+        log::debug!("setting the headset sink volume to {volume}");
+
+        let operation = self
+            .context
+            .introspect()
+            .set_sink_volume_by_index(sink.index, &volume, None);
+        self.wait(operation)
+    }
+
+    /// Mutes or unmutes the headset's source.
+    pub fn set_source_mute(&mut self, mute: bool) -> anyhow::Result<()> {
+        let source = self.headset_source()?;
+
+        log::debug!(
+            "{} the headset source",
+            if mute { "muting" } else { "unmuting" }
+        );
+
+        let operation =
+            self.context
+                .introspect()
+                .set_source_mute_by_index(source.index, mute, None);
+        self.wait(operation)
+    }
+
+    /// Returns `true` if the headset's source is muted.
+    pub fn is_source_muted(&mut self) -> anyhow::Result<bool> {
+        Ok(self.headset_source()?.mute)
+    }
+
+    /// Toggles the mute state of the headset's source, returning whether it is now muted.
+    pub fn toggle_source_mute(&mut self) -> anyhow::Result<bool> {
+        let mute = !self.headset_source()?.mute;
+
+        self.set_source_mute(mute)?;
+
+        Ok(mute)
+    }
+}
+
+/// Controls the headset's sink volume with the wheel and its source mute state with the mute
+/// button.
+#[derive(Debug)]
+pub struct AudioControl {
+    /// The connection to the sound server.
+    audio: Audio,
+    /// The percentage to change the volume by for each wheel report.
+    volume_step: u8,
+    /// How much faster scrolling increases the volume step, see
+    /// [`ButtonState::accelerated_scroll_delta`].
+    acceleration: f32,
+    /// Whether the mute button toggles the mute state of the source.
+    mute_button: bool,
+}
+
+impl AudioControl {
+    /// Connects to the sound server, changing the volume by `volume_step` percent per wheel report.
+    pub fn new(volume_step: u8, mute_button: bool) -> anyhow::Result<Self> {
+        Ok(Self::with_audio(
+            Audio::connect()?,
+            volume_step,
+            mute_button,
+        ))
+    }
+
+    /// Uses an existing connection to the sound server, keeping the devices it selects.
+    pub fn with_audio(audio: Audio, volume_step: u8, mute_button: bool) -> Self {
+        Self {
+            audio,
+            volume_step,
+            acceleration: 0.0,
+            mute_button,
+        }
+    }
+
+    /// Sets how much faster scrolling increases the volume step.
+    ///
+    /// Each wheel report changes the volume by `volume_step * (1 + acceleration * rate)` percent,
+    /// where `rate` is the number of wheel reports per second. The default is `0.0`, which
+    /// changes the volume by `volume_step` regardless of the speed.
+    pub fn set_acceleration(&mut self, acceleration: f32) {
+        self.acceleration = acceleration;
+    }
+
+    /// Changes the volume or mute state according to the given button state.
+    pub fn handle(&mut self, state: ButtonState) -> anyhow::Result<()> {
+        let delta = state.accelerated_scroll_delta(self.acceleration);
+        if delta != 0.0 {
+            let change = (delta * f32::from(self.volume_step)).round() as i32;
+            self.audio.change_sink_volume(change)?;
+        }
+        if self.mute_button && state.mute_button_pressed() {
+            self.audio.toggle_source_mute()?;
+        }
+
+        Ok(())
+    }
+
+    /// Turns the audio control into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, _, state| {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to control the audio: {err}");
+            }
+        })
+    }
+}
+
+impl Plugin for AudioControl {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to control the audio: {err}");
+            }
+        }
+    }
+}
@@ -1,9 +1,37 @@
 //! Battery and charging status related things.
 
-use std::fmt;
+use std::{fmt, time::Duration};
 
 use crate::FromBytes;
 
+/// How old the battery status passed to the handlers may be, as it changes slowly.
+pub(crate) const BATTERY_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// Estimates the charge of the battery from its voltage.
+///
+/// The voltage curve of a battery depends on its age and on the firmware reporting it, so a
+/// curve measured for a specific headset can be used with [`Headset::set_battery_model`] instead
+/// of [`DefaultBatteryModel`]. It is not used if the headset reports its state of charge itself.
+///
+/// [`Headset::set_battery_model`]: crate::Headset::set_battery_model
+pub trait BatteryModel: fmt::Debug + Send {
+    /// Returns the estimated charge in percent, given the voltage in mV.
+    ///
+    /// The charging status is given as the voltage rises while the battery is charging.
+    fn estimate(&self, voltage: u16, charging_status: ChargingStatus) -> f32;
+}
+
+/// The voltage curve measured for the G933 and G935 by HeadsetControl, which is used unless
+/// another [`BatteryModel`] is set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultBatteryModel;
+
+impl BatteryModel for DefaultBatteryModel {
+    fn estimate(&self, voltage: u16, _: ChargingStatus) -> f32 {
+        estimate_battery_level(voltage)
+    }
+}
+
 /// See https://github.com/Sapd/HeadsetControl/blob/master/src/devices/logitech_g633_g933_935.c
 fn estimate_battery_level(voltage: u16) -> f32 {
     if voltage <= 3525 {
@@ -22,6 +50,8 @@ fn estimate_battery_level(voltage: u16) -> f32 {
 
 /// The current status of charging
 #[derive(Debug, Eq, Hash, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum ChargingStatus {
     /// Battery is discharging
     Discharging,
@@ -42,16 +72,65 @@ impl fmt::Display for ChargingStatus {
 }
 
 /// Battery status
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BatteryStatus {
     /// Charging status
     pub charging_status: ChargingStatus,
     /// Battery voltage
     pub voltage: u16,
-    /// Charge percentage
+    /// Charge percentage, as reported by the headset or estimated from the voltage
     pub charge: f32,
 }
 
+impl BatteryStatus {
+    /// Returns the charge in whole percent, limited to 0 to 100.
+    pub fn percent(&self) -> u8 {
+        self.charge.clamp(0.0, 100.0).round() as u8
+    }
+
+    /// Returns the battery voltage in mV.
+    pub fn voltage_millivolts(&self) -> u16 {
+        self.voltage
+    }
+
+    /// Returns the battery voltage in V.
+    pub fn voltage_volts(&self) -> f32 {
+        f32::from(self.voltage) / 1000.0
+    }
+
+    /// Returns `true` if the battery is discharging and its charge is at or below the threshold
+    /// in percent.
+    ///
+    /// A charging battery never counts as low, however empty it is.
+    pub fn is_low(&self, threshold: u8) -> bool {
+        self.charging_status == ChargingStatus::Discharging && self.charge <= f32::from(threshold)
+    }
+
+    /// Parses the battery status, returning `None` if the bytes do not hold one, like the empty
+    /// notifications while the headset is turned off.
+    pub(crate) fn parse(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0, 0, ..] => None,
+            [_, _, 1 | 3 | 7, ..] => Some(Self::from_bytes(bytes)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BatteryStatus {
+    /// Formats the status like `87% (4012 mV, discharging)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}% ({} mV, {})",
+            self.percent(),
+            self.voltage,
+            self.charging_status
+        )
+    }
+}
+
 impl FromBytes for BatteryStatus {
     fn from_bytes(bytes: &[u8]) -> Self {
         let charging_status = match bytes[2] {
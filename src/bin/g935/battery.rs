@@ -0,0 +1,246 @@
+//! Printing of the battery status for status bars.
+
+use std::{io::Write as _, path::Path, time::Duration};
+
+use g935::{BatteryStatus, ChargingStatus, Headset, PowerState};
+
+use crate::{
+    daemon::{Event, Published, Request, Response},
+    socket::Connection,
+};
+
+/// The charge in percent below which the battery counts as low.
+const LOW: f32 = 25.0;
+
+/// The charge in percent below which the battery counts as critical.
+const CRITICAL: f32 = 10.0;
+
+/// The format to print the battery status in.
+#[derive(clap::ArgEnum, Debug, Clone, Copy)]
+pub(crate) enum Format {
+    /// the charge and charging status as plain text
+    Plain,
+    /// JSON for a Waybar custom module with `return-type` json
+    Waybar,
+    /// the full text, short text and color lines of i3blocks
+    I3blocks,
+    /// a line of text with Polybar color tags
+    Polybar,
+    /// the battery status as JSON
+    Json,
+}
+
+/// Returns the CSS-like classes describing the battery status.
+fn classes(status: Option<&BatteryStatus>) -> Vec<&'static str> {
+    let Some(status) = status else {
+        return vec!["disconnected"];
+    };
+
+    let mut classes = vec![match status.charging_status {
+        ChargingStatus::Discharging => "discharging",
+        ChargingStatus::Charging => "charging",
+        ChargingStatus::Full => "full",
+    }];
+    if status.charge < CRITICAL {
+        classes.push("critical");
+    } else if status.charge < LOW {
+        classes.push("low");
+    }
+
+    classes
+}
+
+/// Returns the exit code for monitoring systems like Nagios: 2 if the battery is discharging and
+/// at or below the critical charge, 1 if it is at or below the warning charge and 0 otherwise.
+pub(crate) fn exit_code(status: &BatteryStatus, warn: Option<u8>, crit: Option<u8>) -> i32 {
+    let at_most =
+        |threshold: Option<u8>| threshold.is_some_and(|threshold| status.is_low(threshold));
+
+    if at_most(crit) {
+        2
+    } else if at_most(warn) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Returns the color signaling a low battery, if it is low.
+fn warning_color(status: &BatteryStatus) -> Option<&'static str> {
+    if status.charging_status != ChargingStatus::Discharging {
+        None
+    } else if status.charge < CRITICAL {
+        Some("#ff0000")
+    } else if status.charge < LOW {
+        Some("#ffa500")
+    } else {
+        None
+    }
+}
+
+/// Formats the battery status, which is `None` if the headset is turned off.
+fn format(status: Option<&BatteryStatus>, format: Format) -> String {
+    let percent = status.map(|status| format!("{}%", status.percent()));
+    let text = percent.as_deref().unwrap_or("off");
+
+    match format {
+        Format::Plain => match status {
+            Some(status) => format!("{text} ({})", status.charging_status),
+            None => text.to_string(),
+        },
+        Format::Waybar => {
+            let tooltip = match status {
+                Some(status) => format!("G935: {status}"),
+                None => "G935: turned off".to_string(),
+            };
+
+            serde_json::json!({
+                "text": text,
+                "tooltip": tooltip,
+                "class": classes(status),
+                "percentage": status.map_or(0, BatteryStatus::percent),
+            })
+            .to_string()
+        }
+        Format::I3blocks => {
+            let full_text = match status {
+                Some(status) => format!("{text} ({})", status.charging_status),
+                None => text.to_string(),
+            };
+
+            match status.and_then(warning_color) {
+                Some(color) => format!("{full_text}\n{text}\n{color}"),
+                None => format!("{full_text}\n{text}"),
+            }
+        }
+        Format::Polybar => match status.and_then(warning_color) {
+            Some(color) => format!("%{{F{color}}}{text}%{{F-}}"),
+            None => text.to_string(),
+        },
+        Format::Json => serde_json::json!({
+            "connected": status.is_some(),
+            "battery": status,
+            "class": classes(status),
+        })
+        .to_string(),
+    }
+}
+
+/// Reads the battery status from the daemon if it is running, or from the headset otherwise.
+pub(crate) fn read_status(
+    socket: &Path,
+    headset: &mut Option<Headset>,
+) -> anyhow::Result<BatteryStatus> {
+    if headset.is_none() {
+        if let Ok(mut connection) = Connection::connect(socket) {
+            return match connection.request(&Request::GetBattery)? {
+                Response::Battery(status) => Ok(status),
+                response => Err(anyhow::anyhow!("unexpected response {response:?}")),
+            };
+        }
+    }
+
+    let headset = match headset {
+        Some(headset) => headset,
+        None => headset.insert(Headset::open()?),
+    };
+
+    headset.get_battery_status()
+}
+
+/// Prints the formatted battery status, unless it is the same as the one printed last.
+fn print_if_changed(
+    last: &mut Option<String>,
+    status: Option<&BatteryStatus>,
+    output_format: Format,
+) -> anyhow::Result<()> {
+    let text = format(status, output_format);
+    if last.as_ref() != Some(&text) {
+        println!("{text}");
+        std::io::stdout().flush()?;
+        *last = Some(text);
+    }
+
+    Ok(())
+}
+
+/// Prints the battery status in the format whenever it changes, until interrupted.
+///
+/// If the daemon is running, its battery events are used. Otherwise the headset is kept open and
+/// asked for the battery status every `poll_interval`.
+pub(crate) fn watch(
+    socket: &Path,
+    output_format: Format,
+    poll_interval: Duration,
+) -> anyhow::Result<()> {
+    let mut last = None;
+
+    if let Ok(mut connection) = Connection::connect(socket) {
+        let status = match connection.request(&Request::GetBattery) {
+            Ok(Response::Battery(status)) => Some(status),
+            _ => None,
+        };
+        print_if_changed(&mut last, status.as_ref(), output_format)?;
+
+        let mut result = Ok(());
+        connection.subscribe(|Published { event, .. }| {
+            let status = match event {
+                Event::Battery(status) => Some(status),
+                Event::PowerState(PowerState::Disconnected) => None,
+                Event::PowerState(_) | Event::Button(_) | Event::Profile(_) => return,
+            };
+            if result.is_ok() {
+                result = print_if_changed(&mut last, status.as_ref(), output_format);
+            }
+        })?;
+
+        return result;
+    }
+
+    let mut headset = None;
+    loop {
+        let status = match read_status(socket, &mut headset) {
+            Ok(status) => Some(status),
+            Err(err) => {
+                log::debug!("could not read the battery status: {err:#}");
+                None
+            }
+        };
+        print_if_changed(&mut last, status.as_ref(), output_format)?;
+
+        std::thread::sleep(poll_interval);
+    }
+}
+
+/// Prints the battery status in the format, repeating it every `interval` if given.
+///
+/// When repeating, failures to read the battery status are shown as a turned off headset.
+pub(crate) fn print(
+    socket: &Path,
+    output_format: Format,
+    interval: Option<Duration>,
+) -> anyhow::Result<()> {
+    let mut headset = None;
+
+    let Some(interval) = interval else {
+        let status = read_status(socket, &mut headset)?;
+        println!("{}", format(Some(&status), output_format));
+
+        return Ok(());
+    };
+
+    loop {
+        let status = match read_status(socket, &mut headset) {
+            Ok(status) => Some(status),
+            Err(err) => {
+                log::debug!("could not read the battery status: {err:#}");
+                None
+            }
+        };
+
+        println!("{}", format(status.as_ref(), output_format));
+        std::io::stdout().flush()?;
+
+        std::thread::sleep(interval);
+    }
+}
@@ -0,0 +1,64 @@
+//! Showing the battery charge with the side lights.
+
+use std::time::{Duration, Instant};
+
+use g935::{
+    config::{OverlayId, RunConfig},
+    lights::{Color, Effect, Light},
+    plugin::Plugin,
+    Event, EventKind, Headset,
+};
+
+/// How long the battery charge is shown.
+const SHOW_DURATION: Duration = Duration::from_millis(1000);
+
+/// How old the shown battery charge may be, as it changes slowly.
+const BATTERY_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// Shows the battery charge with the side lights for a second when the mute button is pressed,
+/// going from red to green.
+#[derive(Debug, Default)]
+pub(crate) struct BatteryLights {
+    /// When the battery charge was shown and the overlay showing it, while it is shown.
+    shown: Option<(Instant, OverlayId)>,
+}
+
+impl Plugin for BatteryLights {
+    fn on_event(&mut self, config: &mut RunConfig, headset: &mut Headset, event: Event) {
+        let EventKind::Buttons(state) = event.kind else {
+            return;
+        };
+        if !state.mute_button_pressed() {
+            return;
+        }
+
+        match headset.battery_status_cached(BATTERY_MAX_AGE) {
+            Ok(battery_status) => {
+                let percent = (battery_status.charge * 2.55).round() as u8;
+                let effect = Effect::Static {
+                    color: Color::new(255 - percent, percent, 0),
+                };
+
+                if let Some((_, overlay)) = self.shown.take() {
+                    config.pop_overlay(overlay);
+                }
+                self.shown = Some((Instant::now(), config.push_overlay(Light::Side, effect)));
+            }
+            Err(err) => log::warn!("failed to get battery status: {err}"),
+        }
+    }
+
+    fn on_tick(&mut self, config: &mut RunConfig, _: &mut Headset) {
+        if let Some((shown_at, overlay)) = self.shown {
+            if shown_at.elapsed() >= SHOW_DURATION {
+                self.shown = None;
+                config.pop_overlay(overlay);
+            }
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.shown
+            .map(|(shown_at, _)| SHOW_DURATION.saturating_sub(shown_at.elapsed()))
+    }
+}
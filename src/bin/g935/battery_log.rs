@@ -0,0 +1,119 @@
+//! Records the battery status over time, to follow how the battery wears out.
+
+use std::{
+    fs::File,
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context as _;
+use crossbeam_channel::Receiver;
+use g935::BatteryStatus;
+
+use crate::{
+    battery, config_file,
+    daemon::{Event, Published},
+    state,
+};
+
+/// The first line of a new log, naming the columns.
+const HEADER: &str = "timestamp,voltage_mv,percent,charging_status";
+
+/// A CSV file the battery status is appended to.
+#[derive(Debug)]
+pub(crate) struct BatteryLog {
+    /// The opened file.
+    file: File,
+    /// The path of the file.
+    path: PathBuf,
+}
+
+/// Returns the path of the log written by the daemon, which is
+/// `$XDG_STATE_HOME/g935/battery.csv`.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(state::dir()?.join("battery.csv"))
+}
+
+impl BatteryLog {
+    /// Opens the log at the path for appending, writing the header if it is new.
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("could not create {}", dir.display()))?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open the battery log {}", path.display()))?;
+
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{HEADER}")?;
+        }
+
+        Ok(Self {
+            file,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Appends the battery status with the current time in seconds since the Unix epoch.
+    pub(crate) fn record(&mut self, status: &BatteryStatus) -> anyhow::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        writeln!(
+            self.file,
+            "{timestamp},{},{:.1},{}",
+            status.voltage, status.charge, status.charging_status
+        )
+        .with_context(|| format!("could not write the battery log {}", self.path.display()))
+    }
+}
+
+/// Reads the battery status every `interval` and appends it to the log.
+///
+/// The daemon is asked if it is running. While the headset is turned off, nothing is recorded.
+pub(crate) fn run(socket: &Path, output: &Path, interval: Duration) -> anyhow::Result<()> {
+    let mut log = BatteryLog::open(output)?;
+    let mut headset = None;
+
+    loop {
+        match battery::read_status(socket, &mut headset) {
+            Ok(status) => log.record(&status)?,
+            Err(err) => log::debug!("could not read the battery status: {err:#}"),
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+/// Appends every change of the battery status published by the daemon to the log in the
+/// background.
+pub(crate) fn serve(
+    settings: &config_file::BatteryLog,
+    events: Receiver<Published>,
+) -> anyhow::Result<()> {
+    let path = settings
+        .path
+        .clone()
+        .or_else(default_path)
+        .context("could not determine the battery log location, set its path")?;
+    let mut log = BatteryLog::open(&path)?;
+
+    std::thread::spawn(move || {
+        for Published { event, .. } in events {
+            if let Event::Battery(status) = event {
+                if let Err(err) = log.record(&status) {
+                    log::warn!("{err:#}");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,1334 @@
+//! The declarative configuration file of the daemon.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use g935::{
+    bindings::{Bindings, Trigger},
+    config::{RestorePolicy, RunConfig},
+    lights::{self, Color, Effect, Light},
+    macros::{Macro, MacroControl, MacroKeyMap, MacroPlayer},
+    profiles::{self, ProfileAction, Profiles},
+};
+use serde::Deserialize;
+
+use crate::{daemon::Publisher, schedule::Schedule};
+
+/// The default time in milliseconds within which presses of several G keys form a chord.
+const DEFAULT_CHORD_MS: u64 = 50;
+
+/// The contents of a configuration file.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    /// The sidetone level in percent.
+    pub(crate) sidetone: Option<u8>,
+    /// The gain in dB of each equalizer band.
+    pub(crate) equalizer: Option<Vec<i8>>,
+    /// The light effects.
+    pub(crate) lights: Lights,
+    /// The options for the button handling.
+    pub(crate) buttons: Buttons,
+    /// The shell commands to run when a button event occurs.
+    pub(crate) bindings: BTreeMap<Trigger, String>,
+    /// The Rhai scripts to run when a button event occurs.
+    pub(crate) scripts: BTreeMap<Trigger, String>,
+    /// The name of the profile selected initially, defaulting to the first one.
+    pub(crate) profile: Option<String>,
+    /// The profiles that can be switched between, in alphabetical order.
+    pub(crate) profiles: BTreeMap<String, Profile>,
+    /// The profile switches performed when a button event occurs.
+    ///
+    /// These are `"next"`, `"previous"` or the name of the profile to select.
+    pub(crate) profile_bindings: BTreeMap<Trigger, String>,
+    /// The macros that can be bound to the G keys, by name.
+    pub(crate) macros: BTreeMap<String, Macro>,
+    /// The macros played when the G keys are pressed.
+    pub(crate) macro_bindings: MacroBindings,
+    /// The virtual input device for the buttons.
+    pub(crate) uinput: Uinput,
+    /// The control of the headset's sink and source.
+    pub(crate) audio: Audio,
+    /// The control of media players with the G keys.
+    pub(crate) media: Media,
+    /// The muting of the capture device when the microphone arm is flipped up.
+    pub(crate) mic_mute: MicMute,
+    /// The light showing that the microphone is muted.
+    pub(crate) mute_indicator: MuteIndicator,
+    /// The light showing the average color of the screen.
+    pub(crate) ambient: Ambient,
+    /// The control of OBS with the microphone arm.
+    pub(crate) obs: Obs,
+    /// The lights by the time of day.
+    pub(crate) schedule: Vec<ScheduleEntry>,
+    /// The connection of the daemon to an MQTT broker.
+    pub(crate) mqtt: Mqtt,
+    /// The WebSocket endpoint of the daemon.
+    pub(crate) websocket: WebSocket,
+    /// The HTTP endpoint of the daemon.
+    pub(crate) http: Http,
+    /// The desktop notifications of the daemon.
+    pub(crate) notifications: Notifications,
+    /// The recording of the battery status by the daemon.
+    pub(crate) battery_log: BatteryLog,
+    /// What is restored when g935 stops, defaulting to [`RestorePolicy::Defaults`].
+    pub(crate) restore_on_exit: Option<RestorePolicy>,
+}
+
+/// The options for the button handling of a configuration file.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Buttons {
+    /// The minimum time in milliseconds between two runs of the same binding.
+    pub(crate) rate_limit_ms: Option<u64>,
+    /// The time in milliseconds a G key needs to be held to count as a long press.
+    pub(crate) long_press_ms: Option<u64>,
+    /// The time in milliseconds within which a second press of a G key counts as a double press.
+    pub(crate) double_press_ms: Option<u64>,
+    /// The time in milliseconds between two repeats while the wheel is scrolled.
+    pub(crate) scroll_repeat_ms: Option<u64>,
+    /// The time in milliseconds within which presses of several G keys form a chord.
+    ///
+    /// Chords are only detected if a chord is bound, as they delay the presses of the G keys.
+    pub(crate) chord_ms: Option<u64>,
+    /// The G keys, like `"g1"`, that keep acting as their default F keys through the virtual
+    /// input device, so that only the others need to be handled by the bindings.
+    pub(crate) passthrough: Vec<String>,
+    /// The time in milliseconds handling a button event may take before a warning is logged.
+    pub(crate) latency_budget_ms: Option<u64>,
+    /// The time in milliseconds after a report of the microphone arm within which a report of the
+    /// same position is dropped.
+    pub(crate) mic_arm_debounce_ms: Option<u64>,
+    /// The time in milliseconds after a press of the mute button within which another press is
+    /// dropped.
+    pub(crate) mute_debounce_ms: Option<u64>,
+}
+
+#[cfg(all(feature = "uinput", target_os = "linux"))]
+impl Buttons {
+    /// Returns the keys emitted by the virtual input device, with the default keys for the
+    /// passthrough G keys, and the `base` keys or none for the other buttons.
+    fn passthrough_keys(
+        &self,
+        base: Option<g935::uinput::KeyMap>,
+    ) -> anyhow::Result<g935::uinput::KeyMap> {
+        use g935::uinput::KeyMap;
+
+        let firmware = KeyMap::FIRMWARE;
+        let mut keys = base.unwrap_or(KeyMap {
+            g1: None,
+            g2: None,
+            g3: None,
+            ..firmware
+        });
+
+        for key in &self.passthrough {
+            match key.as_str() {
+                "g1" => keys.g1 = firmware.g1,
+                "g2" => keys.g2 = firmware.g2,
+                "g3" => keys.g3 = firmware.g3,
+                _ => anyhow::bail!("unknown passthrough key {key:?}, expected g1, g2 or g3"),
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+/// The light effects of a configuration file.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Lights {
+    /// The effect of the side lights.
+    pub(crate) side: Effect,
+    /// The effect of the logo light.
+    pub(crate) logo: Effect,
+    /// The brightness in percent both effects are dimmed to.
+    pub(crate) brightness: Option<u8>,
+}
+
+/// A profile of a configuration file.
+///
+/// Unset settings fall back to the ones outside of the profiles, whereas the bindings are added
+/// to the ones outside of the profiles.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Profile {
+    /// The sidetone level in percent.
+    pub(crate) sidetone: Option<u8>,
+    /// The gain in dB of each equalizer band.
+    pub(crate) equalizer: Option<Vec<i8>>,
+    /// The light effects.
+    pub(crate) lights: ProfileLights,
+    /// The shell commands to run when a button event occurs.
+    pub(crate) bindings: BTreeMap<Trigger, String>,
+    /// The macros played when the G keys are pressed, overriding the ones outside of the profiles.
+    pub(crate) macro_bindings: MacroBindings,
+}
+
+/// The light effects of a profile.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProfileLights {
+    /// The effect of the side lights.
+    pub(crate) side: Option<Effect>,
+    /// The effect of the logo light.
+    pub(crate) logo: Option<Effect>,
+}
+
+/// The names of the macros bound to the G keys in a configuration file.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MacroBindings {
+    /// The macro played for the G1 key.
+    pub(crate) g1: Option<String>,
+    /// The macro played for the G2 key.
+    pub(crate) g2: Option<String>,
+    /// The macro played for the G3 key.
+    pub(crate) g3: Option<String>,
+}
+
+impl MacroBindings {
+    /// Returns the names of the bound macros.
+    fn names(&self) -> impl Iterator<Item = &String> {
+        [&self.g1, &self.g2, &self.g3].into_iter().flatten()
+    }
+
+    /// Returns the bindings, falling back to the `base` ones for unbound keys.
+    fn or(&self, base: &MacroBindings) -> MacroBindings {
+        MacroBindings {
+            g1: self.g1.clone().or_else(|| base.g1.clone()),
+            g2: self.g2.clone().or_else(|| base.g2.clone()),
+            g3: self.g3.clone().or_else(|| base.g3.clone()),
+        }
+    }
+
+    /// Returns the macros of the keys, given all macros by name.
+    fn key_map(&self, macros: &BTreeMap<String, Macro>) -> MacroKeyMap {
+        let lookup =
+            |name: &Option<String>| name.as_ref().and_then(|name| macros.get(name)).cloned();
+
+        MacroKeyMap {
+            g1: lookup(&self.g1),
+            g2: lookup(&self.g2),
+            g3: lookup(&self.g3),
+        }
+    }
+}
+
+/// The virtual input device options of a configuration file.
+///
+/// The keys are given by their Linux names, such as `"KEY_F13"`, or `"none"` to not emit a key.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Uinput {
+    /// Whether the virtual input device should be created.
+    pub(crate) enabled: bool,
+    /// The key emitted for the G1 key.
+    pub(crate) g1: Option<String>,
+    /// The key emitted for the G2 key.
+    pub(crate) g2: Option<String>,
+    /// The key emitted for the G3 key.
+    pub(crate) g3: Option<String>,
+    /// The key emitted for scrolling up.
+    pub(crate) scroll_up: Option<String>,
+    /// The key emitted for scrolling down.
+    pub(crate) scroll_down: Option<String>,
+    /// The key emitted for the mute button.
+    pub(crate) mute: Option<String>,
+}
+
+#[cfg(all(feature = "uinput", target_os = "linux"))]
+impl Uinput {
+    /// Returns the key map described by the options.
+    fn key_map(&self) -> anyhow::Result<g935::uinput::KeyMap> {
+        use g935::uinput::{KeyCode, KeyMap};
+
+        /// Parses the key name, falling back to the default.
+        fn key(name: &Option<String>, default: Option<KeyCode>) -> anyhow::Result<Option<KeyCode>> {
+            match name.as_deref() {
+                None => Ok(default),
+                Some("none") => Ok(None),
+                Some(name) => name
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| anyhow::anyhow!("unknown key {name:?}")),
+            }
+        }
+
+        let default = KeyMap::default();
+
+        Ok(KeyMap {
+            g1: key(&self.g1, default.g1)?,
+            g2: key(&self.g2, default.g2)?,
+            g3: key(&self.g3, default.g3)?,
+            scroll_up: key(&self.scroll_up, default.scroll_up)?,
+            scroll_down: key(&self.scroll_down, default.scroll_down)?,
+            mute: key(&self.mute, default.mute)?,
+        })
+    }
+}
+
+/// The audio control options of a configuration file.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Audio {
+    /// Whether the wheel and the mute button should control the headset's sink and source.
+    pub(crate) enabled: bool,
+    /// The percentage the volume is changed by for each wheel report.
+    pub(crate) volume_step: u8,
+    /// How much faster scrolling increases the volume step.
+    pub(crate) acceleration: f32,
+    /// Whether the mute button should toggle the mute state of the headset's microphone.
+    pub(crate) mute_button: bool,
+    /// The name of the sink whose volume is changed, instead of the headset's own sink.
+    pub(crate) sink: Option<String>,
+    /// A regular expression matching the name or description of the sink whose volume is
+    /// changed.
+    pub(crate) sink_pattern: Option<String>,
+    /// The name of the source whose mute state is changed, instead of the headset's own source.
+    pub(crate) source: Option<String>,
+    /// A regular expression matching the name or description of the source whose mute state is
+    /// changed.
+    pub(crate) source_pattern: Option<String>,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume_step: 2,
+            acceleration: 0.0,
+            mute_button: true,
+            sink: None,
+            sink_pattern: None,
+            source: None,
+            source_pattern: None,
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Audio {
+    /// Connects to the sound server, selecting the configured sink and source.
+    fn connect(&self) -> anyhow::Result<g935::audio::Audio> {
+        use g935::audio::DeviceSelector;
+
+        /// Returns the selector of the device with the name or the pattern, if either is given.
+        fn selector(
+            kind: &str,
+            name: &Option<String>,
+            pattern: &Option<String>,
+        ) -> anyhow::Result<DeviceSelector> {
+            match (name, pattern) {
+                (None, None) => Ok(DeviceSelector::Auto),
+                (Some(name), None) => Ok(DeviceSelector::Name(name.clone())),
+                (None, Some(pattern)) => DeviceSelector::pattern(pattern)
+                    .with_context(|| format!("invalid {kind}_pattern {pattern:?}")),
+                (Some(_), Some(_)) => {
+                    anyhow::bail!("only one of {kind} and {kind}_pattern can be given")
+                }
+            }
+        }
+
+        let sink = selector("sink", &self.sink, &self.sink_pattern)?;
+        let source = selector("source", &self.source, &self.source_pattern)?;
+
+        let mut audio =
+            g935::audio::Audio::connect().context("could not connect to the sound server")?;
+        audio.select_sink(sink);
+        audio.select_source(source);
+
+        Ok(audio)
+    }
+}
+
+/// The media control options of a configuration file.
+///
+/// The actions are given by their names, such as `"play_pause"`, or `"none"` to not perform an
+/// action.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Media {
+    /// Whether the G keys should control media players.
+    pub(crate) enabled: bool,
+    /// The action performed for the G1 key.
+    pub(crate) g1: Option<String>,
+    /// The action performed for the G2 key.
+    pub(crate) g2: Option<String>,
+    /// The action performed for the G3 key.
+    pub(crate) g3: Option<String>,
+}
+
+#[cfg(feature = "mpris")]
+impl Media {
+    /// Returns the key map described by the options.
+    fn key_map(&self) -> anyhow::Result<g935::mpris::MediaKeyMap> {
+        use g935::mpris::{MediaAction, MediaKeyMap};
+
+        /// Parses the action name, falling back to the default.
+        fn action(
+            name: &Option<String>,
+            default: Option<MediaAction>,
+        ) -> anyhow::Result<Option<MediaAction>> {
+            match name.as_deref() {
+                None => Ok(default),
+                Some("none") => Ok(None),
+                Some(name) => name.parse().map(Some),
+            }
+        }
+
+        let default = MediaKeyMap::default();
+
+        Ok(MediaKeyMap {
+            g1: action(&self.g1, default.g1)?,
+            g2: action(&self.g2, default.g2)?,
+            g3: action(&self.g3, default.g3)?,
+        })
+    }
+}
+
+/// The microphone muting options of a configuration file.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MicMute {
+    /// Whether the capture device should be muted while the microphone arm is flipped up.
+    pub(crate) enabled: bool,
+}
+
+/// The mute indicator options of a configuration file.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MuteIndicator {
+    /// Whether a light should show that the microphone is muted.
+    pub(crate) enabled: bool,
+    /// The light showing that the microphone is muted.
+    pub(crate) light: Light,
+    /// The effect shown while the microphone is muted.
+    pub(crate) effect: Effect,
+    /// Whether a flipped up microphone arm counts as muted.
+    pub(crate) mic_arm: bool,
+    /// Whether a muted source in the sound server counts as muted.
+    pub(crate) source: bool,
+}
+
+impl Default for MuteIndicator {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            light: Light::Side,
+            effect: Effect::static_color(Color::new(255, 0, 0)),
+            mic_arm: true,
+            source: false,
+        }
+    }
+}
+
+impl MuteIndicator {
+    /// Returns the plugin showing the indicator, following the source selected in the audio
+    /// options.
+    fn plugin(&self, audio: &Audio) -> anyhow::Result<g935::mute_indicator::MuteIndicator> {
+        let indicator = g935::mute_indicator::MuteIndicator::new(self.light, self.effect)
+            .follow_mic_arm(self.mic_arm);
+
+        if !self.source {
+            return Ok(indicator);
+        }
+
+        #[cfg(feature = "audio")]
+        return Ok(indicator.with_audio(audio.connect()?));
+        #[cfg(not(feature = "audio"))]
+        {
+            let _ = audio;
+            log::warn!(
+                "ignoring the source setting of the mute indicator, because g935 was built \
+                 without audio"
+            );
+            Ok(indicator)
+        }
+    }
+}
+
+/// A time of day in seconds since midnight, written as `HH:MM` in the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimeOfDay(pub(crate) u32);
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        let parsed = time.split_once(':').and_then(|(hours, minutes)| {
+            let hours = hours.parse::<u32>().ok().filter(|hours| *hours < 24)?;
+            let minutes = minutes
+                .parse::<u32>()
+                .ok()
+                .filter(|minutes| *minutes < 60)?;
+
+            Some(TimeOfDay(hours * 3600 + minutes * 60))
+        });
+
+        parsed.ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid time of day {time:?}, expected HH:MM"))
+        })
+    }
+}
+
+/// An entry of the lighting schedule of a configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ScheduleEntry {
+    /// When the entry starts in the local time zone, lasting until the next one starts.
+    pub(crate) start: TimeOfDay,
+    /// The effect of the side light.
+    #[serde(default)]
+    pub(crate) side: Effect,
+    /// The effect of the logo light.
+    #[serde(default)]
+    pub(crate) logo: Effect,
+    /// The brightness in percent that static colors are dimmed to.
+    #[serde(default = "ScheduleEntry::full_brightness")]
+    pub(crate) brightness: u8,
+    /// How long the fade from the previous entry takes in minutes, starting at `start`.
+    #[serde(default)]
+    pub(crate) fade_minutes: u32,
+}
+
+impl ScheduleEntry {
+    /// Returns the default brightness, which leaves static colors untouched.
+    fn full_brightness() -> u8 {
+        100
+    }
+
+    /// Returns the effect of the light, with static colors dimmed to the brightness.
+    pub(crate) fn effect(&self, light: Light) -> Effect {
+        let effect = match light {
+            Light::Side => self.side,
+            Light::Logo => self.logo,
+        };
+
+        match effect {
+            Effect::Static { color } => {
+                let dim =
+                    |channel: u8| (u16::from(channel) * u16::from(self.brightness) / 100) as u8;
+
+                Effect::Static {
+                    color: Color::new(dim(color.r), dim(color.g), dim(color.b)),
+                }
+            }
+            effect => effect,
+        }
+    }
+}
+
+/// The ambient light options of a configuration file.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Ambient {
+    /// Whether a light should show the average color of the screen.
+    pub(crate) enabled: bool,
+    /// The light showing the average color of the screen.
+    pub(crate) light: Light,
+    /// The time between two samples of the screen in milliseconds.
+    pub(crate) interval_ms: u64,
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            light: Light::Side,
+            interval_ms: 250,
+        }
+    }
+}
+
+/// The OBS options of a configuration file.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Obs {
+    /// Whether g935 should connect to OBS.
+    pub(crate) enabled: bool,
+    /// The URL of the WebSocket server of OBS.
+    pub(crate) url: String,
+    /// The password of the WebSocket server of OBS.
+    pub(crate) password: Option<String>,
+    /// Whether a light should show that OBS is streaming.
+    pub(crate) show_streaming: bool,
+    /// The light showing that OBS is streaming.
+    pub(crate) light: Light,
+    /// The effect shown while OBS is streaming.
+    pub(crate) effect: Effect,
+    /// The scene switched to when the microphone arm is flipped up.
+    pub(crate) scene_up: Option<String>,
+    /// The scene switched to when the microphone arm is flipped down.
+    pub(crate) scene_down: Option<String>,
+    /// The source shown while the microphone arm is flipped up.
+    pub(crate) source: Option<String>,
+    /// The scene containing the source.
+    pub(crate) source_scene: Option<String>,
+    /// The hotkey triggered when the microphone arm is flipped up.
+    pub(crate) hotkey_up: Option<String>,
+    /// The hotkey triggered when the microphone arm is flipped down.
+    pub(crate) hotkey_down: Option<String>,
+}
+
+impl Default for Obs {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "ws://localhost:4455".to_string(),
+            password: None,
+            show_streaming: true,
+            light: Light::Logo,
+            effect: Effect::static_color(Color::new(255, 0, 0)),
+            scene_up: None,
+            scene_down: None,
+            source: None,
+            source_scene: None,
+            hotkey_up: None,
+            hotkey_down: None,
+        }
+    }
+}
+
+#[cfg(feature = "obs")]
+impl Obs {
+    /// Returns what is done in OBS when the microphone arm is flipped.
+    fn actions(&self) -> anyhow::Result<Vec<g935::obs::MicArmAction>> {
+        use g935::obs::MicArmAction;
+
+        let mut actions = Vec::new();
+
+        match (&self.scene_up, &self.scene_down) {
+            (Some(up), Some(down)) => actions.push(MicArmAction::SwitchScene {
+                up: up.clone(),
+                down: down.clone(),
+            }),
+            (None, None) => (),
+            _ => anyhow::bail!("the OBS scenes need both scene_up and scene_down"),
+        }
+        match (&self.source, &self.source_scene) {
+            (Some(source), Some(scene)) => actions.push(MicArmAction::ShowSource {
+                scene: scene.clone(),
+                source: source.clone(),
+            }),
+            (None, None) => (),
+            _ => anyhow::bail!("the OBS source needs both source and source_scene"),
+        }
+        if self.hotkey_up.is_some() || self.hotkey_down.is_some() {
+            actions.push(MicArmAction::Hotkey {
+                up: self.hotkey_up.clone(),
+                down: self.hotkey_down.clone(),
+            });
+        }
+
+        Ok(actions)
+    }
+}
+
+/// The MQTT options of a configuration file.
+///
+/// These are only read when the daemon starts.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Mqtt {
+    /// Whether the daemon should connect to the broker.
+    pub(crate) enabled: bool,
+    /// The host name of the broker.
+    pub(crate) host: String,
+    /// The port of the broker.
+    pub(crate) port: u16,
+    /// The client ID used to connect to the broker.
+    pub(crate) client_id: String,
+    /// The user name to authenticate with.
+    pub(crate) username: Option<String>,
+    /// The password to authenticate with.
+    pub(crate) password: Option<String>,
+    /// The prefix of all topics of the daemon.
+    pub(crate) topic: String,
+    /// Whether Home Assistant discovery messages should be published.
+    pub(crate) discovery: bool,
+    /// The prefix of the Home Assistant discovery topics.
+    pub(crate) discovery_prefix: String,
+}
+
+impl Default for Mqtt {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "g935".to_string(),
+            username: None,
+            password: None,
+            topic: "g935".to_string(),
+            discovery: true,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+/// The WebSocket options of a configuration file.
+///
+/// These are only read when the daemon starts.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WebSocket {
+    /// Whether the daemon should accept WebSocket connections.
+    pub(crate) enabled: bool,
+    /// The address and port to listen on.
+    pub(crate) bind: String,
+}
+
+impl Default for WebSocket {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:9935".to_string(),
+        }
+    }
+}
+
+/// The HTTP options of a configuration file.
+///
+/// These are only read when the daemon starts.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Http {
+    /// Whether the daemon should accept HTTP requests.
+    pub(crate) enabled: bool,
+    /// The address and port to listen on.
+    pub(crate) bind: String,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:9936".to_string(),
+        }
+    }
+}
+
+/// The desktop notification options of a configuration file.
+///
+/// `{battery_percent}` in the battery messages is replaced by the charge, and an empty message
+/// disables its notification. These are only read when the daemon starts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Notifications {
+    /// Whether the daemon should show desktop notifications.
+    pub(crate) enabled: bool,
+    /// The charge in percent below which the battery counts as low.
+    pub(crate) low_battery: f32,
+    /// The message shown when the battery gets low.
+    pub(crate) low_battery_message: String,
+    /// The message shown when the battery is fully charged.
+    pub(crate) charged_message: String,
+    /// The message shown when the headset is turned on.
+    pub(crate) connected_message: String,
+    /// The message shown when the headset is turned off.
+    pub(crate) disconnected_message: String,
+    /// The message shown when another profile is selected, where `{profile}` is replaced by its
+    /// name.
+    pub(crate) profile_message: String,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low_battery: 15.0,
+            low_battery_message: "The battery is low ({battery_percent}%)".to_string(),
+            charged_message: "The battery is fully charged".to_string(),
+            connected_message: "The headset was turned on".to_string(),
+            disconnected_message: "The headset was turned off".to_string(),
+            profile_message: "Switched to the {profile} profile".to_string(),
+        }
+    }
+}
+
+/// The battery log options of a configuration file.
+///
+/// These are only read when the daemon starts.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct BatteryLog {
+    /// Whether the daemon should record the battery status.
+    pub(crate) enabled: bool,
+    /// The CSV file to append to, defaulting to `$XDG_STATE_HOME/g935/battery.csv`.
+    pub(crate) path: Option<PathBuf>,
+}
+
+/// Returns the path of the configuration file used if none is given explicitly.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(config_dir.join("g935").join("config.toml"))
+}
+
+impl ConfigFile {
+    /// Returns the built-in configuration used by the `run-continuous` command.
+    pub(crate) fn builtin() -> Self {
+        toml::from_str(include_str!("default_config.toml"))
+            .expect("the built-in configuration is valid")
+    }
+
+    /// Reads and validates the configuration file at the given path.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read config file {}", path.display()))?;
+
+        Self::parse(&contents).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Parses and validates the contents of a configuration file.
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        let config_file: ConfigFile = toml::from_str(contents).context("could not parse it")?;
+
+        config_file
+            .lights
+            .side
+            .validate()
+            .context("invalid side light effect")?;
+        config_file
+            .lights
+            .logo
+            .validate()
+            .context("invalid logo light effect")?;
+        if let Some(brightness) = config_file.lights.brightness {
+            anyhow::ensure!(
+                brightness <= 100,
+                "the brightness of the lights must be at most 100, found {brightness}"
+            );
+        }
+        config_file
+            .mute_indicator
+            .effect
+            .validate()
+            .context("invalid mute indicator effect")?;
+        config_file
+            .obs
+            .effect
+            .validate()
+            .context("invalid OBS streaming effect")?;
+        #[cfg(feature = "obs")]
+        config_file.obs.actions()?;
+        config_file.validate_schedule()?;
+        config_file.validate_profiles()?;
+        config_file.validate_macros()?;
+        #[cfg(feature = "scripting")]
+        config_file.script_bindings()?;
+
+        Ok(config_file)
+    }
+
+    /// Checks that the effects of the schedule are valid.
+    fn validate_schedule(&self) -> anyhow::Result<()> {
+        for entry in &self.schedule {
+            let hours = entry.start.0 / 3600;
+            let minutes = entry.start.0 / 60 % 60;
+
+            entry
+                .side
+                .validate()
+                .and_then(|()| entry.logo.validate())
+                .with_context(|| {
+                    format!("invalid effect in the schedule at {hours:02}:{minutes:02}")
+                })?;
+            anyhow::ensure!(
+                entry.brightness <= 100,
+                "the brightness in the schedule at {hours:02}:{minutes:02} must be at most 100"
+            );
+            anyhow::ensure!(
+                entry.fade_minutes < 24 * 60,
+                "the fade in the schedule at {hours:02}:{minutes:02} must be shorter than a day"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the profiles are consistent.
+    fn validate_profiles(&self) -> anyhow::Result<()> {
+        for (name, profile) in &self.profiles {
+            for effect in [profile.lights.side, profile.lights.logo]
+                .into_iter()
+                .flatten()
+            {
+                effect
+                    .validate()
+                    .with_context(|| format!("invalid light effect in the {name} profile"))?;
+            }
+        }
+
+        if let Some(name) = &self.profile {
+            anyhow::ensure!(
+                self.profiles.contains_key(name),
+                "the selected profile {name:?} does not exist"
+            );
+        }
+
+        for (trigger, action) in &self.profile_bindings {
+            anyhow::ensure!(
+                !self.profiles.is_empty(),
+                "profile_bindings require at least one profile"
+            );
+
+            if let ProfileAction::Select(name) = action.parse().unwrap_or_else(|err| match err {}) {
+                anyhow::ensure!(
+                    self.profiles.contains_key(&name),
+                    "{trigger} selects the profile {name:?}, which does not exist"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the macros are valid and that the bound ones exist.
+    fn validate_macros(&self) -> anyhow::Result<()> {
+        for (name, macro_) in &self.macros {
+            macro_
+                .validate()
+                .with_context(|| format!("invalid macro {name:?}"))?;
+        }
+
+        let bindings = std::iter::once(&self.macro_bindings).chain(
+            self.profiles
+                .values()
+                .map(|profile| &profile.macro_bindings),
+        );
+        for name in bindings.flat_map(MacroBindings::names) {
+            anyhow::ensure!(
+                self.macros.contains_key(name),
+                "the bound macro {name:?} does not exist"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the script bindings declared in the configuration file.
+    #[cfg(feature = "scripting")]
+    fn script_bindings(&self) -> anyhow::Result<g935::scripting::ScriptBindings> {
+        let mut scripts = g935::scripting::ScriptBindings::new();
+
+        for (&trigger, source) in &self.scripts {
+            scripts.bind(trigger, source)?;
+        }
+        scripts.set_detector(self.trigger_detector());
+
+        Ok(scripts)
+    }
+
+    /// Returns the detector for the triggers of the scripts.
+    #[cfg(feature = "scripting")]
+    fn trigger_detector(&self) -> g935::bindings::TriggerDetector {
+        let mut detector = g935::bindings::TriggerDetector::new();
+
+        if let Some(threshold) = self.buttons.long_press_ms {
+            detector.set_long_press_threshold(Duration::from_millis(threshold));
+        }
+        if let Some(window) = self.buttons.double_press_ms {
+            detector.set_double_press_window(Duration::from_millis(window));
+        }
+        if let Some(interval) = self.buttons.scroll_repeat_ms {
+            detector.set_scroll_repeat_interval(Duration::from_millis(interval));
+        }
+        detector.set_chord_window(self.chord_window());
+
+        detector
+    }
+
+    /// Returns the player for the macros, if any macro is bound.
+    fn macro_player(&self) -> anyhow::Result<Option<MacroPlayer>> {
+        let bindings = std::iter::once(&self.macro_bindings).chain(
+            self.profiles
+                .values()
+                .map(|profile| &profile.macro_bindings),
+        );
+        let names: BTreeSet<_> = bindings.flat_map(MacroBindings::names).collect();
+
+        if names.is_empty() {
+            return Ok(None);
+        }
+
+        MacroPlayer::new(names.into_iter().filter_map(|name| self.macros.get(name)))
+            .context("could not set up the macros")
+            .map(Some)
+    }
+
+    /// Returns the profiles declared in the configuration file, if there are any.
+    ///
+    /// The macros of the profiles are played using the `player`.
+    pub(crate) fn profiles(&self, player: Option<&MacroPlayer>) -> Option<Profiles> {
+        let profiles = self
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                let mut bindings = self.bindings();
+                for (&trigger, command) in &profile.bindings {
+                    bindings.bind(trigger, command);
+                }
+
+                profiles::Profile {
+                    name: name.clone(),
+                    side_light: profile.lights.side.unwrap_or(self.lights.side),
+                    logo_light: profile.lights.logo.unwrap_or(self.lights.logo),
+                    sidetone: profile.sidetone.or(self.sidetone),
+                    equalizer: profile.equalizer.clone().or_else(|| self.equalizer.clone()),
+                    bindings,
+                    macros: player.map(|player| {
+                        let bindings = profile.macro_bindings.or(&self.macro_bindings);
+                        MacroControl::new(player, bindings.key_map(&self.macros))
+                    }),
+                }
+            })
+            .collect();
+
+        let mut profiles = Profiles::new(profiles)?;
+
+        if let Some(name) = &self.profile {
+            profiles.select(name);
+        }
+        for (&trigger, action) in &self.profile_bindings {
+            profiles.bind(trigger, action.parse().unwrap_or_else(|err| match err {}));
+        }
+        if let Some(threshold) = self.buttons.long_press_ms {
+            profiles.set_long_press_threshold(Duration::from_millis(threshold));
+        }
+        if let Some(window) = self.buttons.double_press_ms {
+            profiles.set_double_press_window(Duration::from_millis(window));
+        }
+        if let Some(interval) = self.buttons.scroll_repeat_ms {
+            profiles.set_scroll_repeat_interval(Duration::from_millis(interval));
+        }
+        profiles.set_chord_window(self.chord_window());
+
+        Some(profiles)
+    }
+
+    /// Returns the time within which presses form a chord, if any chord is bound.
+    fn chord_window(&self) -> Option<Duration> {
+        let mut triggers = self
+            .bindings
+            .keys()
+            .chain(self.scripts.keys())
+            .chain(self.profile_bindings.keys())
+            .chain(
+                self.profiles
+                    .values()
+                    .flat_map(|profile| profile.bindings.keys()),
+            );
+
+        triggers
+            .any(Trigger::is_chord)
+            .then(|| Duration::from_millis(self.buttons.chord_ms.unwrap_or(DEFAULT_CHORD_MS)))
+    }
+
+    /// Returns the bindings declared in the configuration file.
+    pub(crate) fn bindings(&self) -> Bindings {
+        let mut bindings = Bindings::new();
+
+        for (&trigger, command) in &self.bindings {
+            bindings.bind(trigger, command);
+        }
+        if let Some(rate_limit) = self.buttons.rate_limit_ms {
+            bindings.set_rate_limit(Duration::from_millis(rate_limit));
+        }
+        if let Some(threshold) = self.buttons.long_press_ms {
+            bindings.set_long_press_threshold(Duration::from_millis(threshold));
+        }
+        if let Some(window) = self.buttons.double_press_ms {
+            bindings.set_double_press_window(Duration::from_millis(window));
+        }
+        if let Some(interval) = self.buttons.scroll_repeat_ms {
+            bindings.set_scroll_repeat_interval(Duration::from_millis(interval));
+        }
+        bindings.set_chord_window(self.chord_window());
+
+        bindings
+    }
+
+    /// Translates the configuration file into the run configuration.
+    ///
+    /// If the `previous` configuration file was already applied, only the changes are applied.
+    /// When running as a daemon, the button events are also published by the `publisher`.
+    pub(crate) fn apply(
+        &self,
+        config: &mut RunConfig,
+        previous: Option<&ConfigFile>,
+        publisher: Option<&Publisher>,
+    ) -> anyhow::Result<()> {
+        // Without profiles, the player is only needed if the button handler is replaced
+        let player = if self.profiles.is_empty() {
+            None
+        } else {
+            self.macro_player()?
+        };
+        let profiles = self.profiles(player.as_ref());
+
+        config.set_restore_on_exit(self.restore_on_exit.unwrap_or(RestorePolicy::Defaults));
+        config.set_latency_budget(self.buttons.latency_budget_ms.map(Duration::from_millis));
+        config.set_mic_arm_debounce(self.buttons.mic_arm_debounce_ms.map(Duration::from_millis));
+        config.set_mute_debounce(self.buttons.mute_debounce_ms.map(Duration::from_millis));
+
+        // The config only marks these as changed if they differ from the current value
+        config.set_light_brightness(Light::Side, self.lights.brightness);
+        config.set_light_brightness(Light::Logo, self.lights.brightness);
+        match &profiles {
+            Some(profiles) => profiles.selected().apply(config),
+            None => {
+                config.set_lights(lights::FullLightSettings {
+                    side: self.lights.side,
+                    logo: self.lights.logo,
+                });
+                config.set_sidetone(self.sidetone);
+                config.set_equalizer(self.equalizer.clone());
+            }
+        }
+
+        // Replacing the plugins would lose the button state, so keep them if possible.
+        // With profiles, the plugins are always replaced, as the initial profile was applied.
+        if profiles.is_none()
+            && previous.is_some_and(|previous| {
+                previous.profiles.is_empty()
+                    && previous.bindings == self.bindings
+                    && previous.scripts == self.scripts
+                    && previous.buttons == self.buttons
+                    && previous.macros == self.macros
+                    && previous.macro_bindings == self.macro_bindings
+                    && previous.uinput == self.uinput
+                    && previous.audio == self.audio
+                    && previous.media == self.media
+                    && previous.mic_mute == self.mic_mute
+                    && previous.mute_indicator == self.mute_indicator
+                    && previous.ambient == self.ambient
+                    && previous.obs == self.obs
+                    && previous.schedule == self.schedule
+            })
+        {
+            return Ok(());
+        }
+
+        config.clear_plugins();
+
+        if let Some(publisher) = publisher {
+            config.add_plugin(Box::new(publisher.button_publisher()));
+        }
+
+        match profiles {
+            Some(mut profiles) => {
+                if let Some(publisher) = publisher {
+                    profiles.set_change_handler(Some(publisher.profile_handler()));
+                }
+                config.add_plugin(Box::new(profiles));
+            }
+            None => {
+                let bindings = self.bindings();
+                if !bindings.is_empty() {
+                    config.add_plugin(Box::new(bindings));
+                }
+                if let Some(player) = self.macro_player()? {
+                    let keys = self.macro_bindings.key_map(&self.macros);
+                    config.add_plugin(Box::new(MacroControl::new(&player, keys)));
+                }
+            }
+        }
+
+        if self.uinput.enabled {
+            #[cfg(all(feature = "uinput", target_os = "linux"))]
+            config.add_plugin(Box::new(
+                g935::uinput::VirtualInput::new(
+                    self.buttons
+                        .passthrough_keys(Some(self.uinput.key_map()?))?,
+                )
+                .context("could not create the virtual input device")?,
+            ));
+            #[cfg(not(all(feature = "uinput", target_os = "linux")))]
+            log::warn!("ignoring the uinput settings, because g935 was built without uinput");
+        } else if !self.buttons.passthrough.is_empty() {
+            #[cfg(all(feature = "uinput", target_os = "linux"))]
+            config.add_plugin(Box::new(
+                g935::uinput::VirtualInput::new(self.buttons.passthrough_keys(None)?)
+                    .context("could not create the virtual input device")?,
+            ));
+            #[cfg(not(all(feature = "uinput", target_os = "linux")))]
+            log::warn!("ignoring the passthrough G keys, because g935 was built without uinput");
+        }
+
+        if self.audio.enabled {
+            #[cfg(feature = "audio")]
+            {
+                let mut control = g935::audio::AudioControl::with_audio(
+                    self.audio.connect()?,
+                    self.audio.volume_step,
+                    self.audio.mute_button,
+                );
+                control.set_acceleration(self.audio.acceleration);
+                config.add_plugin(Box::new(control));
+            }
+            #[cfg(not(feature = "audio"))]
+            log::warn!("ignoring the audio settings, because g935 was built without audio");
+        }
+
+        if self.media.enabled {
+            #[cfg(feature = "mpris")]
+            config.add_plugin(Box::new(
+                g935::mpris::MediaControl::new(self.media.key_map()?)
+                    .context("could not connect to the session bus")?,
+            ));
+            #[cfg(not(feature = "mpris"))]
+            log::warn!("ignoring the media settings, because g935 was built without mpris");
+        }
+
+        if self.mic_mute.enabled {
+            #[cfg(feature = "alsa")]
+            config.add_plugin(Box::new(
+                g935::mic_mute::FlipToMute::new()
+                    .context("could not set up muting the microphone")?,
+            ));
+            #[cfg(not(feature = "alsa"))]
+            log::warn!("ignoring the mic_mute settings, because g935 was built without alsa");
+        }
+
+        if self.mute_indicator.enabled {
+            config.add_plugin(Box::new(self.mute_indicator.plugin(&self.audio)?));
+        }
+
+        if !self.schedule.is_empty() {
+            config.add_plugin(Box::new(Schedule::new(self.schedule.clone())));
+        }
+
+        if self.ambient.enabled {
+            #[cfg(feature = "ambient")]
+            {
+                let mut ambient = g935::ambient::AmbientLight::new(self.ambient.light)
+                    .context("could not sample the screen")?;
+                ambient.set_interval(Duration::from_millis(self.ambient.interval_ms));
+                config.add_plugin(Box::new(ambient));
+            }
+            #[cfg(not(feature = "ambient"))]
+            log::warn!("ignoring the ambient settings, because g935 was built without ambient");
+        }
+
+        if self.obs.enabled {
+            #[cfg(feature = "obs")]
+            {
+                let mut on_air =
+                    g935::obs::OnAir::connect(&self.obs.url, self.obs.password.clone());
+                for action in self.obs.actions()? {
+                    on_air = on_air.on_mic_arm(action);
+                }
+                if self.obs.show_streaming {
+                    on_air = on_air.show_streaming(self.obs.light, self.obs.effect);
+                }
+                config.add_plugin(Box::new(on_air));
+            }
+            #[cfg(not(feature = "obs"))]
+            log::warn!("ignoring the obs settings, because g935 was built without obs");
+        }
+
+        if !self.scripts.is_empty() {
+            #[cfg(feature = "scripting")]
+            config.add_plugin(Box::new(self.script_bindings()?));
+            #[cfg(not(feature = "scripting"))]
+            log::warn!("ignoring the scripts, because g935 was built without scripting");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the TOML examples of the configuration in the README.
+    fn readme_examples() -> Vec<&'static str> {
+        let readme = include_str!("../../../README.md");
+        let configuration = &readme[readme
+            .find("## Configuration")
+            .expect("the README documents the configuration")..];
+
+        configuration
+            .split("```toml\n")
+            .skip(1)
+            .map(|block| &block[..block.find("```").expect("the example is closed")])
+            .collect()
+    }
+
+    /// Returns the error of parsing the contents, including its causes.
+    fn parse_error(contents: &str) -> String {
+        format!("{:#}", ConfigFile::parse(contents).unwrap_err())
+    }
+
+    #[test]
+    fn parses_the_builtin_config() {
+        let builtin = ConfigFile::parse(include_str!("default_config.toml")).unwrap();
+
+        assert_eq!(builtin, ConfigFile::builtin());
+        assert_eq!(
+            builtin.bindings[&Trigger::MicFlippedUp],
+            "amixer set Capture nocap"
+        );
+        builtin
+            .apply(&mut RunConfig::default(), None, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn parses_the_examples_of_the_readme() {
+        let examples = readme_examples();
+        assert!(examples.len() > 5);
+
+        for example in examples {
+            let result = ConfigFile::parse(example);
+            // Without the feature, macros with key events are rejected instead of being ignored
+            if !cfg!(all(feature = "uinput", target_os = "linux")) && example.contains("[macros.") {
+                assert!(format!("{:#}", result.unwrap_err()).contains("uinput feature"));
+            } else if let Err(err) = result {
+                panic!("could not parse the example {example:?}: {err:#}");
+            }
+        }
+    }
+
+    #[test]
+    fn parses_a_full_config() {
+        let config_file = ConfigFile::parse(readme_examples()[0]).unwrap();
+
+        assert_eq!(config_file.sidetone, Some(30));
+        assert_eq!(config_file.buttons.long_press_ms, Some(500));
+        assert_eq!(config_file.buttons.mute_debounce_ms, Some(150));
+        assert_eq!(
+            config_file.bindings[&Trigger::G2DoublePressed],
+            "playerctl next"
+        );
+        assert_eq!(config_file.restore_on_exit, Some(RestorePolicy::Defaults));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(parse_error("sidetone = 30\nsidetones = 30").contains("unknown field `sidetones`"));
+        assert!(parse_error("[buttons]\nlong_press = 500").contains("unknown field `long_press`"));
+        assert!(parse_error("[bindings]\ng4_pressed = \"true\"").contains("g4_pressed"));
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(parse_error("sidetone = 300").contains("sidetone"));
+        assert!(parse_error("sidetone = \"loud\"").contains("sidetone"));
+        assert!(parse_error("equalizer = [-200]").contains("equalizer"));
+        assert!(parse_error("[lights]\nbrightness = 150").contains("at most 100, found 150"));
+        assert!(
+            parse_error("[lights.side]\neffect = \"static\"\ncolor = \"#12\"").contains("#rrggbb")
+        );
+        assert!(parse_error("restore_on_exit = \"sometimes\"").contains("sometimes"));
+    }
+}
@@ -0,0 +1,183 @@
+//! The subcommands controlling a running daemon.
+
+use std::path::Path;
+
+use g935::lights::{Color, Effect, Light};
+
+use crate::{
+    daemon::{Request, Response},
+    output,
+    socket::Connection,
+};
+
+/// The kind of a light effect.
+#[derive(clap::ArgEnum, Debug, Clone, Copy)]
+pub(crate) enum EffectKind {
+    /// the light is turned off
+    Off,
+    /// the light shows a static color
+    Static,
+    /// the light breathes in a color
+    Breathing,
+    /// the light cycles through all colors
+    ColorCycle,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum CtlCommand {
+    /// return the battery status
+    GetBattery,
+    /// set the effect of a light
+    SetLights {
+        /// the light to change (side or logo)
+        light: Light,
+        /// the effect to show
+        #[clap(arg_enum)]
+        effect: EffectKind,
+        /// the color of the static and breathing effects, as #rrggbb or by name
+        #[clap(short, long)]
+        color: Option<Color>,
+        /// the duration of one breath or color cycle in milliseconds
+        #[clap(short, long)]
+        rate_ms: Option<u64>,
+        /// the brightness in percent of the breathing and color cycle effects
+        #[clap(short, long)]
+        brightness: Option<u8>,
+    },
+    /// set the brightness of a light in percent, keeping its effect
+    SetBrightness {
+        /// the light to change (side or logo)
+        light: Light,
+        /// the brightness in percent, which scales the color of static effects
+        brightness: u8,
+    },
+    /// return the sidetone level in percent
+    GetSidetone,
+    /// set the sidetone level in percent
+    SetSidetone {
+        /// the sidetone level in percent
+        level: u8,
+    },
+    /// return the gain in dB of each equalizer band
+    GetEqualizer,
+    /// set the gain in dB of each equalizer band
+    SetEqualizer {
+        /// the gain in dB of each band
+        #[clap(allow_hyphen_values = true, required = true)]
+        gains: Vec<i8>,
+    },
+    /// return the statistics about the communication with the headset
+    GetStats,
+    /// return whether the headset is linked to the receiver, and how fast it responds
+    GetWirelessStatus,
+    /// print the events of the daemon as JSON, one per line
+    Subscribe,
+}
+
+/// Returns the effect described by the arguments.
+pub(crate) fn effect(
+    kind: EffectKind,
+    color: Option<Color>,
+    rate_ms: Option<u64>,
+    brightness: Option<u8>,
+) -> anyhow::Result<Effect> {
+    let color = || color.ok_or_else(|| anyhow::anyhow!("the effect requires --color"));
+
+    let effect = match kind {
+        EffectKind::Off => Effect::Off,
+        EffectKind::Static => Effect::static_color(color()?),
+        EffectKind::Breathing => {
+            let mut builder = Effect::breathing(color()?);
+            if let Some(rate_ms) = rate_ms {
+                builder = builder.rate_ms(rate_ms)?;
+            }
+            if let Some(brightness) = brightness {
+                builder = builder.brightness(brightness)?;
+            }
+
+            builder.build()
+        }
+        EffectKind::ColorCycle => {
+            let mut builder = Effect::color_cycle();
+            if let Some(rate_ms) = rate_ms {
+                builder = builder.rate_ms(rate_ms)?;
+            }
+            if let Some(brightness) = brightness {
+                builder = builder.brightness(brightness)?;
+            }
+
+            builder.build()
+        }
+    };
+
+    Ok(effect)
+}
+
+/// Runs the command against the daemon listening on the socket, printing the response as JSON if
+/// `json` is set.
+pub(crate) fn run(socket: &Path, command: CtlCommand, json: bool) -> anyhow::Result<()> {
+    let mut connection = Connection::connect(socket)?;
+
+    let request = match command {
+        CtlCommand::GetBattery => Request::GetBattery,
+        CtlCommand::SetLights {
+            light,
+            effect: kind,
+            color,
+            rate_ms,
+            brightness,
+        } => Request::SetLights {
+            light,
+            effect: effect(kind, color, rate_ms, brightness)?,
+        },
+        CtlCommand::SetBrightness { light, brightness } => {
+            Request::SetBrightness { light, brightness }
+        }
+        CtlCommand::GetSidetone => Request::GetSidetone,
+        CtlCommand::SetSidetone { level } => Request::SetSidetone { level },
+        CtlCommand::GetEqualizer => Request::GetEqualizer,
+        CtlCommand::SetEqualizer { gains } => Request::SetEqualizer { gains },
+        CtlCommand::GetStats => Request::GetStats,
+        CtlCommand::GetWirelessStatus => Request::GetWirelessStatus,
+        CtlCommand::Subscribe => {
+            return connection.subscribe(|event| match serde_json::to_string(&event) {
+                Ok(event) => println!("{event}"),
+                Err(err) => log::error!("could not print event: {err}"),
+            });
+        }
+    };
+
+    let response = connection.request(&request)?;
+    if json {
+        match response {
+            Response::Battery(status) => output::print_json(&status),
+            Response::Sidetone(level) => output::print_json(&serde_json::json!({ "level": level })),
+            Response::Equalizer(gains) => {
+                output::print_json(&serde_json::json!({ "gains": gains }))
+            }
+            Response::Stats(stats) => output::print_json(&stats),
+            Response::WirelessStatus(status) => output::print_json(&status),
+            Response::Done => (),
+        }
+
+        return Ok(());
+    }
+
+    match response {
+        Response::Battery(status) => println!("{} {}", status.charging_status, status.charge),
+        Response::Sidetone(level) => println!("{level}"),
+        Response::Equalizer(gains) => println!(
+            "{}",
+            gains
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        Response::Stats(stats) => println!("{stats}"),
+        Response::WirelessStatus(status) => println!("{status}"),
+        Response::Done => (),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+//! The daemon owning the headset, serving requests of other processes.
+//!
+//! Only the daemon talks to the headset, so that concurrent requests cannot get mixed up.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use g935::{
+    bindings::{Trigger, TriggerDetector},
+    config::{PeriodicHandler, RunConfig},
+    lights::{self, Effect, Light},
+    plugin::Plugin,
+    profiles::ProfileChangeHandler,
+    BatteryStatus, Headset, PowerState, Stats, Waker, WirelessStatus,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::state::{State, StateStore};
+
+/// How often the battery status is polled to notify about changes.
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A request to the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub(crate) enum Request {
+    /// Returns the battery status.
+    GetBattery,
+    /// Sets the effect of a light.
+    SetLights {
+        /// The light to change.
+        light: Light,
+        /// The new effect of the light.
+        effect: Effect,
+    },
+    /// Sets the brightness of a light, keeping its effect.
+    SetBrightness {
+        /// The light to change.
+        light: Light,
+        /// The brightness in percent.
+        brightness: u8,
+    },
+    /// Returns the sidetone level.
+    GetSidetone,
+    /// Sets the sidetone level.
+    SetSidetone {
+        /// The sidetone level in percent.
+        level: u8,
+    },
+    /// Returns the equalizer gains.
+    GetEqualizer,
+    /// Sets the equalizer gains.
+    SetEqualizer {
+        /// The gain in dB of each band.
+        gains: Vec<i8>,
+    },
+    /// Returns the statistics about the communication with the headset.
+    GetStats,
+    /// Returns the state of the wireless link to the headset.
+    GetWirelessStatus,
+}
+
+/// The response to a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Response {
+    /// The battery status.
+    Battery(BatteryStatus),
+    /// The sidetone level in percent.
+    Sidetone(u8),
+    /// The equalizer gains in dB.
+    Equalizer(Vec<i8>),
+    /// The statistics about the communication with the headset.
+    Stats(Stats),
+    /// The state of the wireless link to the headset.
+    WirelessStatus(WirelessStatus),
+    /// The request was performed.
+    Done,
+}
+
+/// An event published by the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Event {
+    /// A button event occurred.
+    Button(Trigger),
+    /// The headset was turned on or off.
+    PowerState(PowerState),
+    /// The battery status changed.
+    Battery(BatteryStatus),
+    /// Another profile was selected.
+    Profile(String),
+}
+
+/// An event as published to the subscribers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Published {
+    /// The event.
+    #[serde(flatten)]
+    pub(crate) event: Event,
+    /// The number of the event, counting up from 0 since the daemon started.
+    pub(crate) sequence: u64,
+    /// When the event occurred in microseconds on the monotonic clock, which is the time since
+    /// boot on Linux.
+    pub(crate) timestamp_us: u64,
+}
+
+/// A request together with the channel for its response.
+type Envelope = (Request, Sender<anyhow::Result<Response>>);
+
+/// Sends requests to the daemon.
+#[derive(Debug, Clone)]
+pub(crate) struct Client {
+    /// The channel to send requests on.
+    requests: Sender<Envelope>,
+    /// The waker making the daemon handle the requests right away.
+    waker: Waker,
+}
+
+impl Client {
+    /// Sends the request to the daemon, waiting for the response.
+    pub(crate) fn request(&self, request: Request) -> anyhow::Result<Response> {
+        let (reply, response) = crossbeam_channel::bounded(1);
+
+        self.requests
+            .send((request, reply))
+            .map_err(|_| anyhow::anyhow!("the daemon stopped"))?;
+        self.waker.wake();
+
+        response
+            .recv()
+            .map_err(|_| anyhow::anyhow!("the daemon stopped"))?
+    }
+}
+
+/// Publishes events to all subscribers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Publisher {
+    /// The channels of the subscribers.
+    subscribers: Arc<Mutex<Vec<Sender<Published>>>>,
+    /// The sequence number of the next event.
+    sequence: Arc<AtomicU64>,
+}
+
+impl Publisher {
+    /// Returns a channel receiving all future events.
+    pub(crate) fn subscribe(&self) -> Receiver<Published> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        self.subscribers.lock().unwrap().push(sender);
+
+        receiver
+    }
+
+    /// Sends the event to all subscribers, forgetting the ones that went away.
+    pub(crate) fn publish(&self, event: Event) {
+        self.publish_at(event, g935::monotonic_time());
+    }
+
+    /// Sends the event that occurred at the time of [`g935::monotonic_time`] to all subscribers.
+    pub(crate) fn publish_at(&self, event: Event, timestamp: Duration) {
+        log::debug!("publishing {event:?}");
+
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // Taken while locked, so that the events are sent in the order of their numbers
+        let published = Published {
+            event,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            timestamp_us: timestamp.as_micros().try_into().unwrap_or(u64::MAX),
+        };
+
+        subscribers.retain(|subscriber| subscriber.send(published.clone()).is_ok());
+    }
+
+    /// Returns a plugin publishing the button events.
+    pub(crate) fn button_publisher(&self) -> ButtonPublisher {
+        ButtonPublisher {
+            publisher: self.clone(),
+            detector: TriggerDetector::new(),
+        }
+    }
+
+    /// Returns a handler publishing the profile changes.
+    pub(crate) fn profile_handler(&self) -> ProfileChangeHandler {
+        let publisher = self.clone();
+
+        Box::new(move |name| publisher.publish(Event::Profile(name.to_string())))
+    }
+}
+
+/// Publishes the triggers detected in the button events.
+#[derive(Debug)]
+pub(crate) struct ButtonPublisher {
+    /// The publisher of the events.
+    publisher: Publisher,
+    /// The detector of the triggers.
+    detector: TriggerDetector,
+}
+
+impl Plugin for ButtonPublisher {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: g935::Event) {
+        if let g935::EventKind::Buttons(state) = event.kind {
+            for trigger in self.detector.detect(state) {
+                self.publisher
+                    .publish_at(Event::Button(trigger), event.timestamp);
+            }
+        }
+    }
+}
+
+/// Serves the requests of the clients while the headset is running.
+#[derive(Debug)]
+pub(crate) struct Daemon {
+    /// The channel receiving the requests.
+    requests: Receiver<Envelope>,
+    /// The publisher of the events.
+    publisher: Publisher,
+    /// The last published battery status.
+    battery: Option<BatteryStatus>,
+    /// When the battery status was last polled.
+    last_battery_poll: Option<Instant>,
+    /// The state file recording the settings changed by requests, if it is used.
+    state: Option<StateStore>,
+}
+
+impl Daemon {
+    /// Creates the daemon and a client to send requests to it, using the waker of the config
+    /// the daemon is installed into.
+    ///
+    /// The settings changed by requests are recorded in the state file, if one is given.
+    pub(crate) fn new(waker: Waker, state: Option<StateStore>) -> (Self, Client) {
+        let (requests, receiver) = crossbeam_channel::unbounded();
+
+        let daemon = Self {
+            requests: receiver,
+            publisher: Publisher::default(),
+            battery: None,
+            last_battery_poll: None,
+            state,
+        };
+
+        (daemon, Client { requests, waker })
+    }
+
+    /// Returns the publisher of the events of the daemon.
+    pub(crate) fn publisher(&self) -> Publisher {
+        self.publisher.clone()
+    }
+
+    /// Performs the request.
+    fn perform(
+        &mut self,
+        config: &mut RunConfig,
+        headset: &mut Headset,
+        request: Request,
+    ) -> anyhow::Result<Response> {
+        match request {
+            Request::GetBattery => headset.get_battery_status().map(Response::Battery),
+            Request::SetLights { light, effect } => {
+                // The effect comes from a client, so it may not be encodable
+                effect.validate()?;
+                headset.set_lights(&lights::LightSettings {
+                    light,
+                    effect,
+                    profile_type: lights::ProfileType::Temporary,
+                })?;
+
+                // Keep the config in sync, so that the effect is restored after reconnects
+                match light {
+                    Light::Side => config.set_side_light_effect(effect),
+                    Light::Logo => config.set_logo_light_effect(effect),
+                }
+                self.record(|state| state.set_lights(light, effect));
+
+                Ok(Response::Done)
+            }
+            Request::SetBrightness { light, brightness } => {
+                headset.set_brightness(light, brightness)?;
+                config.set_light_brightness(light, Some(brightness));
+                self.record(|state| state.set_brightness(light, brightness));
+
+                Ok(Response::Done)
+            }
+            Request::GetSidetone => headset.get_sidetone().map(Response::Sidetone),
+            Request::SetSidetone { level } => {
+                headset.set_sidetone(level)?;
+                config.set_sidetone(Some(level));
+                self.record(|state| state.sidetone = Some(level));
+
+                Ok(Response::Done)
+            }
+            Request::GetEqualizer => headset.get_equalizer().map(Response::Equalizer),
+            Request::SetEqualizer { gains } => {
+                headset.set_equalizer(&gains)?;
+                config.set_equalizer(Some(gains.clone()));
+                self.record(|state| state.equalizer = Some(gains));
+
+                Ok(Response::Done)
+            }
+            Request::GetStats => Ok(Response::Stats(headset.stats())),
+            Request::GetWirelessStatus => Ok(Response::WirelessStatus(headset.wireless_status())),
+        }
+    }
+
+    /// Records the changed settings in the state file, if it is used.
+    fn record(&mut self, change: impl FnOnce(&mut State)) {
+        if let Some(state) = &mut self.state {
+            state.update(change);
+        }
+    }
+
+    /// Performs all pending requests.
+    fn handle_requests(&mut self, config: &mut RunConfig, headset: &mut Headset) {
+        while let Ok((request, reply)) = self.requests.try_recv() {
+            log::debug!("handling {request:?}");
+
+            // The client may have given up waiting, which is fine
+            reply.send(self.perform(config, headset, request)).ok();
+        }
+    }
+
+    /// Polls the battery status periodically, publishing changes.
+    fn poll_battery(&mut self, headset: &mut Headset, force: bool) {
+        if !force
+            && self
+                .last_battery_poll
+                .is_some_and(|last_poll| last_poll.elapsed() < BATTERY_POLL_INTERVAL)
+        {
+            return;
+        }
+        self.last_battery_poll = Some(Instant::now());
+
+        match headset.get_battery_status() {
+            Ok(status) if self.battery != Some(status) => {
+                self.battery = Some(status);
+                self.publisher.publish(Event::Battery(status));
+            }
+            Ok(_) => (),
+            Err(err) => log::debug!("could not poll the battery status: {err}"),
+        }
+    }
+
+    /// Installs the handlers serving the requests and publishing the events into the config.
+    ///
+    /// The button events are published by the plugin of [`Publisher::button_publisher`], whereas
+    /// `periodic` is called in addition to serving the requests. The requests wake the loop, so
+    /// the periodic handler is only called as often as the battery is polled.
+    pub(crate) fn install(mut self, config: &mut RunConfig, mut periodic: PeriodicHandler) {
+        config.set_periodic_interval(config.periodic_interval().min(BATTERY_POLL_INTERVAL));
+
+        let publisher = self.publisher();
+        let reconnected = Rc::new(Cell::new(false));
+        let reconnected2 = Rc::clone(&reconnected);
+
+        config.set_power_state_change_handler(Some(Box::new(move |_, _, power_state| {
+            // Poll right away after reconnecting, as the battery was likely charged meanwhile
+            reconnected.set(power_state == PowerState::Connected);
+            publisher.publish(Event::PowerState(power_state));
+        })));
+
+        config.set_periodic_handler(Some(Box::new(move |config, headset| {
+            periodic(config, headset);
+
+            self.handle_requests(config, headset);
+            self.poll_battery(headset, reconnected2.take());
+        })));
+    }
+}
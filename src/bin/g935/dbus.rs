@@ -0,0 +1,434 @@
+//! The D-Bus interface of the daemon.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use g935::{
+    lights::{Color, Effect, Light},
+    BatteryStatus, ChargingStatus, PowerState,
+};
+use zbus::{fdo, zvariant::Value};
+
+use crate::daemon::{Client, Event, Published, Request, Response};
+
+/// The well-known name of the daemon on the session bus.
+const NAME: &str = "org.g935.Headset1";
+
+/// The object path of the headset.
+const PATH: &str = "/org/g935/Headset1";
+
+/// The interface of the headset.
+const INTERFACE: &str = "org.g935.Headset1";
+
+/// The object path of the battery.
+const BATTERY_PATH: &str = "/org/g935/Headset1/battery";
+
+/// The interface of UPower devices, which the battery implements.
+const UPOWER_DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// The UPower device type of headsets.
+const UPOWER_TYPE_HEADSET: u32 = 17;
+
+/// The `org.g935.Headset1` interface, forwarding the method calls to the daemon.
+#[derive(Debug)]
+struct HeadsetInterface {
+    /// The client sending the requests to the daemon.
+    client: Client,
+}
+
+impl HeadsetInterface {
+    /// Sends the request to the daemon, translating errors to D-Bus errors.
+    fn request(&self, request: Request) -> fdo::Result<Response> {
+        self.client
+            .request(request)
+            .map_err(|err| fdo::Error::Failed(format!("{err:#}")))
+    }
+
+    /// Sets the effect of the light, given by its name.
+    fn set_lights(&self, light: &str, effect: Effect) -> fdo::Result<()> {
+        let light = parse::<Light>(light)?;
+        effect
+            .validate()
+            .map_err(|err| fdo::Error::InvalidArgs(format!("{err:#}")))?;
+
+        self.request(Request::SetLights { light, effect })?;
+
+        Ok(())
+    }
+}
+
+/// Parses a method argument.
+fn parse<T: std::str::FromStr<Err = anyhow::Error>>(arg: &str) -> fdo::Result<T> {
+    arg.parse()
+        .map_err(|err: anyhow::Error| fdo::Error::InvalidArgs(format!("{err:#}")))
+}
+
+/// Returns the error for a response that does not match the request.
+fn unexpected(response: Response) -> fdo::Error {
+    fdo::Error::Failed(format!("unexpected response {response:?}"))
+}
+
+#[zbus::interface(name = "org.g935.Headset1")]
+impl HeadsetInterface {
+    /// Returns the charge in percent, the voltage in mV and the charging status.
+    fn battery(&self) -> fdo::Result<(f64, u16, String)> {
+        match self.request(Request::GetBattery)? {
+            Response::Battery(status) => Ok((
+                status.charge.into(),
+                status.voltage,
+                status.charging_status.to_string(),
+            )),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Turns the light (`side` or `logo`) off.
+    fn set_lights_off(&self, light: &str) -> fdo::Result<()> {
+        self.set_lights(light, Effect::Off)
+    }
+
+    /// Sets the light to a static color, given as `#rrggbb` or by name.
+    fn set_lights_static(&self, light: &str, color: &str) -> fdo::Result<()> {
+        self.set_lights(light, Effect::static_color(parse::<Color>(color)?))
+    }
+
+    /// Lets the light breathe in a color, taking `rate_ms` milliseconds per breath.
+    fn set_lights_breathing(
+        &self,
+        light: &str,
+        color: &str,
+        rate_ms: u32,
+        brightness: u8,
+    ) -> fdo::Result<()> {
+        self.set_lights(
+            light,
+            Effect::Breathing {
+                color: parse(color)?,
+                rate: std::time::Duration::from_millis(rate_ms.into()),
+                brightness,
+            },
+        )
+    }
+
+    /// Lets the light cycle through all colors, taking `rate_ms` milliseconds per cycle.
+    fn set_lights_color_cycle(&self, light: &str, rate_ms: u32, brightness: u8) -> fdo::Result<()> {
+        self.set_lights(
+            light,
+            Effect::ColorCycle {
+                rate: std::time::Duration::from_millis(rate_ms.into()),
+                brightness,
+            },
+        )
+    }
+
+    /// Returns the sidetone level in percent.
+    fn sidetone(&self) -> fdo::Result<u8> {
+        match self.request(Request::GetSidetone)? {
+            Response::Sidetone(level) => Ok(level),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Sets the sidetone level in percent.
+    fn set_sidetone(&self, level: u8) -> fdo::Result<()> {
+        self.request(Request::SetSidetone { level })?;
+
+        Ok(())
+    }
+
+    /// Returns the gain in dB of each equalizer band.
+    fn equalizer(&self) -> fdo::Result<Vec<i16>> {
+        match self.request(Request::GetEqualizer)? {
+            Response::Equalizer(gains) => Ok(gains.into_iter().map(i16::from).collect()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Sets the gain in dB of each equalizer band.
+    fn set_equalizer(&self, gains: Vec<i16>) -> fdo::Result<()> {
+        let gains = gains
+            .into_iter()
+            .map(i8::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| fdo::Error::InvalidArgs("equalizer gain out of range".to_string()))?;
+
+        self.request(Request::SetEqualizer { gains })?;
+
+        Ok(())
+    }
+}
+
+/// The last known state of the battery.
+#[derive(Debug, Default)]
+struct BatteryState {
+    /// The last battery status, if it was read since the daemon started.
+    status: Option<BatteryStatus>,
+    /// Whether the headset is turned on.
+    connected: bool,
+    /// When the battery status was last read, in seconds since the Unix epoch.
+    update_time: u64,
+}
+
+impl BatteryState {
+    /// Returns whether the headset is turned on and its battery status is known.
+    fn is_present(&self) -> bool {
+        self.connected && self.status.is_some()
+    }
+
+    /// Returns the charge in percent.
+    fn percentage(&self) -> f64 {
+        self.status
+            .map_or(0.0, |status| status.charge.clamp(0.0, 100.0).into())
+    }
+
+    /// Returns the voltage in V.
+    fn voltage(&self) -> f64 {
+        self.status
+            .map_or(0.0, |status| status.voltage_volts().into())
+    }
+
+    /// Returns the charging state, as defined by UPower.
+    fn state(&self) -> u32 {
+        match self.status.map(|status| status.charging_status) {
+            None => 0,
+            Some(ChargingStatus::Charging) => 1,
+            Some(ChargingStatus::Discharging) => 2,
+            Some(ChargingStatus::Full) => 4,
+        }
+    }
+
+    /// Returns the name of the icon representing the battery.
+    fn icon_name(&self) -> String {
+        let Some(status) = self.status.filter(|_| self.connected) else {
+            return "battery-missing-symbolic".to_string();
+        };
+
+        let level = match status.charge {
+            charge if charge >= 90.0 => "full",
+            charge if charge >= 50.0 => "good",
+            charge if charge >= 20.0 => "low",
+            _ => "caution",
+        };
+        let charging = match status.charging_status {
+            ChargingStatus::Discharging => "",
+            ChargingStatus::Charging | ChargingStatus::Full => "-charging",
+        };
+
+        format!("battery-{level}{charging}-symbolic")
+    }
+
+    /// Returns the properties that change with the battery state.
+    fn changed_properties(&self) -> HashMap<&'static str, Value<'static>> {
+        HashMap::from([
+            ("IsPresent", Value::from(self.is_present())),
+            ("Percentage", Value::from(self.percentage())),
+            ("Voltage", Value::from(self.voltage())),
+            ("State", Value::from(self.state())),
+            ("UpdateTime", Value::from(self.update_time)),
+            ("IconName", Value::from(self.icon_name())),
+        ])
+    }
+}
+
+/// The battery of the headset, implementing the `org.freedesktop.UPower.Device` interface.
+///
+/// UPower does not accept devices from other programs, so this is a separate object that
+/// programs speaking the UPower interface can be pointed to.
+#[derive(Debug)]
+struct UPowerDevice {
+    /// The last known state of the battery.
+    state: Arc<Mutex<BatteryState>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.UPower.Device")]
+impl UPowerDevice {
+    /// The path of the device.
+    #[zbus(property)]
+    fn native_path(&self) -> &str {
+        "g935"
+    }
+
+    /// The vendor of the device.
+    #[zbus(property)]
+    fn vendor(&self) -> &str {
+        "Logitech"
+    }
+
+    /// The model of the device.
+    #[zbus(property)]
+    fn model(&self) -> &str {
+        "G935"
+    }
+
+    /// The type of the device.
+    #[zbus(property, name = "Type")]
+    fn kind(&self) -> u32 {
+        UPOWER_TYPE_HEADSET
+    }
+
+    /// Whether the device powers the computer.
+    #[zbus(property)]
+    fn power_supply(&self) -> bool {
+        false
+    }
+
+    /// Whether the battery can be recharged.
+    #[zbus(property)]
+    fn is_rechargeable(&self) -> bool {
+        true
+    }
+
+    /// Whether the headset is turned on and its battery status is known.
+    #[zbus(property)]
+    fn is_present(&self) -> bool {
+        self.state.lock().unwrap().is_present()
+    }
+
+    /// The charge in percent.
+    #[zbus(property)]
+    fn percentage(&self) -> f64 {
+        self.state.lock().unwrap().percentage()
+    }
+
+    /// The voltage in V.
+    #[zbus(property)]
+    fn voltage(&self) -> f64 {
+        self.state.lock().unwrap().voltage()
+    }
+
+    /// The charging state, as defined by UPower.
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        self.state.lock().unwrap().state()
+    }
+
+    /// The coarse battery level, which is not used, because the percentage is known.
+    #[zbus(property)]
+    fn battery_level(&self) -> u32 {
+        1
+    }
+
+    /// When the battery status was last read, in seconds since the Unix epoch.
+    #[zbus(property)]
+    fn update_time(&self) -> u64 {
+        self.state.lock().unwrap().update_time
+    }
+
+    /// The name of the icon representing the battery.
+    #[zbus(property)]
+    fn icon_name(&self) -> String {
+        self.state.lock().unwrap().icon_name()
+    }
+}
+
+/// Updates the battery state with the event, emitting `PropertiesChanged` for it.
+fn update_battery(
+    connection: &zbus::blocking::Connection,
+    state: &Mutex<BatteryState>,
+    event: &Event,
+) -> zbus::Result<()> {
+    let changed = {
+        let mut state = state.lock().unwrap();
+
+        match *event {
+            Event::Battery(status) => {
+                state.status = Some(status);
+                state.connected = true;
+                state.update_time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+            }
+            Event::PowerState(power_state) => {
+                state.connected = power_state == PowerState::Connected;
+            }
+            Event::Button(_) | Event::Profile(_) => return Ok(()),
+        }
+
+        state.changed_properties()
+    };
+
+    connection.emit_signal(
+        None::<&str>,
+        BATTERY_PATH,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        &(UPOWER_DEVICE_INTERFACE, changed, Vec::<&str>::new()),
+    )
+}
+
+/// Emits the event as a signal.
+///
+/// The signals are `ButtonEvent(trigger)`, `PowerStateChanged(state)`,
+/// `BatteryChanged(charge, voltage, charging_status)` and `ProfileChanged(name)`.
+fn emit(connection: &zbus::blocking::Connection, event: Event) -> zbus::Result<()> {
+    match event {
+        Event::Button(trigger) => connection.emit_signal(
+            None::<&str>,
+            PATH,
+            INTERFACE,
+            "ButtonEvent",
+            &(trigger.to_string(),),
+        ),
+        Event::PowerState(power_state) => connection.emit_signal(
+            None::<&str>,
+            PATH,
+            INTERFACE,
+            "PowerStateChanged",
+            &(power_state.to_string(),),
+        ),
+        Event::Battery(status) => connection.emit_signal(
+            None::<&str>,
+            PATH,
+            INTERFACE,
+            "BatteryChanged",
+            &(
+                f64::from(status.charge),
+                status.voltage,
+                status.charging_status.to_string(),
+            ),
+        ),
+        Event::Profile(name) => {
+            connection.emit_signal(None::<&str>, PATH, INTERFACE, "ProfileChanged", &(name,))
+        }
+    }
+}
+
+/// Exports the daemon on the session bus, emitting the events as signals.
+///
+/// The service stays available as long as the returned connection is alive.
+pub(crate) fn serve(
+    client: Client,
+    events: crossbeam_channel::Receiver<Published>,
+) -> anyhow::Result<zbus::blocking::Connection> {
+    let battery = Arc::new(Mutex::new(BatteryState::default()));
+
+    let connection = zbus::blocking::connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(PATH, HeadsetInterface { client })?
+        .serve_at(
+            BATTERY_PATH,
+            UPowerDevice {
+                state: Arc::clone(&battery),
+            },
+        )?
+        .build()?;
+
+    let signal_connection = connection.clone();
+    std::thread::spawn(move || {
+        for Published { event, .. } in events {
+            if let Err(err) = update_battery(&signal_connection, &battery, &event) {
+                log::warn!("could not update the battery properties: {err}");
+            }
+            if let Err(err) = emit(&signal_connection, event) {
+                log::warn!("could not emit signal: {err}");
+            }
+        }
+    });
+
+    log::info!("serving {NAME} on the session bus");
+
+    Ok(connection)
+}
@@ -0,0 +1,188 @@
+//! A facade accepting the flags of HeadsetControl and printing its output formats.
+//!
+//! It is used if the program is called as `headsetcontrol`, for example through a symlink, or if
+//! the first argument is `--hc`, so that scripts written for HeadsetControl keep working.
+
+use std::{ffi::OsString, path::Path};
+
+use clap::Parser as _;
+use g935::{
+    lights::{self, Color, Effect, Light},
+    BatteryStatus, ChargingStatus, Headset,
+};
+
+use crate::{
+    daemon::{Request, Response},
+    socket,
+};
+
+/// The largest sidetone level HeadsetControl accepts, which corresponds to 100%.
+const MAX_SIDETONE: u8 = 128;
+
+/// The flags of HeadsetControl that the headset supports.
+#[derive(clap::Parser, Debug)]
+#[clap(
+    name = "headsetcontrol",
+    about = "the HeadsetControl compatible flags of g935"
+)]
+struct Args {
+    /// print the battery level
+    #[clap(short, long)]
+    battery: bool,
+    /// set the sidetone level between 0 and 128
+    #[clap(short, long, value_name = "LEVEL")]
+    sidetone: Option<u8>,
+    /// turn the lights off (0) or on (1)
+    #[clap(short, long, value_name = "0|1")]
+    light: Option<u8>,
+    /// print only the values, as expected by scripts
+    #[clap(short = 'c', long)]
+    short_output: bool,
+}
+
+/// Returns `true` if the program was called as HeadsetControl or with `--hc`.
+pub(crate) fn is_requested(args: &[OsString]) -> bool {
+    let called_as = args
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|name| name == "headsetcontrol");
+
+    called_as || args.get(1).is_some_and(|arg| arg == "--hc")
+}
+
+/// Talks to the daemon if it is running, or to the headset otherwise.
+enum Target {
+    /// A connection to the daemon.
+    Daemon(socket::Connection),
+    /// The directly opened headset.
+    Headset(Box<Headset>),
+}
+
+impl Target {
+    /// Connects to the daemon or opens the headset.
+    fn open() -> anyhow::Result<Self> {
+        match socket::Connection::connect(&socket::default_path()) {
+            Ok(connection) => Ok(Self::Daemon(connection)),
+            Err(_) => Ok(Self::Headset(Box::new(Headset::open()?))),
+        }
+    }
+
+    /// Returns the battery status.
+    fn battery(&mut self) -> anyhow::Result<BatteryStatus> {
+        match self {
+            Self::Daemon(connection) => match connection.request(&Request::GetBattery)? {
+                Response::Battery(status) => Ok(status),
+                response => Err(anyhow::anyhow!("unexpected response {response:?}")),
+            },
+            Self::Headset(headset) => headset.get_battery_status(),
+        }
+    }
+
+    /// Sets the sidetone level in percent.
+    fn set_sidetone(&mut self, level: u8) -> anyhow::Result<()> {
+        match self {
+            Self::Daemon(connection) => connection
+                .request(&Request::SetSidetone { level })
+                .map(drop),
+            Self::Headset(headset) => headset.set_sidetone(level),
+        }
+    }
+
+    /// Sets the effect of both lights.
+    fn set_lights(&mut self, effect: Effect) -> anyhow::Result<()> {
+        for light in [Light::Side, Light::Logo] {
+            match self {
+                Self::Daemon(connection) => {
+                    connection.request(&Request::SetLights { light, effect })?;
+                }
+                Self::Headset(headset) => {
+                    headset.set_lights(&lights::LightSettings {
+                        light,
+                        effect,
+                        profile_type: lights::ProfileType::Temporary,
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Performs the actions given by the HeadsetControl flags, returning the exit code.
+///
+/// The first argument, if it is `--hc`, is skipped.
+pub(crate) fn main(mut args: Vec<OsString>) -> i32 {
+    if args.get(1).is_some_and(|arg| arg == "--hc") {
+        args.remove(1);
+    }
+    let args = Args::parse_from(args);
+
+    match run(&args) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{err:#}");
+            1
+        }
+    }
+}
+
+/// Performs the actions, printing their results in the format of HeadsetControl.
+fn run(args: &Args) -> anyhow::Result<()> {
+    if let Some(level) = args.sidetone {
+        anyhow::ensure!(
+            level <= MAX_SIDETONE,
+            "the sidetone level must be between 0 and {MAX_SIDETONE}"
+        );
+    }
+    if let Some(light) = args.light {
+        anyhow::ensure!(light <= 1, "the light must be 0 (off) or 1 (on)");
+    }
+
+    let mut target = Target::open()?;
+
+    if !args.short_output {
+        println!("Found Logitech G935 Gaming Headset!");
+    }
+
+    if let Some(level) = args.sidetone {
+        let percent =
+            (u16::from(level) * 100 + u16::from(MAX_SIDETONE) / 2) / u16::from(MAX_SIDETONE);
+        target.set_sidetone(percent as u8)?;
+
+        if !args.short_output {
+            println!("Set sidetone to {level}");
+        }
+    }
+
+    if let Some(light) = args.light {
+        // Turning the lights on shows the same white light as the tray icon
+        let effect = match light {
+            0 => Effect::Off,
+            _ => Effect::static_color(Color::new(255, 255, 255)),
+        };
+        target.set_lights(effect)?;
+
+        if !args.short_output {
+            println!("Set lights to {light}");
+        }
+    }
+
+    if args.battery {
+        let status = target.battery()?;
+        let percent = status.percent();
+
+        match (args.short_output, status.charging_status) {
+            (true, ChargingStatus::Charging) => print!("-1"),
+            (true, _) => print!("{percent}"),
+            (false, ChargingStatus::Charging) => println!("Battery: Charging"),
+            (false, _) => println!("Battery: {percent}%"),
+        }
+    }
+
+    if !args.short_output {
+        println!("Success!");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! The HTTP endpoint of the daemon, for environments where the Unix socket and D-Bus are
+//! awkward, like containers or Windows.
+//!
+//! `GET /battery` returns the battery status, `POST /lights` sets the effect of a light given as
+//! `{"light":"side","effect":{...}}`, and `GET /events` streams the events as server-sent events.
+//! Errors are returned as `{"error":"..."}`.
+
+use std::io::Write as _;
+
+use anyhow::Context as _;
+use g935::lights::{Effect, Light};
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request as HttpRequest, Response as HttpResponse, Server};
+
+use crate::{
+    config_file,
+    daemon::{Client, Publisher, Request, Response},
+};
+
+/// The body of `POST /lights`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetLights {
+    /// The light to change.
+    light: Light,
+    /// The new effect of the light.
+    effect: Effect,
+}
+
+/// Returns a response with the value as JSON.
+fn json(status: u16, value: &impl serde::Serialize) -> HttpResponse<std::io::Cursor<Vec<u8>>> {
+    let header =
+        Header::from_bytes("Content-Type", "application/json").expect("the header is valid");
+
+    HttpResponse::from_string(serde_json::to_string(value).unwrap_or_default())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+/// Returns a response with the error message.
+fn error(status: u16, message: impl std::fmt::Display) -> HttpResponse<std::io::Cursor<Vec<u8>>> {
+    json(status, &serde_json::json!({ "error": message.to_string() }))
+}
+
+/// Performs the request, returning its response.
+fn perform(client: &Client, request: Request) -> HttpResponse<std::io::Cursor<Vec<u8>>> {
+    match client.request(request) {
+        Ok(Response::Battery(status)) => json(200, &status),
+        Ok(Response::Done) => json(200, &serde_json::json!({})),
+        Ok(response) => json(200, &response),
+        Err(err) => error(500, format!("{err:#}")),
+    }
+}
+
+/// Streams the events to the client until it disconnects.
+fn stream_events(request: HttpRequest, publisher: &Publisher) -> anyhow::Result<()> {
+    let events = publisher.subscribe();
+    let mut writer = request.into_writer();
+
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n"
+    )?;
+    writer.flush()?;
+
+    for event in events {
+        write!(writer, "data: {}\n\n", serde_json::to_string(&event)?)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Answers the request, streaming the events in their own thread.
+fn handle(mut request: HttpRequest, client: &Client, publisher: &Publisher) -> anyhow::Result<()> {
+    let response = match (request.method(), request.url()) {
+        (Method::Get, "/battery") => perform(client, Request::GetBattery),
+        (Method::Post, "/lights") => {
+            match serde_json::from_reader::<_, SetLights>(request.as_reader()) {
+                Ok(SetLights { light, effect }) => {
+                    perform(client, Request::SetLights { light, effect })
+                }
+                Err(err) => error(400, format!("invalid request: {err}")),
+            }
+        }
+        (Method::Get, "/events") => {
+            let publisher = publisher.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = stream_events(request, &publisher) {
+                    log::debug!("HTTP event stream closed: {err:#}");
+                }
+            });
+
+            return Ok(());
+        }
+        (_, "/battery" | "/lights" | "/events") => error(405, "method not allowed"),
+        (_, url) => error(404, format!("unknown path {url}")),
+    };
+
+    request.respond(response)?;
+
+    Ok(())
+}
+
+/// Listens for HTTP requests as configured, answering them in a background thread.
+pub(crate) fn serve(
+    options: &config_file::Http,
+    client: Client,
+    publisher: Publisher,
+) -> anyhow::Result<()> {
+    let server = Server::http(&options.bind)
+        .map_err(|err| anyhow::anyhow!(err))
+        .with_context(|| format!("could not listen on {}", options.bind))?;
+
+    log::info!("accepting HTTP requests on {}", options.bind);
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if let Err(err) = handle(request, &client, &publisher) {
+                log::debug!("could not answer HTTP request: {err:#}");
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,815 @@
+mod battery;
+mod battery_lights;
+mod battery_log;
+mod config_file;
+mod ctl;
+mod daemon;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod headsetcontrol;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "notifications")]
+mod notifications;
+mod onboard;
+mod output;
+#[cfg(all(feature = "uinput", target_os = "linux"))]
+mod record;
+mod reload;
+#[cfg(feature = "logind")]
+mod resume;
+mod schedule;
+mod signals;
+mod socket;
+mod state;
+mod systemd;
+#[cfg(feature = "tray")]
+mod tray;
+mod udev;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+use std::{path::PathBuf, time::Duration};
+
+use clap::StructOpt;
+use g935::{
+    config::{PeriodicHandler, RunConfig},
+    transport::{CaptureFile, Replay},
+    DeviceDescriptor, Headset,
+};
+
+use crate::{
+    battery_lights::BatteryLights, config_file::ConfigFile, ctl::CtlCommand, reload::Reloader,
+    signals::Signals, systemd::Notifier,
+};
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// run in continuous mode
+    RunContinuous,
+    /// run in continuous mode using a configuration file
+    Run {
+        /// the configuration file to use (defaults to ~/.config/g935/config.toml)
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+    },
+    /// apply the settings of a configuration file
+    Apply {
+        /// the configuration file to use (defaults to ~/.config/g935/config.toml)
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+        /// exit after applying the lights, sidetone and equalizer once, ignoring the bindings,
+        /// instead of running continuously
+        #[clap(long)]
+        oneshot: bool,
+    },
+    /// run as a daemon that other programs can talk to
+    Daemon {
+        /// the configuration file to use (defaults to ~/.config/g935/config.toml, if it exists)
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+        /// the socket to listen on (defaults to $XDG_RUNTIME_DIR/g935.sock)
+        #[clap(long)]
+        socket: Option<PathBuf>,
+    },
+    /// run as a daemon showing a system tray icon
+    #[cfg(feature = "tray")]
+    Tray {
+        /// the configuration file to use (defaults to ~/.config/g935/config.toml, if it exists)
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+        /// the socket to listen on (defaults to $XDG_RUNTIME_DIR/g935.sock)
+        #[clap(long)]
+        socket: Option<PathBuf>,
+    },
+    /// control the running daemon
+    Ctl {
+        /// the socket the daemon listens on (defaults to $XDG_RUNTIME_DIR/g935.sock)
+        #[clap(long)]
+        socket: Option<PathBuf>,
+        /// the action to perform
+        #[clap(subcommand)]
+        command: CtlCommand,
+    },
+    /// return the battery level
+    GetBatteryLevel {
+        /// exit with 1 if the battery is discharging and at most this many percent charged
+        #[clap(long)]
+        warn: Option<u8>,
+        /// exit with 2 if the battery is discharging and at most this many percent charged
+        #[clap(long)]
+        crit: Option<u8>,
+        /// only print the charge in percent
+        #[clap(long, conflicts_with = "voltage")]
+        percent_only: bool,
+        /// only print the voltage in mV
+        #[clap(long)]
+        voltage: bool,
+    },
+    /// print the path and serial number of every connected headset
+    List,
+    /// print the name, model, protocol version and serial number of the headset
+    Info,
+    /// print the index and ID of every HID++ feature the headset supports
+    Features,
+    /// print the interval between reports of the receiver in ms, or set it
+    ReportRate {
+        /// the new interval in ms
+        interval_ms: Option<u8>,
+    },
+    /// store the effect a light shows after the headset is turned on, showing the change and
+    /// only writing it with --yes
+    StoreLight {
+        /// the light to change (side or logo)
+        light: g935::lights::Light,
+        /// the effect to store
+        #[clap(arg_enum)]
+        effect: ctl::EffectKind,
+        /// the color of the static and breathing effects, as #rrggbb or by name
+        #[clap(short, long)]
+        color: Option<g935::lights::Color>,
+        /// the duration of one breath or color cycle in milliseconds
+        #[clap(short, long)]
+        rate_ms: Option<u64>,
+        /// the brightness in percent of the breathing and color cycle effects
+        #[clap(short, long)]
+        brightness: Option<u8>,
+        /// write the change after showing it
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// back up or restore the settings stored in the headset
+    Profile {
+        /// the action to perform
+        #[clap(subcommand)]
+        command: onboard::ProfileCommand,
+    },
+    /// print the battery status for status bars, asking the daemon if it is running
+    Battery {
+        /// the format to print the battery status in
+        #[clap(short, long, arg_enum, default_value = "plain")]
+        format: battery::Format,
+        /// print the battery status again every given number of seconds
+        #[clap(short, long)]
+        interval: Option<u64>,
+        /// print the battery status whenever it changes, using the events of the daemon if it is
+        /// running, or asking the headset every --interval seconds (defaults to 60) otherwise
+        #[clap(short, long)]
+        watch: bool,
+        /// the socket the daemon listens on (defaults to $XDG_RUNTIME_DIR/g935.sock)
+        #[clap(long)]
+        socket: Option<PathBuf>,
+    },
+    /// append the battery status to a CSV file periodically, asking the daemon if it is running
+    LogBattery {
+        /// how often to record the battery status in seconds
+        #[clap(short, long, default_value = "60")]
+        interval: u64,
+        /// the CSV file to append to
+        #[clap(short, long)]
+        output: PathBuf,
+        /// the socket the daemon listens on (defaults to $XDG_RUNTIME_DIR/g935.sock)
+        #[clap(long)]
+        socket: Option<PathBuf>,
+    },
+    /// record a macro from the keyboard and print it for the configuration file
+    #[cfg(all(feature = "uinput", target_os = "linux"))]
+    RecordMacro {
+        /// the name of the macro
+        #[clap(default_value = "recorded")]
+        name: String,
+        /// the key that stops the recording
+        #[clap(long, default_value = "KEY_ESC")]
+        stop_key: String,
+    },
+    /// write a systemd unit running the daemon
+    InstallService {
+        /// write a user unit instead of a system unit
+        #[clap(long)]
+        user: bool,
+        /// overwrite an existing unit
+        #[clap(short, long)]
+        force: bool,
+    },
+    /// write the udev rule allowing the logged in user to access the headset, or only print it
+    /// with --dry-run
+    InstallUdevRules,
+    /// print the JSON Schema of the output of the commands with --json
+    Schema,
+}
+
+/// How to talk to the headset.
+#[derive(clap::Args, Debug)]
+struct Connection {
+    /// record all reports exchanged with the headset to the given file as JSON lines
+    #[clap(long, global = true, conflicts_with = "replay")]
+    capture: Option<PathBuf>,
+    /// replay a file recorded with --capture instead of talking to the headset
+    #[clap(long, global = true)]
+    replay: Option<PathBuf>,
+    /// use the headset with the given serial number, as shown by `g935 list`
+    #[clap(long, global = true, conflicts_with_all = &["replay", "path"])]
+    serial: Option<String>,
+    /// use the headset with the given HID path, as shown by `g935 list`
+    #[clap(long, global = true, conflicts_with = "replay")]
+    path: Option<String>,
+    /// the HID++ index of the headset behind a receiver hosting several devices
+    #[clap(long, global = true, conflicts_with = "replay")]
+    device_index: Option<u8>,
+    /// only log the reports that would change the settings of the headset, instead of sending
+    /// them
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// change the settings even if the responses of the headset do not match any known layout
+    #[clap(long, global = true)]
+    force: bool,
+}
+
+impl Connection {
+    /// Returns the headset selected by the arguments, or `None` to use the first one.
+    fn descriptor(&self) -> anyhow::Result<Option<DeviceDescriptor>> {
+        if self.serial.is_none() && self.path.is_none() && self.device_index.is_none() {
+            return Ok(None);
+        }
+
+        g935::discover()?
+            .into_iter()
+            .find(|headset| {
+                let serial_matches = self
+                    .serial
+                    .as_ref()
+                    .is_none_or(|serial| headset.serial_number.as_ref() == Some(serial));
+                let path_matches = self.path.as_ref().is_none_or(|path| headset.path == *path);
+
+                serial_matches && path_matches
+            })
+            .map(|headset| match self.device_index {
+                Some(device_index) => Some(headset.with_device_index(device_index)),
+                None => Some(headset),
+            })
+            .ok_or_else(|| anyhow::anyhow!("no matching headset is connected, see `g935 list`"))
+    }
+}
+
+/// The format of the log messages.
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    /// human-readable lines
+    Text,
+    /// one JSON object per line, including the spans of the requests to the headset
+    Json,
+}
+
+#[derive(clap::Parser, Debug)]
+struct Args {
+    /// how verbose the program should be
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: usize,
+    /// whether the program should be silent
+    #[clap(short, long)]
+    silent: bool,
+    /// the format of the log messages
+    #[clap(long, arg_enum, default_value = "text")]
+    log_format: LogFormat,
+    /// print the output as JSON with a stable schema, as shown by `g935 schema`
+    #[clap(long, global = true)]
+    json: bool,
+    /// how to talk to the headset
+    #[clap(flatten)]
+    connection: Connection,
+    /// the action to perform
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Sets up the logger according to the arguments.
+fn init_logging(args: &Args) {
+    use log::LevelFilter;
+
+    let level_filter = match (args.silent, args.verbose) {
+        (true, _) => LevelFilter::Off,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    #[cfg(feature = "tracing")]
+    {
+        use tracing_subscriber::filter::LevelFilter as TracingLevelFilter;
+
+        // The log messages are forwarded to the subscriber, so that they appear within the spans
+        let level_filter = match level_filter {
+            LevelFilter::Off => TracingLevelFilter::OFF,
+            LevelFilter::Error => TracingLevelFilter::ERROR,
+            LevelFilter::Warn => TracingLevelFilter::WARN,
+            LevelFilter::Info => TracingLevelFilter::INFO,
+            LevelFilter::Debug => TracingLevelFilter::DEBUG,
+            LevelFilter::Trace => TracingLevelFilter::TRACE,
+        };
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(level_filter)
+            .with_writer(std::io::stderr);
+
+        match args.log_format {
+            LogFormat::Text => subscriber.init(),
+            LogFormat::Json => subscriber.json().init(),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    {
+        use simplelog::*;
+        TermLogger::init(
+            level_filter,
+            Config::default(),
+            TerminalMode::Stderr,
+            ColorChoice::Auto,
+        )
+        .unwrap();
+
+        if args.log_format == LogFormat::Json {
+            log::warn!("ignoring --log-format json, because g935 was built without tracing");
+        }
+    }
+}
+
+fn main() {
+    let raw_args = std::env::args_os().collect::<Vec<_>>();
+    if headsetcontrol::is_requested(&raw_args) {
+        std::process::exit(headsetcontrol::main(raw_args));
+    }
+
+    let args = Args::parse_from(raw_args);
+
+    init_logging(&args);
+
+    match args.command {
+        Command::Run { config } => run(config, &args.connection, false),
+        Command::Apply { config, oneshot } => run(config, &args.connection, oneshot),
+        Command::Daemon { config, socket } => {
+            run_daemon(config, socket, &args.connection, |_, _, _| ())
+        }
+        #[cfg(feature = "tray")]
+        Command::Tray { config, socket } => run_daemon(
+            config,
+            socket,
+            &args.connection,
+            |client, publisher, config_file| {
+                if let Err(err) = tray::serve(client.clone(), publisher.subscribe(), config_file) {
+                    log::error!("could not show the tray icon: {err:#}");
+                    std::process::exit(1);
+                }
+            },
+        ),
+        Command::Ctl { socket, command } => {
+            let socket = socket.unwrap_or_else(socket::default_path);
+            if let Err(err) = ctl::run(&socket, command, args.json) {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(all(feature = "uinput", target_os = "linux"))]
+        Command::RecordMacro { name, stop_key } => {
+            if let Err(err) = record::record_macro(&name, &stop_key, args.json) {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::InstallService { user, force } => {
+            if let Err(err) = systemd::install_service(user, force, args.json) {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::InstallUdevRules => {
+            if let Err(err) = udev::install_rules(args.connection.dry_run, args.json) {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::Battery {
+            format,
+            interval,
+            watch,
+            socket,
+        } => {
+            let socket = socket.unwrap_or_else(socket::default_path);
+            let format = if args.json {
+                battery::Format::Json
+            } else {
+                format
+            };
+            let result = if watch {
+                let poll_interval = Duration::from_secs(interval.unwrap_or(60));
+                battery::watch(&socket, format, poll_interval)
+            } else {
+                battery::print(&socket, format, interval.map(Duration::from_secs))
+            };
+            if let Err(err) = result {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::LogBattery {
+            interval,
+            output,
+            socket,
+        } => {
+            let socket = socket.unwrap_or_else(socket::default_path);
+            if let Err(err) = battery_log::run(&socket, &output, Duration::from_secs(interval)) {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::GetBatteryLevel {
+            warn,
+            crit,
+            percent_only,
+            voltage,
+        } => match open_headset(&args.connection).get_battery_status() {
+            Ok(status) => {
+                if args.json {
+                    output::print_json(&status);
+                } else if percent_only {
+                    println!("{}", status.charge);
+                } else if voltage {
+                    println!("{}", status.voltage);
+                } else {
+                    println!("{} {}", status.charging_status, status.charge);
+                }
+
+                std::process::exit(battery::exit_code(&status, warn, crit));
+            }
+            Err(err) => {
+                log::error!("could not read battery status: {err}");
+                std::process::exit(1);
+            }
+        },
+        Command::List => match g935::discover() {
+            Ok(headsets) if args.json => output::print_json(&headsets),
+            Ok(headsets) => {
+                for headset in headsets {
+                    println!(
+                        "{} {} {}",
+                        headset.path,
+                        headset.serial_number.as_deref().unwrap_or("-"),
+                        headset.product.as_deref().unwrap_or("-")
+                    );
+                }
+            }
+            Err(err) => {
+                log::error!("could not list the headsets: {err:#}");
+                std::process::exit(1);
+            }
+        },
+        Command::Info => {
+            let headset = open_headset(&args.connection);
+            let info = headset.info();
+            if args.json {
+                output::print_json(info);
+                return;
+            }
+            let (major, minor) = info.protocol_version;
+
+            println!("name: {}", info.name);
+            println!("model: {}", info.model);
+            println!("protocol version: {major}.{minor}");
+            if let Some(serial_number) = &info.serial_number {
+                println!("serial number: {serial_number}");
+            }
+        }
+        Command::Features => match open_headset(&args.connection).feature_ids() {
+            Ok(ids) if args.json => output::print_json(
+                &ids.into_iter()
+                    .enumerate()
+                    .map(|(index, id)| serde_json::json!({ "index": index, "id": id }))
+                    .collect::<Vec<_>>(),
+            ),
+            Ok(ids) => {
+                for (index, id) in ids.into_iter().enumerate() {
+                    println!("{index:#04x} {id:#06x}");
+                }
+            }
+            Err(err) => {
+                log::error!("could not list the features: {err:#}");
+                std::process::exit(1);
+            }
+        },
+        Command::ReportRate { interval_ms } => {
+            let mut headset = open_headset(&args.connection);
+            let result = match interval_ms {
+                Some(interval_ms) => headset.set_report_rate(interval_ms),
+                None => headset.get_report_rate().map(|interval_ms| {
+                    if args.json {
+                        output::print_json(&serde_json::json!({ "interval_ms": interval_ms }));
+                    } else {
+                        println!("{interval_ms} ms");
+                    }
+                }),
+            };
+
+            if let Err(err) = result {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::StoreLight {
+            light,
+            effect,
+            color,
+            rate_ms,
+            brightness,
+            yes,
+        } => {
+            let result = ctl::effect(effect, color, rate_ms, brightness).and_then(|effect| {
+                onboard::store_light(
+                    &mut open_headset(&args.connection),
+                    light,
+                    effect,
+                    yes,
+                    args.json,
+                )
+            });
+            if let Err(err) = result {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::Profile { command } => {
+            if let Err(err) = onboard::run(&mut open_headset(&args.connection), command, args.json)
+            {
+                log::error!("{err:#}");
+                std::process::exit(1);
+            }
+        }
+        Command::Schema => match serde_json::to_string_pretty(&output::schema()) {
+            Ok(schema) => println!("{schema}"),
+            Err(err) => {
+                log::error!("could not print the schema: {err}");
+                std::process::exit(1);
+            }
+        },
+        Command::RunContinuous => {
+            let mut config = g935::config::RunConfig::default();
+            config.set_dry_run(args.connection.dry_run);
+            let config_file = ConfigFile::builtin();
+            config_file
+                .apply(&mut config, None, None)
+                .expect("the built-in configuration can be applied");
+
+            config.add_plugin(Box::new(BatteryLights::default()));
+            let tasks = background_tasks(&mut config, None);
+            config.set_periodic_handler(Some(tasks));
+
+            open_headset(&args.connection).run_with_config(config);
+        }
+    }
+}
+
+/// Runs using the configuration file, or only applies its settings once if `oneshot` is set.
+fn run(config: Option<PathBuf>, connection: &Connection, oneshot: bool) {
+    let Some(path) = config.or_else(config_file::default_path) else {
+        log::error!("could not determine the config file location, use --config");
+        std::process::exit(1);
+    };
+
+    let config_file = match ConfigFile::load(&path) {
+        Ok(config_file) => config_file,
+        Err(err) => {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut config = g935::config::RunConfig::default();
+    config.set_dry_run(connection.dry_run);
+    if let Err(err) = config_file.apply(&mut config, None, None) {
+        log::error!("{err:#}");
+        std::process::exit(1);
+    }
+
+    if oneshot {
+        // Nothing would handle the button events after exiting, so leave them to the headset
+        config.clear_plugins();
+
+        if let Err(err) = open_headset(connection).apply_config(&mut config) {
+            log::error!("could not apply the settings: {err:#}");
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    let reloader = match Reloader::new(path, config_file, None, config.waker()) {
+        Ok(reloader) => reloader,
+        Err(err) => {
+            log::error!("could not listen for config reloads: {err:#}");
+            std::process::exit(1);
+        }
+    };
+    let tasks = background_tasks(&mut config, Some(reloader));
+    config.set_periodic_handler(Some(tasks));
+
+    open_headset(connection).run_with_config(config);
+}
+
+/// Runs the daemon, calling `frontend` with the client and publisher of the daemon and the
+/// configuration file before the headset is run.
+fn run_daemon(
+    config: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    connection: &Connection,
+    frontend: impl FnOnce(&daemon::Client, &daemon::Publisher, &ConfigFile),
+) {
+    let mut headset = open_headset(connection);
+
+    // Unlike `run`, the daemon is useful without a configuration file
+    let path = config.or_else(|| config_file::default_path().filter(|path| path.exists()));
+    let config_file = match path.as_deref().map(ConfigFile::load).transpose() {
+        Ok(config_file) => config_file.unwrap_or_default(),
+        Err(err) => {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut config = g935::config::RunConfig::default();
+    config.set_dry_run(connection.dry_run);
+
+    let state = state::default_path().map(state::StateStore::open);
+    let restored = state.as_ref().map(|state| state.state().clone());
+    let (daemon, client) = daemon::Daemon::new(config.waker(), state);
+    let publisher = daemon.publisher();
+
+    if let Err(err) = config_file.apply(&mut config, None, Some(&publisher)) {
+        log::error!("{err:#}");
+        std::process::exit(1);
+    }
+
+    // The configuration file takes precedence, so the state is only restored without one
+    if let (None, Some(restored)) = (&path, restored) {
+        restored.apply(&mut config);
+    }
+
+    if config_file.mqtt.enabled {
+        #[cfg(feature = "mqtt")]
+        mqtt::serve(&config_file.mqtt, client.clone(), publisher.subscribe());
+        #[cfg(not(feature = "mqtt"))]
+        log::warn!("ignoring the mqtt settings, because g935 was built without mqtt");
+    }
+
+    if config_file.websocket.enabled {
+        #[cfg(feature = "websocket")]
+        if let Err(err) =
+            websocket::serve(&config_file.websocket, client.clone(), publisher.clone())
+        {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        }
+        #[cfg(not(feature = "websocket"))]
+        log::warn!("ignoring the websocket settings, because g935 was built without websocket");
+    }
+
+    if config_file.http.enabled {
+        #[cfg(feature = "http")]
+        if let Err(err) = http::serve(&config_file.http, client.clone(), publisher.clone()) {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        }
+        #[cfg(not(feature = "http"))]
+        log::warn!("ignoring the http settings, because g935 was built without http");
+    }
+
+    if config_file.notifications.enabled {
+        #[cfg(feature = "notifications")]
+        notifications::serve(&config_file.notifications, publisher.subscribe());
+        #[cfg(not(feature = "notifications"))]
+        log::warn!(
+            "ignoring the notifications settings, because g935 was built without \
+             notifications"
+        );
+    }
+
+    if config_file.battery_log.enabled {
+        if let Err(err) = battery_log::serve(&config_file.battery_log, publisher.subscribe()) {
+            log::error!("{err:#}");
+            std::process::exit(1);
+        }
+    }
+
+    frontend(&client, &publisher, &config_file);
+
+    let waker = config.waker();
+    let reloader =
+        match path.map(|path| Reloader::new(path, config_file, Some(publisher.clone()), waker)) {
+            Some(Ok(reloader)) => Some(reloader),
+            Some(Err(err)) => {
+                log::error!("could not listen for config reloads: {err:#}");
+                std::process::exit(1);
+            }
+            None => None,
+        };
+
+    let socket = socket.unwrap_or_else(socket::default_path);
+    if let Err(err) = socket::serve(&socket, client.clone(), publisher.clone()) {
+        log::error!("{err:#}");
+        std::process::exit(1);
+    }
+
+    #[cfg(feature = "dbus")]
+    let _connection = match dbus::serve(client, publisher.subscribe()) {
+        Ok(connection) => connection,
+        Err(err) => {
+            log::error!("could not export the D-Bus service: {err:#}");
+            std::process::exit(1);
+        }
+    };
+    #[cfg(not(feature = "dbus"))]
+    drop(client);
+
+    let tasks = background_tasks(&mut config, reloader);
+    daemon.install(&mut config, tasks);
+    headset.run_with_config(config);
+}
+
+/// Returns the periodic handler doing the background tasks of the long running commands.
+///
+/// The tasks wake the loop when needed, so the periodic handler is only called as often as the
+/// watchdog needs to be pinged.
+fn background_tasks(config: &mut RunConfig, mut reloader: Option<Reloader>) -> PeriodicHandler {
+    let mut notifier = Notifier::new();
+    config.set_periodic_interval(notifier.tick_interval());
+
+    // Without a configuration file to reload, SIGHUP applies the settings again
+    let signals = match Signals::new(config, reloader.is_none()) {
+        Ok(signals) => Some(signals),
+        Err(err) => {
+            log::warn!("could not listen for signals: {err:#}");
+            None
+        }
+    };
+
+    #[cfg(feature = "logind")]
+    let resume_watcher = match resume::ResumeWatcher::new(config.waker()) {
+        Ok(resume_watcher) => Some(resume_watcher),
+        Err(err) => {
+            log::warn!("could not listen for resumes from suspend: {err:#}");
+            None
+        }
+    };
+
+    Box::new(move |config, headset| {
+        if let Some(reloader) = &mut reloader {
+            reloader.reload_if_requested(config);
+        }
+
+        if let Some(signals) = &signals {
+            signals.handle(config, headset);
+        }
+
+        #[cfg(feature = "logind")]
+        if let Some(resume_watcher) = &resume_watcher {
+            resume_watcher.resync_if_resumed(config, headset);
+        }
+
+        notifier.tick();
+    })
+}
+
+/// Opens the headset as requested by the connection options, exiting if that fails.
+fn open_headset(connection: &Connection) -> Headset {
+    let headset = match (&connection.capture, &connection.replay) {
+        (_, Some(path)) => Replay::load(path).and_then(Headset::open_with_transport),
+        (capture, None) => {
+            connection
+                .descriptor()
+                .and_then(|descriptor| match (capture, descriptor) {
+                    (Some(path), Some(descriptor)) => {
+                        CaptureFile::create(path).and_then(|capture| {
+                            Headset::open_descriptor_with_capture(&descriptor, capture)
+                        })
+                    }
+                    (Some(path), None) => {
+                        CaptureFile::create(path).and_then(Headset::open_with_capture)
+                    }
+                    (None, Some(descriptor)) => Headset::open_descriptor(&descriptor),
+                    (None, None) => Headset::open(),
+                })
+        }
+    };
+
+    match headset {
+        Ok(mut headset) => {
+            headset.set_dry_run(connection.dry_run);
+            headset.set_force(connection.force);
+            headset
+        }
+        Err(err) => {
+            log::error!("could not open the headset: {err:#}");
+            std::process::exit(1);
+        }
+    }
+}
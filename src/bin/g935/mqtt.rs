@@ -0,0 +1,321 @@
+//! The MQTT interface of the daemon, including Home Assistant discovery.
+
+use std::time::Duration;
+
+use anyhow::Context as _;
+use crossbeam_channel::{Receiver, Sender};
+use g935::{
+    bindings::Trigger,
+    lights::{Effect, Light},
+    MicArm, PowerState,
+};
+use rumqttc::{Client, Connection, LastWill, MqttOptions, Packet, QoS};
+
+use crate::{
+    config_file,
+    daemon::{self, Event, Published, Request, Response},
+};
+
+/// How long to wait before reconnecting after the connection to the broker failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The button events announced as Home Assistant device triggers, with their type and subtype.
+const DEVICE_TRIGGERS: &[(Trigger, &str, &str)] = &[
+    (Trigger::G1Pressed, "button_short_press", "button_1"),
+    (Trigger::G1LongPressed, "button_long_press", "button_1"),
+    (Trigger::G1DoublePressed, "button_double_press", "button_1"),
+    (Trigger::G2Pressed, "button_short_press", "button_2"),
+    (Trigger::G2LongPressed, "button_long_press", "button_2"),
+    (Trigger::G2DoublePressed, "button_double_press", "button_2"),
+    (Trigger::G3Pressed, "button_short_press", "button_3"),
+    (Trigger::G3LongPressed, "button_long_press", "button_3"),
+    (Trigger::G3DoublePressed, "button_double_press", "button_3"),
+    (Trigger::MutePressed, "button_short_press", "button_4"),
+    (Trigger::MicFlippedUp, "button_short_release", "turn_off"),
+    (Trigger::MicFlippedDown, "button_short_press", "turn_on"),
+];
+
+/// Work that the connection thread hands off, as it must not block.
+#[derive(Debug)]
+enum Job {
+    /// The connection to the broker was (re)established.
+    Connected,
+    /// A light command was received.
+    SetLights {
+        /// The topic the command was received on.
+        topic: String,
+        /// The effect as JSON.
+        payload: Vec<u8>,
+    },
+}
+
+/// The topics used by the daemon.
+#[derive(Debug, Clone)]
+struct Topics {
+    /// The prefix of all topics of the daemon.
+    base: String,
+    /// The prefix of the Home Assistant discovery topics, if discovery is enabled.
+    discovery: Option<String>,
+}
+
+impl Topics {
+    /// Returns the topic below the base topic.
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{name}", self.base)
+    }
+
+    /// Returns the discovery messages describing the headset to Home Assistant.
+    fn discovery_messages(&self, discovery: &str) -> Vec<(String, serde_json::Value)> {
+        let device = serde_json::json!({
+            "identifiers": ["g935"],
+            "name": "Logitech G935",
+            "manufacturer": "Logitech",
+            "model": "G935",
+        });
+        let entity = |component: &str, id: &str, mut config: serde_json::Value| {
+            config["unique_id"] = format!("g935_{id}").into();
+            config["availability_topic"] = self.topic("availability").into();
+            config["device"] = device.clone();
+
+            (format!("{discovery}/{component}/g935/{id}/config"), config)
+        };
+
+        let mut messages = vec![
+            entity(
+                "sensor",
+                "battery",
+                serde_json::json!({
+                    "name": "Battery",
+                    "device_class": "battery",
+                    "unit_of_measurement": "%",
+                    "state_topic": self.topic("battery"),
+                    "value_template": "{{ value_json.charge | round(0) }}",
+                }),
+            ),
+            entity(
+                "binary_sensor",
+                "charging",
+                serde_json::json!({
+                    "name": "Charging",
+                    "device_class": "battery_charging",
+                    "state_topic": self.topic("battery"),
+                    "value_template":
+                        "{{ 'ON' if value_json.charging_status == 'charging' else 'OFF' }}",
+                }),
+            ),
+            entity(
+                "binary_sensor",
+                "power_state",
+                serde_json::json!({
+                    "name": "Connected",
+                    "device_class": "connectivity",
+                    "state_topic": self.topic("power_state"),
+                    "payload_on": PowerState::Connected.to_string(),
+                    "payload_off": PowerState::Disconnected.to_string(),
+                }),
+            ),
+            entity(
+                "sensor",
+                "profile",
+                serde_json::json!({
+                    "name": "Profile",
+                    "icon": "mdi:account-switch",
+                    "state_topic": self.topic("profile"),
+                }),
+            ),
+            entity(
+                "binary_sensor",
+                "microphone",
+                serde_json::json!({
+                    "name": "Microphone",
+                    "icon": "mdi:microphone",
+                    "state_topic": self.topic("mic_arm"),
+                    "payload_on": "down",
+                    "payload_off": "up",
+                }),
+            ),
+        ];
+
+        for &(trigger, kind, subtype) in DEVICE_TRIGGERS {
+            messages.push(entity(
+                "device_automation",
+                &trigger.to_string(),
+                serde_json::json!({
+                    "automation_type": "trigger",
+                    "topic": self.topic("button"),
+                    "payload": trigger.to_string(),
+                    "type": kind,
+                    "subtype": subtype,
+                }),
+            ));
+        }
+
+        messages
+    }
+}
+
+/// Publishes the message, logging failures.
+fn publish(client: &Client, topic: String, retain: bool, payload: impl Into<Vec<u8>>) {
+    if let Err(err) = client.publish(topic, QoS::AtLeastOnce, retain, payload) {
+        log::warn!("could not publish to the MQTT broker: {err}");
+    }
+}
+
+/// Forwards the events of the daemon to the broker.
+fn forward_events(client: Client, topics: Topics, events: Receiver<Published>) {
+    for Published { event, .. } in events {
+        match event {
+            Event::Button(trigger) => {
+                publish(&client, topics.topic("button"), false, trigger.to_string());
+
+                let mic_arm = match trigger {
+                    Trigger::MicFlippedUp => Some(MicArm::Up),
+                    Trigger::MicFlippedDown => Some(MicArm::Down),
+                    _ => None,
+                };
+                if let Some(mic_arm) = mic_arm {
+                    publish(&client, topics.topic("mic_arm"), true, mic_arm.to_string());
+                }
+            }
+            Event::PowerState(power_state) => publish(
+                &client,
+                topics.topic("power_state"),
+                true,
+                power_state.to_string(),
+            ),
+            Event::Battery(status) => match serde_json::to_vec(&status) {
+                Ok(payload) => publish(&client, topics.topic("battery"), true, payload),
+                Err(err) => log::error!("could not serialize the battery status: {err}"),
+            },
+            Event::Profile(name) => publish(&client, topics.topic("profile"), true, name),
+        }
+    }
+}
+
+/// Performs the light command received on the topic.
+fn set_lights(
+    daemon: &daemon::Client,
+    topics: &Topics,
+    topic: &str,
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    let light: Light = topic
+        .strip_prefix(&topics.topic("lights/"))
+        .and_then(|topic| topic.strip_suffix("/set"))
+        .context("unexpected topic")?
+        .parse()?;
+    let effect: Effect = serde_json::from_slice(payload).context("invalid effect")?;
+    effect.validate()?;
+
+    daemon.request(Request::SetLights { light, effect })?;
+
+    Ok(())
+}
+
+/// Performs the jobs handed off by the connection thread.
+fn perform_jobs(client: Client, topics: Topics, daemon: daemon::Client, jobs: Receiver<Job>) {
+    for job in jobs {
+        match job {
+            Job::Connected => {
+                log::info!("connected to the MQTT broker");
+
+                if let Err(err) = client.subscribe(topics.topic("lights/+/set"), QoS::AtLeastOnce) {
+                    log::warn!("could not subscribe to the light commands: {err}");
+                }
+
+                publish(&client, topics.topic("availability"), true, "online");
+                if let Some(discovery) = &topics.discovery {
+                    for (topic, config) in topics.discovery_messages(discovery) {
+                        publish(&client, topic, true, config.to_string());
+                    }
+                }
+
+                // The retained states are lost if the broker restarted
+                if let Ok(Response::Battery(status)) = daemon.request(Request::GetBattery) {
+                    publish(
+                        &client,
+                        topics.topic("power_state"),
+                        true,
+                        PowerState::Connected.to_string(),
+                    );
+                    if let Ok(payload) = serde_json::to_vec(&status) {
+                        publish(&client, topics.topic("battery"), true, payload);
+                    }
+                }
+            }
+            Job::SetLights { topic, payload } => {
+                if let Err(err) = set_lights(&daemon, &topics, &topic, &payload) {
+                    log::warn!("ignoring the light command on {topic}: {err:#}");
+                }
+            }
+        }
+    }
+}
+
+/// Drives the connection to the broker, which reconnects when iterated after a failure.
+fn drive(mut connection: Connection, topics: Topics, jobs: Sender<Job>) {
+    let light_commands = topics.topic("lights/");
+
+    for notification in connection.iter() {
+        let job = match notification {
+            Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => Job::Connected,
+            Ok(rumqttc::Event::Incoming(Packet::Publish(publish)))
+                if publish.topic.starts_with(&light_commands) =>
+            {
+                Job::SetLights {
+                    topic: publish.topic,
+                    payload: publish.payload.to_vec(),
+                }
+            }
+            Ok(_) => continue,
+            Err(err) => {
+                log::warn!("lost the connection to the MQTT broker: {err}");
+                std::thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        };
+
+        if jobs.send(job).is_err() {
+            return;
+        }
+    }
+}
+
+/// Connects to the broker in the background, publishing the events and performing the light
+/// commands.
+pub(crate) fn serve(
+    options: &config_file::Mqtt,
+    daemon: daemon::Client,
+    events: Receiver<Published>,
+) {
+    let topics = Topics {
+        base: options.topic.clone(),
+        discovery: options.discovery.then(|| options.discovery_prefix.clone()),
+    };
+
+    let mut mqtt_options = MqttOptions::new(&options.client_id, &options.host, options.port);
+    mqtt_options.set_keep_alive(Duration::from_secs(30));
+    mqtt_options.set_last_will(LastWill::new(
+        topics.topic("availability"),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &options.username {
+        mqtt_options.set_credentials(username, options.password.as_deref().unwrap_or_default());
+    }
+
+    let (client, connection) = Client::new(mqtt_options, 64);
+    let (jobs, receiver) = crossbeam_channel::unbounded();
+
+    std::thread::spawn({
+        let topics = topics.clone();
+        move || drive(connection, topics, jobs)
+    });
+    std::thread::spawn({
+        let client = client.clone();
+        let topics = topics.clone();
+        move || perform_jobs(client, topics, daemon, receiver)
+    });
+    std::thread::spawn(move || forward_events(client, topics, events));
+}
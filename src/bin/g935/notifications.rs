@@ -0,0 +1,111 @@
+//! Desktop notifications about the battery and the power state of the headset.
+
+use crossbeam_channel::Receiver;
+use g935::{BatteryStatus, ChargingStatus, PowerState};
+use notify_rust::{Notification, Urgency};
+
+use crate::{
+    config_file,
+    daemon::{Event, Published},
+};
+
+/// The icon shown in the notifications.
+const ICON: &str = "audio-headset";
+
+/// Decides which events are worth a notification.
+#[derive(Debug)]
+struct Notifier {
+    /// The notification settings.
+    settings: config_file::Notifications,
+    /// The last battery status.
+    battery: Option<BatteryStatus>,
+    /// Whether the low battery was already reported since the battery was last charged.
+    low_battery_reported: bool,
+}
+
+impl Notifier {
+    /// Shows the notification, unless its message is empty.
+    fn show(&self, message: &str, battery: Option<&BatteryStatus>, urgency: Urgency) {
+        if message.is_empty() {
+            return;
+        }
+
+        let body = match battery {
+            Some(status) => message.replace("{battery_percent}", &format!("{:.0}", status.charge)),
+            None => message.to_string(),
+        };
+
+        if let Err(err) = Notification::new()
+            .appname("g935")
+            .summary("G935")
+            .body(&body)
+            .icon(ICON)
+            .urgency(urgency)
+            .show()
+        {
+            log::warn!("could not show a notification: {err}");
+        }
+    }
+
+    /// Shows the notifications caused by the event.
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::Button(_) => (),
+            Event::Profile(name) => self.show(
+                &self.settings.profile_message.replace("{profile}", name),
+                None,
+                Urgency::Low,
+            ),
+            Event::PowerState(PowerState::Connected) => {
+                self.show(&self.settings.connected_message, None, Urgency::Low)
+            }
+            Event::PowerState(PowerState::Disconnected) => {
+                self.show(&self.settings.disconnected_message, None, Urgency::Low)
+            }
+            Event::PowerState(PowerState::Reconnecting | PowerState::Suspended) => (),
+            Event::Battery(status) => {
+                let previous = self.battery.replace(*status);
+
+                if status.charging_status != ChargingStatus::Discharging
+                    || status.charge >= self.settings.low_battery
+                {
+                    self.low_battery_reported = false;
+                } else if !self.low_battery_reported {
+                    self.low_battery_reported = true;
+                    self.show(
+                        &self.settings.low_battery_message,
+                        Some(status),
+                        Urgency::Critical,
+                    );
+                }
+
+                // Only report a change to full, not a battery that was full all along
+                if status.charging_status == ChargingStatus::Full
+                    && previous
+                        .is_some_and(|previous| previous.charging_status != ChargingStatus::Full)
+                {
+                    self.show(
+                        &self.settings.charged_message,
+                        Some(status),
+                        Urgency::Normal,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Shows notifications about the events in the background.
+pub(crate) fn serve(settings: &config_file::Notifications, events: Receiver<Published>) {
+    let mut notifier = Notifier {
+        settings: settings.clone(),
+        battery: None,
+        low_battery_reported: false,
+    };
+
+    std::thread::spawn(move || {
+        for Published { event, .. } in events {
+            notifier.handle(&event);
+        }
+    });
+}
@@ -0,0 +1,209 @@
+//! Backing up and changing the settings stored in the headset.
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use g935::{
+    lights::{Effect, FullLightSettings, Light},
+    Headset,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::output;
+
+/// The version of the dump format, which is increased on incompatible changes.
+const VERSION: u32 = 1;
+
+/// The subcommands managing the settings stored in the headset.
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum ProfileCommand {
+    /// print the settings stored in the headset as JSON
+    Dump,
+    /// store the settings of a dump in the headset, showing the changes and only writing them
+    /// with --yes
+    Restore {
+        /// the dump to restore
+        path: std::path::PathBuf,
+        /// write the changes after showing them
+        #[clap(short, long)]
+        yes: bool,
+    },
+}
+
+/// The headset a dump was made from.
+#[derive(Debug, Serialize, Deserialize)]
+struct Origin {
+    /// The name the headset reports for itself.
+    name: String,
+    /// The product name of the USB receiver.
+    model: String,
+}
+
+/// The settings stored in the headset.
+#[derive(Debug, Serialize, Deserialize)]
+struct Dump {
+    /// The version of the format.
+    version: u32,
+    /// The headset the dump was made from.
+    headset: Origin,
+    /// The effects the lights show after the headset is turned on.
+    lights: FullLightSettings,
+}
+
+/// How the stored effect of a light changes.
+#[derive(Debug, Serialize)]
+struct Change {
+    /// The light whose effect changes.
+    light: Light,
+    /// The effect stored before.
+    previous: Effect,
+    /// The effect to store.
+    effect: Effect,
+    /// Whether the effect was written.
+    written: bool,
+}
+
+impl Change {
+    /// Returns `true` if the stored effect changes.
+    fn is_change(&self) -> bool {
+        self.previous != self.effect
+    }
+
+    /// Prints the change for humans.
+    fn print(&self) {
+        if self.is_change() {
+            println!(
+                "{} light: {:?} -> {:?}",
+                self.light, self.previous, self.effect
+            );
+        } else {
+            println!("the {} light already stores {:?}", self.light, self.effect);
+        }
+    }
+}
+
+/// Runs the profile command.
+pub(crate) fn run(
+    headset: &mut Headset,
+    command: ProfileCommand,
+    json: bool,
+) -> anyhow::Result<()> {
+    match command {
+        ProfileCommand::Dump => dump(headset),
+        ProfileCommand::Restore { path, yes } => restore(headset, &path, yes, json),
+    }
+}
+
+/// Prints the settings stored in the headset.
+fn dump(headset: &mut Headset) -> anyhow::Result<()> {
+    let dump = Dump {
+        version: VERSION,
+        headset: Origin {
+            name: headset.info().name.clone(),
+            model: headset.info().model.clone(),
+        },
+        lights: FullLightSettings {
+            side: headset.get_permanent_light(Light::Side)?,
+            logo: headset.get_permanent_light(Light::Logo)?,
+        },
+    };
+
+    println!("{}", serde_json::to_string_pretty(&dump)?);
+
+    Ok(())
+}
+
+/// Stores the settings of the dump in the headset.
+fn restore(headset: &mut Headset, path: &Path, yes: bool, json: bool) -> anyhow::Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read the dump {}", path.display()))?;
+    let dump: Dump = serde_json::from_str(&content)
+        .with_context(|| format!("could not parse the dump {}", path.display()))?;
+
+    anyhow::ensure!(
+        dump.version == VERSION,
+        "unsupported dump version {}, expected {VERSION}",
+        dump.version
+    );
+    if dump.headset.name != headset.info().name {
+        log::warn!(
+            "the dump was made from a {}, but the headset is a {}",
+            dump.headset.name,
+            headset.info().name
+        );
+    }
+
+    let changes = review(
+        headset,
+        &[
+            (Light::Side, dump.lights.side),
+            (Light::Logo, dump.lights.logo),
+        ],
+        yes,
+    )?;
+
+    if json {
+        output::print_json(&changes);
+    } else {
+        changes.iter().for_each(Change::print);
+    }
+
+    write(headset, &changes, yes)
+}
+
+/// Shows how the stored effect of the light changes, and stores it if `yes` is set.
+pub(crate) fn store_light(
+    headset: &mut Headset,
+    light: Light,
+    effect: Effect,
+    yes: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let changes = review(headset, &[(light, effect)], yes)?;
+
+    if json {
+        output::print_json(&changes[0]);
+    } else {
+        changes[0].print();
+    }
+
+    write(headset, &changes, yes)
+}
+
+/// Compares the effects with the ones stored for the lights, which are written if `yes` is set.
+fn review(
+    headset: &mut Headset,
+    effects: &[(Light, Effect)],
+    yes: bool,
+) -> anyhow::Result<Vec<Change>> {
+    effects
+        .iter()
+        .map(|&(light, effect)| {
+            effect
+                .validate()
+                .with_context(|| format!("invalid effect for the {light} light"))?;
+            let previous = headset.get_permanent_light(light)?;
+
+            Ok(Change {
+                light,
+                previous,
+                effect,
+                written: yes && previous != effect,
+            })
+        })
+        .collect()
+}
+
+/// Writes the changed effects, failing without writing any if `yes` is not set.
+fn write(headset: &mut Headset, changes: &[Change], yes: bool) -> anyhow::Result<()> {
+    if !changes.iter().any(Change::is_change) {
+        return Ok(());
+    }
+    anyhow::ensure!(yes, "not storing the changes without --yes");
+
+    for change in changes.iter().filter(|change| change.is_change()) {
+        headset.set_permanent_light(change.light, change.effect, true)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! The machine-readable output selected with `--json`, and the JSON Schema describing it.
+//!
+//! The schema is the contract with scripts, so fields are only ever added to it, never renamed or
+//! removed.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Prints the value as a single line of JSON.
+pub(crate) fn print_json(value: &impl Serialize) {
+    match serde_json::to_string(value) {
+        Ok(json) => println!("{json}"),
+        Err(err) => log::error!("could not print the output as JSON: {err}"),
+    }
+}
+
+/// Returns the schema of an object with the given properties, which are always present, even if
+/// they are `null`.
+fn object(properties: Value) -> Value {
+    let required = properties
+        .as_object()
+        .map(|properties| properties.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// Returns the JSON Schema of the output of every command with `--json`.
+///
+/// The schema of each command is found in `$defs` under the name of the command.
+pub(crate) fn schema() -> Value {
+    let battery_status = object(json!({
+        "charging_status": { "enum": ["discharging", "charging", "full"] },
+        "voltage": { "type": "integer", "description": "the battery voltage in mV" },
+        "charge": { "type": "number", "description": "the estimated charge in percent" },
+    }));
+    let path = object(json!({
+        "path": { "type": "string", "description": "the path of the written file" },
+        "written": { "type": "boolean", "description": "false with --dry-run" },
+    }));
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "g935 --json",
+        "description": "The output of the g935 commands with --json. Commands that print nothing \
+                        otherwise print nothing with --json either.",
+        "$defs": {
+            "battery_status": battery_status,
+            "get-battery-level": { "$ref": "#/$defs/battery_status" },
+            "battery": object(json!({
+                "connected": { "type": "boolean" },
+                "battery": {
+                    "oneOf": [{ "$ref": "#/$defs/battery_status" }, { "type": "null" }],
+                },
+                "class": { "type": "array", "items": { "type": "string" } },
+            })),
+            "list": {
+                "type": "array",
+                "items": object(json!({
+                    "path": { "type": "string" },
+                    "serial_number": { "type": ["string", "null"] },
+                    "product": { "type": ["string", "null"] },
+                    "device_index": { "type": "integer" },
+                    "wired": { "type": "boolean" },
+                })),
+            },
+            "info": object(json!({
+                "name": { "type": "string" },
+                "model": { "type": "string" },
+                "protocol_version": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "integer" }, { "type": "integer" }],
+                    "description": "the major and minor version",
+                },
+                "serial_number": { "type": ["string", "null"] },
+            })),
+            "features": {
+                "type": "array",
+                "items": object(json!({
+                    "index": { "type": "integer" },
+                    "id": { "type": "integer", "description": "the ID in the HID++ specification" },
+                })),
+            },
+            "report-rate": object(json!({
+                "interval_ms": { "type": "integer" },
+            })),
+            "store-light": object(json!({
+                "light": { "enum": ["logo", "side"] },
+                "previous": { "type": "object", "description": "the effect stored before" },
+                "effect": { "type": "object", "description": "the effect to store" },
+                "written": { "type": "boolean", "description": "false without --yes" },
+            })),
+            "profile dump": object(json!({
+                "version": { "const": 1 },
+                "headset": object(json!({
+                    "name": { "type": "string" },
+                    "model": { "type": "string" },
+                })),
+                "lights": object(json!({
+                    "side": { "type": "object", "description": "the stored effect" },
+                    "logo": { "type": "object", "description": "the stored effect" },
+                })),
+            })),
+            "profile restore": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/store-light" },
+            },
+            "ctl get-battery": { "$ref": "#/$defs/battery_status" },
+            "ctl get-sidetone": object(json!({
+                "level": { "type": "integer", "description": "the sidetone level in percent" },
+            })),
+            "ctl get-equalizer": object(json!({
+                "gains": {
+                    "type": "array",
+                    "items": { "type": "integer" },
+                    "description": "the gain of each band in dB",
+                },
+            })),
+            "ctl get-stats": object(json!({
+                "requests": { "type": "integer" },
+                "timeouts": { "type": "integer" },
+                "retries": { "type": "integer" },
+                "unrequested_messages": { "type": "integer" },
+                "parse_failures": { "type": "integer" },
+                "handler_latency": object(json!({
+                    "counts": {
+                        "type": "array",
+                        "items": { "type": "integer" },
+                        "description": "the events handled within 1, 5, 10, 50, 100 and 500 ms, \
+                                        and above",
+                    },
+                    "max_us": { "type": "integer" },
+                })),
+            })),
+            "ctl get-wireless-status": object(json!({
+                "linked": { "type": "boolean" },
+                "latency_us": { "type": ["integer", "null"] },
+                "reports_reconnections": { "type": "boolean" },
+            })),
+            "ctl subscribe": {
+                "description": "one event per line, with exactly one of the event properties",
+                "type": "object",
+                "properties": {
+                    "button": { "type": "string", "description": "the trigger, like g1_pressed" },
+                    "power_state": {
+                        "enum": ["connected", "disconnected", "reconnecting", "suspended"],
+                    },
+                    "battery": { "$ref": "#/$defs/battery_status" },
+                    "profile": { "type": "string" },
+                    "sequence": { "type": "integer" },
+                    "timestamp_us": { "type": "integer" },
+                },
+                "required": ["sequence", "timestamp_us"],
+            },
+            "record-macro": object(json!({
+                "name": { "type": "string" },
+                "steps": {
+                    "type": "array",
+                    "items": { "type": "object" },
+                    "description": "the steps as written to the configuration file",
+                },
+            })),
+            "install-service": path.clone(),
+            "install-udev-rules": {
+                "allOf": [path],
+                "properties": {
+                    "content": { "type": "string", "description": "the rule, with --dry-run" },
+                },
+            },
+        },
+    })
+}
@@ -0,0 +1,40 @@
+//! Recording macros from the keyboard.
+
+use anyhow::Context as _;
+use g935::macros::{self, Macro, Playback};
+
+use crate::output;
+
+/// Records a macro until the stop key is pressed, printing it as a configuration file snippet.
+///
+/// With `json`, the name and steps are printed as JSON instead.
+pub(crate) fn record_macro(name: &str, stop_key: &str, json: bool) -> anyhow::Result<()> {
+    let stop_key = stop_key
+        .parse()
+        .map_err(|_| anyhow::anyhow!("unknown key {stop_key:?}"))?;
+
+    eprintln!("recording, press {stop_key:?} to stop");
+    let steps = macros::record(stop_key)?;
+
+    let macro_ = Macro {
+        playback: Playback::Once,
+        steps,
+    };
+    macro_.validate()?;
+
+    if json {
+        output::print_json(&serde_json::json!({ "name": name, "steps": macro_.steps }));
+
+        return Ok(());
+    }
+
+    println!("[macros.{}]", toml::Value::from(name));
+    println!("steps = [");
+    for step in &macro_.steps {
+        let step = toml::Value::try_from(step).context("could not serialize the macro")?;
+        println!("    {step},");
+    }
+    println!("]");
+
+    Ok(())
+}
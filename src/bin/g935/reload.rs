@@ -0,0 +1,128 @@
+//! Reloading of the configuration file while running.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use g935::{config::RunConfig, Waker};
+
+use crate::{config_file::ConfigFile, daemon::Publisher};
+
+/// Reloads the configuration file on `SIGHUP` or, with the `watch` feature, when it changes.
+#[derive(Debug)]
+pub(crate) struct Reloader {
+    /// The path of the configuration file.
+    path: PathBuf,
+    /// The currently applied configuration file.
+    current: ConfigFile,
+    /// Whether a reload was requested.
+    requested: Arc<AtomicBool>,
+    /// The publisher of the button events, when running as a daemon.
+    publisher: Option<Publisher>,
+    /// The watcher notifying about changes of the configuration file.
+    #[cfg(feature = "watch")]
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl Reloader {
+    /// Starts listening for reload requests of the already applied configuration file, using
+    /// the waker to reload right away.
+    pub(crate) fn new(
+        path: PathBuf,
+        current: ConfigFile,
+        publisher: Option<Publisher>,
+        waker: Waker,
+    ) -> anyhow::Result<Self> {
+        let requested = Arc::new(AtomicBool::new(false));
+
+        #[cfg(unix)]
+        {
+            let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+            let requested = Arc::clone(&requested);
+            let waker = waker.clone();
+
+            std::thread::spawn(move || {
+                for _ in signals.forever() {
+                    requested.store(true, Ordering::Relaxed);
+                    waker.wake();
+                }
+            });
+        }
+
+        Ok(Self {
+            #[cfg(feature = "watch")]
+            _watcher: watch(&path, Arc::clone(&requested), waker)?,
+            path,
+            current,
+            requested,
+            publisher,
+        })
+    }
+
+    /// Reloads the configuration file if requested, applying only the changed settings.
+    ///
+    /// If the new configuration file is invalid, the current configuration stays in place.
+    pub(crate) fn reload_if_requested(&mut self, config: &mut RunConfig) {
+        if !self.requested.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        log::info!("reloading config file {}", self.path.display());
+
+        let new = match ConfigFile::load(&self.path) {
+            Ok(new) => new,
+            Err(err) => {
+                log::error!("keeping the current configuration: {err:#}");
+                return;
+            }
+        };
+
+        if let Err(err) = new.apply(config, Some(&self.current), self.publisher.as_ref()) {
+            log::error!("failed to apply the reloaded configuration: {err:#}");
+        }
+        self.current = new;
+    }
+}
+
+/// Watches the configuration file, setting `requested` and waking the loop whenever it changes.
+#[cfg(feature = "watch")]
+fn watch(
+    path: &std::path::Path,
+    requested: Arc<AtomicBool>,
+    waker: Waker,
+) -> anyhow::Result<notify::RecommendedWatcher> {
+    use notify::Watcher as _;
+
+    let file_name = path.file_name().map(ToOwned::to_owned);
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        match event {
+            // Editors often replace the file instead of writing to it, so the whole directory is
+            // watched and the events are filtered by the file name
+            Ok(event)
+                if (event.kind.is_modify() || event.kind.is_create())
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == file_name.as_deref()) =>
+            {
+                requested.store(true, Ordering::Relaxed);
+                waker.wake();
+            }
+            Ok(_) => (),
+            Err(err) => log::warn!("error while watching the config file: {err}"),
+        }
+    })?;
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+
+    Ok(watcher)
+}
@@ -0,0 +1,76 @@
+//! Resynchronization of the headset after the computer resumed from suspend.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use g935::{config::RunConfig, Headset, Waker};
+
+/// Listens for logind's `PrepareForSleep` signal to notice when the computer suspends and
+/// resumes.
+#[derive(Debug)]
+pub(crate) struct ResumeWatcher {
+    /// Whether the computer was about to suspend since the last check.
+    suspending: Arc<AtomicBool>,
+    /// Whether the computer resumed since the last check.
+    resumed: Arc<AtomicBool>,
+}
+
+impl ResumeWatcher {
+    /// Starts listening for resumes on the system bus, using the waker to resync right away.
+    pub(crate) fn new(waker: Waker) -> anyhow::Result<Self> {
+        let connection = zbus::blocking::Connection::system()?;
+        let manager = zbus::blocking::Proxy::new(
+            &connection,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        let signals = manager.receive_signal("PrepareForSleep")?;
+
+        let suspending = Arc::new(AtomicBool::new(false));
+        let suspending2 = Arc::clone(&suspending);
+        let resumed = Arc::new(AtomicBool::new(false));
+        let resumed2 = Arc::clone(&resumed);
+
+        std::thread::spawn(move || {
+            // The proxy needs to stay alive to keep receiving the signals
+            let _manager = manager;
+
+            for signal in signals {
+                match signal.body().deserialize::<bool>() {
+                    // The argument is `true` before suspending and `false` after resuming
+                    Ok(false) => {
+                        log::info!("the computer resumed from suspend");
+                        resumed2.store(true, Ordering::Relaxed);
+                        waker.wake();
+                    }
+                    Ok(true) => {
+                        log::info!("the computer is about to suspend");
+                        suspending2.store(true, Ordering::Relaxed);
+                        waker.wake();
+                    }
+                    Err(err) => log::warn!("unexpected PrepareForSleep signal: {err}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            suspending,
+            resumed,
+        })
+    }
+
+    /// Stops talking to the headset if the computer is about to suspend, and reconnects to it
+    /// and applies the whole configuration again if the computer resumed since the last call.
+    pub(crate) fn resync_if_resumed(&self, config: &mut RunConfig, headset: &mut Headset) {
+        if self.suspending.swap(false, Ordering::Relaxed) {
+            headset.suspend(config);
+        }
+
+        if self.resumed.swap(false, Ordering::Relaxed) {
+            headset.resume(config);
+        }
+    }
+}
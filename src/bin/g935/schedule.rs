@@ -0,0 +1,135 @@
+//! Changing the lights by the time of day.
+
+use std::time::Duration;
+
+use g935::{
+    config::RunConfig,
+    lights::{Color, Effect, FullLightSettings, Light},
+    plugin::Plugin,
+    Headset,
+};
+
+use crate::config_file::ScheduleEntry;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// How often the schedule is evaluated while no transition is in progress.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the lights are updated during a transition.
+const TRANSITION_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Returns the seconds since midnight in the local time zone.
+///
+/// The time zone is looked up by the C library, so `TZ` and `/etc/localtime` are respected.
+fn local_seconds_of_day() -> u32 {
+    // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes to the given `tm`
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return now.rem_euclid(libc::time_t::from(SECONDS_PER_DAY)) as u32;
+        }
+
+        (tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec) as u32
+    }
+}
+
+/// Blends two static colors or off, where `t` goes from 0 (`from`) to 1 (`to`).
+///
+/// Other effects cannot be blended, so they are switched to halfway through the transition.
+fn blend(from: Effect, to: Effect, t: f32) -> Effect {
+    let color = |effect| match effect {
+        Effect::Off => Some(Color::BLACK),
+        Effect::Static { color } => Some(color),
+        _ => None,
+    };
+
+    match (color(from), color(to)) {
+        (Some(from), Some(to)) => {
+            let channel = |from: u8, to: u8| {
+                (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u8
+            };
+            let color = Color::new(
+                channel(from.r, to.r),
+                channel(from.g, to.g),
+                channel(from.b, to.b),
+            );
+
+            if color == Color::BLACK {
+                Effect::Off
+            } else {
+                Effect::Static { color }
+            }
+        }
+        _ if t < 0.5 => from,
+        _ => to,
+    }
+}
+
+/// Sets the lights according to a daily schedule, fading between the entries.
+#[derive(Debug)]
+pub(crate) struct Schedule {
+    /// The entries of the schedule, sorted by their start.
+    entries: Vec<ScheduleEntry>,
+    /// Whether a transition was in progress when the schedule was last evaluated.
+    in_transition: bool,
+}
+
+impl Schedule {
+    /// Creates the schedule from its entries, which must not be empty.
+    pub(crate) fn new(mut entries: Vec<ScheduleEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.start.0);
+
+        Self {
+            entries,
+            in_transition: false,
+        }
+    }
+
+    /// Returns the effect of the light at the time of day, and whether a transition is in
+    /// progress.
+    fn effect_at(&self, light: Light, seconds: u32) -> (Effect, bool) {
+        // The last entry that started, which is the last one of the previous day before the first
+        let current = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.start.0 <= seconds)
+            .unwrap_or(self.entries.len() - 1);
+        let previous = (current + self.entries.len() - 1) % self.entries.len();
+
+        let entry = &self.entries[current];
+        let elapsed = (seconds + SECONDS_PER_DAY - entry.start.0) % SECONDS_PER_DAY;
+        let fade = entry.fade_minutes * 60;
+        let to = entry.effect(light);
+
+        if elapsed >= fade {
+            return (to, false);
+        }
+
+        let from = self.entries[previous].effect(light);
+        (blend(from, to, elapsed as f32 / fade as f32), true)
+    }
+}
+
+impl Plugin for Schedule {
+    fn on_tick(&mut self, config: &mut RunConfig, _: &mut Headset) {
+        let seconds = local_seconds_of_day();
+
+        // The config only sends the effects if they changed
+        let (side, side_in_transition) = self.effect_at(Light::Side, seconds);
+        let (logo, logo_in_transition) = self.effect_at(Light::Logo, seconds);
+        config.set_lights(FullLightSettings { side, logo });
+
+        self.in_transition = side_in_transition || logo_in_transition;
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(if self.in_transition {
+            TRANSITION_INTERVAL
+        } else {
+            CHECK_INTERVAL
+        })
+    }
+}
@@ -0,0 +1,106 @@
+//! The signals controlling the long running commands.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use g935::{config::RunConfig, Headset};
+
+/// How old the battery status logged on `SIGUSR1` may be.
+const STATUS_BATTERY_AGE: Duration = Duration::from_secs(60);
+
+/// Stops the loop on `SIGINT` and `SIGTERM`, logs the status on `SIGUSR1` and, unless a
+/// [`Reloader`](crate::reload::Reloader) handles it, applies the settings again on `SIGHUP`.
+#[derive(Debug, Default)]
+pub(crate) struct Signals {
+    /// Whether the status was requested.
+    status_requested: Arc<AtomicBool>,
+    /// Whether applying the settings again was requested.
+    resync_requested: Arc<AtomicBool>,
+}
+
+impl Signals {
+    /// Starts listening for the signals, handling `SIGHUP` only if `resync_on_hangup` is set.
+    ///
+    /// A second `SIGINT` or `SIGTERM` exits right away, in case stopping the loop hangs.
+    pub(crate) fn new(config: &mut RunConfig, resync_on_hangup: bool) -> anyhow::Result<Self> {
+        let signals = Self::default();
+
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+
+            let mut handled = vec![SIGINT, SIGTERM, SIGUSR1];
+            if resync_on_hangup {
+                handled.push(SIGHUP);
+            }
+            let mut iterator = signal_hook::iterator::Signals::new(handled)?;
+            let stop_token = config.stop_token();
+            let waker = config.waker();
+            let status_requested = Arc::clone(&signals.status_requested);
+            let resync_requested = Arc::clone(&signals.resync_requested);
+
+            std::thread::spawn(move || {
+                for signal in iterator.forever() {
+                    match signal {
+                        SIGINT | SIGTERM if stop_token.is_stopped() => {
+                            log::warn!("exiting without restoring the headset");
+                            std::process::exit(1);
+                        }
+                        SIGINT | SIGTERM => {
+                            log::info!("stopping after receiving signal {signal}");
+                            stop_token.stop();
+                        }
+                        SIGUSR1 => {
+                            status_requested.store(true, Ordering::Relaxed);
+                            waker.wake();
+                        }
+                        SIGHUP => {
+                            resync_requested.store(true, Ordering::Relaxed);
+                            waker.wake();
+                        }
+                        _ => (),
+                    }
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        let _ = (config, resync_on_hangup);
+
+        Ok(signals)
+    }
+
+    /// Handles the signals received since the last call.
+    pub(crate) fn handle(&self, config: &mut RunConfig, headset: &mut Headset) {
+        if self.resync_requested.swap(false, Ordering::Relaxed) {
+            log::info!("applying the settings again");
+            config.set_dirty();
+        }
+
+        if self.status_requested.swap(false, Ordering::Relaxed) {
+            log_status(config, headset);
+        }
+    }
+}
+
+/// Logs the state of the headset and the effects shown on its lights.
+fn log_status(config: &RunConfig, headset: &mut Headset) {
+    let battery = match headset.battery_status_cached(STATUS_BATTERY_AGE) {
+        Ok(status) => status.to_string(),
+        Err(err) => format!("unknown ({err})"),
+    };
+    let lights = config.shown_lights();
+
+    log::info!(
+        "{} ({}): battery {battery}, side light {:?}, logo light {:?}, {}",
+        headset.info().name,
+        headset.info().model,
+        lights.side,
+        lights.logo,
+        headset.stats()
+    );
+}
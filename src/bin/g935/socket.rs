@@ -0,0 +1,185 @@
+//! The Unix socket interface of the daemon.
+//!
+//! The protocol consists of JSON messages, one per line. Each request, such as
+//! `{"request":"get_battery"}`, is answered with either `{"ok":...}` or `{"error":"..."}`.
+//! After `{"request":"subscribe"}`, the daemon sends every event as `{"event":...}` instead.
+
+use std::{
+    io::{BufRead, BufReader, BufWriter, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::{Client, Published, Publisher, Request, Response};
+
+/// A message sent by the daemon.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Reply {
+    /// The request succeeded.
+    Ok(Response),
+    /// The request failed.
+    Error(String),
+    /// An event occurred.
+    Event(Published),
+}
+
+/// The request switching a connection to receiving events.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Subscribe {
+    /// Subscribes to all events.
+    Subscribe,
+}
+
+/// Returns the path of the socket used if none is given explicitly.
+pub(crate) fn default_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("g935.sock")
+}
+
+/// Writes the message as one line.
+fn send(writer: &mut impl Write, message: &impl Serialize) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Serves the requests of one connection.
+fn serve_connection(
+    stream: UnixStream,
+    client: &Client,
+    publisher: &Publisher,
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(stream.try_clone()?);
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+
+        if serde_json::from_str::<Subscribe>(&line).is_ok() {
+            for event in publisher.subscribe() {
+                send(&mut writer, &Reply::Event(event))?;
+            }
+
+            return Ok(());
+        }
+
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => match client.request(request) {
+                Ok(response) => Reply::Ok(response),
+                Err(err) => Reply::Error(format!("{err:#}")),
+            },
+            Err(err) => Reply::Error(format!("invalid request: {err}")),
+        };
+
+        send(&mut writer, &reply)?;
+    }
+
+    Ok(())
+}
+
+/// Listens on the socket at the given path, serving each connection in its own thread.
+pub(crate) fn serve(path: &Path, client: Client, publisher: Publisher) -> anyhow::Result<()> {
+    if path.exists() {
+        // A socket nobody listens on is left over from a daemon that did not exit cleanly
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("another daemon is already listening on {}", path.display());
+        }
+
+        std::fs::remove_file(path)
+            .with_context(|| format!("could not remove stale socket {}", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("could not listen on {}", path.display()))?;
+
+    log::info!("listening on {}", path.display());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("could not accept connection: {err}");
+                    continue;
+                }
+            };
+
+            let client = client.clone();
+            let publisher = publisher.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = serve_connection(stream, &client, &publisher) {
+                    log::debug!("connection closed: {err:#}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// A connection to the socket of a running daemon.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    /// The lines sent by the daemon.
+    reader: BufReader<UnixStream>,
+    /// The stream to send the requests on.
+    writer: BufWriter<UnixStream>,
+}
+
+impl Connection {
+    /// Connects to the daemon listening on the socket at the given path.
+    pub(crate) fn connect(path: &Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path).with_context(|| {
+            format!(
+                "could not connect to {}, is `g935 daemon` running?",
+                path.display()
+            )
+        })?;
+
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    /// Receives the next message of the daemon.
+    fn receive(&mut self) -> anyhow::Result<Reply> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("the daemon closed the connection");
+        }
+
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Sends the request, waiting for the response.
+    pub(crate) fn request(&mut self, request: &Request) -> anyhow::Result<Response> {
+        send(&mut self.writer, request)?;
+
+        match self.receive()? {
+            Reply::Ok(response) => Ok(response),
+            Reply::Error(err) => Err(anyhow::anyhow!(err)),
+            Reply::Event(event) => Err(anyhow::anyhow!("unexpected event {event:?}")),
+        }
+    }
+
+    /// Subscribes to the events, calling `f` for each of them until the daemon stops.
+    pub(crate) fn subscribe(mut self, mut f: impl FnMut(Published)) -> anyhow::Result<()> {
+        send(&mut self.writer, &Subscribe::Subscribe)?;
+
+        loop {
+            match self.receive()? {
+                Reply::Event(event) => f(event),
+                reply => anyhow::bail!("unexpected reply {reply:?}"),
+            }
+        }
+    }
+}
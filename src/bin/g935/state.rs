@@ -0,0 +1,161 @@
+//! Remembers the settings last applied through the daemon, so that they survive restarts.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use g935::{
+    config::RunConfig,
+    lights::{Effect, Light},
+};
+use serde::{Deserialize, Serialize};
+
+/// The settings last applied through the daemon.
+///
+/// Settings that were never changed are `None`, so that restoring them leaves the defaults alone.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct State {
+    /// The effect of the side light.
+    pub(crate) side: Option<Effect>,
+    /// The effect of the logo light.
+    pub(crate) logo: Option<Effect>,
+    /// The brightness in percent of the side light.
+    pub(crate) side_brightness: Option<u8>,
+    /// The brightness in percent of the logo light.
+    pub(crate) logo_brightness: Option<u8>,
+    /// The sidetone level in percent.
+    pub(crate) sidetone: Option<u8>,
+    /// The gain in dB of each equalizer band.
+    pub(crate) equalizer: Option<Vec<i8>>,
+}
+
+/// Returns the directory g935 keeps its state in, which is `$XDG_STATE_HOME/g935`.
+pub(crate) fn dir() -> Option<PathBuf> {
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+
+    Some(state_dir.join("g935"))
+}
+
+/// Returns the path of the state file, which is `$XDG_STATE_HOME/g935/state.json`.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    Some(dir()?.join("state.json"))
+}
+
+impl State {
+    /// Reads the state file at the path, which is empty if it does not exist yet.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("could not read state file {}", path.display()))
+            }
+        };
+
+        let state: State = serde_json::from_str(&contents)
+            .with_context(|| format!("could not parse state file {}", path.display()))?;
+
+        for effect in [state.side, state.logo].into_iter().flatten() {
+            effect
+                .validate()
+                .context("invalid light effect in the state file")?;
+        }
+
+        Ok(state)
+    }
+
+    /// Writes the state file at the path, replacing it atomically.
+    pub(crate) fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("could not create {}", dir.display()))?;
+        }
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("could not write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not write state file {}", path.display()))
+    }
+
+    /// Records the effect of the light.
+    pub(crate) fn set_lights(&mut self, light: Light, effect: Effect) {
+        match light {
+            Light::Side => self.side = Some(effect),
+            Light::Logo => self.logo = Some(effect),
+        }
+    }
+
+    /// Records the brightness of the light.
+    pub(crate) fn set_brightness(&mut self, light: Light, brightness: u8) {
+        match light {
+            Light::Side => self.side_brightness = Some(brightness),
+            Light::Logo => self.logo_brightness = Some(brightness),
+        }
+    }
+
+    /// Applies the recorded settings to the config.
+    pub(crate) fn apply(&self, config: &mut RunConfig) {
+        if let Some(effect) = self.side {
+            config.set_side_light_effect(effect);
+        }
+        if let Some(effect) = self.logo {
+            config.set_logo_light_effect(effect);
+        }
+        if self.side_brightness.is_some() {
+            config.set_light_brightness(Light::Side, self.side_brightness);
+        }
+        if self.logo_brightness.is_some() {
+            config.set_light_brightness(Light::Logo, self.logo_brightness);
+        }
+        if self.sidetone.is_some() {
+            config.set_sidetone(self.sidetone);
+        }
+        if self.equalizer.is_some() {
+            config.set_equalizer(self.equalizer.clone());
+        }
+    }
+}
+
+/// The state file, which is written whenever a setting is changed through the daemon.
+#[derive(Debug)]
+pub(crate) struct StateStore {
+    /// The path of the state file.
+    path: PathBuf,
+    /// The recorded settings.
+    state: State,
+}
+
+impl StateStore {
+    /// Opens the state file at the path, starting out empty if it cannot be read.
+    pub(crate) fn open(path: PathBuf) -> Self {
+        let state = State::load(&path).unwrap_or_else(|err| {
+            log::warn!("{err:#}");
+            State::default()
+        });
+
+        Self { path, state }
+    }
+
+    /// Returns the recorded settings.
+    pub(crate) fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Changes the recorded settings and writes them to the state file.
+    ///
+    /// Failing to write is only logged, as the settings were applied nonetheless.
+    pub(crate) fn update(&mut self, change: impl FnOnce(&mut State)) {
+        let old_state = self.state.clone();
+        change(&mut self.state);
+
+        if self.state != old_state {
+            if let Err(err) = self.state.save(&self.path) {
+                log::warn!("{err:#}");
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! The integration with systemd.
+
+use std::{
+    io::Write as _,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use crate::output;
+
+/// Sends the state to the service manager, if the process was started with `Type=notify`.
+fn notify(state: &str) -> std::io::Result<()> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+
+    let socket = UnixDatagram::unbound()?;
+
+    match path.as_encoded_bytes() {
+        #[cfg(target_os = "linux")]
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt as _;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the interval in which the service manager expects watchdog pings, if it does.
+fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    // The watchdog may be meant for another process, such as the parent shell
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+
+    Some(Duration::from_micros(usec))
+}
+
+/// Tells the service manager that the service is ready and that its event loop is healthy.
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    /// Whether the readiness was already reported.
+    ready: bool,
+    /// The interval in which the watchdog expects pings.
+    watchdog: Option<Duration>,
+    /// When the watchdog was last pinged.
+    last_ping: Instant,
+}
+
+impl Notifier {
+    /// Creates the notifier, reading the watchdog settings from the environment.
+    pub(crate) fn new() -> Self {
+        Self {
+            ready: false,
+            watchdog: watchdog_interval(),
+            last_ping: Instant::now(),
+        }
+    }
+
+    /// Returns how often [`Notifier::tick`] needs to be called.
+    pub(crate) fn tick_interval(&self) -> Duration {
+        self.watchdog.map_or(Duration::MAX, |interval| interval / 2)
+    }
+
+    /// Reports readiness the first time and pings the watchdog when it is due.
+    ///
+    /// This should be called from the event loop, so that the watchdog notices if it hangs.
+    pub(crate) fn tick(&mut self) {
+        if !self.ready {
+            self.ready = true;
+
+            if let Err(err) = notify("READY=1") {
+                log::warn!("could not notify the service manager: {err}");
+            }
+        }
+
+        let Some(interval) = self.watchdog else {
+            return;
+        };
+
+        // Ping twice per interval, so that a slow iteration does not trigger the watchdog
+        if self.last_ping.elapsed() >= interval / 2 {
+            self.last_ping = Instant::now();
+
+            if let Err(err) = notify("WATCHDOG=1") {
+                log::warn!("could not ping the watchdog: {err}");
+            }
+        }
+    }
+}
+
+/// Returns the path of the unit file.
+fn unit_path(user: bool) -> anyhow::Result<PathBuf> {
+    if !user {
+        return Ok(PathBuf::from("/etc/systemd/system/g935.service"));
+    }
+
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .context("could not determine the config directory")?;
+
+    Ok(config_dir.join("systemd").join("user").join("g935.service"))
+}
+
+/// Returns the contents of the unit file running the daemon.
+fn unit(exe: &Path, user: bool) -> String {
+    let wanted_by = if user {
+        "default.target"
+    } else {
+        "multi-user.target"
+    };
+
+    format!(
+        "[Unit]
+Description=Logitech G935 headset daemon
+After=sound.target
+
+[Service]
+Type=notify
+ExecStart={} daemon
+ExecReload=kill -HUP $MAINPID
+Restart=on-failure
+RestartSec=5
+WatchdogSec=30
+
+[Install]
+WantedBy={wanted_by}
+",
+        exe.display()
+    )
+}
+
+/// Writes the unit file running the daemon, refusing to overwrite an existing one unless `force`
+/// is set, and prints where it was written, as JSON if `json` is set.
+pub(crate) fn install_service(user: bool, force: bool, json: bool) -> anyhow::Result<()> {
+    let exe = std::env::current_exe().context("could not determine the g935 executable")?;
+    let path = unit_path(user)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("could not create {}", dir.display()))?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(&path)
+        .with_context(|| format!("could not create {}", path.display()))?;
+    file.write_all(unit(&exe, user).as_bytes())?;
+
+    if json {
+        output::print_json(&serde_json::json!({ "path": path, "written": true }));
+
+        return Ok(());
+    }
+
+    let systemctl = if user {
+        "systemctl --user"
+    } else {
+        "systemctl"
+    };
+    println!("wrote {}", path.display());
+    println!("enable it using `{systemctl} daemon-reload && {systemctl} enable --now g935`");
+
+    Ok(())
+}
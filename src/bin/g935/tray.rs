@@ -0,0 +1,227 @@
+//! The system tray icon of the daemon.
+
+use crossbeam_channel::Receiver;
+use g935::{
+    lights::{Color, Effect, Light},
+    BatteryStatus, ChargingStatus, PowerState,
+};
+use ksni::{
+    blocking::TrayMethods as _,
+    menu::{CheckmarkItem, RadioGroup, RadioItem, StandardItem, SubMenu},
+    MenuItem, ToolTip,
+};
+
+use crate::{
+    config_file,
+    daemon::{Client, Event, Published, Request, Response},
+};
+
+/// The sidetone levels in percent that can be selected in the menu.
+const SIDETONE_LEVELS: [u8; 5] = [0, 25, 50, 75, 100];
+
+/// The state shown by the tray icon.
+#[derive(Debug)]
+struct HeadsetTray {
+    /// The client sending the requests to the daemon.
+    client: Client,
+    /// The last known battery status.
+    battery: Option<BatteryStatus>,
+    /// Whether the headset is turned on.
+    connected: bool,
+    /// The last set sidetone level.
+    sidetone: Option<u8>,
+    /// Whether the lights are turned on.
+    lights_on: bool,
+    /// The effects of the side and logo lights restored when turning the lights on.
+    effects: (Effect, Effect),
+}
+
+impl HeadsetTray {
+    /// Sends the request to the daemon, logging failures.
+    fn request(&self, request: Request) -> bool {
+        match self.client.request(request) {
+            Ok(_) => true,
+            Err(err) => {
+                log::warn!("could not perform the tray action: {err:#}");
+                false
+            }
+        }
+    }
+
+    /// Turns the lights on or off.
+    fn set_lights(&mut self, on: bool) {
+        let (side, logo) = if on {
+            self.effects
+        } else {
+            (Effect::Off, Effect::Off)
+        };
+
+        let side = self.request(Request::SetLights {
+            light: Light::Side,
+            effect: side,
+        });
+        let logo = self.request(Request::SetLights {
+            light: Light::Logo,
+            effect: logo,
+        });
+
+        if side && logo {
+            self.lights_on = on;
+        }
+    }
+
+    /// Returns the description of the battery status.
+    fn battery_text(&self) -> String {
+        match self.battery {
+            Some(status) if self.connected => {
+                format!("{}% ({})", status.percent(), status.charging_status)
+            }
+            _ => "turned off".to_string(),
+        }
+    }
+}
+
+impl ksni::Tray for HeadsetTray {
+    fn id(&self) -> String {
+        "g935".to_string()
+    }
+
+    fn title(&self) -> String {
+        format!("G935: {}", self.battery_text())
+    }
+
+    fn icon_name(&self) -> String {
+        let Some(status) = self.battery.filter(|_| self.connected) else {
+            return "audio-headset".to_string();
+        };
+
+        let level = match status.charge {
+            charge if charge >= 80.0 => "full",
+            charge if charge >= 40.0 => "good",
+            charge if charge >= 20.0 => "low",
+            charge if charge >= 5.0 => "caution",
+            _ => "empty",
+        };
+        match status.charging_status {
+            ChargingStatus::Discharging => format!("battery-{level}"),
+            ChargingStatus::Charging => format!("battery-{level}-charging"),
+            ChargingStatus::Full => "battery-full-charged".to_string(),
+        }
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        ToolTip {
+            icon_name: "audio-headset".to_string(),
+            title: "Logitech G935".to_string(),
+            description: self.battery_text(),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        vec![
+            StandardItem {
+                label: format!("Battery: {}", self.battery_text()),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            CheckmarkItem {
+                label: "Lights".to_string(),
+                enabled: self.connected,
+                checked: self.lights_on,
+                activate: Box::new(|this: &mut Self| this.set_lights(!this.lights_on)),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Sidetone".to_string(),
+                enabled: self.connected,
+                submenu: vec![RadioGroup {
+                    // An unknown level selects nothing, as the index is out of range
+                    selected: SIDETONE_LEVELS
+                        .iter()
+                        .position(|&level| Some(level) == self.sidetone)
+                        .unwrap_or(SIDETONE_LEVELS.len()),
+                    select: Box::new(|this: &mut Self, index| {
+                        let level = SIDETONE_LEVELS[index];
+
+                        if this.request(Request::SetSidetone { level }) {
+                            this.sidetone = Some(level);
+                        }
+                    }),
+                    options: SIDETONE_LEVELS
+                        .iter()
+                        .map(|level| RadioItem {
+                            label: format!("{level}%"),
+                            ..Default::default()
+                        })
+                        .collect(),
+                }
+                .into()],
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Quit".to_string(),
+                icon_name: "application-exit".to_string(),
+                activate: Box::new(|_| std::process::exit(0)),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
+}
+
+/// Shows the tray icon, keeping it up to date with the events.
+pub(crate) fn serve(
+    client: Client,
+    events: Receiver<Published>,
+    config_file: &config_file::ConfigFile,
+) -> anyhow::Result<()> {
+    let lights = &config_file.lights;
+    let lights_on = (lights.side, lights.logo) != (Effect::Off, Effect::Off);
+    // Turning the lights on should show something, even if they are configured to be off
+    let effects = match (lights.side, lights.logo) {
+        (Effect::Off, Effect::Off) => {
+            (Effect::static_color(Color::new(255, 255, 255)), Effect::Off)
+        }
+        effects => effects,
+    };
+
+    let tray = HeadsetTray {
+        client: client.clone(),
+        battery: None,
+        connected: true,
+        sidetone: config_file.sidetone,
+        lights_on,
+        effects,
+    };
+    let handle = tray.spawn()?;
+
+    std::thread::spawn(move || {
+        // The daemon only answers once the headset runs, so this must not block the caller
+        if let Ok(Response::Battery(status)) = client.request(Request::GetBattery) {
+            handle.update(|tray| tray.battery = Some(status));
+        }
+        if let Ok(Response::Sidetone(level)) = client.request(Request::GetSidetone) {
+            handle.update(|tray| tray.sidetone = Some(level));
+        }
+
+        for Published { event, .. } in events {
+            match event {
+                Event::Battery(status) => {
+                    handle.update(|tray| tray.battery = Some(status));
+                }
+                Event::PowerState(power_state) => {
+                    handle.update(|tray| tray.connected = power_state == PowerState::Connected);
+                }
+                Event::Button(_) | Event::Profile(_) => (),
+            }
+        }
+    });
+
+    Ok(())
+}
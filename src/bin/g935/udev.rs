@@ -0,0 +1,61 @@
+//! Installs the udev rule giving the logged in user access to the headset.
+
+use std::{io::Write as _, path::Path};
+
+use anyhow::Context as _;
+
+use crate::output;
+
+/// Where the rule is installed.
+///
+/// The number needs to be below 73, so that the rule runs before the one applying `uaccess`.
+const RULES_PATH: &str = "/etc/udev/rules.d/70-g935.rules";
+
+/// Returns the contents of the rules file.
+fn rules() -> String {
+    format!(
+        "# Gives the logged in user access to the Logitech G935 headset, written by g935\n{}\n",
+        g935::UDEV_RULES
+    )
+}
+
+/// Writes the udev rule, or only prints what would be written if `dry_run` is set, as JSON if
+/// `json` is set.
+pub(crate) fn install_rules(dry_run: bool, json: bool) -> anyhow::Result<()> {
+    let path = Path::new(RULES_PATH);
+
+    if dry_run && json {
+        output::print_json(&serde_json::json!({
+            "path": path,
+            "written": false,
+            "content": rules(),
+        }));
+
+        return Ok(());
+    } else if dry_run {
+        println!("would write {}:", path.display());
+        print!("{}", rules());
+
+        return Ok(());
+    }
+
+    let mut file = std::fs::File::create(path).with_context(|| {
+        format!(
+            "could not create {}, try running as root or use --dry-run",
+            path.display()
+        )
+    })?;
+    file.write_all(rules().as_bytes())?;
+
+    if json {
+        output::print_json(&serde_json::json!({ "path": path, "written": true }));
+
+        return Ok(());
+    }
+    println!("wrote {}", path.display());
+    println!(
+        "apply it using `udevadm control --reload && udevadm trigger`, or replug the receiver"
+    );
+
+    Ok(())
+}
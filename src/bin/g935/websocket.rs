@@ -0,0 +1,108 @@
+//! The WebSocket endpoint of the daemon, for browser-based dashboards and overlays.
+//!
+//! Every connection receives the events as text messages like `{"event":{"button":...}}`, using
+//! the format of the Unix socket. Requests such as `{"request":"set_sidetone","level":30}` are
+//! answered with `{"ok":...}` or `{"error":"..."}` in between.
+
+use std::{
+    io::ErrorKind,
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    config_file,
+    daemon::{Client, Publisher, Request},
+    socket::Reply,
+};
+
+/// How long reading a request waits before the pending events are sent.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Sends the reply as a text message.
+fn send(socket: &mut WebSocket<TcpStream>, reply: &Reply) -> anyhow::Result<()> {
+    socket.send(Message::text(serde_json::to_string(reply)?))?;
+
+    Ok(())
+}
+
+/// Serves one connection, forwarding the events and answering the requests.
+fn serve_connection(
+    stream: TcpStream,
+    client: &Client,
+    publisher: &Publisher,
+) -> anyhow::Result<()> {
+    let mut socket = tungstenite::accept(stream).context("handshake failed")?;
+    // The socket is read with a timeout, so that the events are not held up by a quiet client
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    let events = publisher.subscribe();
+
+    loop {
+        for event in events.try_iter() {
+            send(&mut socket, &Reply::Event(event))?;
+        }
+
+        let message = match socket.read() {
+            Ok(message) => message,
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue;
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let reply = match message {
+            Message::Text(text) => match serde_json::from_str::<Request>(&text) {
+                Ok(request) => match client.request(request) {
+                    Ok(response) => Reply::Ok(response),
+                    Err(err) => Reply::Error(format!("{err:#}")),
+                },
+                Err(err) => Reply::Error(format!("invalid request: {err}")),
+            },
+            Message::Close(_) => return Ok(()),
+            // Pings are answered by tungstenite
+            _ => continue,
+        };
+
+        send(&mut socket, &reply)?;
+    }
+}
+
+/// Listens for WebSocket connections as configured, serving each connection in its own thread.
+pub(crate) fn serve(
+    options: &config_file::WebSocket,
+    client: Client,
+    publisher: Publisher,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&options.bind)
+        .with_context(|| format!("could not listen on {}", options.bind))?;
+
+    log::info!("accepting WebSocket connections on {}", options.bind);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("could not accept WebSocket connection: {err}");
+                    continue;
+                }
+            };
+
+            let client = client.clone();
+            let publisher = publisher.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = serve_connection(stream, &client, &publisher) {
+                    log::debug!("WebSocket connection closed: {err:#}");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,902 @@
+//! Bindings of shell commands to button events.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::Read,
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, OnceLock},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    battery::BATTERY_MAX_AGE,
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonState, Event, EventKind, Headset,
+};
+
+/// A button event that can trigger a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Trigger {
+    /// The G1 key was pressed.
+    G1Pressed,
+    /// The G1 key was released.
+    G1Released,
+    /// The G1 key has been held for the long press threshold.
+    G1LongPressed,
+    /// The G1 key was pressed within the double press window after its previous press.
+    G1DoublePressed,
+    /// The G2 key was pressed.
+    G2Pressed,
+    /// The G2 key was released.
+    G2Released,
+    /// The G2 key has been held for the long press threshold.
+    G2LongPressed,
+    /// The G2 key was pressed within the double press window after its previous press.
+    G2DoublePressed,
+    /// The G3 key was pressed.
+    G3Pressed,
+    /// The G3 key was released.
+    G3Released,
+    /// The G3 key has been held for the long press threshold.
+    G3LongPressed,
+    /// The G3 key was pressed within the double press window after its previous press.
+    G3DoublePressed,
+    /// The G1 and G2 keys were pressed together, within the chord window.
+    G1G2Pressed,
+    /// The G1 and G3 keys were pressed together, within the chord window.
+    G1G3Pressed,
+    /// The G2 and G3 keys were pressed together, within the chord window.
+    G2G3Pressed,
+    /// All G keys were pressed together, within the chord window.
+    G1G2G3Pressed,
+    /// The microphone was flipped up.
+    MicFlippedUp,
+    /// The microphone was flipped down.
+    MicFlippedDown,
+    /// The wheel is being scrolled up.
+    ScrollUp,
+    /// The wheel is being scrolled down.
+    ScrollDown,
+    /// Scrolling up started or continued for another repeat interval.
+    ScrollUpRepeated,
+    /// Scrolling down started or continued for another repeat interval.
+    ScrollDownRepeated,
+    /// The scrolling ended.
+    ScrollEnd,
+    /// The mute button was pressed.
+    MutePressed,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Trigger::G1Pressed => "g1_pressed",
+            Trigger::G1Released => "g1_released",
+            Trigger::G1LongPressed => "g1_long_pressed",
+            Trigger::G1DoublePressed => "g1_double_pressed",
+            Trigger::G2Pressed => "g2_pressed",
+            Trigger::G2Released => "g2_released",
+            Trigger::G2LongPressed => "g2_long_pressed",
+            Trigger::G2DoublePressed => "g2_double_pressed",
+            Trigger::G3Pressed => "g3_pressed",
+            Trigger::G3Released => "g3_released",
+            Trigger::G3LongPressed => "g3_long_pressed",
+            Trigger::G3DoublePressed => "g3_double_pressed",
+            Trigger::G1G2Pressed => "g1_g2_pressed",
+            Trigger::G1G3Pressed => "g1_g3_pressed",
+            Trigger::G2G3Pressed => "g2_g3_pressed",
+            Trigger::G1G2G3Pressed => "g1_g2_g3_pressed",
+            Trigger::MicFlippedUp => "mic_flipped_up",
+            Trigger::MicFlippedDown => "mic_flipped_down",
+            Trigger::ScrollUp => "scroll_up",
+            Trigger::ScrollDown => "scroll_down",
+            Trigger::ScrollUpRepeated => "scroll_up_repeated",
+            Trigger::ScrollDownRepeated => "scroll_down_repeated",
+            Trigger::ScrollEnd => "scroll_end",
+            Trigger::MutePressed => "mute_pressed",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl Trigger {
+    /// Returns `true` if the trigger is a chord of several G keys.
+    pub fn is_chord(&self) -> bool {
+        matches!(
+            self,
+            Trigger::G1G2Pressed
+                | Trigger::G1G3Pressed
+                | Trigger::G2G3Pressed
+                | Trigger::G1G2G3Pressed
+        )
+    }
+
+    /// Returns the chord of the G keys marked as pressed, if at least two are.
+    fn chord(keys: [bool; 3]) -> Option<Trigger> {
+        match keys {
+            [true, true, false] => Some(Trigger::G1G2Pressed),
+            [true, false, true] => Some(Trigger::G1G3Pressed),
+            [false, true, true] => Some(Trigger::G2G3Pressed),
+            [true, true, true] => Some(Trigger::G1G2G3Pressed),
+            _ => None,
+        }
+    }
+}
+
+/// The placeholders that can be used in commands.
+///
+/// The battery placeholders cause a battery status request when the command is run.
+const PLACEHOLDERS: &[&str] = &[
+    "{trigger}",
+    "{battery_percent}",
+    "{battery_voltage}",
+    "{charging_status}",
+];
+
+/// The default minimum time between two runs of the same binding.
+const DEFAULT_RATE_LIMIT: Duration = Duration::from_millis(50);
+
+/// The default time a key needs to be held to count as a long press.
+const DEFAULT_LONG_PRESS_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// The default time within which a second press counts as a double press.
+const DEFAULT_DOUBLE_PRESS_WINDOW: Duration = Duration::from_millis(300);
+
+/// The default time between two repeats while the wheel is scrolled.
+const DEFAULT_SCROLL_REPEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Detects the triggers that fire for successive button states.
+#[derive(Debug)]
+pub struct TriggerDetector {
+    /// The time a key needs to be held to count as a long press.
+    long_press_threshold: Duration,
+    /// The time within which a second press counts as a double press.
+    double_press_window: Duration,
+    /// The time between two repeats while the wheel is scrolled.
+    scroll_repeat_interval: Duration,
+    /// The time within which presses of several G keys form a chord, if chords are detected.
+    chord_window: Option<Duration>,
+    /// When the first of the pending presses occurred.
+    pending_since: Option<Instant>,
+    /// The G keys whose press is held back until it is known whether they form a chord, with
+    /// whether the press was a double press.
+    pending: [Option<bool>; 3],
+    /// The G keys that are part of a chord and have not been released yet.
+    chorded: [bool; 3],
+    /// The button state of the previous event.
+    old_state: ButtonState,
+}
+
+impl Default for TriggerDetector {
+    fn default() -> Self {
+        Self {
+            long_press_threshold: DEFAULT_LONG_PRESS_THRESHOLD,
+            double_press_window: DEFAULT_DOUBLE_PRESS_WINDOW,
+            scroll_repeat_interval: DEFAULT_SCROLL_REPEAT_INTERVAL,
+            chord_window: None,
+            pending_since: None,
+            pending: [None; 3],
+            chorded: [false; 3],
+            old_state: ButtonState::default(),
+        }
+    }
+}
+
+impl TriggerDetector {
+    /// Creates a detector that has not seen any button state yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time a key needs to be held to count as a long press.
+    ///
+    /// The default is 500 ms.
+    pub fn set_long_press_threshold(&mut self, threshold: Duration) {
+        self.long_press_threshold = threshold;
+    }
+
+    /// Sets the time within which a second press counts as a double press.
+    ///
+    /// The default is 300 ms.
+    pub fn set_double_press_window(&mut self, window: Duration) {
+        self.double_press_window = window;
+    }
+
+    /// Sets the time between two repeats while the wheel is scrolled.
+    ///
+    /// The default is 100 ms.
+    pub fn set_scroll_repeat_interval(&mut self, interval: Duration) {
+        self.scroll_repeat_interval = interval;
+    }
+
+    /// Sets the time within which presses of several G keys form a chord, or `None` to not
+    /// detect chords.
+    ///
+    /// While chords are detected, the press of a G key is held back for the window, so that the
+    /// keys of a chord do not also fire their own triggers. Chords are not detected by default.
+    pub fn set_chord_window(&mut self, window: Option<Duration>) {
+        self.chord_window = window;
+    }
+
+    /// Fires the held back presses, either as a chord or as individual presses.
+    fn resolve_pending(
+        &mut self,
+        [press, double_press]: [[Trigger; 3]; 2],
+        fired: &mut Vec<Trigger>,
+    ) {
+        self.pending_since = None;
+        let pending = std::mem::take(&mut self.pending);
+
+        match Trigger::chord(pending.map(|pending| pending.is_some())) {
+            Some(chord) => {
+                for (chorded, pending) in self.chorded.iter_mut().zip(pending) {
+                    *chorded |= pending.is_some();
+                }
+                fired.push(chord);
+            }
+            None => {
+                for (index, pending) in pending.into_iter().enumerate() {
+                    if let Some(double_pressed) = pending {
+                        fired.push(press[index]);
+                        if double_pressed {
+                            fired.push(double_press[index]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the triggers that fire for the given button state.
+    pub fn detect(&mut self, state: ButtonState) -> Vec<Trigger> {
+        self.detect_at(state, Instant::now())
+    }
+
+    /// Returns the triggers that fire for the button state, which is detected at `now`.
+    fn detect_at(&mut self, state: ButtonState, now: Instant) -> Vec<Trigger> {
+        let old = self.old_state;
+        self.old_state = state;
+        let (threshold, window) = (self.long_press_threshold, self.double_press_window);
+        let interval = self.scroll_repeat_interval;
+        let mut fired = Vec::new();
+
+        let press = [Trigger::G1Pressed, Trigger::G2Pressed, Trigger::G3Pressed];
+        let double_press = [
+            Trigger::G1DoublePressed,
+            Trigger::G2DoublePressed,
+            Trigger::G3DoublePressed,
+        ];
+        let long_press = [
+            Trigger::G1LongPressed,
+            Trigger::G2LongPressed,
+            Trigger::G3LongPressed,
+        ];
+        let release = [
+            Trigger::G1Released,
+            Trigger::G2Released,
+            Trigger::G3Released,
+        ];
+
+        let pressed = [
+            state.g1_pressed(&old),
+            state.g2_pressed(&old),
+            state.g3_pressed(&old),
+        ];
+        let double_pressed = [
+            state.g1_double_pressed(&old, window),
+            state.g2_double_pressed(&old, window),
+            state.g3_double_pressed(&old, window),
+        ];
+        let long_pressed = [
+            state.g1_long_pressed(&old, threshold),
+            state.g2_long_pressed(&old, threshold),
+            state.g3_long_pressed(&old, threshold),
+        ];
+        let released = [
+            state.g1_released(&old),
+            state.g2_released(&old),
+            state.g3_released(&old),
+        ];
+
+        for index in 0..3 {
+            if !pressed[index] {
+                continue;
+            }
+
+            if self.chord_window.is_some() {
+                self.pending[index] = Some(double_pressed[index]);
+                self.pending_since.get_or_insert(now);
+            } else {
+                fired.push(press[index]);
+                if double_pressed[index] {
+                    fired.push(double_press[index]);
+                }
+            }
+        }
+
+        // A chord is complete once its window passed or one of its keys is released
+        let window_passed = match (self.pending_since, self.chord_window) {
+            (Some(since), Some(chord_window)) => now.duration_since(since) >= chord_window,
+            _ => false,
+        };
+        let pending_released = (0..3).any(|index| self.pending[index].is_some() && released[index]);
+        if window_passed || pending_released {
+            self.resolve_pending([press, double_press], &mut fired);
+        }
+
+        for index in 0..3 {
+            if self.chorded[index] || self.pending[index].is_some() {
+                if released[index] {
+                    self.chorded[index] = false;
+                }
+                continue;
+            }
+
+            if long_pressed[index] {
+                fired.push(long_press[index]);
+            }
+            if released[index] {
+                fired.push(release[index]);
+            }
+        }
+
+        let others = [
+            (state.mic_flipped_up(&old), Trigger::MicFlippedUp),
+            (state.mic_flipped_down(&old), Trigger::MicFlippedDown),
+            (state.scroll_up(), Trigger::ScrollUp),
+            (state.scroll_down(), Trigger::ScrollDown),
+            (
+                state.scroll_up_repeated(&old, interval),
+                Trigger::ScrollUpRepeated,
+            ),
+            (
+                state.scroll_down_repeated(&old, interval),
+                Trigger::ScrollDownRepeated,
+            ),
+            (state.scroll_end(&old), Trigger::ScrollEnd),
+            (state.mute_button_pressed(), Trigger::MutePressed),
+        ];
+
+        fired.extend(
+            others
+                .into_iter()
+                .filter_map(|(fires, trigger)| fires.then_some(trigger)),
+        );
+
+        fired
+    }
+}
+
+/// Runs shell commands when button events occur.
+///
+/// Commands are run using `sh -c` without waiting for them to finish. They can contain the
+/// placeholders `{trigger}`, `{battery_percent}`, `{battery_voltage}` and `{charging_status}`.
+#[derive(Debug)]
+pub struct Bindings {
+    /// The command to run for each trigger.
+    commands: BTreeMap<Trigger, String>,
+    /// The minimum time between two runs of the same binding.
+    rate_limit: Duration,
+    /// The last time each binding was run.
+    last_run: HashMap<Trigger, Instant>,
+    /// The detector for the triggers of the bindings.
+    detector: TriggerDetector,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            rate_limit: DEFAULT_RATE_LIMIT,
+            last_run: HashMap::new(),
+            detector: TriggerDetector::new(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Creates an empty set of bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the command to the trigger, replacing any previous binding of the trigger.
+    pub fn bind(&mut self, trigger: Trigger, command: impl Into<String>) {
+        self.commands.insert(trigger, command.into());
+    }
+
+    /// Removes the binding of the trigger.
+    pub fn unbind(&mut self, trigger: Trigger) {
+        self.commands.remove(&trigger);
+    }
+
+    /// Returns `true` if no command is bound.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Sets the minimum time between two runs of the same binding.
+    ///
+    /// Events occurring faster than that are dropped. The default is 50 ms.
+    pub fn set_rate_limit(&mut self, rate_limit: Duration) {
+        self.rate_limit = rate_limit;
+    }
+
+    /// Sets the time a key needs to be held to count as a long press.
+    ///
+    /// The default is 500 ms.
+    pub fn set_long_press_threshold(&mut self, threshold: Duration) {
+        self.detector.set_long_press_threshold(threshold);
+    }
+
+    /// Sets the time within which a second press counts as a double press.
+    ///
+    /// The default is 300 ms.
+    pub fn set_double_press_window(&mut self, window: Duration) {
+        self.detector.set_double_press_window(window);
+    }
+
+    /// Sets the time between two repeats while the wheel is scrolled.
+    ///
+    /// The default is 100 ms.
+    pub fn set_scroll_repeat_interval(&mut self, interval: Duration) {
+        self.detector.set_scroll_repeat_interval(interval);
+    }
+
+    /// Sets the time within which presses of several G keys form a chord, or `None` to not
+    /// detect chords.
+    ///
+    /// Chords are not detected by default.
+    pub fn set_chord_window(&mut self, window: Option<Duration>) {
+        self.detector.set_chord_window(window);
+    }
+
+    /// Runs the commands bound to the events that led to the given button state.
+    pub fn handle(&mut self, headset: &mut Headset, state: ButtonState) {
+        for trigger in self.detector.detect(state) {
+            self.run(trigger, headset);
+        }
+    }
+
+    /// Runs the command bound to the trigger, if there is one and the rate limit allows it.
+    ///
+    /// This is useful when the triggers are detected elsewhere, otherwise use
+    /// [`Bindings::handle`].
+    pub fn run(&mut self, trigger: Trigger, headset: &mut Headset) {
+        let Some(command) = self.commands.get(&trigger) else {
+            return;
+        };
+
+        let now = Instant::now();
+        if let Some(last_run) = self.last_run.get(&trigger) {
+            if now.duration_since(*last_run) < self.rate_limit {
+                log::debug!("dropping {trigger} because of the rate limit");
+                return;
+            }
+        }
+        self.last_run.insert(trigger, now);
+
+        let command = expand_placeholders(command, trigger, headset);
+        spawn_shell_command(command);
+    }
+
+    /// Turns the bindings into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, headset, state| self.handle(headset, state))
+    }
+}
+
+impl Plugin for Bindings {
+    fn on_event(&mut self, _: &mut RunConfig, headset: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            self.handle(headset, state);
+        }
+    }
+}
+
+/// Replaces the placeholders in the command.
+fn expand_placeholders(command: &str, trigger: Trigger, headset: &mut Headset) -> String {
+    if !PLACEHOLDERS
+        .iter()
+        .any(|placeholder| command.contains(placeholder))
+    {
+        return command.to_string();
+    }
+
+    let mut command = command.replace("{trigger}", &trigger.to_string());
+
+    if command.contains("{battery_") || command.contains("{charging_status}") {
+        match headset.battery_status_cached(BATTERY_MAX_AGE) {
+            Ok(status) => {
+                command = command
+                    .replace(
+                        "{battery_percent}",
+                        &format!("{}", status.charge.round() as i32),
+                    )
+                    .replace("{battery_voltage}", &status.voltage.to_string())
+                    .replace("{charging_status}", &status.charging_status.to_string());
+            }
+            Err(err) => log::warn!("failed to get battery status for {trigger}: {err}"),
+        }
+    }
+
+    command
+}
+
+/// Runs the command using the shell without waiting for it.
+pub(crate) fn spawn_shell_command(command: String) {
+    log::debug!("running {command:?}");
+
+    let child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .spawn();
+
+    match child {
+        // Wait in the background so that the child does not linger as a zombie
+        Ok(mut child) => {
+            std::thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => log::warn!("{command:?} failed with {status}"),
+                Ok(_) => (),
+                Err(err) => log::warn!("could not wait for {command:?}: {err}"),
+            });
+        }
+        Err(err) => log::warn!("could not run {command:?}: {err}"),
+    }
+}
+
+/// The number of threads running the commands of [`spawn_command`].
+const COMMAND_WORKERS: usize = 4;
+
+/// How often a running command is checked for having exited.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the output of a command is still read after it exited, while processes it started
+/// in the background keep the pipes open.
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// The number of bytes of each output stream kept by [`spawn_command`].
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// A command waiting for a worker of [`spawn_command`].
+type CommandTask = Box<dyn FnOnce() + Send>;
+
+/// The outcome of a command run with [`spawn_command`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// How the command exited, which is after it was killed if it timed out.
+    pub status: ExitStatus,
+    /// Whether the command was killed because it ran for too long.
+    pub timed_out: bool,
+    /// The start of the standard output of the command, up to 64 KiB.
+    pub stdout: Vec<u8>,
+    /// The start of the standard error of the command, up to 64 KiB.
+    pub stderr: Vec<u8>,
+}
+
+/// Runs the command on a background worker, killing it after `timeout`, and calls `on_finish`
+/// with its output on the worker.
+///
+/// Handlers run on the loop polling the headset, so waiting for a command there delays all
+/// events following it. This runs the command without waiting, capturing its output. Up to 4
+/// commands run at the same time, the others wait for a worker. To use the output in the loop,
+/// send it through a channel and wake the loop with a [`Waker`](crate::Waker), or use a
+/// [`HeadsetHandle`](crate::HeadsetHandle).
+///
+/// The standard input of the command is closed. Processes the command starts in the background
+/// are not waited for, and their output after the command exited is discarded. If the command
+/// cannot be started, `on_finish` is called with the error.
+pub fn spawn_command(
+    mut command: Command,
+    timeout: Duration,
+    on_finish: impl FnOnce(anyhow::Result<CommandOutput>) + Send + 'static,
+) {
+    static WORKERS: OnceLock<Sender<CommandTask>> = OnceLock::new();
+
+    let workers = WORKERS.get_or_init(|| {
+        let (sender, receiver) = crossbeam_channel::unbounded::<CommandTask>();
+        for _ in 0..COMMAND_WORKERS {
+            let receiver = receiver.clone();
+            std::thread::spawn(move || {
+                for task in receiver {
+                    task();
+                }
+            });
+        }
+
+        sender
+    });
+
+    let task = Box::new(move || on_finish(run_command(&mut command, timeout)));
+    // The workers never stop, so the channel stays open
+    workers.send(task).ok();
+}
+
+/// Runs the command, killing it after `timeout`, and captures its output.
+fn run_command(command: &mut Command, timeout: Duration) -> anyhow::Result<CommandOutput> {
+    log::debug!("running {command:?} with a timeout of {timeout:?}");
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Killing the group also kills the processes started by the command, which would otherwise
+    // keep the pipes open
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("could not run {command:?}: {err}"))?;
+    // The pipes are read while waiting, as the command blocks once their buffers are full
+    let (stdout, stdout_reader) = capture(child.stdout.take());
+    let (stderr, stderr_reader) = capture(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if !timed_out && Instant::now() >= deadline {
+            log::warn!("killing {command:?}, which ran for more than {timeout:?}");
+            timed_out = true;
+            kill(&mut child)?;
+        }
+        std::thread::sleep(COMMAND_POLL_INTERVAL);
+    };
+
+    // Processes the command started in the background may keep the pipes open for much longer,
+    // so the output they write afterwards is left to the readers to discard
+    let stop_reading = deadline.min(Instant::now() + OUTPUT_GRACE_PERIOD);
+    while !(stdout_reader.is_finished() && stderr_reader.is_finished())
+        && Instant::now() < stop_reading
+    {
+        std::thread::sleep(COMMAND_POLL_INTERVAL);
+    }
+
+    let stdout = std::mem::take(&mut *stdout.lock().unwrap());
+    let stderr = std::mem::take(&mut *stderr.lock().unwrap());
+
+    Ok(CommandOutput {
+        status,
+        timed_out,
+        stdout,
+        stderr,
+    })
+}
+
+/// Kills the child together with the processes it started.
+fn kill(child: &mut std::process::Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // SAFETY: killing a process group has no memory safety requirements
+        if unsafe { libc::kill(-pid, libc::SIGKILL) } == 0 {
+            return Ok(());
+        }
+    }
+
+    child.kill()
+}
+
+/// Reads the start of the output stream into the returned buffer in the background, discarding
+/// the rest so that the writer is not blocked.
+fn capture(stream: Option<impl Read + Send + 'static>) -> (Arc<Mutex<Vec<u8>>>, JoinHandle<()>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+
+    let reader = std::thread::spawn({
+        let output = Arc::clone(&output);
+        move || {
+            let Some(mut stream) = stream else {
+                return;
+            };
+
+            let mut buf = [0; 4096];
+            loop {
+                match stream.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => {
+                        let mut output = output.lock().unwrap();
+                        let kept = len.min(MAX_CAPTURED_OUTPUT.saturating_sub(output.len()));
+                        output.extend_from_slice(&buf[..kept]);
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(err) => {
+                        log::debug!("could not read the output of a command: {err}");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    (output, reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons::{Buttons, Wheel};
+
+    /// Feeds the button states reported at the given milliseconds after the start to a detector.
+    struct Session {
+        /// The detector under test.
+        detector: TriggerDetector,
+        /// The state reported last.
+        state: ButtonState,
+        /// The time the milliseconds are counted from.
+        start: Instant,
+    }
+
+    impl Session {
+        /// Creates a session without any held keys.
+        fn new(detector: TriggerDetector) -> Self {
+            Self {
+                detector,
+                state: ButtonState::default(),
+                start: Instant::now(),
+            }
+        }
+
+        /// Returns the time `ms` milliseconds after the start.
+        fn at(&self, ms: u64) -> Instant {
+            self.start + Duration::from_millis(ms)
+        }
+
+        /// Reports the G keys held at `ms`, returning the fired triggers.
+        fn keys(&mut self, ms: u64, [g1, g2, g3]: [bool; 3]) -> Vec<Trigger> {
+            let now = self.at(ms);
+            self.state.update_buttons(Buttons { g1, g2, g3 }, now);
+            self.detector.detect_at(self.state.at(now), now)
+        }
+
+        /// Reports the direction of the wheel at `ms`, returning the fired triggers.
+        fn wheel(&mut self, ms: u64, up: bool, down: bool) -> Vec<Trigger> {
+            let now = self.at(ms);
+            self.state.update_wheel(Wheel { up, down }, now);
+            self.detector.detect_at(self.state.at(now), now)
+        }
+
+        /// Repeats the last state at `ms`, like the loop does while a key is held.
+        fn tick(&mut self, ms: u64) -> Vec<Trigger> {
+            let now = self.at(ms);
+            self.detector.detect_at(self.state.tick(now), now)
+        }
+    }
+
+    /// Only the G1 key.
+    const G1: [bool; 3] = [true, false, false];
+
+    /// No G key.
+    const NONE: [bool; 3] = [false; 3];
+
+    #[test]
+    fn detects_presses_and_releases() {
+        let mut session = Session::new(TriggerDetector::new());
+
+        assert_eq!(session.keys(0, G1), [Trigger::G1Pressed]);
+        assert_eq!(session.tick(20), []);
+        assert_eq!(session.keys(40, NONE), [Trigger::G1Released]);
+        assert_eq!(
+            session.keys(1000, [false, true, true]),
+            [Trigger::G2Pressed, Trigger::G3Pressed]
+        );
+    }
+
+    #[test]
+    fn detects_double_presses_within_the_window() {
+        let mut session = Session::new(TriggerDetector::new());
+
+        session.keys(0, G1);
+        session.keys(50, NONE);
+        assert_eq!(
+            session.keys(300, G1),
+            [Trigger::G1Pressed, Trigger::G1DoublePressed]
+        );
+        session.keys(350, NONE);
+        // 400 ms after the previous press, outside of the 300 ms window
+        assert_eq!(session.keys(700, G1), [Trigger::G1Pressed]);
+    }
+
+    #[test]
+    fn detects_long_presses_while_held() {
+        let mut session = Session::new(TriggerDetector::new());
+
+        session.keys(0, G1);
+        assert_eq!(session.tick(480), []);
+        assert_eq!(session.tick(500), [Trigger::G1LongPressed]);
+        // Fires only once per press
+        assert_eq!(session.tick(520), []);
+        assert_eq!(session.tick(1000), []);
+        assert_eq!(session.keys(1020, NONE), [Trigger::G1Released]);
+
+        // A short press does not count as a long press
+        session.keys(2000, G1);
+        assert_eq!(session.keys(2100, NONE), [Trigger::G1Released]);
+        assert_eq!(session.tick(2600), []);
+    }
+
+    #[test]
+    fn repeats_scrolling_at_the_interval() {
+        let mut session = Session::new(TriggerDetector::new());
+
+        assert_eq!(
+            session.wheel(0, true, false),
+            [Trigger::ScrollUp, Trigger::ScrollUpRepeated]
+        );
+        assert_eq!(session.tick(50), []);
+        assert_eq!(session.tick(100), [Trigger::ScrollUpRepeated]);
+        assert_eq!(session.tick(150), []);
+        assert_eq!(session.tick(210), [Trigger::ScrollUpRepeated]);
+        assert_eq!(session.wheel(250, false, false), [Trigger::ScrollEnd]);
+        assert_eq!(
+            session.wheel(300, false, true),
+            [Trigger::ScrollDown, Trigger::ScrollDownRepeated]
+        );
+    }
+
+    /// Returns a detector with a chord window of 50 ms.
+    fn chord_detector() -> TriggerDetector {
+        let mut detector = TriggerDetector::new();
+        detector.set_chord_window(Some(Duration::from_millis(50)));
+
+        detector
+    }
+
+    #[test]
+    fn fires_only_the_chord_for_keys_pressed_within_the_window() {
+        let mut session = Session::new(chord_detector());
+
+        assert_eq!(session.keys(0, G1), []);
+        assert_eq!(session.keys(20, [true, true, false]), []);
+        assert_eq!(session.tick(40), []);
+        assert_eq!(session.tick(60), [Trigger::G1G2Pressed]);
+        // Neither the keys of the chord nor their releases fire on their own
+        assert_eq!(session.tick(600), []);
+        assert_eq!(session.keys(700, [false, true, false]), []);
+        assert_eq!(session.keys(720, NONE), []);
+
+        // The next press is not part of the chord anymore
+        session.keys(1000, G1);
+        assert_eq!(session.tick(1050), [Trigger::G1Pressed]);
+        assert_eq!(session.keys(1100, NONE), [Trigger::G1Released]);
+    }
+
+    #[test]
+    fn fires_a_lone_press_after_the_chord_window() {
+        let mut session = Session::new(chord_detector());
+
+        assert_eq!(session.keys(0, G1), []);
+        assert_eq!(session.tick(30), []);
+        assert_eq!(session.tick(50), [Trigger::G1Pressed]);
+        assert_eq!(session.keys(100, NONE), [Trigger::G1Released]);
+
+        // Releasing the key resolves the press before the window passed
+        session.keys(1000, [false, false, true]);
+        assert_eq!(
+            session.keys(1010, NONE),
+            [Trigger::G3Pressed, Trigger::G3Released]
+        );
+    }
+
+    #[test]
+    fn fires_the_presses_of_each_key_without_chords() {
+        let mut session = Session::new(TriggerDetector::new());
+
+        assert_eq!(session.keys(0, G1), [Trigger::G1Pressed]);
+        assert_eq!(session.keys(20, [true, true, false]), [Trigger::G2Pressed]);
+        assert_eq!(session.tick(60), []);
+        assert_eq!(
+            session.keys(100, NONE),
+            [Trigger::G1Released, Trigger::G2Released]
+        );
+    }
+}
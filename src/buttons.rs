@@ -1,29 +1,239 @@
 //! Code for interacting with buttons.
 
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
 use crate::FromBytes;
 
 /// A complete map of the state of all buttons.
+///
+/// States compare equal if the buttons, the wheel and the microphone arm are in the same state,
+/// regardless of when they were reported.
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ButtonState {
     /// The state of the buttons.
     pub(crate) buttons: Buttons,
     /// The state of the wheel.
     pub(crate) wheel: Wheel,
-    /// The state of the microphone arm.
-    pub(crate) mic_arm: MicArm,
+    /// The state of the microphone arm, if it was reported since the connection was opened.
+    pub(crate) mic_arm: Option<MicArm>,
     /// Whether the mute button was pressed during the recording of this state.
     pub(crate) mute_button: bool,
+    /// When the buttons changed, as tracked by the run loop.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) timing: Timing,
+    /// Whether this state was not reported by the headset, but repeated while a button is held.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) tick: bool,
+}
+
+impl PartialEq for ButtonState {
+    fn eq(&self, other: &Self) -> bool {
+        self.buttons == other.buttons
+            && self.wheel == other.wheel
+            && self.mic_arm == other.mic_arm
+            && self.mute_button == other.mute_button
+    }
+}
+
+impl Eq for ButtonState {}
+
+/// The times at which the buttons changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Timing {
+    /// When the state was recorded.
+    now: Option<Instant>,
+    /// When each G key was last pressed.
+    pressed_at: [Option<Instant>; 3],
+    /// When each G key was pressed before its last press.
+    previous_press_at: [Option<Instant>; 3],
+    /// When the wheel started scrolling in its current direction.
+    scroll_since: Option<Instant>,
+    /// When the wheel last reported a notch and in which direction, positive being up.
+    last_notch: Option<(Instant, i32)>,
+    /// The time between the last notch and the one before it, if they were close enough in the
+    /// same direction to count as continuous scrolling.
+    notch_interval: Option<Duration>,
 }
 
+/// Notches further apart than this do not count as continuous scrolling.
+const CONTINUOUS_SCROLL_TIMEOUT: Duration = Duration::from_millis(500);
+
 impl ButtonState {
+    /// Sets the state of the G keys reported at `now`.
+    pub(crate) fn update_buttons(&mut self, buttons: Buttons, now: Instant) {
+        for (key, (was_held, is_held)) in [
+            (self.buttons.g1, buttons.g1),
+            (self.buttons.g2, buttons.g2),
+            (self.buttons.g3, buttons.g3),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if !was_held && is_held {
+                self.timing.previous_press_at[key] = self.timing.pressed_at[key].replace(now);
+            }
+        }
+
+        self.buttons = buttons;
+    }
+
+    /// Sets the state of the wheel reported at `now`.
+    pub(crate) fn update_wheel(&mut self, wheel: Wheel, now: Instant) {
+        if !wheel.up && !wheel.down {
+            self.timing.scroll_since = None;
+        } else if (wheel.up, wheel.down) != (self.wheel.up, self.wheel.down) {
+            self.timing.scroll_since = Some(now);
+        }
+
+        // Each report with a direction is one notch of the wheel
+        let direction = i32::from(wheel.up) - i32::from(wheel.down);
+        if direction != 0 {
+            self.timing.notch_interval = match self.timing.last_notch {
+                Some((last, last_direction)) if last_direction == direction => {
+                    Some(now.saturating_duration_since(last))
+                        .filter(|&interval| interval < CONTINUOUS_SCROLL_TIMEOUT)
+                }
+                _ => None,
+            };
+            self.timing.last_notch = Some((now, direction));
+        }
+
+        self.wheel = wheel;
+    }
+
+    /// Returns the state as reported at `now`.
+    pub(crate) fn at(mut self, now: Instant) -> Self {
+        self.timing.now = Some(now);
+        self
+    }
+
+    /// Returns the state as repeated at `now`, without a new report from the headset.
+    pub(crate) fn tick(mut self, now: Instant) -> Self {
+        self.tick = true;
+        self.at(now)
+    }
+
+    /// Returns `true` if a key is held or the wheel is scrolled, so the state should be repeated
+    /// for the timed events.
+    pub(crate) fn is_held(&self) -> bool {
+        self.buttons.g1 || self.buttons.g2 || self.buttons.g3 || self.wheel.up || self.wheel.down
+    }
+
+    /// Returns whether the G key with the index is held.
+    fn key_held(&self, key: usize) -> bool {
+        [self.buttons.g1, self.buttons.g2, self.buttons.g3][key]
+    }
+
+    /// Returns for how long the G key with the index has been held, if it is held.
+    fn held_for(&self, key: usize) -> Option<Duration> {
+        match (self.timing.now, self.timing.pressed_at[key]) {
+            (Some(now), Some(pressed_at)) if self.key_held(key) => {
+                Some(now.saturating_duration_since(pressed_at))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the G key with the index has just been held for the threshold.
+    fn long_pressed(&self, old: &ButtonState, key: usize, threshold: Duration) -> bool {
+        let held_long =
+            |state: &ButtonState| state.held_for(key).is_some_and(|held| held >= threshold);
+
+        held_long(self) && !held_long(old)
+    }
+
+    /// Returns `true` if the G key with the index was pressed within the window after its
+    /// previous press.
+    fn double_pressed(&self, old: &ButtonState, key: usize, window: Duration) -> bool {
+        let pressed = !old.key_held(key) && self.key_held(key);
+
+        match (
+            self.timing.pressed_at[key],
+            self.timing.previous_press_at[key],
+        ) {
+            (Some(press), Some(previous)) if pressed => {
+                press.saturating_duration_since(previous) <= window
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if scrolling in the direction started or continued for another interval.
+    fn scroll_repeated(
+        &self,
+        old: &ButtonState,
+        direction: fn(&Wheel) -> bool,
+        interval: Duration,
+    ) -> bool {
+        let (Some(now), Some(since)) = (self.timing.now, self.timing.scroll_since) else {
+            return false;
+        };
+        if !direction(&self.wheel) {
+            return false;
+        }
+        if !direction(&old.wheel) || old.timing.scroll_since != Some(since) {
+            return true;
+        }
+
+        let repetitions = |time: Instant| {
+            time.saturating_duration_since(since).as_nanos() / interval.as_nanos().max(1)
+        };
+        old.timing
+            .now
+            .is_some_and(|old_now| repetitions(now) > repetitions(old_now))
+    }
+
     /// Returns `true` if the microphone was flipped up.
+    ///
+    /// The first report of the microphone arm position counts as a flip into that position.
     pub fn mic_flipped_up(&self, old: &ButtonState) -> bool {
-        old.mic_arm == MicArm::Down && self.mic_arm == MicArm::Up
+        old.mic_arm != Some(MicArm::Up) && self.mic_arm == Some(MicArm::Up)
     }
 
     /// Returns `true` if the microphone was flipped down.
+    ///
+    /// The first report of the microphone arm position counts as a flip into that position.
     pub fn mic_flipped_down(&self, old: &ButtonState) -> bool {
-        old.mic_arm == MicArm::Up && self.mic_arm == MicArm::Down
+        old.mic_arm != Some(MicArm::Down) && self.mic_arm == Some(MicArm::Down)
+    }
+
+    /// Returns `true` if the G1 key is held.
+    pub fn g1(&self) -> bool {
+        self.buttons.g1
+    }
+
+    /// Returns `true` if the G2 key is held.
+    pub fn g2(&self) -> bool {
+        self.buttons.g2
+    }
+
+    /// Returns `true` if the G3 key is held.
+    pub fn g3(&self) -> bool {
+        self.buttons.g3
+    }
+
+    /// Returns the state of the wheel.
+    pub fn wheel(&self) -> Wheel {
+        self.wheel
+    }
+
+    /// Returns `true` if the mute button was pressed during the recording of this state.
+    ///
+    /// This is the same as [`ButtonState::mute_button_pressed`].
+    pub fn mute(&self) -> bool {
+        self.mute_button
+    }
+
+    /// Returns the position of the microphone arm.
+    ///
+    /// The headset only reports the position when the arm is flipped, so this is `None` until
+    /// the first flip after the connection was opened.
+    pub fn mic_arm(&self) -> Option<MicArm> {
+        self.mic_arm
     }
 
     /// Returns `true` if the G1 key was pressed.
@@ -36,6 +246,18 @@ impl ButtonState {
         old.buttons.g1 && !self.buttons.g1
     }
 
+    /// Returns `true` if the G1 key has just been held for the threshold.
+    ///
+    /// This fires while the key is still held, as the state is repeated while a key is held.
+    pub fn g1_long_pressed(&self, old: &ButtonState, threshold: Duration) -> bool {
+        self.long_pressed(old, 0, threshold)
+    }
+
+    /// Returns `true` if the G1 key was pressed within the window after its previous press.
+    pub fn g1_double_pressed(&self, old: &ButtonState, window: Duration) -> bool {
+        self.double_pressed(old, 0, window)
+    }
+
     /// Returns `true` if the G2 key was pressed.
     pub fn g2_pressed(&self, old: &ButtonState) -> bool {
         !old.buttons.g2 && self.buttons.g2
@@ -46,6 +268,18 @@ impl ButtonState {
         old.buttons.g2 && !self.buttons.g2
     }
 
+    /// Returns `true` if the G2 key has just been held for the threshold.
+    ///
+    /// This fires while the key is still held, as the state is repeated while a key is held.
+    pub fn g2_long_pressed(&self, old: &ButtonState, threshold: Duration) -> bool {
+        self.long_pressed(old, 1, threshold)
+    }
+
+    /// Returns `true` if the G2 key was pressed within the window after its previous press.
+    pub fn g2_double_pressed(&self, old: &ButtonState, window: Duration) -> bool {
+        self.double_pressed(old, 1, window)
+    }
+
     /// Returns `true` if the G3 key was pressed.
     pub fn g3_pressed(&self, old: &ButtonState) -> bool {
         !old.buttons.g3 && self.buttons.g3
@@ -56,14 +290,69 @@ impl ButtonState {
         old.buttons.g3 && !self.buttons.g3
     }
 
-    /// Returns `true` if the scroll wheel is being scrolled down.
+    /// Returns `true` if the G3 key has just been held for the threshold.
+    ///
+    /// This fires while the key is still held, as the state is repeated while a key is held.
+    pub fn g3_long_pressed(&self, old: &ButtonState, threshold: Duration) -> bool {
+        self.long_pressed(old, 2, threshold)
+    }
+
+    /// Returns `true` if the G3 key was pressed within the window after its previous press.
+    pub fn g3_double_pressed(&self, old: &ButtonState, window: Duration) -> bool {
+        self.double_pressed(old, 2, window)
+    }
+
+    /// Returns `true` if the headset reported that the scroll wheel is being scrolled down.
+    ///
+    /// This is `false` for repeated states, see [`ButtonState::scroll_down_repeated`].
     pub fn scroll_down(&self) -> bool {
-        self.wheel.down
+        self.wheel.down && !self.tick
     }
 
-    /// Returns `true` if the scroll wheel is being scrolled up.
+    /// Returns `true` if the headset reported that the scroll wheel is being scrolled up.
+    ///
+    /// This is `false` for repeated states, see [`ButtonState::scroll_up_repeated`].
     pub fn scroll_up(&self) -> bool {
-        self.wheel.up
+        self.wheel.up && !self.tick
+    }
+
+    /// Returns the number of notches the wheel was scrolled by in this state, positive being up.
+    ///
+    /// Unlike [`ButtonState::scroll_up`] and [`ButtonState::scroll_down`], this is only nonzero
+    /// for the report of the notch itself.
+    pub fn scroll_delta(&self) -> i32 {
+        match (self.timing.now, self.timing.last_notch) {
+            (Some(now), Some((notch, direction))) if now == notch => direction,
+            _ => 0,
+        }
+    }
+
+    /// Returns how fast the wheel is scrolled in notches per second, or `0.0` if the notch of
+    /// this state did not continue a scroll in the same direction.
+    pub fn scroll_rate(&self) -> f32 {
+        match self.timing.notch_interval {
+            Some(interval) if self.scroll_delta() != 0 => 1.0 / interval.as_secs_f32().max(0.001),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns the scroll delta scaled up with the scroll rate.
+    ///
+    /// Each notch counts as `1 + acceleration * rate` notches, so with an acceleration of `0.1`,
+    /// scrolling at 10 notches per second doubles the delta. An acceleration of `0.0` returns
+    /// the plain [`ButtonState::scroll_delta`].
+    pub fn accelerated_scroll_delta(&self, acceleration: f32) -> f32 {
+        self.scroll_delta() as f32 * (1.0 + acceleration * self.scroll_rate())
+    }
+
+    /// Returns `true` if scrolling down started or continued for another interval.
+    pub fn scroll_down_repeated(&self, old: &ButtonState, interval: Duration) -> bool {
+        self.scroll_repeated(old, |wheel| wheel.down, interval)
+    }
+
+    /// Returns `true` if scrolling up started or continued for another interval.
+    pub fn scroll_up_repeated(&self, old: &ButtonState, interval: Duration) -> bool {
+        self.scroll_repeated(old, |wheel| wheel.up, interval)
     }
 
     /// Returns `true` if the mute button is being pressed.
@@ -73,12 +362,164 @@ impl ButtonState {
 
     /// Returns `true` if the scrolling ended.
     pub fn scroll_end(&self, old: &ButtonState) -> bool {
-        (old.scroll_down() || old.scroll_up()) && !self.scroll_down() && !self.scroll_up()
+        (old.wheel.down || old.wheel.up) && !self.wheel.down && !self.wheel.up
+    }
+
+    /// Returns the changes from the old state to this one.
+    ///
+    /// This covers the changes that do not depend on timing, the timed ones like long presses
+    /// are still found with the methods taking a threshold.
+    pub fn diff(&self, old: &ButtonState) -> ButtonChanges {
+        let changes = [
+            self.g1_pressed(old),
+            self.g1_released(old),
+            self.g2_pressed(old),
+            self.g2_released(old),
+            self.g3_pressed(old),
+            self.g3_released(old),
+            self.mic_flipped_up(old),
+            self.mic_flipped_down(old),
+            self.wheel.up && !old.wheel.up,
+            self.wheel.down && !old.wheel.down,
+            self.scroll_end(old),
+            self.mute_button_pressed(),
+        ];
+
+        ButtonChanges {
+            changes: changes
+                .into_iter()
+                .enumerate()
+                .fold(0, |bits, (index, changed)| {
+                    bits | (u16::from(changed) << index)
+                }),
+        }
+    }
+}
+
+/// A change between two button states, see [`ButtonState::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ButtonChange {
+    /// The G1 key was pressed.
+    G1Pressed,
+    /// The G1 key was released.
+    G1Released,
+    /// The G2 key was pressed.
+    G2Pressed,
+    /// The G2 key was released.
+    G2Released,
+    /// The G3 key was pressed.
+    G3Pressed,
+    /// The G3 key was released.
+    G3Released,
+    /// The microphone was flipped up, or first reported as flipped up.
+    MicFlippedUp,
+    /// The microphone was flipped down, or first reported as flipped down.
+    MicFlippedDown,
+    /// The wheel started scrolling up.
+    ScrollUpStarted,
+    /// The wheel started scrolling down.
+    ScrollDownStarted,
+    /// The scrolling ended.
+    ScrollEnded,
+    /// The mute button was pressed.
+    MutePressed,
+}
+
+impl ButtonChange {
+    /// All changes, in the order they are iterated in.
+    pub const ALL: [ButtonChange; 12] = [
+        ButtonChange::G1Pressed,
+        ButtonChange::G1Released,
+        ButtonChange::G2Pressed,
+        ButtonChange::G2Released,
+        ButtonChange::G3Pressed,
+        ButtonChange::G3Released,
+        ButtonChange::MicFlippedUp,
+        ButtonChange::MicFlippedDown,
+        ButtonChange::ScrollUpStarted,
+        ButtonChange::ScrollDownStarted,
+        ButtonChange::ScrollEnded,
+        ButtonChange::MutePressed,
+    ];
+
+    /// Returns the change of the G key with the index that it was pressed, or released if
+    /// `pressed` is `false`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not 0, 1 or 2.
+    pub fn g_key(index: usize, pressed: bool) -> Self {
+        Self::ALL[index * 2 + usize::from(!pressed)]
+    }
+}
+
+/// The changes between two button states, returned by [`ButtonState::diff`].
+///
+/// This is a small set that can be copied freely. Iterating over it yields the changes in the
+/// order of [`ButtonChange::ALL`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonChanges {
+    /// A bit for each change, in the order of [`ButtonChange::ALL`].
+    changes: u16,
+}
+
+impl ButtonChanges {
+    /// Returns `true` if the change happened.
+    pub fn contains(&self, change: ButtonChange) -> bool {
+        self.changes & (1 << change as u16) != 0
+    }
+
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.changes == 0
+    }
+
+    /// Returns an iterator over the changes that happened.
+    pub fn iter(&self) -> ButtonChangesIter {
+        ButtonChangesIter {
+            changes: *self,
+            next: 0,
+        }
+    }
+}
+
+impl IntoIterator for ButtonChanges {
+    type Item = ButtonChange;
+    type IntoIter = ButtonChangesIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over [`ButtonChanges`].
+#[derive(Debug, Clone)]
+pub struct ButtonChangesIter {
+    /// The changes iterated over.
+    changes: ButtonChanges,
+    /// The index in [`ButtonChange::ALL`] of the next change to check.
+    next: usize,
+}
+
+impl Iterator for ButtonChangesIter {
+    type Item = ButtonChange;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let change = *ButtonChange::ALL
+            .get(self.next..)?
+            .iter()
+            .find(|&&change| self.changes.contains(change))?;
+        self.next = change as usize + 1;
+
+        Some(change)
     }
 }
 
 /// Contains a bool for each button, to show if it is pressed
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Buttons {
     /// If g1 button is pressed
     pub(crate) g1: bool,
@@ -89,24 +530,38 @@ pub(crate) struct Buttons {
 }
 
 impl FromBytes for Buttons {
+    /// Reads the buttons from the parameters of a G-key notification.
     fn from_bytes(bytes: &[u8]) -> Self {
         Self {
-            g1: bytes[4] & 1 != 0,
-            g2: bytes[4] & 2 != 0,
-            g3: bytes[4] & 4 != 0,
+            g1: bytes[0] & 1 != 0,
+            g2: bytes[0] & 2 != 0,
+            g3: bytes[0] & 4 != 0,
         }
     }
 }
 
 /// Contains a bool for each direction of the wheel, to show if it is active
-#[derive(Debug, Default, Clone, Copy)]
-pub(crate) struct Wheel {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wheel {
     /// If the wheel is currently scrolling up
     pub(crate) up: bool,
     /// If the wheel is currently scrolling down
     pub(crate) down: bool,
 }
 
+impl Wheel {
+    /// Returns `true` if the wheel is currently scrolling up.
+    pub fn up(&self) -> bool {
+        self.up
+    }
+
+    /// Returns `true` if the wheel is currently scrolling down.
+    pub fn down(&self) -> bool {
+        self.down
+    }
+}
+
 impl FromBytes for Wheel {
     fn from_bytes(bytes: &[u8]) -> Self {
         Self {
@@ -117,14 +572,27 @@ impl FromBytes for Wheel {
 }
 
 /// The state of the microphone arm.
+///
+/// The headset mutes the microphone while the arm is flipped up.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum MicArm {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum MicArm {
     /// The microphone arm is currently flipped up.
     Up,
     /// The microphone arm is currently flipped down.
     Down,
 }
 
+impl fmt::Display for MicArm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MicArm::Up => write!(f, "up"),
+            MicArm::Down => write!(f, "down"),
+        }
+    }
+}
+
 impl FromBytes for MicArm {
     fn from_bytes(bytes: &[u8]) -> Self {
         match bytes[1] {
@@ -139,8 +607,187 @@ impl FromBytes for MicArm {
     }
 }
 
-impl Default for MicArm {
-    fn default() -> Self {
-        MicArm::Up
+/// Drops reports repeating the last accepted one shortly after it, as a marginal wireless link
+/// sometimes delivers a report twice.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Debouncer {
+    /// When the last report was accepted.
+    last_accepted: Option<Instant>,
+}
+
+impl Debouncer {
+    /// Returns `true` if a report received at `now` should be dropped, because it repeats the last
+    /// accepted report within the window, otherwise accepting it.
+    pub(crate) fn is_bounce(
+        &mut self,
+        window: Option<Duration>,
+        repeated: bool,
+        now: Instant,
+    ) -> bool {
+        let bounce = repeated
+            && window
+                .zip(self.last_accepted)
+                .is_some_and(|(window, last)| now.saturating_duration_since(last) < window);
+        if !bounce {
+            self.last_accepted = Some(now);
+        }
+
+        bounce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the state with the G keys held, the wheel scrolled and the microphone arm in the
+    /// position.
+    fn state(
+        [g1, g2, g3]: [bool; 3],
+        (up, down): (bool, bool),
+        mic_arm: Option<MicArm>,
+    ) -> ButtonState {
+        ButtonState {
+            buttons: Buttons { g1, g2, g3 },
+            wheel: Wheel { up, down },
+            mic_arm,
+            ..ButtonState::default()
+        }
+    }
+
+    #[test]
+    fn diffs_the_g_keys() {
+        for index in 0..3 {
+            let mut keys = [false; 3];
+            keys[index] = true;
+            let held = state(keys, (false, false), None);
+
+            let pressed = held.diff(&ButtonState::default());
+            assert_eq!(
+                pressed.iter().collect::<Vec<_>>(),
+                [ButtonChange::g_key(index, true)]
+            );
+            let released = ButtonState::default().diff(&held);
+            assert_eq!(
+                released.iter().collect::<Vec<_>>(),
+                [ButtonChange::g_key(index, false)]
+            );
+            assert!(held.diff(&held).is_empty());
+        }
+
+        // Keys changing at once are listed in the order of ButtonChange::ALL
+        let changes = state([false, true, true], (false, false), None).diff(&state(
+            [true, true, false],
+            (false, false),
+            None,
+        ));
+        assert_eq!(
+            changes.into_iter().collect::<Vec<_>>(),
+            [ButtonChange::G1Released, ButtonChange::G3Pressed]
+        );
+    }
+
+    #[test]
+    fn diffs_the_mic_arm() {
+        let up = state([false; 3], (false, false), Some(MicArm::Up));
+        let down = state([false; 3], (false, false), Some(MicArm::Down));
+
+        // The first report counts as a flip
+        assert!(up
+            .diff(&ButtonState::default())
+            .contains(ButtonChange::MicFlippedUp));
+        assert!(down
+            .diff(&ButtonState::default())
+            .contains(ButtonChange::MicFlippedDown));
+        assert_eq!(
+            down.diff(&up).iter().collect::<Vec<_>>(),
+            [ButtonChange::MicFlippedDown]
+        );
+        assert_eq!(
+            up.diff(&down).iter().collect::<Vec<_>>(),
+            [ButtonChange::MicFlippedUp]
+        );
+        assert!(up.diff(&up).is_empty());
+    }
+
+    #[test]
+    fn diffs_the_wheel() {
+        let up = state([false; 3], (true, false), None);
+        let down = state([false; 3], (false, true), None);
+
+        assert_eq!(
+            up.diff(&ButtonState::default()).iter().collect::<Vec<_>>(),
+            [ButtonChange::ScrollUpStarted]
+        );
+        assert_eq!(
+            down.diff(&up).iter().collect::<Vec<_>>(),
+            [ButtonChange::ScrollDownStarted]
+        );
+        // Scrolling on is not a change
+        assert!(down.diff(&down).is_empty());
+        assert_eq!(
+            ButtonState::default()
+                .diff(&down)
+                .iter()
+                .collect::<Vec<_>>(),
+            [ButtonChange::ScrollEnded]
+        );
+    }
+
+    #[test]
+    fn diffs_the_mute_button() {
+        let muted = ButtonState {
+            mute_button: true,
+            ..ButtonState::default()
+        };
+
+        assert_eq!(
+            muted
+                .diff(&ButtonState::default())
+                .iter()
+                .collect::<Vec<_>>(),
+            [ButtonChange::MutePressed]
+        );
+        // Each state with the press counts, as the headset reports each press once
+        assert!(muted.diff(&muted).contains(ButtonChange::MutePressed));
+        assert!(ButtonState::default().diff(&muted).is_empty());
+    }
+
+    #[test]
+    fn drops_repeats_inside_the_debounce_window() {
+        let start = Instant::now();
+        let window = Some(Duration::from_millis(100));
+        let mut debouncer = Debouncer::default();
+
+        assert!(!debouncer.is_bounce(window, true, start));
+        assert!(debouncer.is_bounce(window, true, start + Duration::from_millis(50)));
+        // The window is counted from the accepted report, not from the dropped one
+        assert!(debouncer.is_bounce(window, true, start + Duration::from_millis(99)));
+        // Without a window, nothing is dropped
+        assert!(!debouncer.is_bounce(None, true, start + Duration::from_millis(99)));
+    }
+
+    #[test]
+    fn accepts_repeats_outside_of_the_debounce_window() {
+        let start = Instant::now();
+        let window = Some(Duration::from_millis(100));
+        let mut debouncer = Debouncer::default();
+
+        assert!(!debouncer.is_bounce(window, true, start));
+        assert!(!debouncer.is_bounce(window, true, start + Duration::from_millis(100)));
+        assert!(!debouncer.is_bounce(window, true, start + Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn restarts_the_debounce_window_on_other_reports() {
+        let start = Instant::now();
+        let window = Some(Duration::from_millis(100));
+        let mut debouncer = Debouncer::default();
+
+        assert!(!debouncer.is_bounce(window, true, start));
+        // A different report is accepted within the window and starts a new one
+        assert!(!debouncer.is_bounce(window, false, start + Duration::from_millis(80)));
+        assert!(debouncer.is_bounce(window, true, start + Duration::from_millis(150)));
+        assert!(!debouncer.is_bounce(window, true, start + Duration::from_millis(180)));
     }
 }
@@ -0,0 +1,45 @@
+//! What the connected headset supports, so that front-ends can offer only what works.
+
+use std::ops::RangeInclusive;
+
+/// The number of light zones of the models known to work, by the name they report.
+///
+/// The logo light is zone 0 and the side lights are zone 1.
+const KNOWN_LIGHT_ZONES: &[(&str, u8)] = &[("G935", 2), ("G933", 2), ("G633", 2)];
+
+/// The range of sidetone levels in percent accepted by [`Headset::set_sidetone`].
+///
+/// [`Headset::set_sidetone`]: crate::Headset::set_sidetone
+pub(crate) const SIDETONE_RANGE: RangeInclusive<u8> = 0..=100;
+
+/// What the connected headset supports, derived from its features and the known models.
+///
+/// This is found out when the headset is opened, see [`Headset::capabilities`].
+///
+/// [`Headset::capabilities`]: crate::Headset::capabilities
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Whether the headset has an RGB logo light besides its side lights.
+    pub has_rgb_logo: bool,
+    /// Whether the headset has G keys that can be reported, see
+    /// [`Headset::set_gkeys_reporting`](crate::Headset::set_gkeys_reporting).
+    pub has_gkeys: bool,
+    /// Whether the headset notifies about changes of its battery, rather than only answering
+    /// requests.
+    pub battery_event_support: bool,
+    /// The number of equalizer bands, if the headset has an equalizer.
+    pub eq_bands: Option<usize>,
+    /// The accepted sidetone levels in percent, if the headset supports sidetone.
+    pub sidetone_range: Option<RangeInclusive<u8>>,
+}
+
+impl Capabilities {
+    /// Returns the number of light zones of the model with the name, if it is known.
+    pub(crate) fn known_light_zones(name: &str) -> Option<u8> {
+        KNOWN_LIGHT_ZONES
+            .iter()
+            .find(|(model, _)| name.contains(model))
+            .map(|(_, zones)| *zones)
+    }
+}
@@ -3,9 +3,25 @@
 use std::{
     fmt,
     ops::{Deref, DerefMut},
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use crate::{buttons::ButtonState, lights, Headset, PowerState};
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{
+    buttons::ButtonState,
+    handle::{HeadsetHandle, Job},
+    lights,
+    plugin::Plugin,
+    stop::StopToken,
+    waker::Wakeup,
+    Headset, PowerState, RawReport, Waker,
+};
 
 /// A wrapper that simply hides its inner type in `Debug` implementations.
 ///
@@ -41,6 +57,25 @@ impl<T> From<T> for OpaqueDebug<T> {
     }
 }
 
+/// Calls the handler, catching a panic so that it does not stop the loop.
+///
+/// Returns `None` after logging the panic if the handler panicked.
+fn catch_panic<T>(name: &str, handler: impl FnOnce() -> T) -> Option<T> {
+    match std::panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(result) => Some(result),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            log::error!("the {name} panicked: {message}");
+
+            None
+        }
+    }
+}
+
 /// A field in the config that tracks whether it was changed.
 #[derive(Debug)]
 pub(crate) struct ConfigField<T> {
@@ -67,6 +102,16 @@ impl<T> ConfigField<T> {
         self.force_sync();
     }
 
+    /// Sets the value of the config field, but only marks it as changed if it differs.
+    fn update(&mut self, val: T)
+    where
+        T: PartialEq,
+    {
+        if self.val != val {
+            self.set(val);
+        }
+    }
+
     /// Sets the dirty flag to force synchronization.
     fn force_sync(&mut self) {
         self.dirty = true;
@@ -80,6 +125,23 @@ impl<T> ConfigField<T> {
     }
 }
 
+impl<H> ConfigField<Option<OpaqueDebug<H>>> {
+    /// Puts back the handler taken to call it, unless it was replaced meanwhile or it panicked
+    /// and should be removed.
+    fn restore_handler(&mut self, handler: H, remove: bool) {
+        if self.dirty {
+            return;
+        }
+
+        if remove {
+            // Removing the handler may change which events need to be enabled
+            self.force_sync();
+        } else {
+            self.val = Some(handler.into());
+        }
+    }
+}
+
 impl<T> Deref for ConfigField<T> {
     type Target = T;
 
@@ -95,67 +157,358 @@ impl<T> DerefMut for ConfigField<T> {
 }
 
 /// The type of a handler for button presses.
-pub type ButtonHandler = Box<dyn FnMut(&mut Config, &mut Headset, ButtonState)>;
+pub type ButtonHandler = Box<dyn FnMut(&mut RunConfig, &mut Headset, ButtonState)>;
 
 /// A handler for changes in the power state of the headset.
-pub type PowerStateChangeHandler = Box<dyn FnMut(&mut Config, &mut Headset, PowerState)>;
+pub type PowerStateChangeHandler = Box<dyn FnMut(&mut RunConfig, &mut Headset, PowerState)>;
+
+/// A handler for the reports of the headset that the library does not understand.
+pub type RawReportHandler = Box<dyn FnMut(&mut RunConfig, &mut Headset, &RawReport)>;
 
 /// The type of a handler for periodic updates.
-pub type PeriodicHandler = Box<dyn FnMut(&mut Config, &mut Headset)>;
+pub type PeriodicHandler = Box<dyn FnMut(&mut RunConfig, &mut Headset)>;
+
+/// Identifies a timer added with [`RunConfig::add_timer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+/// A handler called at a fixed interval.
+struct Timer {
+    /// The identifier returned when the timer was added.
+    id: TimerId,
+    /// How often the handler is called.
+    interval: Duration,
+    /// When the handler is called next.
+    due: Instant,
+    /// The handler, unless it is being called right now.
+    handler: Option<PeriodicHandler>,
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timer")
+            .field("id", &self.id)
+            .field("interval", &self.interval)
+            .field("due", &self.due)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Identifies an overlay pushed with [`RunConfig::push_overlay`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OverlayId(u64);
+
+/// An effect temporarily shown on a light instead of the configured one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Overlay {
+    /// The identifier returned when the overlay was pushed.
+    id: OverlayId,
+    /// The light showing the effect.
+    light: lights::Light,
+    /// The effect shown.
+    effect: lights::Effect,
+}
+
+/// What [`Headset::run_with_config`] does after the config could not be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Keep running, retrying to apply the config after a delay.
+    Continue,
+    /// Reopen the connection to the headset before applying the config again.
+    Reconnect,
+    /// Return from [`Headset::run_with_config`].
+    Stop,
+}
+
+/// What is restored when [`Headset::run_with_config`] stops driving the headset.
+///
+/// The headset keeps the G key reporting and the shown light effects until it is turned off, so
+/// without restoring them, the G keys stay silent and the lights keep showing what the handlers
+/// last set.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum RestorePolicy {
+    /// Leave the headset as it is.
+    #[default]
+    Keep,
+    /// Disable the G key reporting, so that the G keys act as F1 to F3 again.
+    Buttons,
+    /// Disable the G key reporting and show the effects stored in the headset, see
+    /// [`Headset::restore_defaults`].
+    Defaults,
+}
+
+/// A handler for failures to apply the config, called with the error and the number of
+/// consecutive failures.
+pub type ErrorHandler = Box<dyn FnMut(&anyhow::Error, u32) -> ErrorPolicy>;
+
+/// The delay before applying the config again after the first failure.
+const SYNC_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The maximum delay before applying the config again after repeated failures.
+const MAX_SYNC_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// After how many failed requests in a row the connection is reset, unless configured.
+const DEFAULT_RECONNECT_AFTER: u32 = 5;
+
+/// How often the periodic handler is called while the headset is idle, unless configured.
+const DEFAULT_PERIODIC_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The old name of [`RunConfig`], which clashed with the light configuration.
+#[deprecated(note = "renamed to `RunConfig`")]
+pub type Config = RunConfig;
 
 /// The configuration for running the software.
 #[derive(Debug, Default)]
-pub struct Config {
+pub struct RunConfig {
     /// The handler for the buttons press.
     pub(crate) button_handler: ConfigField<Option<OpaqueDebug<ButtonHandler>>>,
     /// The handler for the power state change.
     pub(crate) power_state_change_handler:
         ConfigField<Option<OpaqueDebug<PowerStateChangeHandler>>>,
+    /// The handler for reports that are not understood.
+    pub(crate) raw_report_handler: ConfigField<Option<OpaqueDebug<RawReportHandler>>>,
     /// The handler for periodic callbacks.
     pub(crate) periodic_handler: ConfigField<Option<OpaqueDebug<PeriodicHandler>>>,
-    /// The light effect to use for the side lights.
-    pub(crate) side_light_effect: ConfigField<lights::Effect>,
-    /// The light effect to use for the logo lights.
-    pub(crate) logo_light_effect: ConfigField<lights::Effect>,
+    /// The light effects to use, which are always sent together.
+    pub(crate) lights: ConfigField<lights::FullLightSettings>,
+    /// The effects shown instead of the configured ones, with the most recent one last.
+    overlays: ConfigField<Vec<Overlay>>,
+    /// The brightness in percent the side light effect is dimmed to, if it should be dimmed.
+    pub(crate) side_light_brightness: ConfigField<Option<u8>>,
+    /// The brightness in percent the logo light effect is dimmed to, if it should be dimmed.
+    pub(crate) logo_light_brightness: ConfigField<Option<u8>>,
+    /// The sidetone level, if it should be set.
+    pub(crate) sidetone: ConfigField<Option<u8>>,
+    /// The equalizer gains, if they should be set.
+    pub(crate) equalizer: ConfigField<Option<Vec<i8>>>,
+    /// Whether the settings are only logged instead of being sent to the headset.
+    dry_run: bool,
+    /// The handler for failures to apply the config.
+    pub(crate) error_handler: Option<OpaqueDebug<ErrorHandler>>,
+    /// The number of consecutive failures to apply the config.
+    sync_failures: u32,
+    /// When to apply the config again after a failure.
+    retry_sync_at: Option<Instant>,
+    /// The channel for the jobs of the handles, once a handle was requested.
+    jobs: Option<(Sender<Job>, Receiver<Job>)>,
+    /// The wakeup of the polling loop, once a waker was requested.
+    pub(crate) wakeup: Option<Wakeup>,
+    /// How often the periodic handler is called while the headset is idle.
+    periodic_interval: Option<Duration>,
+    /// After how many failed requests in a row the connection is reset.
+    reconnect_after: Option<u32>,
+    /// The handlers called at their own intervals.
+    timers: Vec<Timer>,
+    /// The plugins extending the loop.
+    plugins: ConfigField<Vec<OpaqueDebug<Box<dyn Plugin>>>>,
+    /// Incremented whenever the plugins are cleared, to notice it while they are called.
+    plugins_generation: u64,
+    /// The identifier of the next added timer.
+    next_timer_id: u64,
+    /// The identifier of the next pushed overlay.
+    next_overlay_id: u64,
+    /// Whether the lights are written on the next sync even if the headset already shows them.
+    rewrite_lights: bool,
+    /// Whether the reporting of the G keys is sent on the next sync even if the handlers did not
+    /// change.
+    resend_gkeys_reporting: bool,
+    /// Whether handlers and plugins are removed after they panicked.
+    remove_panicking_handlers: bool,
+    /// What is restored when the loop stops.
+    restore_on_exit: RestorePolicy,
+    /// Whether the loop was asked to stop, shared with the stop tokens.
+    stopped: Arc<AtomicBool>,
+    /// How long handling an event may take before a warning is logged, if it is limited.
+    latency_budget: Option<Duration>,
+    /// For how long a repeated report of the microphone arm is dropped, if it is debounced.
+    mic_arm_debounce: Option<Duration>,
+    /// For how long a repeated report of the mute button is dropped, if it is debounced.
+    mute_debounce: Option<Duration>,
 }
 
-impl Config {
+impl RunConfig {
     /// Syncs the current configuration with
     pub(crate) fn sync_configuration(&mut self, headset: &mut Headset) -> anyhow::Result<()> {
-        if self.button_handler.needs_sync() {
-            headset.enable_buttons(self.button_handler.is_some())?;
+        headset.set_dry_run(self.dry_run);
+
+        // All flags need to be cleared
+        if self.button_handler.needs_sync()
+            | self.plugins.needs_sync()
+            | std::mem::take(&mut self.resend_gkeys_reporting)
+        {
+            headset.set_gkeys_reporting(self.wants_buttons())?;
+        }
+
+        // The power state change handler does not need to be communicated to the device.
+        self.power_state_change_handler.needs_sync();
+        self.raw_report_handler.needs_sync();
+
+        // The lights may have been changed by something else, which the headset does not report
+        if std::mem::take(&mut self.rewrite_lights) {
+            headset.forget_acknowledged_lights();
         }
 
-        if self.power_state_change_handler.needs_sync() {}
+        // All flags need to be cleared, and both lights are sent if either changed, so that
+        // they never show effects of different configurations. Effects the headset already
+        // shows are skipped.
+        if self.lights.needs_sync()
+            | self.overlays.needs_sync()
+            | self.side_light_brightness.needs_sync()
+            | self.logo_light_brightness.needs_sync()
+        {
+            headset.set_dimmed_full_lights(
+                &self.shown_lights(),
+                lights::ProfileType::Temporary,
+                |light| self.light_brightness(light),
+            )?;
+        }
 
-        if self.side_light_effect.needs_sync() {
-            headset.set_lights(&lights::Config {
-                light: lights::Light::Side,
-                effect: *self.side_light_effect,
-                profile_type: lights::ProfileType::Temporary,
-            })?;
+        if self.sidetone.needs_sync() {
+            if let Some(level) = *self.sidetone {
+                headset.set_sidetone(level)?;
+            }
         }
 
-        if self.logo_light_effect.needs_sync() {
-            headset.set_lights(&lights::Config {
-                light: lights::Light::Logo,
-                effect: *self.logo_light_effect,
-                profile_type: lights::ProfileType::Temporary,
-            })?;
+        if self.equalizer.needs_sync() {
+            if let Some(gains) = &*self.equalizer {
+                headset.set_equalizer(gains)?;
+            }
         }
 
         Ok(())
     }
 
+    /// Syncs the configuration, unless it is too early after a failure.
+    ///
+    /// The delay after a failure doubles with each consecutive failure.
+    pub(crate) fn sync_with_backoff(&mut self, headset: &mut Headset) -> anyhow::Result<()> {
+        if self
+            .retry_sync_at
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+        {
+            return Ok(());
+        }
+        if self.sync_failures > 0 {
+            headset.device.stats.retries += 1;
+        }
+
+        match self.sync_configuration(headset) {
+            Ok(()) => {
+                self.sync_failures = 0;
+                self.retry_sync_at = None;
+
+                Ok(())
+            }
+            Err(err) => {
+                self.sync_failures = self.sync_failures.saturating_add(1);
+                let delay = SYNC_RETRY_DELAY
+                    .saturating_mul(1 << (self.sync_failures - 1).min(16))
+                    .min(MAX_SYNC_RETRY_DELAY);
+                self.retry_sync_at = Some(Instant::now() + delay);
+                // The setting that failed is no longer marked as dirty, so send everything again
+                self.set_dirty();
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns a handle to the headset run using this config, usable from other threads.
+    pub fn handle(&mut self) -> HeadsetHandle {
+        let waker = self.waker();
+        let (jobs, _) = self.jobs.get_or_insert_with(crossbeam_channel::unbounded);
+
+        HeadsetHandle {
+            jobs: jobs.clone(),
+            waker,
+        }
+    }
+
+    /// Returns a waker, which makes the polling loop run the periodic handler right away.
+    ///
+    /// This allows calling the periodic handler rarely while the headset is idle, using the waker
+    /// whenever something needs to be handled, for example after sending a request to the
+    /// periodic handler through a channel.
+    pub fn waker(&mut self) -> Waker {
+        self.wakeup.get_or_insert_with(Wakeup::new).waker()
+    }
+
+    /// Returns a token that stops [`Headset::run_with_config`] from other threads.
+    pub fn stop_token(&mut self) -> StopToken {
+        StopToken {
+            stopped: Arc::clone(&self.stopped),
+            waker: self.waker(),
+        }
+    }
+
+    /// Returns whether a stop token asked the loop to stop.
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Returns how long after a failure applying the config is retried.
+    pub(crate) fn sync_retry_delay(&self) -> Option<Duration> {
+        self.retry_sync_at
+            .map(|retry_at| retry_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Runs the jobs sent by the handles.
+    pub(crate) fn run_jobs(&mut self, headset: &mut Headset) {
+        let Some((_, receiver)) = &self.jobs else {
+            return;
+        };
+
+        let jobs: Vec<_> = receiver.try_iter().collect();
+        for job in jobs {
+            catch_panic("job of a handle", || job(self, headset));
+        }
+    }
+
+    /// Retries applying the config right away, for example after reconnecting.
+    pub(crate) fn reset_backoff(&mut self) {
+        self.retry_sync_at = None;
+    }
+
+    /// Calls the configured error handler, logging the error if there is none.
+    pub(crate) fn call_error_handler(&mut self, err: &anyhow::Error) -> ErrorPolicy {
+        match &mut self.error_handler {
+            Some(handler) => {
+                let failures = self.sync_failures;
+                catch_panic("error handler", || handler(err, failures)).unwrap_or_else(|| {
+                    if self.remove_panicking_handlers {
+                        self.error_handler = None;
+                    }
+
+                    ErrorPolicy::Continue
+                })
+            }
+            None => {
+                log::error!("failed config re-synchronization: {err}");
+
+                ErrorPolicy::Continue
+            }
+        }
+    }
+
     /// Explicitly sets the configuration to dirty to enable a re-synchronization.
     ///
-    /// This is for example useful after a device restart.
-    pub(crate) fn set_dirty(&mut self) {
-        self.button_handler.force_sync();
-        self.power_state_change_handler.force_sync();
-        self.periodic_handler.force_sync();
-        self.side_light_effect.force_sync();
-        self.logo_light_effect.force_sync();
+    /// This is for example useful after a device restart or after the computer resumed from
+    /// suspend. Unlike other changes, which skip the effects the headset already shows, this
+    /// writes both lights again, in case something else changed them.
+    ///
+    /// The handlers are left alone, as marking them would look like they were replaced while
+    /// they are called, which is where this is usually called from.
+    pub fn set_dirty(&mut self) {
+        self.resend_gkeys_reporting = true;
+        self.lights.force_sync();
+        self.overlays.force_sync();
+        self.side_light_brightness.force_sync();
+        self.logo_light_brightness.force_sync();
+        self.sidetone.force_sync();
+        self.equalizer.force_sync();
+        self.rewrite_lights = true;
     }
 
     /// Calls the configured button handler, if it exists.
@@ -164,11 +517,14 @@ impl Config {
             // Clear the dirty flag in case it was set to check for changes to the handler itself
             self.button_handler.dirty = false;
 
-            button_handler(self, headset, button_state);
+            let panicked = catch_panic("button handler", || {
+                button_handler(self, headset, button_state)
+            })
+            .is_none();
 
-            if !self.button_handler.dirty {
-                *self.button_handler = Some(button_handler);
-            }
+            let remove = panicked && self.remove_panicking_handlers;
+            self.button_handler
+                .restore_handler(button_handler.inner, remove);
         }
     }
 
@@ -188,11 +544,14 @@ impl Config {
             // Clear the dirty flag in case it was set to check for changes to the handler itself
             self.power_state_change_handler.dirty = false;
 
-            power_state_change_handler(self, headset, power_state);
+            let panicked = catch_panic("power state change handler", || {
+                power_state_change_handler(self, headset, power_state)
+            })
+            .is_none();
 
-            if !self.power_state_change_handler.dirty {
-                *self.power_state_change_handler = Some(power_state_change_handler);
-            }
+            let remove = panicked && self.remove_panicking_handlers;
+            self.power_state_change_handler
+                .restore_handler(power_state_change_handler.inner, remove);
         }
     }
 
@@ -202,33 +561,452 @@ impl Config {
             .set(handler.map(|handler| OpaqueDebug { inner: handler }));
     }
 
+    /// Calls the configured raw report handler, logging the report if there is none.
+    pub(crate) fn call_raw_report_handler(&mut self, headset: &mut Headset, report: &RawReport) {
+        let Some(mut raw_report_handler) = self.raw_report_handler.take() else {
+            log::info!("unhandled message from device: {:02x?}", report.bytes);
+            return;
+        };
+        // Clear the dirty flag in case it was set to check for changes to the handler itself
+        self.raw_report_handler.dirty = false;
+
+        let panicked = catch_panic("raw report handler", || {
+            raw_report_handler(self, headset, report)
+        })
+        .is_none();
+
+        let remove = panicked && self.remove_panicking_handlers;
+        self.raw_report_handler
+            .restore_handler(raw_report_handler.inner, remove);
+    }
+
+    /// Sets the handler for the reports of the headset that the library does not understand.
+    ///
+    /// This allows prototyping support for features the library does not know yet. The handler
+    /// receives every report that is not handled otherwise, annotated with the ID of its feature
+    /// if the feature is known. Without a handler, the reports are logged.
+    pub fn set_raw_report_handler(&mut self, handler: Option<RawReportHandler>) {
+        self.raw_report_handler
+            .set(handler.map(|handler| OpaqueDebug { inner: handler }));
+    }
+
     /// Calls the configured periodic handler, if it exists.
     pub(crate) fn call_periodic_handler(&mut self, headset: &mut Headset) {
         if let Some(mut periodic_handler) = self.periodic_handler.take() {
             // Clear the dirty flag in case it was set to check for changes to the handler itself
             self.periodic_handler.dirty = false;
 
-            periodic_handler(self, headset);
+            let panicked =
+                catch_panic("periodic handler", || periodic_handler(self, headset)).is_none();
+
+            let remove = panicked && self.remove_panicking_handlers;
+            self.periodic_handler
+                .restore_handler(periodic_handler.inner, remove);
+        }
+    }
+
+    /// Sets how often [`Headset::run_with_config`] calls the periodic handler while the headset
+    /// is idle, which defaults to 500 ms.
+    ///
+    /// Without a periodic handler, the loop only wakes up for events of the headset, the jobs of
+    /// the handles and wakers, and to keep the settings applied. [`Duration::MAX`] does the same
+    /// with a periodic handler, which then relies on a [`Waker`].
+    pub fn set_periodic_interval(&mut self, interval: Duration) {
+        self.periodic_interval = Some(interval);
+    }
+
+    /// Returns how often the periodic handler is called while the headset is idle.
+    pub fn periodic_interval(&self) -> Duration {
+        self.periodic_interval.unwrap_or(DEFAULT_PERIODIC_INTERVAL)
+    }
+
+    /// Returns how long [`Headset::run_with_config`] waits for an event of the headset.
+    pub(crate) fn idle_timeout(&self) -> Duration {
+        if self.periodic_handler.is_some() {
+            self.periodic_interval()
+        } else {
+            Duration::MAX
+        }
+    }
+
+    /// Sets the handler for periodic updates together with how often it is called while the
+    /// headset is idle.
+    ///
+    /// The periodic handler is also called after every event, use [`RunConfig::add_timer`] for
+    /// handlers that should only be called at their interval.
+    pub fn set_periodic_handler_with_interval(
+        &mut self,
+        interval: Duration,
+        handler: PeriodicHandler,
+    ) {
+        self.set_periodic_interval(interval);
+        self.set_periodic_handler(Some(handler));
+    }
+
+    /// Adds a handler called every `interval`, independently of the periodic handler and the
+    /// other timers.
+    ///
+    /// The handler is first called after one interval. If the loop falls behind, the missed calls
+    /// are skipped instead of being made up in quick succession.
+    pub fn add_timer(&mut self, interval: Duration, handler: PeriodicHandler) -> TimerId {
+        let id = TimerId(self.next_timer_id);
+        self.next_timer_id += 1;
+
+        self.timers.push(Timer {
+            id,
+            interval,
+            due: Instant::now() + interval,
+            handler: Some(handler),
+        });
+
+        id
+    }
+
+    /// Removes the timer, returning whether it existed.
+    ///
+    /// Timers can also remove themselves while they are called.
+    pub fn remove_timer(&mut self, id: TimerId) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+
+        self.timers.len() != len
+    }
+
+    /// Calls the timers that are due.
+    pub(crate) fn call_timers(&mut self, headset: &mut Headset) {
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .timers
+            .iter()
+            .filter(|timer| timer.due <= now)
+            .map(|timer| timer.id)
+            .collect();
+
+        for id in due {
+            // The timer may have been removed by a previous one
+            let Some(mut handler) = self.timer_mut(id).and_then(|timer| timer.handler.take())
+            else {
+                continue;
+            };
+
+            if catch_panic("timer", || handler(self, headset)).is_none()
+                && self.remove_panicking_handlers
+            {
+                self.remove_timer(id);
+                continue;
+            }
 
-            if !self.periodic_handler.dirty {
-                *self.periodic_handler = Some(periodic_handler);
+            if let Some(timer) = self.timer_mut(id) {
+                timer.handler = Some(handler);
+                timer.due += timer.interval;
+                if timer.due <= now {
+                    timer.due = now + timer.interval;
+                }
             }
         }
     }
 
+    /// Returns the timer with the identifier, if it exists.
+    fn timer_mut(&mut self, id: TimerId) -> Option<&mut Timer> {
+        self.timers.iter_mut().find(|timer| timer.id == id)
+    }
+
+    /// Returns how long until the next timer is due.
+    pub(crate) fn next_timer_delay(&self) -> Option<Duration> {
+        self.timers
+            .iter()
+            .map(|timer| timer.due.saturating_duration_since(Instant::now()))
+            .min()
+    }
+
+    /// Adds a plugin extending the loop, which is called after the plugins added before.
+    pub fn add_plugin(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin.into());
+        self.plugins.force_sync();
+    }
+
+    /// Removes all plugins, together with all overlays, as those are usually pushed by plugins.
+    pub fn clear_plugins(&mut self) {
+        self.plugins.set(Vec::new());
+        self.overlays.update(Vec::new());
+        self.plugins_generation += 1;
+    }
+
+    /// Calls the function for every plugin.
+    pub(crate) fn call_plugins(
+        &mut self,
+        headset: &mut Headset,
+        mut f: impl FnMut(&mut dyn Plugin, &mut RunConfig, &mut Headset),
+    ) {
+        let mut plugins = std::mem::take(&mut *self.plugins);
+        let generation = self.plugins_generation;
+        let len = plugins.len();
+
+        let remove = self.remove_panicking_handlers;
+        plugins.retain_mut(|plugin| {
+            let panicked = catch_panic("plugin", || f(&mut ***plugin, self, headset)).is_none();
+
+            !(panicked && remove)
+        });
+        if plugins.len() != len {
+            // Removing a plugin may change which events need to be enabled
+            self.plugins.force_sync();
+        }
+
+        // Keep the plugins added meanwhile, unless the plugins were replaced
+        if self.plugins_generation == generation {
+            plugins.append(&mut self.plugins);
+            *self.plugins = plugins;
+        }
+    }
+
+    /// Returns how soon a plugin needs to be called while the headset is idle.
+    pub(crate) fn plugin_tick_interval(&self) -> Option<Duration> {
+        self.plugins
+            .iter()
+            .filter_map(|plugin| plugin.tick_interval())
+            .min()
+    }
+
+    /// Returns whether the button events need to be enabled.
+    pub(crate) fn wants_buttons(&self) -> bool {
+        self.button_handler.is_some() || !self.plugins.is_empty()
+    }
+
     /// Sets the handler for periodic updates.
     pub fn set_periodic_handler(&mut self, handler: Option<PeriodicHandler>) {
         self.periodic_handler
             .set(handler.map(|handler| OpaqueDebug { inner: handler }));
     }
 
+    /// Sets whether handlers, timers and plugins are removed after they panicked.
+    ///
+    /// A panic in a handler, a timer, a plugin or a job of a [`HeadsetHandle`] never stops
+    /// [`Headset::run_with_config`]: it is caught and logged, and the loop continues. By default,
+    /// the handler is called again for the next event, which suits handlers that only panic in
+    /// rare cases. With this set, it is removed instead, so that a handler that panics every time
+    /// does not flood the log. Removing the button handler and the last plugin also lets the G
+    /// keys act as F1 to F3 again.
+    pub fn set_remove_panicking_handlers(&mut self, remove: bool) {
+        self.remove_panicking_handlers = remove;
+    }
+
+    /// Sets what [`Headset::run_with_config`] restores when it stops, which defaults to
+    /// [`RestorePolicy::Keep`].
+    ///
+    /// The policy is applied when the loop returns after [`ErrorPolicy::Stop`] or a [`StopToken`]
+    /// was used, and when it unwinds from a panic. The policy last set before that counts, so
+    /// handlers can change it. Nothing is restored if the process is killed or exits without unwinding, so a signal
+    /// needs to stop the loop through a [`StopToken`] for the policy to apply.
+    pub fn set_restore_on_exit(&mut self, policy: RestorePolicy) {
+        self.restore_on_exit = policy;
+    }
+
+    /// Returns what is restored when [`Headset::run_with_config`] stops.
+    pub fn restore_on_exit(&self) -> RestorePolicy {
+        self.restore_on_exit
+    }
+
+    /// Sets how long handling an event may take, from receiving its report until its handlers
+    /// and plugins finished, before a warning is logged.
+    ///
+    /// Handlers run one after the other on the loop, so a handler waiting for a command delays
+    /// all events following it. The latencies are always counted in [`Stats::handler_latency`],
+    /// whereas the warnings are off by default.
+    ///
+    /// [`Stats::handler_latency`]: crate::Stats::handler_latency
+    pub fn set_latency_budget(&mut self, budget: Option<Duration>) {
+        self.latency_budget = budget;
+    }
+
+    /// Returns how long handling an event may take before a warning is logged.
+    pub fn latency_budget(&self) -> Option<Duration> {
+        self.latency_budget
+    }
+
+    /// Sets for how long after a report of the microphone arm a report of the same position is
+    /// dropped.
+    ///
+    /// Over a marginal wireless link, the headset occasionally sends a report twice, which would
+    /// invoke the handlers twice for a single flip. Reports of the other position are never
+    /// dropped. Debouncing is off by default.
+    pub fn set_mic_arm_debounce(&mut self, window: Option<Duration>) {
+        self.mic_arm_debounce = window;
+    }
+
+    /// Returns for how long a repeated report of the microphone arm is dropped.
+    pub fn mic_arm_debounce(&self) -> Option<Duration> {
+        self.mic_arm_debounce
+    }
+
+    /// Sets for how long after a press of the mute button another press is dropped.
+    ///
+    /// Like [`RunConfig::set_mic_arm_debounce`], this keeps a report sent twice from invoking the
+    /// handlers twice, at the cost of ignoring presses in quick succession. Only presses reported
+    /// right after another one are dropped, as a repeated report arrives right after itself.
+    /// Debouncing is off by default.
+    pub fn set_mute_debounce(&mut self, window: Option<Duration>) {
+        self.mute_debounce = window;
+    }
+
+    /// Returns for how long another press of the mute button is dropped.
+    pub fn mute_debounce(&self) -> Option<Duration> {
+        self.mute_debounce
+    }
+
+    /// Sets the handler deciding what to do after the config could not be applied.
+    ///
+    /// Without a handler, the error is logged and applying the config is retried. Either way,
+    /// the delay before the next attempt doubles with each consecutive failure, up to 30 s.
+    pub fn set_error_handler(&mut self, handler: Option<ErrorHandler>) {
+        self.error_handler = handler.map(|handler| OpaqueDebug { inner: handler });
+    }
+
+    /// Sets after how many failed requests in a row [`Headset::run_with_config`] resets the
+    /// connection to the headset, which defaults to 5, while 0 never resets it.
+    ///
+    /// Failures while the headset is known to be turned off are not counted, as those are
+    /// expected. See [`Headset::reset_connection`].
+    pub fn set_reconnect_after(&mut self, failures: u32) {
+        self.reconnect_after = Some(failures);
+    }
+
+    /// Returns after how many failed requests in a row the connection is reset.
+    pub fn reconnect_after(&self) -> u32 {
+        self.reconnect_after.unwrap_or(DEFAULT_RECONNECT_AFTER)
+    }
+
+    /// Sets whether the settings are only logged instead of being sent to the headset.
+    ///
+    /// This applies to everything the headset is asked to change while it runs using this
+    /// config, see [`Headset::set_dry_run`].
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns whether the settings are only logged instead of being sent to the headset.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
     /// Sets the effect for the side light.
+    ///
+    /// The headset is only updated if the effect changed.
     pub fn set_side_light_effect(&mut self, effect: lights::Effect) {
-        self.side_light_effect.set(effect);
+        self.set_light_effect(lights::Light::Side, effect);
     }
 
     /// Sets the effect for the logo light.
+    ///
+    /// The headset is only updated if the effect changed.
     pub fn set_logo_light_effect(&mut self, effect: lights::Effect) {
-        self.logo_light_effect.set(effect);
+        self.set_light_effect(lights::Light::Logo, effect);
+    }
+
+    /// Sets the effect for the light.
+    ///
+    /// The headset is only updated if the effect changed, in which case the effects of both
+    /// lights are sent.
+    pub fn set_light_effect(&mut self, light: lights::Light, effect: lights::Effect) {
+        let mut lights = *self.lights;
+        lights.set_effect(light, effect);
+        self.set_lights(lights);
+    }
+
+    /// Sets the effects of both lights.
+    ///
+    /// The headset is only updated if an effect changed, in which case both are sent right after
+    /// each other.
+    pub fn set_lights(&mut self, lights: lights::FullLightSettings) {
+        self.lights.update(lights);
+    }
+
+    /// Returns the effect of the light.
+    pub fn light_effect(&self, light: lights::Light) -> lights::Effect {
+        self.lights.effect(light)
+    }
+
+    /// Returns the effects of both lights.
+    ///
+    /// These are the configured effects, which may be hidden by overlays.
+    pub fn lights(&self) -> lights::FullLightSettings {
+        *self.lights
+    }
+
+    /// Shows the effect on the light until the overlay is popped, hiding the configured effect
+    /// and the overlays pushed before.
+    ///
+    /// This allows plugins to show something temporarily, such as the battery charge, without
+    /// knowing what to restore afterwards. Changing the configured effect meanwhile only takes
+    /// effect once all overlays of the light are popped.
+    pub fn push_overlay(&mut self, light: lights::Light, effect: lights::Effect) -> OverlayId {
+        let id = OverlayId(self.next_overlay_id);
+        self.next_overlay_id += 1;
+
+        self.overlays.push(Overlay { id, light, effect });
+        self.overlays.force_sync();
+
+        id
+    }
+
+    /// Removes the overlay, returning whether it existed.
+    ///
+    /// Overlays can be popped in any order. Once the topmost overlay of a light is popped, the
+    /// one below it is shown again, or the configured effect if there is none.
+    pub fn pop_overlay(&mut self, id: OverlayId) -> bool {
+        let Some(index) = self.overlays.iter().position(|overlay| overlay.id == id) else {
+            return false;
+        };
+
+        self.overlays.remove(index);
+        self.overlays.force_sync();
+
+        true
+    }
+
+    /// Returns the effects shown on the lights, which are the most recent overlays of each light
+    /// or the configured effects.
+    pub fn shown_lights(&self) -> lights::FullLightSettings {
+        let mut lights = *self.lights;
+        for overlay in self.overlays.iter() {
+            lights.set_effect(overlay.light, overlay.effect);
+        }
+
+        lights
+    }
+
+    /// Sets the brightness in percent the effect of the light is dimmed to, without changing the
+    /// effect itself.
+    ///
+    /// See [`Effect::with_brightness`](lights::Effect::with_brightness) for how the effects are
+    /// dimmed. If it is `None`, the effect is shown as it is set. The headset is only updated if
+    /// the brightness changed.
+    pub fn set_light_brightness(&mut self, light: lights::Light, brightness: Option<u8>) {
+        match light {
+            lights::Light::Side => self.side_light_brightness.update(brightness),
+            lights::Light::Logo => self.logo_light_brightness.update(brightness),
+        }
+    }
+
+    /// Returns the brightness in percent the effect of the light is dimmed to, if it is dimmed.
+    pub fn light_brightness(&self, light: lights::Light) -> Option<u8> {
+        match light {
+            lights::Light::Side => *self.side_light_brightness,
+            lights::Light::Logo => *self.logo_light_brightness,
+        }
+    }
+
+    /// Sets the sidetone level in percent.
+    ///
+    /// If it is `None`, the sidetone level of the headset is left untouched.
+    pub fn set_sidetone(&mut self, level: Option<u8>) {
+        self.sidetone.update(level);
+    }
+
+    /// Sets the gain in dB of each equalizer band.
+    ///
+    /// If it is `None`, the equalizer of the headset is left untouched.
+    pub fn set_equalizer(&mut self, gains: Option<Vec<i8>>) {
+        self.equalizer.update(gains);
     }
 }
@@ -1,15 +1,85 @@
 //! A wrapper around the device read/write interface.
 
-use std::{collections::VecDeque, fmt};
+use std::{collections::VecDeque, fmt, ops::Deref};
 
-use hidapi::HidDevice;
+use crate::{
+    buttons::MicArm,
+    protocol::{self, HidppReport, Message},
+    transport::{Transport, MAX_REPORT_LEN},
+    waker::Wakeup,
+    Error, Stats,
+};
+
+/// A report exchanged with the device, stored inline so that polling does not allocate.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Report {
+    /// The bytes of the report, followed by unused ones.
+    buf: [u8; MAX_REPORT_LEN],
+    /// The length of the report.
+    len: usize,
+}
+
+impl Report {
+    /// Copies the bytes into a report, cutting them off after [`MAX_REPORT_LEN`] bytes.
+    pub(crate) fn new(bytes: &[u8]) -> Self {
+        let len = bytes.len().min(MAX_REPORT_LEN);
+        let mut buf = [0; MAX_REPORT_LEN];
+        buf[..len].copy_from_slice(&bytes[..len]);
+
+        Self { buf, len }
+    }
+}
+
+impl Default for Report {
+    /// Returns an empty report, which stands for a read that timed out.
+    fn default() -> Self {
+        Self {
+            buf: [0; MAX_REPORT_LEN],
+            len: 0,
+        }
+    }
+}
+
+impl Deref for Report {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Debug for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x?}", &**self)
+    }
+}
 
 /// Implements the communication with the hardware.
 pub(crate) struct Device {
-    /// The raw inner `HidDevice` of this device.
-    device: HidDevice,
+    /// The transport exchanging the reports with the device.
+    transport: Box<dyn Transport>,
     /// The buffer for unhandled messages.
-    msg_buffer: VecDeque<Vec<u8>>,
+    msg_buffer: VecDeque<Report>,
+    /// The last reported position of the microphone arm.
+    mic_arm: Option<MicArm>,
+    /// The statistics about the communication.
+    pub(crate) stats: Stats,
+    /// The HID++ index of the device behind the receiver, which is 0xff for the receiver itself.
+    pub(crate) device_index: u8,
+    /// Whether requests changing settings are only logged instead of being sent.
+    pub(crate) dry_run: bool,
+    /// The number of requests that failed since the last one that succeeded.
+    pub(crate) consecutive_failures: u32,
+    /// Whether the responses of the device do not match any known layout.
+    pub(crate) untrusted: bool,
+    /// Whether settings are changed even if the device is untrusted.
+    pub(crate) force: bool,
+    /// A second handle to the hidraw node, used to wait for messages together with wakeups.
+    ///
+    /// Every open handle receives its own copy of the messages, so this one is only used to
+    /// notice that messages are available and is drained afterwards.
+    #[cfg(target_os = "linux")]
+    hidraw: Option<std::fs::File>,
 }
 
 impl fmt::Debug for Device {
@@ -19,70 +89,306 @@ impl fmt::Debug for Device {
 }
 
 impl Device {
-    /// Creates a new device from the underlying device.
-    pub(crate) fn new(device: hidapi::HidDevice) -> Self {
+    /// Creates a new device with the HID++ device index using the transport, which was opened
+    /// from the given HID path if it talks to a HID device.
+    pub(crate) fn new(
+        transport: Box<dyn Transport>,
+        _path: Option<&std::ffi::CStr>,
+        device_index: u8,
+    ) -> Self {
         Self {
-            device,
+            transport,
             msg_buffer: VecDeque::new(),
+            mic_arm: None,
+            stats: Stats::default(),
+            device_index,
+            dry_run: false,
+            consecutive_failures: 0,
+            untrusted: false,
+            force: false,
+            #[cfg(target_os = "linux")]
+            hidraw: _path.and_then(open_hidraw),
         }
     }
 
+    /// Returns the serial number reported by the USB device, if there is one.
+    pub(crate) fn serial_number(&self) -> Option<String> {
+        self.transport.serial_number()
+    }
+
+    /// Returns the product name reported by the USB device, if there is one.
+    pub(crate) fn product(&self) -> Option<String> {
+        self.transport.product()
+    }
+
     /// Writes the given `data` to the device.
     fn write(&mut self, data: &[u8]) -> anyhow::Result<usize> {
         log::trace!("writing {:02x?}", data);
 
-        Ok(self.device.write(data)?)
+        self.transport.write(data)
     }
 
-    /// Reads from the device into the given buffer, returning a slice to the read data.
-    fn read(&mut self, timeout: i32) -> anyhow::Result<Vec<u8>> {
-        let mut buf = [0; 1024];
+    /// Reads a report from the device, which is empty if none arrived before the timeout.
+    fn read(&mut self, timeout: i32) -> anyhow::Result<Report> {
+        let mut buf = [0; MAX_REPORT_LEN];
 
-        let len = self.device.read_timeout(&mut buf, timeout)?;
-        let result = buf[0..len].to_vec();
+        let len = self.transport.read(&mut buf, timeout)?.min(MAX_REPORT_LEN);
+        let len = protocol::report_len(buf[0]).map_or(len, |report_len| report_len.min(len));
+        let result = Report { buf, len };
 
         if len != 0 {
-            log::trace!("read {:02x?}", result);
+            log::trace!("read {result:?}");
+        }
+
+        if let Some(Message::MicArm(mic_arm)) = protocol::decode(&result) {
+            self.mic_arm = Some(mic_arm);
         }
 
         Ok(result)
     }
 
     /// Sends a request to the device, returning the reply.
-    pub(crate) fn request(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
-        self.write(data)?;
+    ///
+    /// Consecutive failures are counted, to notice a connection that stopped working.
+    pub(crate) fn request(&mut self, data: &[u8]) -> anyhow::Result<Report> {
+        self.request_all(&[data]).map(|replies| replies[0])
+    }
+
+    /// Sends the requests to the device right after each other, returning the replies in the
+    /// order of the requests.
+    ///
+    /// This saves waiting for each reply before sending the next request, which the device
+    /// allows, as it handles the requests in the order they were sent. It fails if any request
+    /// fails. Consecutive failures are counted, to notice a connection that stopped working.
+    pub(crate) fn request_all(&mut self, requests: &[&[u8]]) -> anyhow::Result<Vec<Report>> {
+        let result = self.exchange(requests);
+
+        match result {
+            Ok(_) => self.consecutive_failures = 0,
+            Err(_) => self.consecutive_failures = self.consecutive_failures.saturating_add(1),
+        }
+
+        result
+    }
+
+    /// Writes the requests to the device and waits for their replies.
+    ///
+    /// As the device handles the requests in order, a reply is matched to the first request with
+    /// the same header that is still waiting for one.
+    fn exchange(&mut self, requests: &[&[u8]]) -> anyhow::Result<Vec<Report>> {
+        self.stats.requests += requests.len() as u64;
+        for data in requests {
+            self.write(data)?;
+        }
 
         let start = std::time::Instant::now();
+        let mut foreign_responses = 0;
+        let mut replies = vec![None; requests.len()];
 
-        loop {
+        while replies.iter().any(Option::is_none) {
             let result = self.read(500)?;
 
-            if result.len() < 4 || result[0..4] != data[0..4] {
-                log::debug!("buffering unrequested message for later");
+            let pending = requests.iter().zip(&replies).position(|(data, reply)| {
+                reply.is_none() && result.len() >= 4 && result[0..4] == data[0..4]
+            });
+            if let Some(index) = pending {
+                replies[index] = Some(result);
+                continue;
+            }
 
-                self.msg_buffer.push_back(result.to_vec());
-            } else if result.len() == 0 {
-                return Err(anyhow::anyhow!("request timed out"));
-            } else {
-                return Ok(result);
+            if requests
+                .iter()
+                .any(|data| is_foreign_response(data, &result))
+            {
+                foreign_responses += 1;
             }
 
+            log::debug!("buffering unrequested message for later");
+
+            self.msg_buffer.push_back(result);
+
             if start.elapsed() > std::time::Duration::from_secs(2) {
+                self.stats.timeouts += 1;
+
+                if foreign_responses > 0 {
+                    return Err(Error::DeviceBusy {
+                        reason: format!(
+                            "the request timed out, but {foreign_responses} responses to requests \
+                             of another program were received"
+                        ),
+                    }
+                    .into());
+                }
+
                 return Err(anyhow::anyhow!("request timed out"));
             }
         }
+
+        Ok(replies.into_iter().flatten().collect())
+    }
+
+    /// Reads all messages that are already available, buffering them for later.
+    pub(crate) fn read_pending(&mut self) -> anyhow::Result<()> {
+        loop {
+            let result = self.read(0)?;
+
+            if result.is_empty() {
+                return Ok(());
+            }
+
+            self.msg_buffer.push_back(result);
+        }
+    }
+
+    /// Returns the last reported position of the microphone arm.
+    pub(crate) fn mic_arm(&self) -> Option<MicArm> {
+        self.mic_arm
+    }
+
+    /// Forgets the position of the microphone arm, because it may have changed unnoticed.
+    pub(crate) fn forget_mic_arm(&mut self) {
+        self.mic_arm = None;
     }
 
     /// Returns the next unrequested message sent by the device if there is one.
-    pub(crate) fn next_unrequested_msg(&mut self, timeout: i32) -> Option<Vec<u8>> {
+    ///
+    /// An empty message is returned if no message arrived before the timeout or the wakeup fired.
+    /// Failed reads are counted like failed requests, to notice a connection that stopped working.
+    pub(crate) fn next_unrequested_msg(
+        &mut self,
+        timeout: i32,
+        wakeup: Option<&Wakeup>,
+    ) -> anyhow::Result<Report> {
         if let Some(msg) = self.msg_buffer.pop_front() {
             log::debug!(
                 "returning an unrequested message from the buffer instead of reading it fresh"
             );
 
-            return Some(msg);
+            return Ok(msg);
+        }
+
+        let result = self.read_unrequested(timeout, wakeup);
+        if result.is_err() {
+            self.consecutive_failures = self.consecutive_failures.saturating_add(1);
         }
 
-        self.read(timeout).ok().map(|slice| slice.to_vec())
+        result
+    }
+
+    /// Waits for the next message and reads it, returning an empty message if none arrived.
+    fn read_unrequested(
+        &mut self,
+        timeout: i32,
+        _wakeup: Option<&Wakeup>,
+    ) -> anyhow::Result<Report> {
+        #[cfg(target_os = "linux")]
+        match self.wait(timeout, _wakeup) {
+            Ok(true) => {
+                // Several messages may have arrived, but the handle is only readable once
+                self.read_pending()?;
+
+                return Ok(self.msg_buffer.pop_front().unwrap_or_default());
+            }
+            Ok(false) => return Ok(Report::default()),
+            Err(err) => log::debug!("could not wait for messages, reading instead: {err}"),
+        }
+
+        self.read(timeout)
+    }
+
+    /// Waits until the device sent a message, returning `false` if the timeout expired or the
+    /// wakeup fired first.
+    ///
+    /// Unlike reading with a timeout, this allows waiting without waking up regularly, as other
+    /// threads can interrupt the wait using the wakeup.
+    #[cfg(target_os = "linux")]
+    fn wait(&mut self, timeout: i32, wakeup: Option<&Wakeup>) -> std::io::Result<bool> {
+        use std::{io::Read as _, os::fd::AsRawFd as _};
+
+        let Some(hidraw) = &self.hidraw else {
+            return Err(std::io::Error::other("the hidraw node is not open"));
+        };
+
+        let receiver = wakeup.and_then(Wakeup::receiver);
+        let mut fds = [
+            hidraw.as_raw_fd(),
+            receiver.map_or(-1, |receiver| receiver.as_raw_fd()),
+        ]
+        .map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let nfds = if receiver.is_some() { 2 } else { 1 };
+
+        // SAFETY: the pointer and length describe a valid slice of initialized `pollfd`s, whose
+        // file descriptors stay open during the call
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), nfds, timeout) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+
+            // Signals interrupt the wait like wakeups, so that their handlers take effect
+            return match err.kind() {
+                std::io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(err),
+            };
+        }
+
+        if let Some(wakeup) = wakeup {
+            wakeup.clear();
+        }
+
+        if fds[0].revents == 0 {
+            return Ok(false);
+        }
+
+        // The messages are read through the transport, so only this handle's copies are dropped
+        let mut buf = [0; MAX_REPORT_LEN];
+        while matches!((&*hidraw).read(&mut buf), Ok(len) if len > 0) {}
+
+        Ok(true)
+    }
+}
+
+/// Opens another handle to the hidraw node at the path, if the device is a hidraw node.
+#[cfg(target_os = "linux")]
+fn open_hidraw(path: &std::ffi::CStr) -> Option<std::fs::File> {
+    use std::os::unix::{ffi::OsStrExt as _, fs::OpenOptionsExt as _};
+
+    let path = std::path::Path::new(std::ffi::OsStr::from_bytes(path.to_bytes()));
+
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+    {
+        Ok(file) => Some(file),
+        Err(err) => {
+            log::debug!(
+                "could not open {} to wait for messages, polling instead: {err}",
+                path.display()
+            );
+
+            None
+        }
+    }
+}
+
+/// Returns `true` if the message is a response to a request on the same feature that was not sent
+/// by this program.
+///
+/// Notifications of the headset use the function and software ID 0, whereas requests always
+/// use a software ID, so other responses on the requested feature hint at another program
+/// talking to the headset concurrently.
+fn is_foreign_response(request: &[u8], msg: &[u8]) -> bool {
+    match (HidppReport::parse(request), HidppReport::parse(msg)) {
+        (Some(request), Some(msg)) => {
+            msg.device_index() == request.device_index()
+                && msg.feature_index() == request.feature_index()
+                && !msg.is_notification()
+                && (msg.function(), msg.software_id())
+                    != (request.function(), request.software_id())
+        }
+        _ => false,
     }
 }
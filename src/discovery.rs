@@ -0,0 +1,222 @@
+//! Finding and opening the connected headsets.
+
+use std::fmt;
+
+use crate::transport::Transport;
+
+/// The usage page of the HID interface accepting HID++ reports.
+#[cfg(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux")))]
+pub(crate) const HIDPP_USAGE_PAGE: u16 = 0xff43;
+
+/// The vendor ID of Logitech.
+#[cfg(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux")))]
+pub(crate) const VENDOR_ID: u16 = 0x046d;
+
+/// The product ID of the headset connected through its wireless receiver.
+#[cfg(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux")))]
+pub(crate) const RECEIVER_PRODUCT_ID: u16 = 0x0a87;
+
+/// The HID++ device index addressing the device the receiver belongs to, as opposed to the
+/// devices paired with it.
+pub const RECEIVER_INDEX: u8 = 0xff;
+
+/// A connected headset, as found by [`discover`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceDescriptor {
+    /// The path of the HID device, which changes when the receiver is plugged in again.
+    pub path: String,
+    /// The serial number of the USB receiver, if it reports one.
+    pub serial_number: Option<String>,
+    /// The product name of the USB receiver, if it reports one.
+    pub product: Option<String>,
+    /// The HID++ index of the headset behind the receiver.
+    ///
+    /// This is [`RECEIVER_INDEX`] for the headset's own receiver, whereas receivers hosting
+    /// several devices, such as Lightspeed receivers, address them as 1 to 6.
+    pub device_index: u8,
+    /// Whether the headset is connected using its USB cable instead of the receiver.
+    pub wired: bool,
+}
+
+impl DeviceDescriptor {
+    /// Returns the descriptor addressing the device with the HID++ index behind the same
+    /// receiver.
+    pub fn with_device_index(self, device_index: u8) -> Self {
+        Self {
+            device_index,
+            ..self
+        }
+    }
+
+    /// Returns the name of the lock file ensuring that only one instance uses this headset.
+    pub(crate) fn lock_name(&self) -> String {
+        let id = self.serial_number.as_deref().unwrap_or(&self.path);
+        let id = id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+
+        match self.device_index {
+            RECEIVER_INDEX => format!("g935-{id}.lock"),
+            index => format!("g935-{id}-{index}.lock"),
+        }
+    }
+
+    /// Returns the descriptor of the same headset among the connected ones, which may have
+    /// another path now.
+    pub(crate) fn find_again(&self) -> anyhow::Result<Self> {
+        let headsets = discover()?;
+
+        headsets
+            .iter()
+            .find(|headset| {
+                self.serial_number.is_some() && headset.serial_number == self.serial_number
+            })
+            .or_else(|| headsets.iter().find(|headset| headset.path == self.path))
+            .map(|headset| headset.clone().with_device_index(self.device_index))
+            .ok_or_else(|| anyhow::anyhow!("the headset {self} is not connected anymore"))
+    }
+}
+
+impl fmt::Display for DeviceDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, " (serial number {serial_number})")?;
+        }
+        if self.wired {
+            write!(f, " (wired)")?;
+        }
+        if self.device_index != RECEIVER_INDEX {
+            write!(f, " (device {})", self.device_index)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if the USB device is the headset, either connected through its receiver or
+/// using its cable.
+///
+/// The headset uses another product ID when connected using its cable, so it is recognized by
+/// its name then.
+#[cfg(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux")))]
+pub(crate) fn is_headset(vendor_id: u16, product_id: u16, product: Option<&str>) -> bool {
+    vendor_id == VENDOR_ID
+        && (product_id == RECEIVER_PRODUCT_ID || product.is_some_and(|name| name.contains("G935")))
+}
+
+/// Returns all connected headsets.
+///
+/// Each of them can be opened using [`crate::Headset::open_descriptor`], for example to run one
+/// loop per headset in its own thread.
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub fn discover() -> anyhow::Result<Vec<DeviceDescriptor>> {
+    Ok(crate::hidraw::discover())
+}
+
+/// Returns all connected headsets.
+///
+/// Each of them can be opened using [`crate::Headset::open_descriptor`], for example to run one
+/// loop per headset in its own thread.
+#[cfg(all(feature = "hidapi", not(all(feature = "hidraw", target_os = "linux"))))]
+pub fn discover() -> anyhow::Result<Vec<DeviceDescriptor>> {
+    let api = hidapi::HidApi::new()?;
+    let mut interfaces = api
+        .device_list()
+        .filter(|info| is_headset(info.vendor_id(), info.product_id(), info.product_string()))
+        .collect::<Vec<_>>();
+
+    // Windows lists every top level collection as its own device, of which only the one with the
+    // vendor usage page accepts HID++ reports, whereas Linux lists them all with one path
+    if interfaces
+        .iter()
+        .any(|info| info.usage_page() == HIDPP_USAGE_PAGE)
+    {
+        interfaces.retain(|info| info.usage_page() == HIDPP_USAGE_PAGE);
+    }
+
+    let mut headsets = Vec::<DeviceDescriptor>::new();
+    for info in interfaces {
+        let path = info.path().to_string_lossy().into_owned();
+        if headsets.iter().any(|headset| headset.path == path) {
+            continue;
+        }
+
+        headsets.push(DeviceDescriptor {
+            path,
+            serial_number: info
+                .serial_number()
+                .filter(|serial_number| !serial_number.is_empty())
+                .map(str::to_string),
+            product: info.product_string().map(str::to_string),
+            device_index: RECEIVER_INDEX,
+            wired: info.product_id() != RECEIVER_PRODUCT_ID,
+        });
+    }
+
+    Ok(headsets)
+}
+
+/// Returns all connected headsets, which are none, as no backend for HID devices was built.
+#[cfg(not(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux"))))]
+pub fn discover() -> anyhow::Result<Vec<DeviceDescriptor>> {
+    Ok(Vec::new())
+}
+
+/// Opens the hidraw node of the headset, returning it together with its path.
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub(crate) fn open_transport(
+    descriptor: &DeviceDescriptor,
+) -> anyhow::Result<(Box<dyn Transport>, Option<std::ffi::CString>)> {
+    let hidraw = crate::hidraw::Hidraw::open_path(std::path::Path::new(&descriptor.path))?;
+    let path = hidraw.c_path();
+
+    Ok((Box::new(hidraw), path))
+}
+
+/// Opens the HID device of the headset using hidapi, returning it together with its path.
+#[cfg(all(feature = "hidapi", not(all(feature = "hidraw", target_os = "linux"))))]
+pub(crate) fn open_transport(
+    descriptor: &DeviceDescriptor,
+) -> anyhow::Result<(Box<dyn Transport>, Option<std::ffi::CString>)> {
+    let api = hidapi::HidApi::new()?;
+    let path = std::ffi::CString::new(descriptor.path.as_str())?;
+
+    let hid_device = match api.open_path(&path) {
+        Ok(hid_device) => hid_device,
+        // hidapi does not tell why opening failed, so check whether access is denied
+        #[cfg(target_os = "linux")]
+        Err(_) if is_permission_denied(&descriptor.path) => {
+            return Err(crate::Error::PermissionDenied {
+                path: descriptor.path.clone(),
+            }
+            .into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok((Box::new(hid_device), Some(path)))
+}
+
+/// Fails, as no backend for opening HID devices was built.
+#[cfg(not(any(feature = "hidapi", all(feature = "hidraw", target_os = "linux"))))]
+pub(crate) fn open_transport(
+    _descriptor: &DeviceDescriptor,
+) -> anyhow::Result<(Box<dyn Transport>, Option<std::ffi::CString>)> {
+    anyhow::bail!(
+        "g935 was built without hidapi or hidraw, so only Headset::open_with_transport works"
+    )
+}
+
+/// Returns `true` if the device at the path exists, but the user is not allowed to open it.
+#[cfg(all(feature = "hidapi", not(feature = "hidraw"), target_os = "linux"))]
+fn is_permission_denied(path: &str) -> bool {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .is_err_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+}
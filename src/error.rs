@@ -0,0 +1,75 @@
+//! Errors that callers may want to handle specifically.
+//!
+//! These are returned inside of `anyhow::Error`, so they can be recovered using
+//! `anyhow::Error::downcast_ref`.
+
+use std::fmt;
+
+/// The udev rules giving the logged in user access to the headset, connected through its
+/// receiver or using its cable.
+pub const UDEV_RULES: &str = concat!(
+    r#"KERNEL=="hidraw*", ATTRS{idVendor}=="046d", ATTRS{idProduct}=="0a87", TAG+="uaccess""#,
+    "\n",
+    r#"KERNEL=="hidraw*", ATTRS{idVendor}=="046d", ATTRS{product}=="*G935*", TAG+="uaccess""#,
+);
+
+/// An error with a dedicated meaning.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The headset is used by another program.
+    DeviceBusy {
+        /// Why the headset is believed to be busy.
+        reason: String,
+    },
+    /// The headset was found, but the user is not allowed to open it.
+    PermissionDenied {
+        /// The path of the device that could not be opened.
+        path: String,
+    },
+    /// The headset does not support the feature.
+    Unsupported {
+        /// The name of the feature.
+        feature: String,
+    },
+    /// The headset answered a change with another value than requested, even when asked again.
+    SettingRejected {
+        /// The setting that was changed.
+        setting: String,
+        /// The requested value.
+        requested: String,
+        /// The value the headset reported instead.
+        reported: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DeviceBusy { reason } => write!(
+                f,
+                "the headset is busy: {reason}; make sure that no other g935 instance, \
+                 headsetcontrol or G HUB is using it, or use `g935 ctl` to talk to a running \
+                 `g935 daemon`"
+            ),
+            Error::PermissionDenied { path } => write!(
+                f,
+                "permission denied to open {path}; run `sudo g935 install-udev-rules` or add the \
+                 udev rules\n{UDEV_RULES}\nto a file in /etc/udev/rules.d, then replug the receiver"
+            ),
+            Error::Unsupported { feature } => {
+                write!(f, "the headset does not support {feature}")
+            }
+            Error::SettingRejected {
+                setting,
+                requested,
+                reported,
+            } => write!(
+                f,
+                "the headset rejected {setting}: it reported {reported} instead of {requested}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
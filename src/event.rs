@@ -0,0 +1,66 @@
+//! Events reported by the headset.
+
+use std::time::{Duration, Instant};
+
+use crate::{ButtonState, PowerState};
+
+/// An event reported by the headset, as returned by [`Headset::poll_once`](crate::Headset::poll_once).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// What happened.
+    pub kind: EventKind,
+    /// The number of the event, counting up from 0 for each opened headset.
+    pub sequence: u64,
+    /// When the event was read.
+    pub time: Instant,
+    /// When the event was read according to [`monotonic_time`], for correlating it with other
+    /// logs.
+    pub timestamp: Duration,
+}
+
+/// What happened in an [`Event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A button, the wheel or the microphone arm changed its state.
+    ///
+    /// While a button is held, the state is also repeated without a change, so that timed events
+    /// like long presses can be detected.
+    Buttons(ButtonState),
+    /// The headset was turned on or off.
+    PowerState(PowerState),
+}
+
+/// A report of the headset that the library does not understand, as passed to the handler set
+/// with [`RunConfig::set_raw_report_handler`](crate::config::RunConfig::set_raw_report_handler).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawReport {
+    /// The bytes of the report, starting with the report ID.
+    pub bytes: Vec<u8>,
+    /// The ID of the feature the report belongs to, as listed in the HID++ specification, if it
+    /// is a HID++ report of one of the features the library knows.
+    pub feature_id: Option<u16>,
+}
+
+/// Returns the current time of the monotonic clock.
+///
+/// On Linux, this is the time since boot that is also used by the kernel and the journal (see
+/// `journalctl -o short-monotonic`), elsewhere it is the time since this function was first
+/// called.
+pub fn monotonic_time() -> Duration {
+    #[cfg(target_os = "linux")]
+    {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+
+        // SAFETY: the pointer is valid for writing a `timespec`
+        if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) } == 0 {
+            return Duration::new(time.tv_sec as u64, time.tv_nsec as u32);
+        }
+    }
+
+    static EPOCH: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
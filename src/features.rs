@@ -1,32 +1,155 @@
 //! Deals with features.
 
-use crate::device::Device;
+use std::fmt;
+
+use crate::{
+    device::{Device, Report},
+    protocol::{self, feature, function_byte, LONG_REPORT_LEN},
+    report::FeatureResponse,
+    Error,
+};
 
 /// Represents a feature on the device.
 #[derive(Debug)]
 pub(crate) struct Feature {
     /// The index of the feature.
     index: u8,
+    /// The ID of the feature, as listed in the HID++ specification.
+    id: u16,
 }
 
 impl Feature {
-    /// Makes a request on the feature.
-    pub(crate) fn request(&self, device: &mut Device, body: &[u8]) -> anyhow::Result<Vec<u8>> {
-        assert!(
-            body.len() <= 17,
-            "feature request can be at most 17 bytes large"
+    /// Returns `true` if the device supports the feature.
+    ///
+    /// Unsupported features are resolved to the index 0, which belongs to the root feature.
+    pub(crate) fn is_supported(&self) -> bool {
+        self.index != 0 || self.id == feature::ROOT
+    }
+
+    /// Fails with [`Error::Unsupported`] if the device does not support the feature, which is
+    /// described by the name.
+    pub(crate) fn ensure_supported(&self, name: &str) -> anyhow::Result<()> {
+        if !self.is_supported() {
+            return Err(Error::Unsupported {
+                feature: name.to_string(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the report requesting the function of the feature given by the body, which starts
+    /// with the function byte.
+    fn report(&self, device: &Device, body: &[u8]) -> [u8; LONG_REPORT_LEN] {
+        let (function, params) = body
+            .split_first()
+            .expect("feature request starts with its function");
+
+        protocol::encode_request(device.device_index, self.index, function >> 4, params)
+            .expect("feature request can be at most 17 bytes large")
+    }
+
+    /// Makes a request on the feature that changes a setting, which is described by `meaning`.
+    ///
+    /// In dry-run mode, the request is only logged and answered as if the device confirmed it.
+    pub(crate) fn change(
+        &self,
+        device: &mut Device,
+        body: &[u8],
+        meaning: fmt::Arguments,
+    ) -> anyhow::Result<FeatureResponse> {
+        if device.dry_run {
+            let data = self.report(device, body);
+            log::info!("dry run, not writing {data:02x?}: {meaning}");
+
+            return FeatureResponse::new(Report::new(&data));
+        }
+
+        ensure_trusted(device, meaning)?;
+
+        self.request(device, body)
+    }
+
+    /// Makes requests on the feature that change settings right after each other, without
+    /// waiting for each reply, see [`Feature::change`].
+    pub(crate) fn change_all(
+        &self,
+        device: &mut Device,
+        bodies: &[&[u8]],
+        meaning: fmt::Arguments,
+    ) -> anyhow::Result<Vec<FeatureResponse>> {
+        let data = bodies
+            .iter()
+            .map(|body| self.report(device, body))
+            .collect::<Vec<_>>();
+
+        if device.dry_run {
+            for data in &data {
+                log::info!("dry run, not writing {data:02x?}: {meaning}");
+            }
+
+            return data
+                .iter()
+                .map(|data| FeatureResponse::new(Report::new(data)))
+                .collect();
+        }
+
+        ensure_trusted(device, meaning)?;
+
+        log::trace!(
+            "requesting {} functions of feature {:#06x} at once",
+            data.len(),
+            self.id
         );
 
-        let mut data = [0; 20];
-        data[0] = 0x11;
-        data[1] = 0xff;
-        data[2] = self.index;
+        let requests = data.iter().map(|data| &data[..]).collect::<Vec<_>>();
+
+        device
+            .request_all(&requests)?
+            .into_iter()
+            .map(FeatureResponse::new)
+            .collect()
+    }
+
+    /// Makes a request on the feature.
+    pub(crate) fn request(
+        &self,
+        device: &mut Device,
+        body: &[u8],
+    ) -> anyhow::Result<FeatureResponse> {
+        let data = self.report(device, body);
 
-        data[3..body.len() + 3].copy_from_slice(body);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "feature_request",
+            feature = format_args!("{:#06x}", self.id),
+            index = self.index,
+            function = body.first().map_or(0, |function| function >> 4),
+        )
+        .entered();
+        #[cfg(not(feature = "tracing"))]
+        log::trace!(
+            "requesting function {} of feature {:#06x}",
+            body.first().map_or(0, |function| function >> 4),
+            self.id
+        );
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
 
-        let response = device.request(&data)?;
+        let response = device.request(&data);
 
-        Ok(response.to_vec())
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            latency_us = u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX),
+            outcome = match &response {
+                Ok(_) => "ok".to_string(),
+                Err(err) => format!("{err:#}"),
+            },
+            "feature request finished"
+        );
+
+        FeatureResponse::new(response?)
     }
 }
 
@@ -54,6 +177,20 @@ impl PartialEq<Feature> for &u8 {
     }
 }
 
+/// Fails unless the device may be changed, which is described by `meaning`.
+///
+/// Changes are refused if the responses of the device do not match any known layout, unless
+/// they are forced.
+fn ensure_trusted(device: &Device, meaning: fmt::Arguments) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !device.untrusted || device.force,
+        "not trying to {meaning}, as the responses of the headset do not match any known \
+         layout; force it if you are sure that the headset behaves like a G935"
+    );
+
+    Ok(())
+}
+
 /// Resolves the feature with the given ID.
 fn resolve_feature(
     root_feature: &Feature,
@@ -62,9 +199,13 @@ fn resolve_feature(
 ) -> anyhow::Result<Feature> {
     let feat_bytes = feature.to_be_bytes();
 
-    let response = root_feature.request(device, &[0x01, feat_bytes[0], feat_bytes[1]])?;
+    let response =
+        root_feature.request(device, &[function_byte(0), feat_bytes[0], feat_bytes[1]])?;
 
-    Ok(Feature { index: response[4] })
+    Ok(Feature {
+        index: response.params()[0],
+        id: feature,
+    })
 }
 
 macro_rules! feature_map {
@@ -80,7 +221,7 @@ macro_rules! feature_map {
         impl $name {
             /// Initializes the feature map from the given `Device`.
             $vis fn initialize(device: &mut Device) -> anyhow::Result<Self> {
-                let root = Feature { index: 0 };
+                let root = Feature { index: 0, id: feature::ROOT };
 
                 Ok(Self {
                     $(
@@ -88,6 +229,15 @@ macro_rules! feature_map {
                     )*
                 })
             }
+
+            /// Returns the ID of the supported feature with the index, if it is one of the
+            /// features of the map.
+            $vis fn id_of(&self, index: u8) -> Option<u16> {
+                [$(&self.$feature),*]
+                    .into_iter()
+                    .find(|feature| feature.is_supported() && feature.index == index)
+                    .map(|feature| feature.id)
+            }
         }
     };
 }
@@ -97,20 +247,28 @@ feature_map! {
     #[derive(Debug)]
     pub(crate) struct FeatureMap {
         /// The root feature used for discovering other features.
-        root: 0x0000,
+        root: feature::ROOT,
+        /// The feature used to list all features of the device.
+        feature_set: feature::FEATURE_SET,
         /// The feature used to read battery levels and charging status.
-        battery: 0x1f20,
+        battery: feature::BATTERY_VOLTAGE,
+        /// The feature reporting the state of charge directly, if the firmware supports it.
+        unified_battery: feature::UNIFIED_BATTERY,
         // /// The feature used for information about the device and firmware.
         // devinfo: 0x0002,
         /// The feature used to read the device name.
-        devname: 0x0005,
+        devname: feature::DEVICE_NAME,
         /// The feature that allows access to the GKey buttons.
-        gkey: 0x8010,
+        gkey: feature::GKEY,
         /// The feature that controls the LEDs.
-        lights: 0x8070,
-        // /// The feature that controls side tones.
-        // sidetone: 0x8300,
-        // /// The feature that controls the equalizer.
-        // eq: 0x8310,
+        lights: feature::COLOR_LED_EFFECTS,
+        /// The feature that controls side tones.
+        sidetone: feature::SIDETONE,
+        /// The feature that controls the equalizer.
+        eq: feature::EQUALIZER,
+        /// The feature that controls the report rate, if the receiver supports it.
+        report_rate: feature::REPORT_RATE,
+        /// The feature reporting changes of the wireless link, if the headset supports it.
+        wireless: feature::WIRELESS_STATUS,
     }
 }
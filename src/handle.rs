@@ -0,0 +1,101 @@
+//! A handle to control a running headset from other threads.
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    config::RunConfig,
+    lights::{self, Effect, Light},
+    BatteryStatus, Headset, Waker,
+};
+
+/// A job run by the loop polling the headset.
+pub(crate) type Job = Box<dyn FnOnce(&mut RunConfig, &mut Headset) + Send>;
+
+/// A handle to a headset that is run using a [`RunConfig`], usable from other threads.
+///
+/// The requests are performed by [`Headset::run_with_config`] or [`Headset::poll_once`] between
+/// two events of the headset, waking the polling loop if it is waiting. Requests fail once the
+/// config was dropped.
+#[derive(Debug, Clone)]
+pub struct HeadsetHandle {
+    /// The channel to send the jobs on.
+    pub(crate) jobs: Sender<Job>,
+    /// The waker making the loop run the jobs right away.
+    pub(crate) waker: Waker,
+}
+
+impl HeadsetHandle {
+    /// Runs the function with the config and the headset in the polling loop, returning its
+    /// result.
+    pub fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut RunConfig, &mut Headset) -> T + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let (reply, result) = crossbeam_channel::bounded(1);
+
+        self.jobs
+            .send(Box::new(move |config, headset| {
+                // The caller waits for the result, unless it panicked
+                reply.send(f(config, headset)).ok();
+            }))
+            .map_err(|_| anyhow::anyhow!("the headset is no longer running"))?;
+        self.waker.wake();
+
+        result
+            .recv()
+            .map_err(|_| anyhow::anyhow!("the headset is no longer running"))
+    }
+
+    /// Returns the battery status of the headset.
+    pub fn get_battery_status(&self) -> anyhow::Result<BatteryStatus> {
+        self.run(|_, headset| headset.get_battery_status())?
+    }
+
+    /// Sets the effect of the light, keeping it in the config so it is restored after reconnects.
+    pub fn set_lights(&self, light: Light, effect: Effect) -> anyhow::Result<()> {
+        self.run(move |config, headset| {
+            headset.set_lights(&lights::LightSettings {
+                light,
+                effect,
+                profile_type: lights::ProfileType::Temporary,
+            })?;
+
+            match light {
+                Light::Side => config.set_side_light_effect(effect),
+                Light::Logo => config.set_logo_light_effect(effect),
+            }
+
+            Ok(())
+        })?
+    }
+
+    /// Returns the sidetone level in percent.
+    pub fn get_sidetone(&self) -> anyhow::Result<u8> {
+        self.run(|_, headset| headset.get_sidetone())?
+    }
+
+    /// Sets the sidetone level in percent, keeping it in the config.
+    pub fn set_sidetone(&self, level: u8) -> anyhow::Result<()> {
+        self.run(move |config, headset| {
+            headset.set_sidetone(level)?;
+            config.set_sidetone(Some(level));
+
+            Ok(())
+        })?
+    }
+
+    /// Returns the gain in dB of each equalizer band.
+    pub fn get_equalizer(&self) -> anyhow::Result<Vec<i8>> {
+        self.run(|_, headset| headset.get_equalizer())?
+    }
+
+    /// Sets the gain in dB of each equalizer band, keeping it in the config.
+    pub fn set_equalizer(&self, gains: Vec<i8>) -> anyhow::Result<()> {
+        self.run(move |config, headset| {
+            headset.set_equalizer(&gains)?;
+            config.set_equalizer(Some(gains));
+
+            Ok(())
+        })?
+    }
+}
@@ -0,0 +1,220 @@
+//! Talks to the headset through its Linux hidraw node directly, without hidapi.
+//!
+//! The node is found using the attributes udev exposes in sysfs, so neither hidapi nor libudev
+//! are needed, and its file descriptor can be polled by other event loops.
+
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{Read as _, Write as _},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::{ffi::OsStrExt as _, fs::OpenOptionsExt as _},
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::{
+    discovery::{
+        is_headset, DeviceDescriptor, HIDPP_USAGE_PAGE, RECEIVER_INDEX, RECEIVER_PRODUCT_ID,
+    },
+    transport::Transport,
+    Error,
+};
+
+/// The directory listing the hidraw nodes in sysfs.
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+
+/// The bus type of USB devices in the `HID_ID` of their uevent.
+const BUS_USB: u32 = 0x0003;
+
+/// An open hidraw node of the headset.
+#[derive(Debug)]
+pub struct Hidraw {
+    /// The opened node.
+    file: File,
+    /// The path of the node.
+    path: PathBuf,
+}
+
+impl Hidraw {
+    /// Finds and opens the hidraw node of the first headset accepting HID++ reports.
+    pub fn open() -> anyhow::Result<Self> {
+        let descriptor = discover()
+            .into_iter()
+            .next()
+            .context("the headset was not found")?;
+
+        Self::open_path(Path::new(&descriptor.path))
+    }
+
+    /// Opens the hidraw node at the path.
+    pub fn open_path(path: &Path) -> anyhow::Result<Self> {
+        let file = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(Error::PermissionDenied {
+                    path: path.display().to_string(),
+                }
+                .into());
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("could not open {}", path.display()))
+            }
+        };
+
+        Ok(Self {
+            file,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Returns the path of the node.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the node as a C string, as used for HID paths.
+    pub(crate) fn c_path(&self) -> Option<CString> {
+        CString::new(self.path.as_os_str().as_bytes()).ok()
+    }
+}
+
+impl AsRawFd for Hidraw {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl Transport for Hidraw {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        Ok(self.file.write(report)?)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: the pointer describes a single initialized `pollfd`, whose file descriptor stays
+        // open during the call
+        let ready = unsafe { libc::poll(&mut fd, 1, timeout) };
+        if ready < 0 {
+            let err = std::io::Error::last_os_error();
+
+            return match err.kind() {
+                std::io::ErrorKind::Interrupted => Ok(0),
+                _ => Err(err.into()),
+            };
+        }
+
+        match self.file.read(buf) {
+            Ok(len) => Ok(len),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn product(&self) -> Option<String> {
+        uevent_value(&sysfs_dir(&self.path)?, "HID_NAME")
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        uevent_value(&sysfs_dir(&self.path)?, "HID_UNIQ").filter(|serial| !serial.is_empty())
+    }
+}
+
+/// Returns the hidraw nodes of all connected headsets accepting HID++ reports.
+///
+/// Each headset has several nodes, of which the ones whose report descriptor declares the HID++
+/// usage page are preferred.
+pub(crate) fn discover() -> Vec<DeviceDescriptor> {
+    let Ok(entries) = std::fs::read_dir(SYSFS_HIDRAW) else {
+        return Vec::new();
+    };
+    let mut nodes = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|dir| {
+            product_id(dir).is_some_and(|(vendor_id, product_id)| {
+                is_headset(
+                    vendor_id,
+                    product_id,
+                    uevent_value(dir, "HID_NAME").as_deref(),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    nodes.sort();
+
+    let [low, high] = HIDPP_USAGE_PAGE.to_le_bytes();
+    let hidpp_nodes = nodes
+        .iter()
+        .filter(|dir| {
+            std::fs::read(dir.join("device/report_descriptor")).is_ok_and(|descriptor| {
+                // A usage page item with two bytes of data
+                descriptor.windows(3).any(|item| item == [0x06, low, high])
+            })
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if !hidpp_nodes.is_empty() {
+        nodes = hidpp_nodes;
+    }
+
+    nodes
+        .iter()
+        .filter_map(|dir| {
+            Some(DeviceDescriptor {
+                path: Path::new("/dev")
+                    .join(dir.file_name()?)
+                    .to_string_lossy()
+                    .into_owned(),
+                serial_number: uevent_value(dir, "HID_UNIQ").filter(|serial| !serial.is_empty()),
+                product: uevent_value(dir, "HID_NAME"),
+                device_index: RECEIVER_INDEX,
+                wired: product_id(dir).is_some_and(|(_, id)| id != RECEIVER_PRODUCT_ID),
+            })
+        })
+        .collect()
+}
+
+/// Returns the vendor and product ID of the USB device of the hidraw node, parsed from the
+/// `HID_ID` in its uevent, such as `0003:0000046D:00000A87`.
+fn product_id(dir: &Path) -> Option<(u16, u16)> {
+    let id = uevent_value(dir, "HID_ID")?;
+    let mut parts = id.split(':').map(|part| u32::from_str_radix(part, 16).ok());
+
+    match (parts.next()??, parts.next()??, parts.next()??) {
+        (BUS_USB, vendor_id, product_id) => Some((
+            u16::try_from(vendor_id).ok()?,
+            u16::try_from(product_id).ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Returns the directory in sysfs describing the hidraw node at the path.
+fn sysfs_dir(path: &Path) -> Option<PathBuf> {
+    Some(Path::new(SYSFS_HIDRAW).join(path.file_name()?))
+}
+
+/// Returns the value of the key in the uevent of the HID device of the hidraw node.
+fn uevent_value(dir: &Path, key: &str) -> Option<String> {
+    let uevent = std::fs::read_to_string(dir.join("device/uevent")).ok()?;
+
+    uevent.lines().find_map(|line| {
+        let (line_key, value) = line.split_once('=')?;
+
+        (line_key == key).then(|| value.to_string())
+    })
+}
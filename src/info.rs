@@ -0,0 +1,30 @@
+//! Information identifying the headset.
+
+use std::fmt;
+
+/// Information about the connected headset.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeadsetInfo {
+    /// The name the headset reports for itself.
+    pub name: String,
+    /// The major and minor version of the protocol used by the headset.
+    pub protocol_version: (u8, u8),
+    /// The serial number of the USB receiver, if it reports one.
+    pub serial_number: Option<String>,
+    /// The product name of the USB receiver.
+    pub model: String,
+}
+
+impl fmt::Display for HeadsetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (major, minor) = self.protocol_version;
+        write!(f, "{} ({}, protocol {major}.{minor}", self.name, self.model)?;
+
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, ", serial number {serial_number}")?;
+        }
+
+        write!(f, ")")
+    }
+}
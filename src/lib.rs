@@ -4,25 +4,97 @@
 #![warn(missing_debug_implementations)]
 #![warn(unreachable_pub)]
 
+#[cfg(feature = "ambient")]
+pub mod ambient;
+#[cfg(feature = "audio")]
+pub mod audio;
 mod battery;
+pub mod bindings;
 mod buttons;
+mod capabilities;
 pub mod config;
 mod device;
+mod discovery;
+mod error;
+mod event;
 mod features;
+mod handle;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub mod hidraw;
+mod info;
 pub mod lights;
+pub mod macros;
+#[cfg(feature = "alsa")]
+pub mod mic_mute;
+#[cfg(feature = "mpris")]
+pub mod mpris;
+pub mod mute_indicator;
+#[cfg(feature = "obs")]
+pub mod obs;
+pub mod plugin;
 mod power_state;
+pub mod prelude;
+pub mod profiles;
+pub mod protocol;
+pub mod quirks;
+mod report;
+mod restore;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+mod stats;
+mod stop;
+pub mod transport;
+#[cfg(all(feature = "uinput", target_os = "linux"))]
+pub mod uinput;
+mod waker;
+mod wireless;
 
-use buttons::{Buttons, MicArm, Wheel};
-use config::Config;
-use device::Device;
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+
+use buttons::{Buttons, Debouncer};
+use config::{ErrorPolicy, RestorePolicy};
+use device::{Device, Report};
 use features::FeatureMap;
+use power_state::ConnectionState;
+use protocol::{function_byte, HidppReport, Message};
+use quirks::Quirks;
+use transport::{Capture, CaptureFile, Transport};
 
 pub use crate::{
-    battery::{BatteryStatus, ChargingStatus},
-    buttons::ButtonState,
+    battery::{BatteryModel, BatteryStatus, ChargingStatus, DefaultBatteryModel},
+    buttons::{ButtonChange, ButtonChanges, ButtonChangesIter, ButtonState, MicArm, Wheel},
+    capabilities::Capabilities,
+    config::RunConfig,
+    discovery::{discover, DeviceDescriptor, RECEIVER_INDEX},
+    error::{Error, UDEV_RULES},
+    event::{monotonic_time, Event, EventKind, RawReport},
+    handle::HeadsetHandle,
+    info::HeadsetInfo,
+    lights::LightSettings,
     power_state::PowerState,
+    restore::RestoreOnDrop,
+    stats::{LatencyHistogram, Stats, LATENCY_BUCKETS_MS},
+    stop::StopToken,
+    waker::Waker,
+    wireless::WirelessStatus,
 };
 
+/// How often the button state is repeated while a button is held, for the timed events.
+const TICK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long the loop waits after reading a message failed, before reading again.
+const READ_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+/// How often the headset is pinged while it is idle, to notice when it was turned off or on
+/// without reporting it.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(20);
+
 /// Convert a struct that implements this trait to bytes
 trait AsBytes {
     /// Convert a struct that implements this trait to bytes
@@ -42,54 +114,699 @@ pub struct Headset {
     device: Device,
     /// The features of the headset.
     features: FeatureMap,
+    /// The information about the headset, read when it was opened.
+    info: HeadsetInfo,
+    /// How the headset deviates from the tested one, detected when it was opened.
+    quirks: Quirks,
+    /// What the headset supports, detected when it was opened.
+    capabilities: Capabilities,
+    /// The state of the buttons, as tracked by [`Headset::poll_once`].
+    button_state: ButtonState,
+    /// When the headset was last pinged while it was idle.
+    last_keepalive: Instant,
+    /// Drops repeated reports of the microphone arm.
+    mic_arm_debouncer: Debouncer,
+    /// Drops repeated reports of the mute button.
+    mute_debouncer: Debouncer,
+    /// The last message the headset sent on its own, to notice when it is repeated.
+    previous_report: Option<Report>,
+    /// The state of the connection, as tracked by [`Headset::poll_once`].
+    state: ConnectionState,
+    /// The power states entered but not reported as events yet.
+    pending_power_states: VecDeque<PowerState>,
+    /// The sequence number of the next event.
+    sequence: u64,
+    /// The battery status last read or reported, and when that happened.
+    battery: Option<(BatteryStatus, Instant)>,
+    /// Whether the headset reports its state of charge, which is preferred over the estimate
+    /// from the voltage.
+    reports_charge: bool,
+    /// Estimates the charge from the voltage, unless the headset reports it.
+    battery_model: Box<dyn BatteryModel>,
+    /// The undimmed effect last shown on the side light, to change its brightness.
+    side_effect: Option<lights::Effect>,
+    /// The undimmed effect last shown on the logo light, to change its brightness.
+    logo_effect: Option<lights::Effect>,
+    /// The effect the headset last confirmed showing on the side light, to skip writing it again.
+    side_acknowledged: Option<lights::Effect>,
+    /// The effect the headset last confirmed showing on the logo light, to skip writing it again.
+    logo_acknowledged: Option<lights::Effect>,
+    /// How the connection was opened, to open it again.
+    source: Source,
+    /// The lock ensuring that only one instance uses the headset, held while it is open.
+    _lock: Option<std::fs::File>,
+}
+
+/// How the connection to a headset was opened.
+#[derive(Debug)]
+enum Source {
+    /// The HID device was opened, recording the reports to the capture file if there is one.
+    Hid {
+        /// The headset that was opened.
+        descriptor: DeviceDescriptor,
+        /// The file the reports are recorded to.
+        capture: Option<CaptureFile>,
+    },
+    /// A transport was given, which cannot be opened again.
+    Transport,
 }
 
 impl Headset {
-    /// Opens a connection to the headset.
+    /// Opens a connection to the first connected headset.
+    ///
+    /// Fails with [`Error::DeviceBusy`] if another instance already opened the headset.
     pub fn open() -> anyhow::Result<Self> {
-        let api = hidapi::HidApi::new()?;
-        let mut device = Device::new(api.open(0x046d, 0x0a87)?);
+        Self::open_hid(&Self::first()?, None)
+    }
 
+    /// Opens a connection to the first connected headset, recording all exchanged reports to
+    /// the capture file.
+    ///
+    /// The capture can be replayed using [`transport::Replay`], which needs the `serde` feature.
+    pub fn open_with_capture(capture: CaptureFile) -> anyhow::Result<Self> {
+        Self::open_hid(&Self::first()?, Some(capture))
+    }
+
+    /// Opens a connection to the headset returned by [`discover`].
+    ///
+    /// Fails with [`Error::DeviceBusy`] if another instance already opened the headset.
+    pub fn open_descriptor(descriptor: &DeviceDescriptor) -> anyhow::Result<Self> {
+        Self::open_hid(descriptor, None)
+    }
+
+    /// Opens a connection to the headset returned by [`discover`], recording all exchanged
+    /// reports to the capture file.
+    pub fn open_descriptor_with_capture(
+        descriptor: &DeviceDescriptor,
+        capture: CaptureFile,
+    ) -> anyhow::Result<Self> {
+        Self::open_hid(descriptor, Some(capture))
+    }
+
+    /// Opens a connection to the headset using the transport, for example to replay a capture.
+    ///
+    /// Unlike [`Headset::open`], this does not ensure that only one instance uses the headset,
+    /// and [`Headset::reset_connection`] keeps using the same transport.
+    pub fn open_with_transport(transport: impl Transport + 'static) -> anyhow::Result<Self> {
+        let mut device = Device::new(Box::new(transport), None, RECEIVER_INDEX);
         let features = features::FeatureMap::initialize(&mut device)?;
 
-        log::debug!("read feature map: {:?}", features);
+        Self::init(device, features, Source::Transport, None)
+    }
+
+    /// Returns the first connected headset.
+    fn first() -> anyhow::Result<DeviceDescriptor> {
+        discover()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the headset was not found"))
+    }
+
+    /// Opens the HID device, recording the reports to the capture file if there is one.
+    fn open_hid(
+        descriptor: &DeviceDescriptor,
+        capture: Option<CaptureFile>,
+    ) -> anyhow::Result<Self> {
+        let lock = Self::lock(descriptor)?;
+
+        let (device, features) = Self::open_device(descriptor, capture.as_ref())?;
+        let source = Source::Hid {
+            descriptor: descriptor.clone(),
+            capture,
+        };
 
-        let mut headset = Self { device, features };
+        Self::init(device, features, source, Some(lock))
+    }
+
+    /// Reads the information of the opened headset.
+    fn init(
+        device: Device,
+        features: FeatureMap,
+        source: Source,
+        lock: Option<std::fs::File>,
+    ) -> anyhow::Result<Self> {
+        let mut headset = Self {
+            device,
+            features,
+            // Filled in below, as reading it requires the headset
+            info: HeadsetInfo::default(),
+            quirks: Quirks::default(),
+            capabilities: Capabilities::default(),
+            button_state: ButtonState::default(),
+            last_keepalive: Instant::now(),
+            mic_arm_debouncer: Debouncer::default(),
+            mute_debouncer: Debouncer::default(),
+            previous_report: None,
+            state: ConnectionState::Connected,
+            pending_power_states: VecDeque::new(),
+            sequence: 0,
+            battery: None,
+            reports_charge: false,
+            battery_model: Box::new(DefaultBatteryModel),
+            side_effect: None,
+            logo_effect: None,
+            side_acknowledged: None,
+            logo_acknowledged: None,
+            source,
+            _lock: lock,
+        };
 
         let (ver1, ver2) = headset.get_protocol_version()?;
-        if (ver1, ver2) != (4, 2) {
-            log::warn!("this code was tested with protocol version 4.2, found protocol version {ver1}.{ver2} instead");
+        log::debug!("found protocol version {ver1}.{ver2}");
+
+        headset.info = HeadsetInfo {
+            name: headset.get_device_name()?,
+            protocol_version: (ver1, ver2),
+            serial_number: headset.device.serial_number(),
+            model: headset
+                .device
+                .product()
+                .unwrap_or_else(|| "G935".to_string()),
+        };
+
+        log::info!("connected to device {}", headset.info);
+
+        headset.quirks = match Quirks::known(&headset.info) {
+            Some(quirks) => quirks,
+            None => headset.negotiate_quirks(),
+        };
+        headset.reports_charge = headset.probe_state_of_charge();
+        headset.capabilities = headset.detect_capabilities();
+
+        Ok(headset)
+    }
+
+    /// Finds out what the headset supports from its features, asking it only about the details
+    /// that are not known from its model.
+    fn detect_capabilities(&mut self) -> Capabilities {
+        let light_zones = if self.features.lights.is_supported() {
+            Capabilities::known_light_zones(&self.info.name).unwrap_or_else(|| {
+                // The first byte of the information holds the number of zones
+                match self
+                    .features
+                    .lights
+                    .request(&mut self.device, &[function_byte(0)])
+                {
+                    Ok(response) => response.params()[0],
+                    Err(err) => {
+                        log::debug!("could not read the number of light zones: {err}");
+
+                        0
+                    }
+                }
+            })
+        } else {
+            0
+        };
+
+        let eq_bands = if self.features.eq.is_supported() {
+            match self.get_equalizer_info() {
+                Ok((band_count, _)) => Some(band_count),
+                Err(err) => {
+                    log::debug!("could not read the equalizer bands: {err}");
+
+                    None
+                }
+            }
         } else {
-            log::debug!("found protocol version {ver1}.{ver2}");
+            None
+        };
+
+        let capabilities = Capabilities {
+            has_rgb_logo: light_zones >= 2,
+            has_gkeys: self.features.gkey.is_supported(),
+            battery_event_support: self.features.battery.is_supported(),
+            eq_bands,
+            sidetone_range: self
+                .features
+                .sidetone
+                .is_supported()
+                .then_some(capabilities::SIDETONE_RANGE),
+        };
+        log::debug!("found the capabilities {capabilities:?}");
+
+        capabilities
+    }
+
+    /// Asks the headset whether it reports its state of charge, which only some firmware
+    /// revisions do.
+    fn probe_state_of_charge(&mut self) -> bool {
+        if !self.features.unified_battery.is_supported() {
+            return false;
         }
 
-        let name = headset.get_device_name()?;
+        // The second byte of the capabilities holds the flags, with the state of charge in bit 1
+        match self
+            .features
+            .unified_battery
+            .request(&mut self.device, &[function_byte(0)])
+        {
+            Ok(response) => {
+                let reports_charge = response.params()[1] & 0x02 != 0;
+                log::debug!(
+                    "the headset {} its state of charge",
+                    if reports_charge {
+                        "reports"
+                    } else {
+                        "does not report"
+                    }
+                );
 
-        log::info!("connected to device {name:?}");
+                reports_charge
+            }
+            Err(err) => {
+                log::debug!("could not read the battery capabilities: {err}");
 
-        Ok(headset)
+                false
+            }
+        }
+    }
+
+    /// Sets the charge of the status to the state of charge reported by the headset, or to the
+    /// estimate of the battery model if the headset does not report it.
+    fn with_charge(&mut self, mut status: BatteryStatus) -> BatteryStatus {
+        status.charge = self
+            .battery_model
+            .estimate(status.voltage, status.charging_status);
+        if !self.reports_charge {
+            return status;
+        }
+
+        match self
+            .features
+            .unified_battery
+            .request(&mut self.device, &[function_byte(1)])
+        {
+            Ok(response) => status.charge = f32::from(response.params()[0].min(100)),
+            Err(err) => log::debug!("could not read the state of charge, estimating it: {err}"),
+        }
+
+        status
+    }
+
+    /// Detects the quirks of a variant of the headset that was not tested.
+    ///
+    /// If its responses do not match any known layout, its settings are not changed unless
+    /// [`Headset::set_force`] is used.
+    fn negotiate_quirks(&mut self) -> Quirks {
+        log::warn!(
+            "this code was tested with the G935 using protocol version 4.2, detecting the quirks \
+             of {}",
+            self.info
+        );
+
+        match self
+            .features
+            .battery
+            .request(&mut self.device, &[function_byte(0)])
+        {
+            Ok(response) => match Quirks::negotiate(response.params()) {
+                Some(quirks) => {
+                    log::info!("using the quirks {quirks:?}");
+
+                    quirks
+                }
+                None => {
+                    log::warn!(
+                        "the battery response {response:?} does not match any known layout, \
+                         so the settings of the headset are not changed unless forced"
+                    );
+                    self.device.untrusted = true;
+
+                    Quirks::default()
+                }
+            },
+            Err(err) => {
+                log::debug!("could not detect the quirks, as reading the battery failed: {err}");
+
+                Quirks::default()
+            }
+        }
+    }
+
+    /// Returns what the headset supports, as found out when it was opened.
+    ///
+    /// Front-ends can use this to only offer the settings that the headset supports, instead of
+    /// trying them and handling the errors.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    /// Sets how the charge is estimated from the voltage, replacing [`DefaultBatteryModel`].
+    ///
+    /// The model is only used if the headset does not report its state of charge itself. The
+    /// cached battery status is forgotten, so that it is estimated again.
+    pub fn set_battery_model(&mut self, model: impl BatteryModel + 'static) {
+        self.battery_model = Box::new(model);
+        self.battery = None;
+    }
+
+    /// Returns how the headset deviates from the tested G935 with protocol version 4.2.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Overrides the quirks that were detected when the headset was opened.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Sets whether settings are changed even if the responses of the headset did not match any
+    /// known layout when it was opened.
+    pub fn set_force(&mut self, force: bool) {
+        self.device.force = force;
+    }
+
+    /// Returns the information about the headset, which was read when it was opened.
+    pub fn info(&self) -> &HeadsetInfo {
+        &self.info
+    }
+
+    /// Returns the statistics about the communication with the headset since it was opened.
+    pub fn stats(&self) -> Stats {
+        self.device.stats
+    }
+
+    /// Sets whether requests changing settings are only logged instead of being sent.
+    ///
+    /// In dry-run mode, the reports that would be written are logged at the info level together
+    /// with their meaning, and are answered as if the headset confirmed them. Requests reading
+    /// from the headset are still sent.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.device.dry_run = dry_run;
+    }
+
+    /// Opens the HID device of the headset and reads its feature map, recording the reports to
+    /// the capture file if there is one.
+    fn open_device(
+        descriptor: &DeviceDescriptor,
+        capture: Option<&CaptureFile>,
+    ) -> anyhow::Result<(Device, FeatureMap)> {
+        let (transport, path) = discovery::open_transport(descriptor)?;
+        let transport: Box<dyn Transport> = match capture {
+            Some(capture) => Box::new(Capture::new(transport, capture.clone())),
+            None => transport,
+        };
+        let mut device = Device::new(transport, path.as_deref(), descriptor.device_index);
+
+        let features = features::FeatureMap::initialize(&mut device)?;
+
+        log::debug!("read feature map: {:?}", features);
+
+        Ok((device, features))
+    }
+
+    /// Closes and reopens the connection to the headset.
+    ///
+    /// This is useful when the connection may be stale, for example after the computer resumed
+    /// from suspend. The features are read again and the headset is pinged to check the new
+    /// connection. The settings need to be applied again afterwards, for example using
+    /// [`RunConfig::set_dirty`]. [`Headset::poll_once`] does all of this on its own after too many
+    /// requests failed in a row.
+    pub fn reset_connection(&mut self) -> anyhow::Result<()> {
+        self.reopen().map(drop)
     }
 
-    /// Returns the protocol version used by the headset.
-    fn get_protocol_version(&mut self) -> anyhow::Result<(u8, u8)> {
+    /// Closes and reopens the connection to the headset, returning whether the headset responds
+    /// to a ping afterwards.
+    fn reopen(&mut self) -> anyhow::Result<bool> {
+        let Source::Hid {
+            descriptor,
+            capture,
+        } = &mut self.source
+        else {
+            log::debug!("not resetting the connection, as it uses a given transport");
+            return Ok(self.ping().is_ok());
+        };
+
+        log::info!("resetting the connection to the headset");
+
+        // The receiver may have been plugged in again, giving it another path
+        *descriptor = descriptor.find_again()?;
+        let (mut device, features) = Self::open_device(descriptor, capture.as_ref())?;
+        // The statistics describe the whole lifetime of the headset, not just one connection
+        device.stats = self.device.stats;
+        device.dry_run = self.device.dry_run;
+        device.untrusted = self.device.untrusted;
+        device.force = self.device.force;
+        self.device = device;
+        self.features = features;
+
+        // The headset may be turned off, which does not make the new connection any worse
+        match self.ping() {
+            Ok(latency) => {
+                log::debug!("the headset responded after {latency:?}");
+
+                Ok(true)
+            }
+            Err(err) => {
+                log::debug!("the headset did not respond after resetting: {err}");
+
+                Ok(false)
+            }
+        }
+    }
+
+    /// Reopens the connection while going through the reconnecting state, applying the whole
+    /// config again afterwards.
+    fn reconnect(&mut self, config: &mut RunConfig) {
+        self.transition(config, ConnectionState::Reconnecting);
+
+        let responds = self.reopen().unwrap_or_else(|err| {
+            log::error!("could not reconnect to the headset: {err:#}");
+
+            false
+        });
+
+        config.set_dirty();
+        config.reset_backoff();
+        self.transition(
+            config,
+            if responds {
+                ConnectionState::Connected
+            } else {
+                ConnectionState::DeviceLost
+            },
+        );
+    }
+
+    /// Moves the connection to the state, queueing the power state it reports if it changed.
+    ///
+    /// The headset loses its settings while it is turned off, so they are applied again once it
+    /// is connected again.
+    fn transition(&mut self, config: &mut RunConfig, state: ConnectionState) {
+        if self.state == state {
+            return;
+        }
+
+        log::debug!(
+            "connection state changed from {:?} to {state:?}",
+            self.state
+        );
+        // The headset may have been turned off meanwhile, which resets its lights
+        self.forget_acknowledged_lights();
+
+        match state {
+            ConnectionState::Connected => config.set_dirty(),
+            ConnectionState::DeviceLost => {
+                // The microphone arm may be flipped and the battery charged while the headset is
+                // disconnected
+                self.device.forget_mic_arm();
+                self.battery = None;
+                self.button_state.mic_arm = None;
+                // The release of held buttons is not reported anymore
+                self.button_state
+                    .update_buttons(Buttons::default(), Instant::now());
+                self.button_state
+                    .update_wheel(Wheel::default(), Instant::now());
+            }
+            ConnectionState::Reconnecting | ConnectionState::Suspended => (),
+        }
+
+        self.state = state;
+        self.pending_power_states.push_back(state.power_state());
+    }
+
+    /// Stops talking to the headset because the computer is about to suspend.
+    ///
+    /// The handlers are told through the [`PowerState::Suspended`] event. Until
+    /// [`Headset::resume`] is called, the headset is neither pinged nor reconnected.
+    pub fn suspend(&mut self, config: &mut RunConfig) {
+        self.transition(config, ConnectionState::Suspended);
+    }
+
+    /// Reconnects to the headset after the computer resumed from suspend, applying the whole
+    /// config again.
+    ///
+    /// The handlers are told through the [`PowerState::Reconnecting`] event, followed by
+    /// [`PowerState::Connected`] or [`PowerState::Disconnected`] if the headset does not respond.
+    pub fn resume(&mut self, config: &mut RunConfig) {
+        self.reconnect(config);
+    }
+
+    /// Resets the connection to the headset if too many requests failed in a row, as configured
+    /// with [`RunConfig::set_reconnect_after`].
+    fn reset_if_wedged(&mut self, config: &mut RunConfig) {
+        let limit = config.reconnect_after();
+        if limit == 0
+            || self.state != ConnectionState::Connected
+            || self.device.consecutive_failures < limit
+        {
+            return;
+        }
+
+        log::warn!("{limit} requests failed in a row, resetting the connection");
+        // Failing to reopen keeps the old device, which should not be reset on every poll
+        self.device.consecutive_failures = 0;
+
+        self.reconnect(config);
+    }
+
+    /// Pings the headset while it is idle, to notice when it was turned off or on without
+    /// reporting it.
+    fn keep_alive(&mut self, config: &mut RunConfig) {
+        if self.last_keepalive.elapsed() < KEEPALIVE_INTERVAL {
+            return;
+        }
+        self.last_keepalive = Instant::now();
+
+        match self.state {
+            ConnectionState::Connected | ConnectionState::DeviceLost => match self.ping() {
+                Ok(_) => self.transition(config, ConnectionState::Connected),
+                Err(err) => {
+                    log::debug!("the headset did not respond to a ping: {err}");
+                    self.transition(config, ConnectionState::DeviceLost);
+                }
+            },
+            ConnectionState::Reconnecting | ConnectionState::Suspended => (),
+        }
+    }
+
+    /// Returns the number of requests that failed since the last one that succeeded.
+    ///
+    /// Plugins can use this together with [`Headset::reset_connection`] to recover from a
+    /// connection that stopped working, but the loop already does so on its own.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.device.consecutive_failures
+    }
+
+    /// Takes the lock ensuring that only one instance uses the headset.
+    ///
+    /// The lock is released by the operating system when the process exits.
+    fn lock(descriptor: &DeviceDescriptor) -> anyhow::Result<std::fs::File> {
+        let path = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join(descriptor.lock_name());
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(file),
+            Err(std::fs::TryLockError::WouldBlock) => Err(Error::DeviceBusy {
+                reason: format!("another instance holds the lock {}", path.display()),
+            }
+            .into()),
+            Err(std::fs::TryLockError::Error(err)) => Err(err.into()),
+        }
+    }
+
+    /// Asks the headset for the protocol version it uses.
+    ///
+    /// The version is also part of [`Headset::info`], which does not need to ask the headset.
+    pub fn get_protocol_version(&mut self) -> anyhow::Result<(u8, u8)> {
         let response = self
             .features
             .root
-            .request(&mut self.device, &[0x11, 0x00, 0x00, 0xaf])?;
+            .request(&mut self.device, &[function_byte(1), 0x00, 0x00, 0xaf])?;
 
-        if response[6] != 0xaf {
-            log::error!(
-                "ping response did not match the request: was {:#04x}",
-                response[6]
-            );
+        let [major, minor, data, ..] = *response.params() else {
+            anyhow::bail!("the protocol version response is too short");
+        };
+        if data != 0xaf {
+            log::error!("ping response did not match the request: was {data:#04x}");
         }
 
-        Ok((response[4], response[5]))
+        Ok((major, minor))
     }
 
-    /// Returns the device name of the headset.
-    fn get_device_name(&mut self) -> anyhow::Result<String> {
-        let len = self.features.devname.request(&mut self.device, &[0x01])?[4];
+    /// Pings the headset, returning the round-trip time of the request.
+    ///
+    /// Fails if the headset does not respond, for example because it is turned off.
+    pub fn ping(&mut self) -> anyhow::Result<Duration> {
+        // Differs between pings, so that late responses to earlier pings are not mistaken
+        let data = self.device.stats.requests as u8;
+
+        let start = Instant::now();
+        let response = self
+            .features
+            .root
+            .request(&mut self.device, &[function_byte(1), 0x00, 0x00, data])?;
+        let latency = start.elapsed();
+
+        let echoed = response.params()[2];
+        anyhow::ensure!(
+            echoed == data,
+            "ping response did not match the request: was {echoed:#04x} instead of {data:#04x}"
+        );
+        log::trace!("ping took {latency:?}");
+
+        Ok(latency)
+    }
+
+    /// Returns the state of the wireless link between the receiver and the headset.
+    ///
+    /// This pings the headset, so that a headset that is turned off takes up to 2 s to be reported
+    /// as not linked.
+    pub fn wireless_status(&mut self) -> WirelessStatus {
+        let latency = self.ping().ok();
+
+        WirelessStatus {
+            linked: latency.is_some(),
+            latency_us: latency.map(|latency| latency.as_micros().try_into().unwrap_or(u64::MAX)),
+            reports_reconnections: self.features.wireless.is_supported(),
+        }
+    }
+
+    /// Asks the headset for the IDs of all features it supports, in the order of their indices.
+    ///
+    /// This is meant for mapping the features of the headset that are not supported yet, whose
+    /// IDs can be looked up in the HID++ 2.0 specification.
+    pub fn feature_ids(&mut self) -> anyhow::Result<Vec<u16>> {
+        let count = self
+            .features
+            .feature_set
+            .request(&mut self.device, &[function_byte(0)])?
+            .params()[0];
+
+        // The root feature is not counted, as it always has the index 0
+        (0..=count)
+            .map(|index| {
+                let response = self
+                    .features
+                    .feature_set
+                    .request(&mut self.device, &[function_byte(1), index])?;
+
+                let params = response.params();
+
+                Ok(u16::from_be_bytes([params[0], params[1]]))
+            })
+            .collect()
+    }
+
+    /// Asks the headset for its name.
+    ///
+    /// The name is also part of [`Headset::info`], which does not need to ask the headset.
+    pub fn get_device_name(&mut self) -> anyhow::Result<String> {
+        let len = self
+            .features
+            .devname
+            .request(&mut self.device, &[function_byte(0)])?
+            .params()[0];
 
         let mut name = String::new();
         let part_count = ((len - 1) / 16) + 1;
@@ -97,152 +814,1019 @@ impl Headset {
         for i in 0..part_count {
             let rest_len = len as usize - name.len();
 
-            let response = &self
+            let response = self
                 .features
                 .devname
-                .request(&mut self.device, &[0x11, i])?[4..4 + std::cmp::min(rest_len, 16)];
+                .request(&mut self.device, &[function_byte(1), i])?;
 
-            name += std::str::from_utf8(response)?;
+            name += std::str::from_utf8(&response.params()[..std::cmp::min(rest_len, 16)])?;
         }
 
         Ok(name)
     }
 
-    /// Sets the button status.
-    fn enable_buttons(&mut self, enable: bool) -> anyhow::Result<()> {
+    /// Sets whether the headset reports the G keys to this program.
+    ///
+    /// While reporting is disabled, which it is whenever the headset is powered on, the G keys
+    /// act as the F1 to F3 keys of a keyboard. While it is enabled, they no longer reach the
+    /// keyboard input of the OS, and their presses are only sent as HID++ notifications, which
+    /// the loop reports as button events and other programs can read from the hidraw device.
+    ///
+    /// The setting is kept by the headset, so it outlives this program until it is changed again
+    /// or the headset is powered off. While the headset is driven by a [`RunConfig`], the config owns
+    /// the setting: it enables reporting if a button handler or a plugin is set and disables it
+    /// otherwise, overriding this method whenever the handler or the plugins change.
+    pub fn set_gkeys_reporting(&mut self, enable: bool) -> anyhow::Result<()> {
         log::debug!("{} buttons", if enable { "enabling" } else { "disabling" });
 
-        let response = self
-            .features
-            .gkey
-            .request(&mut self.device, &[0x21, enable as u8])?;
+        let response = self.features.gkey.change(
+            &mut self.device,
+            &[function_byte(2), enable as u8],
+            format_args!("{} buttons", if enable { "enable" } else { "disable" }),
+        )?;
 
-        if response[4] != enable as u8 {
+        if response.params()[0] != enable as u8 {
             log::error!(
                 "enable buttons response did not match the request: expected {}, found {}",
                 enable as u8,
-                response[4]
+                response.params()[0]
             );
         }
 
         Ok(())
     }
 
+    /// Returns the headset to the state it has after being turned on, as far as this program
+    /// changed it.
+    ///
+    /// This disables the reporting of the G keys, so that they act as F1 to F3 again, and shows
+    /// the effects stored in the headset on its lights, replacing overlays and dimming that
+    /// nothing would update anymore. Both are tried even if the other one fails.
+    /// [`Headset::restore_on_drop`] does this when the headset is no longer driven.
+    pub fn restore_defaults(&mut self) -> anyhow::Result<()> {
+        self.restore(RestorePolicy::Defaults)
+    }
+
+    /// Restores what the policy asks for, see [`RestorePolicy`].
+    pub(crate) fn restore(&mut self, policy: RestorePolicy) -> anyhow::Result<()> {
+        let restores_lights = match policy {
+            RestorePolicy::Keep => return Ok(()),
+            RestorePolicy::Buttons => false,
+            RestorePolicy::Defaults => true,
+        };
+
+        let buttons = if self.capabilities.has_gkeys {
+            self.set_gkeys_reporting(false)
+        } else {
+            Ok(())
+        };
+
+        let lights = if restores_lights && self.features.lights.is_supported() {
+            self.show_stored_lights()
+        } else {
+            Ok(())
+        };
+
+        buttons
+            .context("could not disable the G key reporting")
+            .and(lights.context("could not restore the stored light effects"))
+    }
+
+    /// Returns a guard that derefs to the headset and calls [`Headset::restore_defaults`] when
+    /// it is dropped.
+    ///
+    /// The guard is dropped both when the code using it returns and when it panics, so that the
+    /// G keys and the lights are not left as a crashed handler or plugin left them. Panics in
+    /// the handlers and plugins of a [`RunConfig`] are already caught by the loop, which applies
+    /// its own [`RestorePolicy`], see [`RunConfig::set_restore_on_exit`]. Nothing is restored if
+    /// the process is killed or exits without unwinding.
+    pub fn restore_on_drop(&mut self) -> RestoreOnDrop<'_> {
+        RestoreOnDrop::new(self, RestorePolicy::Defaults)
+    }
+
+    /// Shows the effects stored in the headset on its lights.
+    fn show_stored_lights(&mut self) -> anyhow::Result<()> {
+        let lights = lights::FullLightSettings {
+            side: self.get_permanent_light(lights::Light::Side)?,
+            logo: self.get_permanent_light(lights::Light::Logo)?,
+        };
+
+        self.set_full_lights(&lights, lights::ProfileType::Temporary)
+    }
+
     /// Set light configuration.
-    pub fn set_lights(&mut self, lights: &lights::Config) -> anyhow::Result<lights::Config> {
-        log::debug!("setting lights to {lights:?}");
+    ///
+    /// The effect is written as it is, so effects built from user input should be checked with
+    /// [`lights::Effect::validate`] or built using its builders first.
+    /// [`Headset::set_permanent_light`] reviews and verifies changes of the stored effects.
+    pub fn set_lights(
+        &mut self,
+        lights: &lights::LightSettings,
+    ) -> anyhow::Result<lights::LightSettings> {
+        self.set_dimmed_lights(lights, None)
+    }
+
+    /// Sets the same effect on both lights.
+    ///
+    /// See [`Headset::set_full_lights`].
+    pub fn set_all_lights(
+        &mut self,
+        effect: lights::Effect,
+        profile_type: lights::ProfileType,
+    ) -> anyhow::Result<()> {
+        self.set_full_lights(
+            &lights::FullLightSettings {
+                side: effect,
+                logo: effect,
+            },
+            profile_type,
+        )
+    }
 
-        let mut request = lights.as_bytes();
-        request.insert(0, 0x31);
+    /// Sets the effects of both lights right after each other.
+    ///
+    /// Both requests are sent before waiting for the responses, so the lights change together.
+    pub fn set_full_lights(
+        &mut self,
+        lights: &lights::FullLightSettings,
+        profile_type: lights::ProfileType,
+    ) -> anyhow::Result<()> {
+        self.set_dimmed_full_lights(lights, profile_type, |_| None)
+    }
 
-        self.features
+    /// Sets the effects of both lights right after each other, dimmed to the brightness of each
+    /// light if there is one.
+    pub(crate) fn set_dimmed_full_lights(
+        &mut self,
+        lights: &lights::FullLightSettings,
+        profile_type: lights::ProfileType,
+        brightness: impl Fn(lights::Light) -> Option<u8>,
+    ) -> anyhow::Result<()> {
+        let configs = [lights::Light::Side, lights::Light::Logo].map(|light| {
+            let config = lights::LightSettings {
+                light,
+                effect: lights.effect(light),
+                profile_type,
+            };
+
+            (config, brightness(light))
+        });
+
+        let requests = configs
+            .iter()
+            .map(|(config, brightness)| config.request(*brightness))
+            .collect::<Vec<_>>();
+
+        // Effects the headset already shows are not written again, as that makes some of them
+        // flicker
+        let mut changed = Vec::new();
+        let mut bodies = Vec::new();
+        for ((config, brightness), request) in configs.iter().zip(&requests) {
+            let dimmed = config.dimmed(*brightness);
+            if profile_type == lights::ProfileType::Temporary
+                && *self.acknowledged_effect(config.light) == Some(dimmed.effect)
+            {
+                log::debug!(
+                    "the {} light already shows {:?}",
+                    config.light,
+                    dimmed.effect
+                );
+                continue;
+            }
+
+            changed.push(dimmed);
+            bodies.push(request.as_slice());
+        }
+
+        if !bodies.is_empty() {
+            // The requests are sent before waiting for the replies, so that the lights change at
+            // nearly the same time
+            let result = self.write_lights(
+                &changed,
+                &bodies,
+                format_args!(
+                    "set the side light to {:?} and the logo light to {:?}",
+                    lights.side, lights.logo
+                ),
+            );
+            for dimmed in &changed {
+                self.acknowledge(dimmed, result.is_ok());
+            }
+            result?;
+        }
+
+        if profile_type == lights::ProfileType::Temporary {
+            for (config, _) in &configs {
+                *self.shown_effect(config.light) = Some(config.effect);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the effect stored in the headset for the light, which it shows after being
+    /// turned on.
+    pub fn get_permanent_light(&mut self, light: lights::Light) -> anyhow::Result<lights::Effect> {
+        let zone = match light {
+            lights::Light::Logo => 0x00,
+            lights::Light::Side => 0x01,
+        };
+
+        let response = self
+            .features
             .lights
-            .request(&mut self.device, &request)
-            .map(|bytes| lights::Config::from_bytes(&bytes[4..]))
+            .request(&mut self.device, &[function_byte(14), zone])?;
+
+        // The stored effect is reported with the layout of the request setting it
+        let mut params = [0; 13];
+        params[..12].copy_from_slice(&response.params()[..12]);
+        params[12] = 2;
+
+        match lights::LightSettings::parse(&params) {
+            Some(settings) if settings.light == light => Ok(settings.effect),
+            _ => Err(anyhow::anyhow!(
+                "the stored effect {response:?} of the {light} light is not understood"
+            )),
+        }
+    }
+
+    /// Stores the effect of the light in the headset, returning the effect stored before.
+    ///
+    /// Wrong bytes in the stored settings outlive restarts of the headset, so this reads the
+    /// stored effect first and logs how it changes. The effect is only written if `confirm` is
+    /// `true`, and read back afterwards to check that the headset stored it. Nothing is written
+    /// if the effect is stored already.
+    pub fn set_permanent_light(
+        &mut self,
+        light: lights::Light,
+        effect: lights::Effect,
+        confirm: bool,
+    ) -> anyhow::Result<lights::Effect> {
+        let stored = self
+            .get_permanent_light(light)
+            .context("could not read the stored effect to review the change")?;
+        if stored == effect {
+            log::info!("the {light} light already stores {effect:?}");
+
+            return Ok(stored);
+        }
+
+        log::info!("changing the stored effect of the {light} light from {stored:?} to {effect:?}");
+        anyhow::ensure!(
+            confirm,
+            "not changing the stored effect of the {light} light from {stored:?} to {effect:?} \
+             without confirmation"
+        );
+
+        self.set_dimmed_lights(
+            &lights::LightSettings {
+                light,
+                effect,
+                profile_type: lights::ProfileType::Permanent,
+            },
+            None,
+        )?;
+
+        if !self.device.dry_run {
+            let written = self
+                .get_permanent_light(light)
+                .context("could not read back the stored effect")?;
+            anyhow::ensure!(
+                written == effect,
+                "the {light} light stores {written:?} instead of {effect:?}"
+            );
+        }
+
+        Ok(stored)
+    }
+
+    /// Changes the brightness in percent of the effect last shown on the light, keeping the
+    /// effect itself.
+    ///
+    /// The brightness replaces the one of the breathing and color cycle effects, and scales the
+    /// color of the static effect. It always applies to the effect as it was set, so lowering the
+    /// brightness and raising it again restores the original color.
+    ///
+    /// Fails without contacting the headset if no effect was set on the light since the headset
+    /// was opened, as the headset cannot be asked for it.
+    pub fn set_brightness(
+        &mut self,
+        light: lights::Light,
+        brightness: u8,
+    ) -> anyhow::Result<lights::LightSettings> {
+        anyhow::ensure!(
+            brightness <= lights::MAX_BRIGHTNESS,
+            "brightness must be at most {}, found {brightness}",
+            lights::MAX_BRIGHTNESS
+        );
+
+        let effect = (*self.shown_effect(light)).ok_or_else(|| {
+            anyhow::anyhow!("the effect of the {light} light is unknown, set it first")
+        })?;
+
+        self.set_dimmed_lights(
+            &lights::LightSettings {
+                light,
+                effect,
+                profile_type: lights::ProfileType::Temporary,
+            },
+            Some(brightness),
+        )
+    }
+
+    /// Writes the light settings with the request bodies, checking that the headset echoes
+    /// them and returning the settings it reported.
+    ///
+    /// Settings the headset answers differently are written once more, as it occasionally
+    /// ignores a request. If it still does not echo them, this fails with
+    /// [`Error::SettingRejected`].
+    fn write_lights(
+        &mut self,
+        settings: &[lights::LightSettings],
+        bodies: &[&[u8]],
+        meaning: fmt::Arguments,
+    ) -> anyhow::Result<Vec<lights::LightSettings>> {
+        // Only the light and the effect are compared, as those are what the headset shows. An
+        // echo that is not a light setting at all counts as rejecting the write
+        let accepted = |requested: &lights::LightSettings,
+                        reported: &Option<lights::LightSettings>| {
+            reported.is_some_and(|reported| {
+                (requested.light, requested.effect) == (reported.light, reported.effect)
+            })
+        };
+
+        let mut reported = self
+            .features
+            .lights
+            .change_all(&mut self.device, bodies, meaning)?
+            .iter()
+            .map(|response| lights::LightSettings::parse(response.params()))
+            .collect::<Vec<_>>();
+
+        let rejected = (0..settings.len())
+            .filter(|&index| !accepted(&settings[index], &reported[index]))
+            .collect::<Vec<_>>();
+
+        if !rejected.is_empty() {
+            log::warn!(
+                "the headset did not echo the light settings, trying once more to {meaning}"
+            );
+            self.device.stats.retries += 1;
+
+            let retried = self.features.lights.change_all(
+                &mut self.device,
+                &rejected
+                    .iter()
+                    .map(|&index| bodies[index])
+                    .collect::<Vec<_>>(),
+                meaning,
+            )?;
+            for (&index, response) in rejected.iter().zip(&retried) {
+                reported[index] = lights::LightSettings::parse(response.params());
+
+                if !accepted(&settings[index], &reported[index]) {
+                    return Err(Error::SettingRejected {
+                        setting: format!("the effect of the {} light", settings[index].light),
+                        requested: format!("{:?}", settings[index].effect),
+                        reported: reported[index].map_or_else(
+                            || "no valid light setting".to_string(),
+                            |reported| format!("{:?}", reported.effect),
+                        ),
+                    }
+                    .into());
+                }
+            }
+        }
+
+        // Every setting was accepted at this point, so none of them is missing
+        Ok(reported.into_iter().flatten().collect())
+    }
+
+    /// Returns the effect the headset last confirmed showing on the light, dimmed as it was
+    /// written.
+    fn acknowledged_effect(&mut self, light: lights::Light) -> &mut Option<lights::Effect> {
+        match light {
+            lights::Light::Side => &mut self.side_acknowledged,
+            lights::Light::Logo => &mut self.logo_acknowledged,
+        }
+    }
+
+    /// Remembers what the light shows after writing the settings, which is unknown if the write
+    /// failed or only changed the stored effect.
+    fn acknowledge(&mut self, dimmed: &lights::LightSettings, succeeded: bool) {
+        let shown = (succeeded
+            && !self.device.dry_run
+            && dimmed.profile_type == lights::ProfileType::Temporary)
+            .then_some(dimmed.effect);
+
+        *self.acknowledged_effect(dimmed.light) = shown;
+    }
+
+    /// Forgets what the lights show, so that the next effects set on them are written even if
+    /// they did not change.
+    ///
+    /// This is needed whenever the lights may have been changed without this program noticing,
+    /// like by the headset resetting them or by another program.
+    pub(crate) fn forget_acknowledged_lights(&mut self) {
+        self.side_acknowledged = None;
+        self.logo_acknowledged = None;
+    }
+
+    /// Returns the undimmed effect last shown on the light.
+    fn shown_effect(&mut self, light: lights::Light) -> &mut Option<lights::Effect> {
+        match light {
+            lights::Light::Side => &mut self.side_effect,
+            lights::Light::Logo => &mut self.logo_effect,
+        }
+    }
+
+    /// Sets the light configuration with the effect dimmed to the brightness, if there is one,
+    /// remembering the undimmed effect if it is shown right away.
+    pub(crate) fn set_dimmed_lights(
+        &mut self,
+        lights: &lights::LightSettings,
+        brightness: Option<u8>,
+    ) -> anyhow::Result<lights::LightSettings> {
+        let request = lights.request(brightness);
+        let dimmed = lights.dimmed(brightness);
+
+        let response = self.write_lights(
+            &[dimmed],
+            &[&request],
+            format_args!("set the {:?} light to {:?}", lights.light, lights.effect),
+        );
+        self.acknowledge(&dimmed, response.is_ok());
+        let response = response?[0];
+
+        if lights.profile_type == lights::ProfileType::Temporary {
+            *self.shown_effect(lights.light) = Some(lights.effect);
+        }
+
+        Ok(response)
+    }
+
+    /// Sets the sidetone level, which is how loud the microphone is played back on the headset.
+    ///
+    /// The level is given in percent.
+    pub fn set_sidetone(&mut self, level: u8) -> anyhow::Result<()> {
+        log::debug!("setting sidetone to {level}");
+
+        anyhow::ensure!(
+            capabilities::SIDETONE_RANGE.contains(&level),
+            "sidetone level must be at most 100, found {level}"
+        );
+
+        let response = self.features.sidetone.change(
+            &mut self.device,
+            &[function_byte(1), level],
+            format_args!("set the sidetone to {level}%"),
+        )?;
+
+        if response.params()[0] != level {
+            log::error!(
+                "sidetone response did not match the request: expected {level}, found {}",
+                response.params()[0]
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sidetone level in percent.
+    pub fn get_sidetone(&mut self) -> anyhow::Result<u8> {
+        Ok(self
+            .features
+            .sidetone
+            .request(&mut self.device, &[function_byte(0)])?
+            .params()[0])
+    }
+
+    /// Returns the report rates supported by the receiver, as the interval between reports in
+    /// milliseconds.
+    ///
+    /// Fails with [`Error::Unsupported`] if the report rate cannot be changed.
+    pub fn get_report_rates(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.features
+            .report_rate
+            .ensure_supported("changing the report rate")?;
+
+        // Bit n is set if an interval of n + 1 ms is supported
+        let flags = self
+            .features
+            .report_rate
+            .request(&mut self.device, &[function_byte(0)])?
+            .params()[0];
+
+        Ok((0..8)
+            .filter(|bit| flags & (1 << bit) != 0)
+            .map(|bit| bit + 1)
+            .collect())
+    }
+
+    /// Returns the interval between reports of the receiver in milliseconds.
+    ///
+    /// Fails with [`Error::Unsupported`] if the report rate cannot be changed.
+    pub fn get_report_rate(&mut self) -> anyhow::Result<u8> {
+        self.features
+            .report_rate
+            .ensure_supported("changing the report rate")?;
+
+        Ok(self
+            .features
+            .report_rate
+            .request(&mut self.device, &[function_byte(1)])?
+            .params()[0])
+    }
+
+    /// Sets the interval between reports of the receiver in milliseconds, which needs to be one
+    /// of [`Headset::get_report_rates`].
+    ///
+    /// Fails with [`Error::Unsupported`] if the report rate cannot be changed.
+    pub fn set_report_rate(&mut self, interval_ms: u8) -> anyhow::Result<()> {
+        log::debug!("setting the report rate to {interval_ms} ms");
+
+        let rates = self.get_report_rates()?;
+        anyhow::ensure!(
+            rates.contains(&interval_ms),
+            "unsupported report interval of {interval_ms} ms, supported are {rates:?}"
+        );
+
+        self.features.report_rate.change(
+            &mut self.device,
+            &[function_byte(2), interval_ms],
+            format_args!("set the report interval to {interval_ms} ms"),
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the number of equalizer bands and the largest gain in dB per band.
+    fn get_equalizer_info(&mut self) -> anyhow::Result<(usize, i8)> {
+        let response = self
+            .features
+            .eq
+            .request(&mut self.device, &[function_byte(0)])?;
+        let band_count = response.params()[0] as usize;
+
+        // The gains of all bands need to fit into a single request or response
+        anyhow::ensure!(
+            band_count <= 16,
+            "unsupported equalizer with {band_count} bands"
+        );
+
+        Ok((band_count, response.params()[1] as i8))
+    }
+
+    /// Returns the gain in dB of each equalizer band.
+    pub fn get_equalizer(&mut self) -> anyhow::Result<Vec<i8>> {
+        let (band_count, _) = self.get_equalizer_info()?;
+
+        let response = self
+            .features
+            .eq
+            .request(&mut self.device, &[function_byte(2)])?;
+
+        Ok(response.params()[..band_count]
+            .iter()
+            .map(|&gain| gain as i8)
+            .collect())
+    }
+
+    /// Sets the gain in dB of each equalizer band.
+    ///
+    /// There must be exactly one gain for each band of the headset's equalizer.
+    pub fn set_equalizer(&mut self, gains: &[i8]) -> anyhow::Result<()> {
+        log::debug!("setting equalizer to {gains:?}");
+
+        let (band_count, max_gain) = self.get_equalizer_info()?;
+
+        anyhow::ensure!(
+            gains.len() == band_count,
+            "the equalizer has {band_count} bands, but {} gains were given",
+            gains.len()
+        );
+        anyhow::ensure!(
+            gains
+                .iter()
+                .all(|gain| gain.unsigned_abs() <= max_gain.unsigned_abs()),
+            "equalizer gains must be between -{max_gain} and {max_gain} dB, found {gains:?}"
+        );
+
+        let mut request = vec![function_byte(3)];
+        request.extend(gains.iter().map(|&gain| gain as u8));
+
+        self.features.eq.change(
+            &mut self.device,
+            &request,
+            format_args!("set the equalizer gains to {gains:?} dB"),
+        )?;
+
+        Ok(())
     }
 
     /// Get battery status and level.
+    ///
+    /// The charge is the state of charge reported by the headset if its firmware supports it,
+    /// and estimated from the voltage otherwise, which drifts notably near empty and full.
     pub fn get_battery_status(&mut self) -> anyhow::Result<BatteryStatus> {
-        self.features
+        let status = self
+            .features
             .battery
-            .request(&mut self.device, &[0x01])
-            .map(|bytes| BatteryStatus::from_bytes(&bytes[4..]))
+            .request(&mut self.device, &[function_byte(0)])
+            .map(|response| {
+                BatteryStatus::from_bytes(&response.params()[self.quirks.battery_offset..])
+            })?;
+        let status = self.with_charge(status);
+        self.battery = Some((status, Instant::now()));
+
+        Ok(status)
     }
 
-    /// Repeatedly queries the device, running config handlers as the respective events occur.
-    pub fn run_with_config(&mut self, mut config: Config) {
-        if let Err(err) = config.sync_configuration(self) {
-            log::error!("failed initial config synchronization: {err}");
+    /// Returns the last known battery status if it is at most `max_age` old, only asking the
+    /// headset if it is older.
+    ///
+    /// The status is known from previous calls of this function and
+    /// [`Headset::get_battery_status`], and from the battery notifications of the headset handled
+    /// by [`Headset::poll_once`]. It is forgotten when the headset is turned off. This avoids a
+    /// round trip for handlers that need the battery status on every button press.
+    pub fn battery_status_cached(&mut self, max_age: Duration) -> anyhow::Result<BatteryStatus> {
+        match self.battery {
+            Some((status, read_at)) if read_at.elapsed() <= max_age => Ok(status),
+            _ => self.get_battery_status(),
         }
+    }
 
-        let mut button_state = ButtonState::default();
-        let mut power_state;
+    /// Returns the position of the microphone arm.
+    ///
+    /// The headset only reports the position when the arm is flipped and there is no way to
+    /// request it, so this returns the last position reported since the connection was opened
+    /// or the headset reconnected, taking reports that were not processed yet into account.
+    /// Integrations can use this to reconcile their state once it is known.
+    pub fn mic_arm(&mut self) -> anyhow::Result<Option<MicArm>> {
+        self.device.read_pending()?;
 
-        const TIMEOUT_IN_MS: i32 = 500;
-        const RESET_TIME_IN_SEC: i32 = 20;
-        const RESET_COUNTER_AFTER: i32 = RESET_TIME_IN_SEC * 1000 / TIMEOUT_IN_MS;
+        Ok(self.device.mic_arm())
+    }
 
-        let mut counter = 0;
+    /// Repeatedly queries the device, running config handlers as the respective events occur.
+    ///
+    /// While the headset is idle, this sleeps until a message arrives, a handle or [`Waker`]
+    /// wakes it, a timer is due (see [`RunConfig::add_timer`]) or the periodic handler is due (see
+    /// [`RunConfig::set_periodic_interval`]).
+    ///
+    /// This only returns if the error handler of the config returns [`ErrorPolicy::Stop`] or a
+    /// [`StopToken`] of the config was used, see [`RunConfig::stop_token`]. When
+    /// it returns or unwinds from a panic, the headset is restored as configured with
+    /// [`RunConfig::set_restore_on_exit`].
+    pub fn run_with_config(&mut self, mut config: RunConfig) {
+        let mut headset = RestoreOnDrop::new(self, config.restore_on_exit());
+        let mut result = config.sync_with_backoff(&mut headset);
 
         loop {
-            match self.device.next_unrequested_msg(TIMEOUT_IN_MS).as_deref() {
-                Some([]) => {
-                    // Read timed out, but reset the buttons periodically to survive sleeps
-                    counter += 1;
-                    if counter > RESET_COUNTER_AFTER {
-                        counter = 0;
-                        // this is a terrible hack to make it work after reboots, but I cannot be
-                        // bothered to figure out a better method to detect the unresponsiveness of
-                        // the button handlers right now, so it will have to do
-                        //
-                        // the correct method probably involved regularly querying whether the
-                        // buttons are enabled
-                        self.enable_buttons(config.button_handler.is_some()).ok();
-                        self.set_lights(&lights::Config {
-                            light: lights::Light::Side,
-                            effect: *config.side_light_effect,
-                            profile_type: lights::ProfileType::Temporary,
-                        })
-                        .ok();
-                        self.set_lights(&lights::Config {
-                            light: lights::Light::Logo,
-                            effect: *config.logo_light_effect,
-                            profile_type: lights::ProfileType::Temporary,
-                        })
-                        .ok();
-                    }
+            if let Err(err) = result {
+                match config.call_error_handler(&err) {
+                    ErrorPolicy::Continue => (),
+                    ErrorPolicy::Reconnect => headset.reconnect(&mut config),
+                    ErrorPolicy::Stop => return,
                 }
-                Some(bytes @ [0x08, 0x10 | 0x20]) => {
-                    button_state.mic_arm = MicArm::from_bytes(bytes);
-                    log::debug!("mic arm state is {:?}", button_state.mic_arm);
+            }
 
-                    config.call_button_handler(self, button_state);
+            if config.is_stopped() {
+                log::info!("stopping as requested");
+                return;
+            }
+
+            let timeout = config.idle_timeout();
+            result = headset.poll_once(&mut config, timeout).map(drop);
+            headset.set_policy(config.restore_on_exit());
+        }
+    }
+
+    /// Applies the settings of the config to the headset once, without running the loop.
+    ///
+    /// Only the settings are sent, so the handlers and plugins of the config are never called.
+    pub fn apply_config(&mut self, config: &mut RunConfig) -> anyhow::Result<()> {
+        config.set_dirty();
+        config.sync_configuration(self)
+    }
+
+    /// Waits up to `timeout` for the next event of the headset, running the config handlers for
+    /// it and applying the changes to the config.
+    ///
+    /// This allows integrating the headset into a main loop, calling this function whenever it
+    /// is idle. Returns `None` if no event occurred in time or a [`Waker`] of the config woke it.
+    /// While a button is held, this returns after at most 20 ms, so that timed events can be
+    /// detected. It also returns early when a timer of the config or the tick of a plugin is due,
+    /// to retry applying the config and to ping the headset every 20 s while it is idle.
+    ///
+    /// The connection goes through explicit states, which are reported as
+    /// [`EventKind::PowerState`] events whenever they change: it is lost when the headset stops
+    /// responding, reconnected after too many failed requests or [`Headset::resume`], and not
+    /// touched while [`Headset::suspend`]ed. The whole config is applied again whenever the
+    /// headset is connected again. Errors are only returned if the config could not be applied, in which
+    /// case the handlers and plugins for the event were already run.
+    /// After a failure, applying the config is only retried after a delay, which doubles with each
+    /// consecutive failure.
+    pub fn poll_once(
+        &mut self,
+        config: &mut RunConfig,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<Event>> {
+        self.reset_if_wedged(config);
+
+        // The position may have been reported while the headset was used for something else
+        if self.button_state.mic_arm.is_none() {
+            self.button_state.mic_arm = self.device.mic_arm();
+        }
+
+        let held = self.button_state.is_held();
+        let mut timeout = if held {
+            timeout.min(TICK_INTERVAL)
+        } else {
+            timeout.min(KEEPALIVE_INTERVAL.saturating_sub(self.last_keepalive.elapsed()))
+        };
+        for delay in [
+            config.sync_retry_delay(),
+            config.next_timer_delay(),
+            config.plugin_tick_interval(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            timeout = timeout.min(delay);
+        }
+        // Transitions that happened outside of the loop are reported without waiting
+        if let Some(power_state) = self.pending_power_states.pop_front() {
+            return self.dispatch(
+                config,
+                Some(EventKind::PowerState(power_state)),
+                Instant::now(),
+            );
+        }
+        let timeout_in_ms = timeout.as_millis().try_into().unwrap_or(i32::MAX);
+
+        let msg = match self
+            .device
+            .next_unrequested_msg(timeout_in_ms, config.wakeup.as_ref())
+        {
+            Ok(msg) => Some(msg),
+            Err(err) => {
+                log::debug!("could not read from the headset: {err}");
+                // A failed read usually fails again right away, so waiting keeps the loop from
+                // spinning until the headset is noticed to be lost
+                std::thread::sleep(timeout.min(READ_FAILURE_DELAY));
+                self.keep_alive(config);
+
+                None
+            }
+        };
+        let received = Instant::now();
+        let mut repeated = false;
+        if let Some(msg) = msg.filter(|msg| !msg.is_empty()) {
+            self.device.stats.unrequested_messages += 1;
+            // A report delivered twice arrives right after itself
+            repeated = self
+                .previous_report
+                .replace(msg)
+                .is_some_and(|previous| *previous == *msg);
+        }
+
+        let kind = match msg.as_deref() {
+            Some([]) if held => Some(EventKind::Buttons(self.button_state.tick(Instant::now()))),
+            Some([]) => {
+                self.keep_alive(config);
+
+                None
+            }
+            Some(bytes) => match protocol::decode(bytes) {
+                Some(Message::MicArm(mic_arm)) => {
+                    let repeated = self.button_state.mic_arm == Some(mic_arm);
+                    if self.mic_arm_debouncer.is_bounce(
+                        config.mic_arm_debounce(),
+                        repeated,
+                        received,
+                    ) {
+                        log::debug!("dropping repeated mic arm report");
+
+                        None
+                    } else {
+                        self.button_state.mic_arm = Some(mic_arm);
+                        log::debug!("mic arm state is {:?}", self.button_state.mic_arm);
+
+                        Some(EventKind::Buttons(self.button_state.at(Instant::now())))
+                    }
                 }
-                Some([0x08, 0x01]) => {
-                    log::debug!("mute button pressed");
+                Some(Message::MuteButton) => {
+                    if self
+                        .mute_debouncer
+                        .is_bounce(config.mute_debounce(), repeated, received)
+                    {
+                        log::debug!("dropping repeated mute button report");
+
+                        None
+                    } else {
+                        log::debug!("mute button pressed");
 
-                    config.call_button_handler(
-                        self,
-                        ButtonState {
+                        Some(EventKind::Buttons(ButtonState {
                             mute_button: true,
-                            ..button_state
-                        },
-                    );
+                            ..self.button_state.at(Instant::now())
+                        }))
+                    }
                 }
-                Some(bytes @ [0x11, 0xff, feature, 0x00, ..]) if feature == self.features.gkey => {
-                    button_state.buttons = Buttons::from_bytes(bytes);
-                    log::debug!("button state is {:?}", button_state.buttons);
+                Some(Message::Wheel(wheel)) => {
+                    let now = Instant::now();
+                    self.button_state.update_wheel(wheel, now);
+                    log::debug!("wheel state is {:?}", self.button_state.wheel);
 
-                    config.call_button_handler(self, button_state);
+                    Some(EventKind::Buttons(self.button_state.at(now)))
                 }
-                Some(bytes @ [0x01, _, 0x00, 0x00, 0x00]) => {
-                    button_state.wheel = Wheel::from_bytes(bytes);
-                    log::debug!("wheel state is {:?}", button_state.wheel);
+                Some(Message::Hidpp(report)) => self.handle_notification(config, report),
+                None => {
+                    self.report_unhandled(config, bytes);
 
-                    config.call_button_handler(self, button_state);
+                    None
                 }
-                Some([0x11, 0xff, feature, 0x00, rest @ ..])
-                    if feature == self.features.battery =>
-                {
-                    if rest.iter().all(|&b| b == 0x00) {
-                        power_state = PowerState::Disconnected;
-                    } else {
-                        // After the device reconnected, the config needs to be synced again
-                        config.set_dirty();
-                        power_state = PowerState::Connected;
-                    }
+            },
+            None => None,
+        };
+
+        let kind = kind.or_else(|| {
+            self.pending_power_states
+                .pop_front()
+                .map(EventKind::PowerState)
+        });
+
+        self.dispatch(config, kind, received)
+    }
+
+    /// Handles a HID++ report that the headset sent on its own, returning the event it caused.
+    fn handle_notification(
+        &mut self,
+        config: &mut RunConfig,
+        report: HidppReport,
+    ) -> Option<EventKind> {
+        let feature = report.feature_index();
+
+        if report.device_index() != self.device.device_index {
+            log::debug!(
+                "ignoring a message of the device with index {:#04x}",
+                report.device_index()
+            );
+
+            None
+        } else if !report.is_notification() {
+            self.report_unhandled(config, report.bytes());
 
-                    config.call_power_state_change_handler(self, power_state);
+            None
+        } else if feature == self.features.gkey {
+            let params @ [_, ..] = report.params() else {
+                self.report_unhandled(config, report.bytes());
+
+                return None;
+            };
+
+            let now = Instant::now();
+            self.button_state
+                .update_buttons(Buttons::from_bytes(params), now);
+            log::debug!("button state is {:?}", self.button_state.buttons);
+
+            Some(EventKind::Buttons(self.button_state.at(now)))
+        } else if self.features.wireless.is_supported() && feature == self.features.wireless {
+            let [status, request, ..] = *report.params() else {
+                self.report_unhandled(config, report.bytes());
+
+                return None;
+            };
+            log::debug!("wireless status changed: status {status:#04x}, request {request:#04x}");
+
+            // Only reconnections are reported, after which the settings are lost
+            if status == 0x01 {
+                config.set_dirty();
+                self.transition(config, ConnectionState::Connected);
+            }
+
+            None
+        } else if feature == self.features.battery {
+            // The notification is empty while the headset is turned off, but the ping tells
+            // reliably whether the headset still responds
+            if let Err(err) = self.ping() {
+                log::debug!("the headset did not respond to a ping: {err}");
+
+                self.transition(config, ConnectionState::DeviceLost);
+            } else {
+                // The notification has the layout of the response to a battery request
+                if let Some(status) = report
+                    .params()
+                    .get(self.quirks.battery_offset..)
+                    .and_then(BatteryStatus::parse)
+                {
+                    let status = self.with_charge(status);
+                    self.battery = Some((status, Instant::now()));
                 }
-                Some(msg) => log::info!("unhandled message from device: {msg:02x?}"),
-                None => (),
+
+                // After the device reconnected, the config needs to be synced again
+                config.set_dirty();
+                self.transition(config, ConnectionState::Connected);
+            }
+
+            None
+        } else {
+            self.report_unhandled(config, report.bytes());
+
+            None
+        }
+    }
+
+    /// Passes a report that is not understood to the raw report handler of the config.
+    fn report_unhandled(&mut self, config: &mut RunConfig, bytes: &[u8]) {
+        self.device.stats.parse_failures += 1;
+
+        let feature_id = HidppReport::parse(bytes)
+            .and_then(|report| self.features.id_of(report.feature_index()));
+        config.call_raw_report_handler(
+            self,
+            &RawReport {
+                bytes: bytes.to_vec(),
+                feature_id,
+            },
+        );
+    }
+
+    /// Runs the config handlers for the event, if there is one, and applies the changes to the
+    /// config.
+    ///
+    /// How long it took since the event was `received` until its handlers finished is recorded
+    /// in the statistics.
+    fn dispatch(
+        &mut self,
+        config: &mut RunConfig,
+        kind: Option<EventKind>,
+        received: Instant,
+    ) -> anyhow::Result<Option<Event>> {
+        let event = kind.map(|kind| {
+            self.sequence += 1;
+
+            Event {
+                kind,
+                sequence: self.sequence - 1,
+                time: Instant::now(),
+                timestamp: monotonic_time(),
+            }
+        });
+
+        match kind {
+            Some(EventKind::Buttons(button_state)) => {
+                config.call_button_handler(self, button_state)
+            }
+            Some(EventKind::PowerState(power_state)) => {
+                config.call_power_state_change_handler(self, power_state)
             }
+            None => (),
+        }
+
+        if let Some(event) = event {
+            config.call_plugins(self, |plugin, config, headset| {
+                plugin.on_event(config, headset, event);
+
+                match event.kind {
+                    EventKind::PowerState(PowerState::Connected) => {
+                        plugin.on_connect(config, headset)
+                    }
+                    EventKind::PowerState(PowerState::Disconnected) => {
+                        plugin.on_disconnect(config, headset)
+                    }
+                    EventKind::PowerState(PowerState::Reconnecting | PowerState::Suspended)
+                    | EventKind::Buttons(_) => (),
+                }
+            });
+        }
 
-            config.call_periodic_handler(self);
+        if let Some(event) = event {
+            let latency = received.elapsed();
+            self.device.stats.handler_latency.record(latency);
 
-            if let Err(err) = config.sync_configuration(self) {
-                log::error!("failed config re-synchronization: {err}");
+            if let Some(budget) = config.latency_budget() {
+                if latency > budget {
+                    log::warn!(
+                        "handling the {:?} event took {latency:?}, more than the budget of \
+                         {budget:?}",
+                        event.kind
+                    );
+                }
             }
         }
+
+        config.run_jobs(self);
+        config.call_timers(self);
+        config.call_periodic_handler(self);
+        config.call_plugins(self, |plugin, config, headset| {
+            plugin.on_tick(config, headset)
+        });
+        // The changes are kept until the computer resumed, instead of piling up timeouts
+        if self.state != ConnectionState::Suspended {
+            config.sync_with_backoff(self)?;
+        }
+
+        Ok(event)
     }
 }
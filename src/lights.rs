@@ -1,9 +1,19 @@
 //! Configuration structs and stuff for headset lighting
 
-use crate::{AsBytes, FromBytes};
+mod builder;
+mod color;
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use crate::{protocol::function_byte, AsBytes, FromBytes};
+
+pub use builder::{BreathingBuilder, ColorCycleBuilder, MAX_BRIGHTNESS, MAX_RATE};
+pub use color::Color;
 
 /// Describes which light to configure
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Light {
     /// The logo light
     Logo,
@@ -11,50 +21,70 @@ pub enum Light {
     Side,
 }
 
+impl fmt::Display for Light {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Light::Logo => write!(f, "logo"),
+            Light::Side => write!(f, "side"),
+        }
+    }
+}
+
+impl FromStr for Light {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "logo" => Ok(Light::Logo),
+            "side" => Ok(Light::Side),
+            _ => Err(anyhow::anyhow!(
+                "unknown light {s:?}, expected logo or side"
+            )),
+        }
+    }
+}
+
 /// Configuration for the light effect
-#[derive(Debug, Clone, Copy)]
+///
+/// The variants can be constructed directly, but the values are then sent to the headset
+/// unchecked. Use the builders such as [`Effect::breathing`] to validate them, or check them with
+/// [`Effect::validate`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "effect", rename_all = "snake_case"))]
 pub enum Effect {
     /// Settings for the off effect
+    #[default]
     Off,
     /// Settings for the static color effect
     Static {
-        /// Red value
-        red: u8,
-        /// Blue value
-        green: u8,
-        /// Green value
-        blue: u8,
+        /// The color of the light
+        color: Color,
     },
     /// Settings for the breathing effect
     Breathing {
-        /// Red value
-        red: u8,
-        /// Green value
-        green: u8,
-        /// Blue value
-        blue: u8,
-        /// The rate of the breathing effect
-        rate: u16,
+        /// The color of the light
+        color: Color,
+        /// The duration of one breath
+        #[cfg_attr(feature = "serde", serde(with = "rate_ms"))]
+        rate: Duration,
         /// Light brightness
         brightness: u8,
     },
     /// Settings for the color cycle effect
     ColorCycle {
-        /// The rate of the cycle effect
-        rate: u16,
+        /// The duration of one color cycle
+        #[cfg_attr(feature = "serde", serde(with = "rate_ms"))]
+        rate: Duration,
         /// Light brightness
         brightness: u8,
     },
 }
 
-impl Default for Effect {
-    fn default() -> Self {
-        Effect::Off
-    }
-}
-
 /// Profile type (default or not)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum ProfileType {
     /// Temporarily set (until next power-on)
     Temporary,
@@ -62,9 +92,51 @@ pub enum ProfileType {
     Permanent,
 }
 
+/// Converts an effect rate to the milliseconds used by the protocol.
+///
+/// Rates that do not fit are saturated instead of wrapped around.
+fn rate_to_protocol(rate: Duration) -> [u8; 2] {
+    u16::try_from(rate.as_millis())
+        .unwrap_or(u16::MAX)
+        .to_be_bytes()
+}
+
+/// Converts the milliseconds used by the protocol to an effect rate.
+fn rate_from_protocol(bytes: &[u8]) -> Duration {
+    Duration::from_millis(u16::from_be_bytes(bytes.try_into().unwrap()).into())
+}
+
+/// (De)serializes effect rates as milliseconds.
+#[cfg(feature = "serde")]
+mod rate_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serializes the rate as milliseconds.
+    pub(super) fn serialize<S: Serializer>(
+        rate: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(rate.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Deserializes the rate from milliseconds.
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// The old name of [`LightSettings`], which clashed with the configuration of the loop.
+#[deprecated(note = "renamed to `LightSettings`")]
+pub type Config = LightSettings;
+
 /// Headset light configuration
-#[derive(Debug, Clone, Copy)]
-pub struct Config {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightSettings {
     /// Which light to configure
     pub light: Light,
     /// Configuration for the effect
@@ -73,7 +145,35 @@ pub struct Config {
     pub profile_type: ProfileType,
 }
 
-impl AsBytes for Config {
+/// The effects of both lights, which are set together so that they change in step.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FullLightSettings {
+    /// The effect of the main lights on the side
+    pub side: Effect,
+    /// The effect of the logo light
+    pub logo: Effect,
+}
+
+impl FullLightSettings {
+    /// Returns the effect of the light.
+    pub fn effect(&self, light: Light) -> Effect {
+        match light {
+            Light::Side => self.side,
+            Light::Logo => self.logo,
+        }
+    }
+
+    /// Sets the effect of the light.
+    pub fn set_effect(&mut self, light: Light, effect: Effect) {
+        match light {
+            Light::Side => self.side = effect,
+            Light::Logo => self.logo = effect,
+        }
+    }
+}
+
+impl AsBytes for LightSettings {
     fn as_bytes(&self) -> Vec<u8> {
         let mut params = vec![0u8; 13];
 
@@ -91,26 +191,24 @@ impl AsBytes for Config {
 
         match self.effect {
             Effect::Off => (),
-            Effect::Static { red, green, blue } => {
-                params[2] = red;
-                params[3] = green;
-                params[4] = blue;
+            Effect::Static { color } => {
+                params[2] = color.r;
+                params[3] = color.g;
+                params[4] = color.b;
             }
             Effect::Breathing {
-                red,
-                green,
-                blue,
+                color,
                 rate,
                 brightness,
             } => {
-                params[2] = red;
-                params[3] = green;
-                params[4] = blue;
-                params[5..7].copy_from_slice(&rate.to_be_bytes());
+                params[2] = color.r;
+                params[3] = color.g;
+                params[4] = color.b;
+                params[5..7].copy_from_slice(&rate_to_protocol(rate));
                 params[8] = brightness;
             }
             Effect::ColorCycle { rate, brightness } => {
-                params[7..9].copy_from_slice(&rate.to_be_bytes());
+                params[7..9].copy_from_slice(&rate_to_protocol(rate));
                 params[9] = brightness;
             }
         }
@@ -124,7 +222,43 @@ impl AsBytes for Config {
     }
 }
 
-impl FromBytes for Config {
+impl LightSettings {
+    /// Returns the settings with the effect dimmed to the brightness, if there is one.
+    pub(crate) fn dimmed(&self, brightness: Option<u8>) -> Self {
+        let mut dimmed = *self;
+        if let Some(brightness) = brightness {
+            dimmed.effect = self.effect.with_brightness(brightness);
+        }
+
+        dimmed
+    }
+
+    /// Returns the body of the request showing the settings, with the effect dimmed to the
+    /// brightness if there is one.
+    pub(crate) fn request(&self, brightness: Option<u8>) -> Vec<u8> {
+        let dimmed = self.dimmed(brightness);
+
+        log::debug!("setting lights to {dimmed:?}");
+
+        let mut request = dimmed.as_bytes();
+        request.insert(0, function_byte(3));
+
+        request
+    }
+
+    /// Parses the settings of a light as the headset reports them, returning `None` if the
+    /// bytes do not describe a known light and effect.
+    pub(crate) fn parse(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0 | 1, 0..=3, _, _, _, _, _, _, _, _, _, _, 0 | 2, ..] => {
+                Some(Self::from_bytes(bytes))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromBytes for LightSettings {
     fn from_bytes(bytes: &[u8]) -> Self {
         assert!(
             bytes[0] <= 1,
@@ -151,19 +285,15 @@ impl FromBytes for Config {
             effect: match bytes[1] {
                 0 => Effect::Off,
                 1 => Effect::Static {
-                    red: bytes[2],
-                    green: bytes[3],
-                    blue: bytes[4],
+                    color: Color::new(bytes[2], bytes[3], bytes[4]),
                 },
                 2 => Effect::Breathing {
-                    red: bytes[2],
-                    green: bytes[3],
-                    blue: bytes[4],
-                    rate: u16::from_be_bytes(bytes[5..7].try_into().unwrap()),
+                    color: Color::new(bytes[2], bytes[3], bytes[4]),
+                    rate: rate_from_protocol(&bytes[5..7]),
                     brightness: bytes[8],
                 },
                 3 => Effect::ColorCycle {
-                    rate: u16::from_be_bytes(bytes[7..9].try_into().unwrap()),
+                    rate: rate_from_protocol(&bytes[7..9]),
                     brightness: bytes[9],
                 },
                 _ => unreachable!(),
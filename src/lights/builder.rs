@@ -0,0 +1,196 @@
+//! Validating builders for the light effects.
+
+use std::time::Duration;
+
+use super::{Color, Effect};
+
+/// The largest effect rate, as the protocol encodes the rate in 16 bits of milliseconds.
+pub const MAX_RATE: Duration = Duration::from_millis(u16::MAX as u64);
+
+/// The largest brightness, as the protocol encodes the brightness in percent.
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+/// The rate used by the builders if none is specified.
+const DEFAULT_RATE: Duration = Duration::from_millis(10000);
+
+/// Checks that the rate can be encoded by the protocol.
+fn validate_rate(rate: Duration) -> anyhow::Result<Duration> {
+    anyhow::ensure!(
+        rate <= MAX_RATE,
+        "effect rate must be at most {MAX_RATE:?}, found {rate:?}"
+    );
+
+    Ok(rate)
+}
+
+/// Checks that the brightness is a percentage.
+fn validate_brightness(brightness: u8) -> anyhow::Result<u8> {
+    anyhow::ensure!(
+        brightness <= MAX_BRIGHTNESS,
+        "brightness must be at most {MAX_BRIGHTNESS}, found {brightness}"
+    );
+
+    Ok(brightness)
+}
+
+impl Effect {
+    /// Checks that the values of the effect can be encoded by the protocol.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match *self {
+            Effect::Off | Effect::Static { .. } => (),
+            Effect::Breathing {
+                rate, brightness, ..
+            }
+            | Effect::ColorCycle { rate, brightness } => {
+                validate_rate(rate)?;
+                validate_brightness(brightness)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the effect with the brightness in percent, keeping everything else.
+    ///
+    /// The breathing and color cycle effects have their brightness replaced, whereas static colors
+    /// have their channels scaled, so this should be applied to the undimmed effect. A brightness
+    /// of 0 turns the light off.
+    pub fn with_brightness(self, brightness: u8) -> Effect {
+        let brightness = brightness.min(MAX_BRIGHTNESS);
+        if brightness == 0 {
+            return Effect::Off;
+        }
+
+        match self {
+            Effect::Off => Effect::Off,
+            Effect::Static { color } => {
+                let scale = |channel: u8| {
+                    (u16::from(channel) * u16::from(brightness) / u16::from(MAX_BRIGHTNESS)) as u8
+                };
+
+                Effect::Static {
+                    color: Color::new(scale(color.r), scale(color.g), scale(color.b)),
+                }
+            }
+            Effect::Breathing { color, rate, .. } => Effect::Breathing {
+                color,
+                rate,
+                brightness,
+            },
+            Effect::ColorCycle { rate, .. } => Effect::ColorCycle { rate, brightness },
+        }
+    }
+
+    /// Creates a static color effect.
+    pub fn static_color(color: Color) -> Effect {
+        Effect::Static { color }
+    }
+
+    /// Starts building a breathing effect with the given color.
+    pub fn breathing(color: Color) -> BreathingBuilder {
+        BreathingBuilder {
+            color,
+            rate: DEFAULT_RATE,
+            brightness: MAX_BRIGHTNESS,
+        }
+    }
+
+    /// Starts building a color cycle effect.
+    pub fn color_cycle() -> ColorCycleBuilder {
+        ColorCycleBuilder {
+            rate: DEFAULT_RATE,
+            brightness: MAX_BRIGHTNESS,
+        }
+    }
+}
+
+/// A builder for a breathing effect that only accepts values the protocol can encode.
+#[derive(Debug, Clone, Copy)]
+pub struct BreathingBuilder {
+    /// The color of the light
+    color: Color,
+    /// The rate of the breathing effect
+    rate: Duration,
+    /// Light brightness
+    brightness: u8,
+}
+
+impl BreathingBuilder {
+    /// Sets the duration of one breath.
+    pub fn rate(mut self, rate: Duration) -> anyhow::Result<Self> {
+        self.rate = validate_rate(rate)?;
+
+        Ok(self)
+    }
+
+    /// Sets the duration of one breath in milliseconds.
+    pub fn rate_ms(self, rate: u64) -> anyhow::Result<Self> {
+        self.rate(Duration::from_millis(rate))
+    }
+
+    /// Sets the brightness in percent.
+    pub fn brightness(mut self, brightness: u8) -> anyhow::Result<Self> {
+        self.brightness = validate_brightness(brightness)?;
+
+        Ok(self)
+    }
+
+    /// Returns the configured effect.
+    pub fn build(self) -> Effect {
+        Effect::Breathing {
+            color: self.color,
+            rate: self.rate,
+            brightness: self.brightness,
+        }
+    }
+}
+
+impl From<BreathingBuilder> for Effect {
+    fn from(builder: BreathingBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// A builder for a color cycle effect that only accepts values the protocol can encode.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorCycleBuilder {
+    /// The rate of the cycle effect
+    rate: Duration,
+    /// Light brightness
+    brightness: u8,
+}
+
+impl ColorCycleBuilder {
+    /// Sets the duration of one cycle.
+    pub fn rate(mut self, rate: Duration) -> anyhow::Result<Self> {
+        self.rate = validate_rate(rate)?;
+
+        Ok(self)
+    }
+
+    /// Sets the duration of one cycle in milliseconds.
+    pub fn rate_ms(self, rate: u64) -> anyhow::Result<Self> {
+        self.rate(Duration::from_millis(rate))
+    }
+
+    /// Sets the brightness in percent.
+    pub fn brightness(mut self, brightness: u8) -> anyhow::Result<Self> {
+        self.brightness = validate_brightness(brightness)?;
+
+        Ok(self)
+    }
+
+    /// Returns the configured effect.
+    pub fn build(self) -> Effect {
+        Effect::ColorCycle {
+            rate: self.rate,
+            brightness: self.brightness,
+        }
+    }
+}
+
+impl From<ColorCycleBuilder> for Effect {
+    fn from(builder: ColorCycleBuilder) -> Self {
+        builder.build()
+    }
+}
@@ -0,0 +1,407 @@
+//! The color type used by the light effects.
+
+use std::{fmt, str::FromStr};
+
+/// An RGB color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    /// Red value
+    pub r: u8,
+    /// Green value
+    pub g: u8,
+    /// Blue value
+    pub b: u8,
+}
+
+impl Color {
+    /// Black, which turns the light off.
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    /// White
+    pub const WHITE: Color = Color::new(255, 255, 255);
+    /// Red
+    pub const RED: Color = Color::new(255, 0, 0);
+    /// Green
+    pub const GREEN: Color = Color::new(0, 255, 0);
+    /// Blue
+    pub const BLUE: Color = Color::new(0, 0, 255);
+
+    /// Creates a color from its red, green and blue values.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Creates a color from hue (in degrees), saturation and value (both between 0 and 1).
+    ///
+    /// Hues outside of `0..360` wrap around, saturation and value are clamped.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.0);
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+        let m = value - chroma;
+
+        let (r, g, b) = match hue as u32 / 60 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let to_byte = |val: f32| ((val + m) * 255.0).round() as u8;
+
+        Self::new(to_byte(r), to_byte(g), to_byte(b))
+    }
+
+    /// Returns the hue (in degrees), saturation and value (both between 0 and 1) of the color.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let r = self.r as f32 / 255.0;
+        let g = self.g as f32 / 255.0;
+        let b = self.b as f32 / 255.0;
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        (hue, saturation, max)
+    }
+
+    /// Looks up a CSS color name (case insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+
+        CSS_COLORS
+            .binary_search_by_key(&name.as_str(), |&(name, _)| name)
+            .ok()
+            .map(|idx| {
+                let [_, r, g, b] = CSS_COLORS[idx].1.to_be_bytes();
+                Self::new(r, g, b)
+            })
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// Parses `"#rrggbb"`, `"#rgb"`, `"rgb(r, g, b)"` or a CSS color name, ignoring the case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(hex) = s.strip_prefix('#') {
+            anyhow::ensure!(
+                matches!(hex.len(), 3 | 6) && hex.bytes().all(|b| b.is_ascii_hexdigit()),
+                "hex colors must have the form \"#rrggbb\" or \"#rgb\", found {s:?}"
+            );
+
+            // Each digit of the short form stands for both digits of a component
+            let component = |idx: usize| match hex.len() {
+                3 => u8::from_str_radix(&hex[idx..=idx], 16).map(|digit| digit * 0x11),
+                _ => u8::from_str_radix(&hex[2 * idx..2 * idx + 2], 16),
+            };
+
+            return Ok(Self::new(component(0)?, component(1)?, component(2)?));
+        }
+
+        if let Some(args) = s
+            .get(..4)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("rgb("))
+            .and_then(|_| s[4..].strip_suffix(')'))
+        {
+            let components = args
+                .split(',')
+                .map(|component| component.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()?;
+
+            return match components[..] {
+                [r, g, b] => Ok(Self::new(r, g, b)),
+                _ => Err(anyhow::anyhow!(
+                    "rgb colors must have exactly three components, found {s:?}"
+                )),
+            };
+        }
+
+        Self::from_name(s).ok_or_else(|| anyhow::anyhow!("unknown color {s:?}"))
+    }
+}
+
+/// Colors are serialized in the `"#rrggbb"` form.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Colors are deserialized from any form accepted by the `FromStr` implementation.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The CSS color names with their values, sorted by name.
+const CSS_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!("#ff8000".parse::<Color>().unwrap(), Color::new(255, 128, 0));
+        assert_eq!("#FF8000".parse::<Color>().unwrap(), Color::new(255, 128, 0));
+        assert_eq!("#f80".parse::<Color>().unwrap(), Color::new(255, 136, 0));
+        assert_eq!("#F80".parse::<Color>().unwrap(), Color::new(255, 136, 0));
+        assert_eq!(" #000000 ".parse::<Color>().unwrap(), Color::BLACK);
+    }
+
+    #[test]
+    fn parses_rgb_colors() {
+        assert_eq!(
+            "rgb(1, 2, 3)".parse::<Color>().unwrap(),
+            Color::new(1, 2, 3)
+        );
+        assert_eq!(
+            "rgb(255,0,128)".parse::<Color>().unwrap(),
+            Color::new(255, 0, 128)
+        );
+        assert_eq!(
+            "RGB( 4 , 5 , 6 )".parse::<Color>().unwrap(),
+            Color::new(4, 5, 6)
+        );
+    }
+
+    #[test]
+    fn parses_named_colors() {
+        assert_eq!("red".parse::<Color>().unwrap(), Color::RED);
+        assert_eq!("Red".parse::<Color>().unwrap(), Color::RED);
+        assert_eq!(
+            "RebeccaPurple".parse::<Color>().unwrap(),
+            Color::new(0x66, 0x33, 0x99)
+        );
+        assert_eq!(
+            Color::from_name("cornflowerblue"),
+            Some(Color::new(0x64, 0x95, 0xed))
+        );
+        assert_eq!(Color::from_name("no such color"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for color in [
+            "",
+            "#",
+            "#ff80",
+            "#ff80000",
+            "#gg0000",
+            "#ééé",
+            "ff8000",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4)",
+            "rgb(256, 0, 0)",
+            "rgb(-1, 0, 0)",
+            "rgb(1, 2, 3",
+            "rgb()",
+            "reddish",
+        ] {
+            assert!(color.parse::<Color>().is_err(), "{color:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn keeps_the_color_names_sorted() {
+        // The names are looked up with a binary search
+        assert!(CSS_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn displays_colors_in_hex() {
+        let color = Color::new(0x12, 0xab, 0x00);
+
+        assert_eq!(color.to_string(), "#12ab00");
+        assert_eq!(color.to_string().parse::<Color>().unwrap(), color);
+    }
+
+    #[test]
+    fn converts_hsv() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::RED);
+        assert_eq!(Color::from_hsv(120.0, 1.0, 1.0), Color::GREEN);
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), Color::BLUE);
+        assert_eq!(Color::from_hsv(0.0, 0.0, 2.0), Color::WHITE);
+        assert_eq!(Color::new(0, 255, 255).to_hsv(), (180.0, 1.0, 1.0));
+        assert_eq!(Color::BLACK.to_hsv(), (0.0, 0.0, 0.0));
+    }
+}
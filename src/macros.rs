@@ -0,0 +1,488 @@
+//! Macros played back when the G keys are pressed.
+//!
+//! A macro is a sequence of key events, shell commands and delays. Key events are emitted using a
+//! virtual input device, which requires the `uinput` feature.
+
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+
+use crate::{
+    bindings::spawn_shell_command,
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonChange, ButtonState, Event, EventKind, Headset,
+};
+
+/// A step of a macro.
+///
+/// Keys are given by their Linux names, such as `"KEY_A"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Step {
+    /// Presses the key.
+    Press(String),
+    /// Releases the key.
+    Release(String),
+    /// Presses and releases the key.
+    Tap(String),
+    /// Waits before the next step.
+    #[cfg_attr(feature = "serde", serde(rename = "delay_ms", with = "delay_ms"))]
+    Delay(Duration),
+    /// Runs the shell command without waiting for it to finish.
+    Command(String),
+}
+
+impl Step {
+    /// Returns the key pressed or released by the step, if any.
+    fn key(&self) -> Option<&str> {
+        match self {
+            Step::Press(key) | Step::Release(key) | Step::Tap(key) => Some(key),
+            Step::Delay(_) | Step::Command(_) => None,
+        }
+    }
+}
+
+/// (De)serializes delays as milliseconds.
+#[cfg(feature = "serde")]
+mod delay_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Serializes the delay as milliseconds.
+    pub(super) fn serialize<S: Serializer>(
+        delay: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(delay.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Deserializes the delay from milliseconds.
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// How a macro is played back when its key is pressed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Playback {
+    /// The macro is played once per press.
+    #[default]
+    Once,
+    /// The macro is repeated as long as the key is held.
+    WhileHeld,
+    /// The macro is repeated until the key is pressed again.
+    Toggle,
+}
+
+/// A macro, which is a sequence of steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Macro {
+    /// How the macro is played back.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub playback: Playback,
+    /// The steps of the macro.
+    pub steps: Vec<Step>,
+}
+
+impl Macro {
+    /// Checks whether the macro can be played back.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let total_delay: Duration = self
+            .steps
+            .iter()
+            .map(|step| match step {
+                Step::Delay(delay) => *delay,
+                _ => Duration::ZERO,
+            })
+            .sum();
+
+        anyhow::ensure!(
+            self.playback == Playback::Once || !total_delay.is_zero(),
+            "repeated macros need a delay, so that they do not run as fast as possible"
+        );
+
+        #[cfg(all(feature = "uinput", target_os = "linux"))]
+        for key in self.steps.iter().filter_map(Step::key) {
+            key.parse::<evdev::KeyCode>()
+                .map_err(|_| anyhow::anyhow!("unknown key {key:?}"))?;
+        }
+        #[cfg(not(all(feature = "uinput", target_os = "linux")))]
+        anyhow::ensure!(
+            self.steps.iter().all(|step| step.key().is_none()),
+            "macros with key events require the uinput feature"
+        );
+
+        Ok(())
+    }
+}
+
+/// A request to the thread playing the macros.
+#[derive(Debug)]
+enum PlayerRequest {
+    /// Starts playing the macro, stopping the current one.
+    Start(Arc<Macro>),
+    /// Stops playing the current macro.
+    Stop,
+}
+
+/// Emits the key events of macros.
+#[derive(Debug)]
+struct KeyOutput {
+    /// The virtual input device.
+    #[cfg(all(feature = "uinput", target_os = "linux"))]
+    device: evdev::uinput::VirtualDevice,
+    /// The keys that are currently held down.
+    pressed: BTreeSet<String>,
+}
+
+impl KeyOutput {
+    /// Creates the virtual input device for the keys.
+    #[cfg(all(feature = "uinput", target_os = "linux"))]
+    fn new(keys: &BTreeSet<String>) -> anyhow::Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                key.parse::<evdev::KeyCode>()
+                    .map_err(|_| anyhow::anyhow!("unknown key {key:?}"))
+            })
+            .collect::<anyhow::Result<evdev::AttributeSet<_>>>()?;
+
+        let device = evdev::uinput::VirtualDevice::builder()?
+            .name("Logitech G935 Macros")
+            .with_keys(&keys)?
+            .build()?;
+
+        Ok(Self {
+            device,
+            pressed: BTreeSet::new(),
+        })
+    }
+
+    /// Fails, as key events cannot be emitted without the `uinput` feature.
+    #[cfg(not(all(feature = "uinput", target_os = "linux")))]
+    fn new(keys: &BTreeSet<String>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            keys.is_empty(),
+            "macros with key events require the uinput feature"
+        );
+
+        Ok(Self {
+            pressed: BTreeSet::new(),
+        })
+    }
+
+    /// Presses (`value` 1) or releases (`value` 0) the key.
+    fn emit(&mut self, key: &str, value: i32) {
+        if value == 0 {
+            self.pressed.remove(key);
+        } else {
+            self.pressed.insert(key.to_string());
+        }
+
+        #[cfg(all(feature = "uinput", target_os = "linux"))]
+        {
+            let Ok(code) = key.parse::<evdev::KeyCode>() else {
+                return;
+            };
+
+            if let Err(err) = self.device.emit(&[*evdev::KeyEvent::new(code, value)]) {
+                log::error!("failed to emit key events: {err}");
+            }
+        }
+    }
+
+    /// Releases all keys that are held down.
+    fn release_all(&mut self) {
+        for key in std::mem::take(&mut self.pressed) {
+            self.emit(&key, 0);
+        }
+    }
+}
+
+/// Plays the macro once, returning the request that interrupted it, if any.
+fn play(
+    macro_: &Macro,
+    output: &mut KeyOutput,
+    requests: &Receiver<PlayerRequest>,
+) -> Option<PlayerRequest> {
+    for step in &macro_.steps {
+        if let Ok(request) = requests.try_recv() {
+            return Some(request);
+        }
+
+        match step {
+            Step::Press(key) => output.emit(key, 1),
+            Step::Release(key) => output.emit(key, 0),
+            Step::Tap(key) => {
+                output.emit(key, 1);
+                output.emit(key, 0);
+            }
+            Step::Delay(delay) => {
+                let deadline = Instant::now() + *delay;
+
+                match requests.recv_deadline(deadline) {
+                    Ok(request) => return Some(request),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return Some(PlayerRequest::Stop),
+                }
+            }
+            Step::Command(command) => spawn_shell_command(command.clone()),
+        }
+    }
+
+    None
+}
+
+/// Plays the requested macros until all senders are gone.
+fn run_player(mut output: KeyOutput, requests: Receiver<PlayerRequest>) {
+    let mut next = None;
+
+    loop {
+        let request = match next.take() {
+            Some(request) => request,
+            None => match requests.recv() {
+                Ok(request) => request,
+                Err(_) => return,
+            },
+        };
+        let PlayerRequest::Start(macro_) = request else {
+            continue;
+        };
+
+        loop {
+            next = play(&macro_, &mut output, &requests);
+            if next.is_some() || macro_.playback == Playback::Once {
+                break;
+            }
+        }
+
+        // Keys must not stay held down when a macro is interrupted
+        output.release_all();
+    }
+}
+
+/// Plays macros in the background, one at a time.
+///
+/// The player is shared by all [`MacroControl`]s created with it.
+#[derive(Debug, Clone)]
+pub struct MacroPlayer {
+    /// The channel to send requests to the playing thread on.
+    requests: Sender<PlayerRequest>,
+}
+
+impl MacroPlayer {
+    /// Starts the thread playing the macros, creating a virtual input device for the keys used by
+    /// the given macros if necessary.
+    pub fn new<'a>(macros: impl IntoIterator<Item = &'a Macro>) -> anyhow::Result<Self> {
+        let keys = macros
+            .into_iter()
+            .flat_map(|macro_| macro_.steps.iter().filter_map(Step::key))
+            .map(str::to_string)
+            .collect();
+        let output = KeyOutput::new(&keys)?;
+
+        let (requests, receiver) = crossbeam_channel::unbounded();
+        std::thread::spawn(move || run_player(output, receiver));
+
+        Ok(Self { requests })
+    }
+
+    /// Sends the request to the playing thread.
+    fn request(&self, request: PlayerRequest) {
+        // The thread only stops once all senders are gone
+        self.requests.send(request).ok();
+    }
+
+    /// Stops playing the current macro.
+    pub fn stop(&self) {
+        self.request(PlayerRequest::Stop);
+    }
+}
+
+/// The macros played for the G keys.
+///
+/// Keys mapped to `None` do not play a macro.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroKeyMap {
+    /// The macro played for the G1 key.
+    pub g1: Option<Macro>,
+    /// The macro played for the G2 key.
+    pub g2: Option<Macro>,
+    /// The macro played for the G3 key.
+    pub g3: Option<Macro>,
+}
+
+/// Plays macros when the G keys are pressed.
+#[derive(Debug)]
+pub struct MacroControl {
+    /// The player of the macros.
+    player: MacroPlayer,
+    /// The macros of the G1, G2 and G3 keys.
+    macros: [Option<Arc<Macro>>; 3],
+    /// The index of the key whose repeated macro is playing.
+    repeating: Option<usize>,
+    /// The button state of the previous event.
+    old_state: ButtonState,
+}
+
+impl MacroControl {
+    /// Creates the control playing the macros of the keys using the player.
+    pub fn new(player: &MacroPlayer, keys: MacroKeyMap) -> Self {
+        Self {
+            player: player.clone(),
+            macros: [keys.g1, keys.g2, keys.g3].map(|macro_| macro_.map(Arc::new)),
+            repeating: None,
+            old_state: ButtonState::default(),
+        }
+    }
+
+    /// Stops a repeated macro started by this control.
+    pub fn stop(&mut self) {
+        if self.repeating.take().is_some() {
+            self.player.stop();
+        }
+    }
+
+    /// Starts or stops the macros for the events that led to the given button state.
+    pub fn handle(&mut self, state: ButtonState) {
+        let changes = state.diff(&self.old_state);
+        self.old_state = state;
+
+        for index in 0..3 {
+            let Some(macro_) = &self.macros[index] else {
+                continue;
+            };
+            let pressed = changes.contains(ButtonChange::g_key(index, true));
+            let released = changes.contains(ButtonChange::g_key(index, false));
+
+            match macro_.playback {
+                Playback::Once if pressed => self
+                    .player
+                    .request(PlayerRequest::Start(Arc::clone(macro_))),
+                Playback::WhileHeld if pressed => {
+                    self.repeating = Some(index);
+                    self.player
+                        .request(PlayerRequest::Start(Arc::clone(macro_)));
+                }
+                Playback::WhileHeld if released && self.repeating == Some(index) => self.stop(),
+                Playback::Toggle if pressed && self.repeating == Some(index) => self.stop(),
+                Playback::Toggle if pressed => {
+                    self.repeating = Some(index);
+                    self.player
+                        .request(PlayerRequest::Start(Arc::clone(macro_)));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Turns the control into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, _, state| self.handle(state))
+    }
+}
+
+impl Plugin for MacroControl {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            self.handle(state);
+        }
+    }
+
+    fn on_disconnect(&mut self, _: &mut RunConfig, _: &mut Headset) {
+        // The release of held keys is not reported anymore
+        self.stop();
+    }
+}
+
+/// Records the key presses and releases of all keyboards until the stop key is pressed.
+///
+/// The delays between the events are recorded as well. This requires read access to the input
+/// devices in `/dev/input`.
+#[cfg(all(feature = "uinput", target_os = "linux"))]
+pub fn record(stop_key: evdev::KeyCode) -> anyhow::Result<Vec<Step>> {
+    use evdev::EventSummary;
+
+    let (sender, events) = crossbeam_channel::unbounded();
+    let mut keyboards = 0;
+
+    for (path, mut device) in evdev::enumerate() {
+        if !device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(evdev::KeyCode::KEY_A))
+        {
+            continue;
+        }
+
+        log::debug!("recording {}", path.display());
+        keyboards += 1;
+
+        let sender = sender.clone();
+        std::thread::spawn(move || loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(err) => {
+                    log::warn!("stopped recording {}: {err}", path.display());
+                    return;
+                }
+            };
+
+            for event in events {
+                // Auto repeat events (value 2) are not part of the macro
+                if let EventSummary::Key(_, key, value @ (0 | 1)) = event.destructure() {
+                    if sender.send((Instant::now(), key, value)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    anyhow::ensure!(
+        keyboards > 0,
+        "no keyboard found, make sure that you can read the devices in /dev/input"
+    );
+
+    let mut steps = Vec::new();
+    let mut last_event = None;
+
+    for (time, key, value) in events {
+        if key == stop_key {
+            // The release of the key that started the recording may still arrive
+            if value == 1 {
+                break;
+            }
+            continue;
+        }
+
+        if let Some(last_event) = last_event {
+            let delay = time.duration_since(last_event);
+            if delay >= Duration::from_millis(1) {
+                steps.push(Step::Delay(Duration::from_millis(delay.as_millis() as u64)));
+            }
+        }
+        last_event = Some(time);
+
+        let key = format!("{key:?}");
+        steps.push(if value == 1 {
+            Step::Press(key)
+        } else {
+            Step::Release(key)
+        });
+    }
+
+    Ok(steps)
+}
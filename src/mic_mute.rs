@@ -0,0 +1,146 @@
+//! Mutes the capture device of the headset when the microphone arm is flipped up.
+//!
+//! The headset itself only mutes the microphone, but the capture device stays active, so
+//! applications do not notice that the microphone is muted.
+
+use alsa::mixer::{Mixer, Selem, SelemChannelId};
+
+use crate::{
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonState, Event, EventKind, Headset, MicArm,
+};
+
+/// The capture switch of the headset's ALSA sound card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureSwitch {
+    /// The name of the mixer device, such as `hw:1`.
+    device: String,
+    /// The name of the simple mixer control with the capture switch.
+    control: String,
+}
+
+impl CaptureSwitch {
+    /// Finds the capture switch of the headset's sound card.
+    pub fn find() -> anyhow::Result<Self> {
+        for card in alsa::card::Iter::new() {
+            let card = card?;
+            let name = card.get_longname().or_else(|_| card.get_name())?;
+
+            if !name.contains("G935") {
+                continue;
+            }
+
+            let device = format!("hw:{}", card.get_index());
+            let mixer = Mixer::new(&device, false)?;
+
+            let control = mixer
+                .iter()
+                .filter_map(Selem::new)
+                .filter(|selem| selem.has_capture_switch())
+                .find_map(|selem| selem.get_id().get_name().ok().map(ToOwned::to_owned));
+
+            if let Some(control) = control {
+                log::debug!("using capture switch {control:?} of {device} ({name})");
+
+                return Ok(Self { device, control });
+            }
+        }
+
+        anyhow::bail!("could not find the capture switch of the headset's sound card")
+    }
+
+    /// Runs `f` with the capture switch control.
+    ///
+    /// The mixer is opened for every access, so that it survives the sound card being replugged.
+    fn with_selem<T>(&self, f: impl FnOnce(&Selem) -> alsa::Result<T>) -> anyhow::Result<T> {
+        let mixer = Mixer::new(&self.device, false)?;
+        let selem = mixer
+            .iter()
+            .filter_map(Selem::new)
+            .find(|selem| selem.get_id().get_name() == Ok(self.control.as_str()))
+            .ok_or_else(|| anyhow::anyhow!("capture switch {:?} disappeared", self.control))?;
+
+        Ok(f(&selem)?)
+    }
+
+    /// Returns `true` if the capture device is muted.
+    pub fn is_muted(&self) -> anyhow::Result<bool> {
+        self.with_selem(|selem| selem.get_capture_switch(SelemChannelId::mono()))
+            .map(|switch| switch == 0)
+    }
+
+    /// Mutes or unmutes the capture device.
+    pub fn set_muted(&self, muted: bool) -> anyhow::Result<()> {
+        log::debug!(
+            "{} the capture device",
+            if muted { "muting" } else { "unmuting" }
+        );
+
+        self.with_selem(|selem| selem.set_capture_switch_all(!muted as i32))
+    }
+}
+
+/// Mutes the capture device while the microphone arm is flipped up.
+#[derive(Debug)]
+pub struct FlipToMute {
+    /// The capture switch to control.
+    switch: CaptureSwitch,
+    /// The position of the microphone arm that was last applied.
+    applied: Option<MicArm>,
+}
+
+impl FlipToMute {
+    /// Finds the capture switch of the headset.
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            switch: CaptureSwitch::find()?,
+            applied: None,
+        })
+    }
+
+    /// Syncs the capture device with the given microphone arm position.
+    pub fn sync(&mut self, mic_arm: MicArm) -> anyhow::Result<()> {
+        if self.applied == Some(mic_arm) {
+            return Ok(());
+        }
+
+        let muted = mic_arm == MicArm::Up;
+        if self.switch.is_muted()? != muted {
+            self.switch.set_muted(muted)?;
+        }
+        self.applied = Some(mic_arm);
+
+        Ok(())
+    }
+
+    /// Syncs the capture device with the microphone arm position of the given button state.
+    ///
+    /// As the headset only reports the position when the arm is flipped, the capture device is
+    /// synced on the first report.
+    pub fn handle(&mut self, state: ButtonState) -> anyhow::Result<()> {
+        match state.mic_arm() {
+            Some(mic_arm) => self.sync(mic_arm),
+            None => Ok(()),
+        }
+    }
+
+    /// Turns the flip to mute behavior into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, _, state| {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to sync the capture device: {err}");
+            }
+        })
+    }
+}
+
+impl Plugin for FlipToMute {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to sync the capture device: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Controls media players over D-Bus using MPRIS.
+//!
+//! This implements what the `playerctl` bindings do, without depending on `playerctl`.
+
+use std::{fmt, str::FromStr};
+
+use zbus::blocking::{fdo::DBusProxy, Connection, Proxy};
+
+use crate::{
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonChange, ButtonState, Event, EventKind, Headset,
+};
+
+/// The prefix of the bus names of MPRIS media players.
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// The object path of MPRIS media players.
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// The interface used to control MPRIS media players.
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// An action that can be performed on a media player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaAction {
+    /// Toggles between playing and pausing.
+    PlayPause,
+    /// Starts playing.
+    Play,
+    /// Pauses playing.
+    Pause,
+    /// Stops playing.
+    Stop,
+    /// Skips to the next track.
+    Next,
+    /// Skips to the previous track.
+    Previous,
+}
+
+impl MediaAction {
+    /// All actions.
+    const ALL: [MediaAction; 6] = [
+        MediaAction::PlayPause,
+        MediaAction::Play,
+        MediaAction::Pause,
+        MediaAction::Stop,
+        MediaAction::Next,
+        MediaAction::Previous,
+    ];
+
+    /// Returns the name of the MPRIS method performing the action.
+    fn method(self) -> &'static str {
+        match self {
+            MediaAction::PlayPause => "PlayPause",
+            MediaAction::Play => "Play",
+            MediaAction::Pause => "Pause",
+            MediaAction::Stop => "Stop",
+            MediaAction::Next => "Next",
+            MediaAction::Previous => "Previous",
+        }
+    }
+
+    /// Returns the name of the action as used in configuration files.
+    fn name(self) -> &'static str {
+        match self {
+            MediaAction::PlayPause => "play_pause",
+            MediaAction::Play => "play",
+            MediaAction::Pause => "pause",
+            MediaAction::Stop => "stop",
+            MediaAction::Next => "next",
+            MediaAction::Previous => "previous",
+        }
+    }
+}
+
+impl fmt::Display for MediaAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for MediaAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MediaAction::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| anyhow::anyhow!("unknown media action {s:?}"))
+    }
+}
+
+/// The media actions performed for the G keys.
+///
+/// Keys mapped to `None` do not perform any action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaKeyMap {
+    /// The action performed when the G1 key is pressed.
+    pub g1: Option<MediaAction>,
+    /// The action performed when the G2 key is pressed.
+    pub g2: Option<MediaAction>,
+    /// The action performed when the G3 key is pressed.
+    pub g3: Option<MediaAction>,
+}
+
+impl Default for MediaKeyMap {
+    fn default() -> Self {
+        Self {
+            g1: Some(MediaAction::PlayPause),
+            g2: Some(MediaAction::Next),
+            g3: Some(MediaAction::Previous),
+        }
+    }
+}
+
+/// Controls the media players on the session bus with the G keys.
+#[derive(Debug)]
+pub struct MediaControl {
+    /// The connection to the session bus.
+    connection: Connection,
+    /// The actions performed for the G keys.
+    actions: MediaKeyMap,
+    /// The button state of the previous event.
+    old_state: ButtonState,
+}
+
+impl MediaControl {
+    /// Connects to the session bus.
+    pub fn new(actions: MediaKeyMap) -> anyhow::Result<Self> {
+        Ok(Self {
+            connection: Connection::session()?,
+            actions,
+            old_state: ButtonState::default(),
+        })
+    }
+
+    /// Returns the player that should be controlled, if there is any.
+    ///
+    /// Like `playerctl`, this prefers a player that is currently playing.
+    fn player(&self) -> anyhow::Result<Option<Proxy<'static>>> {
+        let names = DBusProxy::new(&self.connection)?.list_names()?;
+
+        let mut players = Vec::new();
+        for name in names {
+            if !name.starts_with(PLAYER_PREFIX) {
+                continue;
+            }
+
+            let player = Proxy::new(
+                &self.connection,
+                name.to_string(),
+                PLAYER_PATH,
+                PLAYER_INTERFACE,
+            )?;
+
+            match player.get_property::<String>("PlaybackStatus") {
+                Ok(status) if status == "Playing" => return Ok(Some(player)),
+                Ok(_) => players.push(player),
+                Err(err) => log::debug!("ignoring player {name}: {err}"),
+            }
+        }
+
+        Ok(players.into_iter().next())
+    }
+
+    /// Performs the action on the active media player.
+    pub fn perform(&self, action: MediaAction) -> anyhow::Result<()> {
+        let Some(player) = self.player()? else {
+            log::info!("not performing {action}, because there is no media player");
+            return Ok(());
+        };
+
+        log::debug!("performing {action} on {}", player.destination());
+
+        player.call_method(action.method(), &())?;
+
+        Ok(())
+    }
+
+    /// Performs the actions for the events that led to the given button state.
+    pub fn handle(&mut self, state: ButtonState) -> anyhow::Result<()> {
+        let changes = state.diff(&self.old_state);
+        self.old_state = state;
+
+        let keys = [
+            (self.actions.g1, ButtonChange::G1Pressed),
+            (self.actions.g2, ButtonChange::G2Pressed),
+            (self.actions.g3, ButtonChange::G3Pressed),
+        ];
+        for (action, change) in keys {
+            if let (Some(action), true) = (action, changes.contains(change)) {
+                self.perform(action)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turns the media control into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, _, state| {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to control the media player: {err}");
+            }
+        })
+    }
+}
+
+impl Plugin for MediaControl {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to control the media player: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Shows on a light of the headset whether the microphone is muted.
+//!
+//! The headset itself has no indicator for a muted microphone, which is easy to miss when it is
+//! muted in software rather than by flipping up the arm.
+
+use std::time::Duration;
+#[cfg(feature = "audio")]
+use std::time::Instant;
+
+#[cfg(feature = "audio")]
+use crate::audio::Audio;
+use crate::{
+    config::{OverlayId, RunConfig},
+    lights::{Effect, Light},
+    plugin::Plugin,
+    Event, EventKind, Headset, MicArm,
+};
+
+/// How often the mute state of the sound server is polled.
+#[cfg(feature = "audio")]
+const SOURCE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Shows an effect on a light while the microphone is muted, using an overlay so that the
+/// configured effect is shown again once it is unmuted.
+///
+/// The microphone counts as muted while the arm is flipped up, or, with the `audio` feature and
+/// [`MuteIndicator::with_audio`], while the headset's source is muted in the sound server.
+#[derive(Debug)]
+pub struct MuteIndicator {
+    /// The light showing the mute state.
+    light: Light,
+    /// The effect shown while muted.
+    effect: Effect,
+    /// Whether a flipped up microphone arm counts as muted.
+    follow_mic_arm: bool,
+    /// The connection to the sound server, if its mute state is followed.
+    #[cfg(feature = "audio")]
+    audio: Option<Audio>,
+    /// Whether the microphone arm is flipped up.
+    arm_muted: bool,
+    /// Whether the headset's source is muted in the sound server.
+    source_muted: bool,
+    /// When the mute state of the sound server was last polled.
+    #[cfg(feature = "audio")]
+    last_source_poll: Option<Instant>,
+    /// The overlay showing the indicator, while it is shown.
+    overlay: Option<OverlayId>,
+}
+
+impl MuteIndicator {
+    /// Creates an indicator showing the effect on the light while the microphone arm is flipped
+    /// up.
+    pub fn new(light: Light, effect: Effect) -> Self {
+        Self {
+            light,
+            effect,
+            follow_mic_arm: true,
+            #[cfg(feature = "audio")]
+            audio: None,
+            arm_muted: false,
+            source_muted: false,
+            #[cfg(feature = "audio")]
+            last_source_poll: None,
+            overlay: None,
+        }
+    }
+
+    /// Sets whether a flipped up microphone arm counts as muted, which it does by default.
+    pub fn follow_mic_arm(mut self, follow_mic_arm: bool) -> Self {
+        self.follow_mic_arm = follow_mic_arm;
+        self
+    }
+
+    /// Also shows the indicator while the headset's source is muted in the sound server.
+    #[cfg(feature = "audio")]
+    pub fn with_audio(mut self, audio: Audio) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    /// Returns `true` if the indicator is shown.
+    pub fn is_shown(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    /// Shows or hides the indicator according to the mute state.
+    fn update(&mut self, config: &mut RunConfig) {
+        let muted = (self.follow_mic_arm && self.arm_muted) || self.source_muted;
+
+        match (muted, self.overlay) {
+            (true, None) => {
+                log::debug!("showing the mute indicator on the {} light", self.light);
+
+                self.overlay = Some(config.push_overlay(self.light, self.effect));
+            }
+            (false, Some(overlay)) => {
+                log::debug!("hiding the mute indicator on the {} light", self.light);
+
+                self.overlay = None;
+                config.pop_overlay(overlay);
+            }
+            _ => (),
+        }
+    }
+
+    /// Polls the mute state of the sound server, if it is followed and due.
+    fn poll_source(&mut self) {
+        #[cfg(feature = "audio")]
+        if let Some(audio) = &mut self.audio {
+            if self
+                .last_source_poll
+                .is_some_and(|last_poll| last_poll.elapsed() < SOURCE_POLL_INTERVAL)
+            {
+                return;
+            }
+            self.last_source_poll = Some(Instant::now());
+
+            match audio.is_source_muted() {
+                Ok(muted) => self.source_muted = muted,
+                Err(err) => log::debug!("could not read the mute state of the source: {err}"),
+            }
+        }
+    }
+}
+
+impl Plugin for MuteIndicator {
+    fn on_event(&mut self, config: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            if let Some(mic_arm) = state.mic_arm() {
+                self.arm_muted = mic_arm == MicArm::Up;
+                self.update(config);
+            }
+        }
+    }
+
+    fn on_tick(&mut self, config: &mut RunConfig, _: &mut Headset) {
+        self.poll_source();
+        self.update(config);
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        #[cfg(feature = "audio")]
+        if self.audio.is_some() {
+            return Some(SOURCE_POLL_INTERVAL);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,450 @@
+//! Puts OBS "on air" with the microphone arm and shows on a light whether it is streaming.
+//!
+//! OBS is controlled through its built-in WebSocket server (obs-websocket 5), which needs to be
+//! enabled in the OBS settings. Only unencrypted `ws://` connections are supported, which is what
+//! OBS offers.
+
+use std::{
+    collections::HashMap,
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use crossbeam_channel::{Receiver, Sender};
+use serde_json::{json, Value};
+use sha2::{Digest as _, Sha256};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::{
+    config::{OverlayId, RunConfig},
+    lights::{Effect, Light},
+    plugin::Plugin,
+    Event, EventKind, Headset, MicArm,
+};
+
+/// The URL of the WebSocket server of OBS, unless configured otherwise.
+pub const DEFAULT_URL: &str = "ws://localhost:4455";
+
+/// The version of the obs-websocket RPC protocol that is spoken.
+const RPC_VERSION: u64 = 1;
+
+/// The event subscription for the outputs, which includes the streaming state.
+const OUTPUTS_EVENTS: u64 = 1 << 6;
+
+/// How long to wait before reconnecting after the connection to OBS failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long reading a message waits before the pending commands are sent.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the plugin checks whether the streaming state changed.
+const TICK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A request to OBS, as its type and its data.
+type Request = (&'static str, Value);
+
+/// What is done in OBS when the microphone arm is flipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MicArmAction {
+    /// Switches to the scene `up` when the arm is flipped up and to `down` when it is flipped
+    /// down.
+    SwitchScene {
+        /// The scene shown while the arm is flipped up.
+        up: String,
+        /// The scene shown while the arm is flipped down.
+        down: String,
+    },
+    /// Shows the source in the scene while the arm is flipped up, like a "mic muted" banner.
+    ShowSource {
+        /// The scene containing the source.
+        scene: String,
+        /// The source shown while the arm is flipped up.
+        source: String,
+    },
+    /// Triggers the hotkeys with the names OBS uses for them, like `OBSBasic.StartStreaming`.
+    Hotkey {
+        /// The hotkey triggered when the arm is flipped up.
+        up: Option<String>,
+        /// The hotkey triggered when the arm is flipped down.
+        down: Option<String>,
+    },
+}
+
+impl MicArmAction {
+    /// Returns the requests performing the action for the position of the arm, as the request
+    /// type and its data.
+    fn requests(&self, mic_arm: MicArm) -> Vec<Request> {
+        let up = mic_arm == MicArm::Up;
+
+        match self {
+            MicArmAction::SwitchScene { up: up_scene, down } => vec![(
+                "SetCurrentProgramScene",
+                json!({ "sceneName": if up { up_scene } else { down } }),
+            )],
+            MicArmAction::ShowSource { scene, source } => vec![(
+                "GetSceneItemId",
+                json!({ "sceneName": scene, "sourceName": source, "enabled": up }),
+            )],
+            MicArmAction::Hotkey {
+                up: up_hotkey,
+                down,
+            } => if up { up_hotkey } else { down }
+                .iter()
+                .map(|name| ("TriggerHotkeyByName", json!({ "hotkeyName": name })))
+                .collect(),
+        }
+    }
+}
+
+/// Performs actions in OBS when the microphone arm is flipped, and shows an effect on a light
+/// while OBS is streaming.
+///
+/// The connection to OBS is made in the background and re-established whenever it fails, so
+/// OBS can be started after the plugin. Flips of the arm while OBS is not connected are
+/// ignored.
+#[derive(Debug)]
+pub struct OnAir {
+    /// The actions performed when the arm is flipped.
+    actions: Vec<MicArmAction>,
+    /// The requests for the connection thread.
+    requests: Sender<Vec<Request>>,
+    /// The streaming state reported by the connection thread.
+    streaming: Receiver<bool>,
+    /// The light and the effect shown while streaming, if the state is shown.
+    streaming_light: Option<(Light, Effect)>,
+    /// The position of the arm when it was last seen.
+    mic_arm: Option<MicArm>,
+    /// The overlay showing that OBS is streaming, while it is shown.
+    overlay: Option<OverlayId>,
+}
+
+impl OnAir {
+    /// Connects to the WebSocket server of OBS at the URL, authenticating with the password if
+    /// OBS asks for one.
+    pub fn connect(url: impl Into<String>, password: Option<String>) -> Self {
+        let (requests, receiver) = crossbeam_channel::unbounded();
+        let (sender, streaming) = crossbeam_channel::unbounded();
+        let url = url.into();
+
+        std::thread::spawn(move || drive(&url, password.as_deref(), &receiver, &sender));
+
+        Self {
+            actions: Vec::new(),
+            requests,
+            streaming,
+            streaming_light: None,
+            mic_arm: None,
+            overlay: None,
+        }
+    }
+
+    /// Performs the action whenever the microphone arm is flipped.
+    pub fn on_mic_arm(mut self, action: MicArmAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    /// Shows the effect on the light while OBS is streaming, using an overlay so that the
+    /// configured effect is shown again afterwards.
+    pub fn show_streaming(mut self, light: Light, effect: Effect) -> Self {
+        self.streaming_light = Some((light, effect));
+        self
+    }
+
+    /// Returns `true` if the light shows that OBS is streaming.
+    pub fn is_shown(&self) -> bool {
+        self.overlay.is_some()
+    }
+}
+
+impl Plugin for OnAir {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        let EventKind::Buttons(state) = event.kind else {
+            return;
+        };
+
+        if let Some(mic_arm) = state.mic_arm() {
+            if self.mic_arm.replace(mic_arm) != Some(mic_arm) && !self.actions.is_empty() {
+                let requests = self
+                    .actions
+                    .iter()
+                    .flat_map(|action| action.requests(mic_arm))
+                    .collect();
+                // The connection thread only stops with the plugin
+                self.requests.send(requests).ok();
+            }
+        }
+    }
+
+    fn on_tick(&mut self, config: &mut RunConfig, _: &mut Headset) {
+        let Some(streaming) = self.streaming.try_iter().last() else {
+            return;
+        };
+        let Some((light, effect)) = self.streaming_light else {
+            return;
+        };
+
+        match (streaming, self.overlay) {
+            (true, None) => {
+                log::debug!("showing on the {light} light that OBS is streaming");
+
+                self.overlay = Some(config.push_overlay(light, effect));
+            }
+            (false, Some(overlay)) => {
+                log::debug!("hiding on the {light} light that OBS is streaming");
+
+                self.overlay = None;
+                config.pop_overlay(overlay);
+            }
+            _ => (),
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        self.streaming_light.map(|_| TICK_INTERVAL)
+    }
+}
+
+/// Keeps connecting to OBS until the plugin is dropped, performing the actions for the flips of
+/// the arm and reporting the streaming state.
+fn drive(
+    url: &str,
+    password: Option<&str>,
+    requests: &Receiver<Vec<Request>>,
+    streaming: &Sender<bool>,
+) {
+    loop {
+        let mut session = match Session::connect(url, password) {
+            Ok(session) => session,
+            Err(err) => {
+                log::debug!("could not connect to OBS at {url}: {err:#}");
+                if !wait_for_reconnect(requests) {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        log::info!("connected to OBS at {url}");
+        match session.run(requests, streaming) {
+            Ok(()) => return,
+            Err(err) => log::warn!("lost the connection to OBS: {err:#}"),
+        }
+        // Without a connection, OBS is not streaming
+        if streaming.send(false).is_err() || !wait_for_reconnect(requests) {
+            return;
+        }
+    }
+}
+
+/// Waits before reconnecting, discarding the flips of the arm meanwhile.
+///
+/// Returns `false` if the plugin was dropped.
+fn wait_for_reconnect(requests: &Receiver<Vec<Request>>) -> bool {
+    let deadline = Instant::now() + RECONNECT_DELAY;
+
+    loop {
+        match requests.recv_deadline(deadline) {
+            Ok(_) => log::debug!("ignoring the flip of the arm, as OBS is not connected"),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => return true,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+/// An identified connection to OBS.
+struct Session {
+    /// The WebSocket connected to OBS.
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    /// The number of the next request.
+    next_request: u64,
+    /// Whether the requests that look up a source show or hide it, by request ID.
+    pending_sources: HashMap<String, (String, bool)>,
+}
+
+impl Session {
+    /// Connects to OBS and identifies, authenticating with the password if needed.
+    fn connect(url: &str, password: Option<&str>) -> anyhow::Result<Self> {
+        let (socket, _) = tungstenite::connect(url)?;
+        let mut session = Self {
+            socket,
+            next_request: 0,
+            pending_sources: HashMap::new(),
+        };
+
+        let hello = session.receive()?;
+        anyhow::ensure!(hello["op"] == 0, "expected the hello of OBS, got {hello}");
+
+        let mut identify = json!({
+            "rpcVersion": RPC_VERSION,
+            "eventSubscriptions": OUTPUTS_EVENTS,
+        });
+        if let Some(auth) = hello["d"].get("authentication") {
+            let password = password.context("OBS asks for a password, but none is configured")?;
+            let challenge = auth["challenge"].as_str().unwrap_or_default();
+            let salt = auth["salt"].as_str().unwrap_or_default();
+            identify["authentication"] = authentication(password, salt, challenge).into();
+        }
+        session.send(json!({ "op": 1, "d": identify }))?;
+
+        let identified = session.receive()?;
+        anyhow::ensure!(
+            identified["op"] == 2,
+            "OBS did not accept the identification: {identified}"
+        );
+
+        if let MaybeTlsStream::Plain(stream) = session.socket.get_ref() {
+            stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        }
+
+        Ok(session)
+    }
+
+    /// Performs the actions for the flips of the arm and reports the streaming state, until the
+    /// plugin is dropped or the connection fails.
+    fn run(
+        &mut self,
+        requests: &Receiver<Vec<Request>>,
+        streaming: &Sender<bool>,
+    ) -> anyhow::Result<()> {
+        self.request("GetStreamStatus", json!({}))?;
+
+        loop {
+            loop {
+                match requests.try_recv() {
+                    Ok(requests) => {
+                        for (request_type, data) in requests {
+                            self.request(request_type, data)?;
+                        }
+                    }
+                    Err(crossbeam_channel::TryRecvError::Empty) => break,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            let message = match self.receive() {
+                Ok(message) => message,
+                Err(err)
+                    if err.downcast_ref::<tungstenite::Error>().is_some_and(|err| {
+                        matches!(
+                            err,
+                            tungstenite::Error::Io(err)
+                                if matches!(
+                                    err.kind(),
+                                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                                )
+                        )
+                    }) =>
+                {
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if let Some(active) = self.handle(&message)? {
+                if streaming.send(active).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Handles a message of OBS, returning the streaming state if it reports one.
+    fn handle(&mut self, message: &Value) -> anyhow::Result<Option<bool>> {
+        let data = &message["d"];
+
+        match message["op"].as_u64() {
+            // An event
+            Some(5) if data["eventType"] == "StreamStateChanged" => {
+                Ok(data["eventData"]["outputActive"].as_bool())
+            }
+            // The response to a request
+            Some(7) => {
+                let status = &data["requestStatus"];
+                if status["result"] != true {
+                    log::warn!(
+                        "OBS could not perform {}: {}",
+                        data["requestType"],
+                        status["comment"].as_str().unwrap_or("unknown error")
+                    );
+                    return Ok(None);
+                }
+
+                let request_id = data["requestId"].as_str().unwrap_or_default();
+                match data["requestType"].as_str() {
+                    Some("GetStreamStatus") => Ok(data["responseData"]["outputActive"].as_bool()),
+                    Some("GetSceneItemId") => {
+                        if let Some((scene, enabled)) = self.pending_sources.remove(request_id) {
+                            self.request(
+                                "SetSceneItemEnabled",
+                                json!({
+                                    "sceneName": scene,
+                                    "sceneItemId": data["responseData"]["sceneItemId"],
+                                    "sceneItemEnabled": enabled,
+                                }),
+                            )?;
+                        }
+
+                        Ok(None)
+                    }
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Sends the request, remembering whether the sources it looks up are shown.
+    fn request(&mut self, request_type: &str, mut data: Value) -> anyhow::Result<()> {
+        let request_id = self.next_request.to_string();
+        self.next_request += 1;
+
+        if request_type == "GetSceneItemId" {
+            let enabled = data["enabled"].as_bool().unwrap_or_default();
+            let scene = data["sceneName"].as_str().unwrap_or_default().to_string();
+            if let Some(data) = data.as_object_mut() {
+                data.remove("enabled");
+            }
+            self.pending_sources
+                .insert(request_id.clone(), (scene, enabled));
+        }
+
+        log::debug!("sending {request_type} {data} to OBS");
+        self.send(json!({
+            "op": 6,
+            "d": {
+                "requestType": request_type,
+                "requestId": request_id,
+                "requestData": data,
+            },
+        }))
+    }
+
+    /// Sends the message as JSON.
+    fn send(&mut self, message: Value) -> anyhow::Result<()> {
+        self.socket.send(Message::text(message.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Receives the next JSON message, skipping the other messages.
+    fn receive(&mut self) -> anyhow::Result<Value> {
+        loop {
+            match self.socket.read()? {
+                Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                Message::Close(_) => anyhow::bail!("OBS closed the connection"),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Returns the authentication string of obs-websocket for the password.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let base64 = base64::engine::general_purpose::STANDARD;
+    let secret = base64.encode(Sha256::digest(format!("{password}{salt}")));
+
+    base64.encode(Sha256::digest(format!("{secret}{challenge}")))
+}
@@ -0,0 +1,41 @@
+//! Extensions of the loop polling the headset.
+
+use std::time::Duration;
+
+use crate::{
+    config::{ButtonHandler, RunConfig},
+    Event, EventKind, Headset,
+};
+
+/// An extension of the loop polling the headset, added using [`RunConfig::add_plugin`].
+///
+/// All methods do nothing by default, so plugins only implement the ones they need. The built-in
+/// integrations implement this trait, so that they can be combined freely.
+pub trait Plugin {
+    /// Called for every event of the headset, after the button handler.
+    fn on_event(&mut self, _config: &mut RunConfig, _headset: &mut Headset, _event: Event) {}
+
+    /// Called after every poll, whether an event occurred or not.
+    fn on_tick(&mut self, _config: &mut RunConfig, _headset: &mut Headset) {}
+
+    /// Called when the headset was turned on.
+    fn on_connect(&mut self, _config: &mut RunConfig, _headset: &mut Headset) {}
+
+    /// Called when the headset was turned off.
+    fn on_disconnect(&mut self, _config: &mut RunConfig, _headset: &mut Headset) {}
+
+    /// Returns how soon [`Plugin::on_tick`] needs to be called while the headset is idle.
+    ///
+    /// `None` means that the plugin only needs to be called after events.
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Plugin for ButtonHandler {
+    fn on_event(&mut self, config: &mut RunConfig, headset: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            self(config, headset, state);
+        }
+    }
+}
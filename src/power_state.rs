@@ -1,10 +1,54 @@
 //! Code for interacting with the power state of the device.
 
+use std::fmt;
+
 /// Represents the current power state of the headset.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum PowerState {
     /// The headset is currently connected.
     Connected,
     /// The headset is turned off.
     Disconnected,
+    /// The connection to the headset is being reopened.
+    Reconnecting,
+    /// The computer is suspended, so the headset is not talked to.
+    Suspended,
+}
+
+impl fmt::Display for PowerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerState::Connected => write!(f, "connected"),
+            PowerState::Disconnected => write!(f, "disconnected"),
+            PowerState::Reconnecting => write!(f, "reconnecting"),
+            PowerState::Suspended => write!(f, "suspended"),
+        }
+    }
+}
+
+/// The state of the connection to the headset, as tracked by the event loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConnectionState {
+    /// The headset responds to requests.
+    Connected,
+    /// The headset stopped responding, most likely because it was turned off.
+    DeviceLost,
+    /// The connection to the headset is being reopened.
+    Reconnecting,
+    /// The computer is suspended, so the headset is not talked to until it resumes.
+    Suspended,
+}
+
+impl ConnectionState {
+    /// Returns the power state reported to the handlers when entering this state.
+    pub(crate) fn power_state(self) -> PowerState {
+        match self {
+            ConnectionState::Connected => PowerState::Connected,
+            ConnectionState::DeviceLost => PowerState::Disconnected,
+            ConnectionState::Reconnecting => PowerState::Reconnecting,
+            ConnectionState::Suspended => PowerState::Suspended,
+        }
+    }
 }
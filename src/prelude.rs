@@ -0,0 +1,9 @@
+//! The types most programs using the library need, to be imported at once with
+//! `use g935::prelude::*;`.
+
+pub use crate::{
+    lights::{Color, Effect, FullLightSettings, Light, ProfileType},
+    plugin::Plugin,
+    BatteryStatus, ButtonChange, ButtonState, Capabilities, ChargingStatus, Event, EventKind,
+    Headset, HeadsetHandle, LightSettings, MicArm, PowerState, RunConfig,
+};
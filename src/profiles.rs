@@ -0,0 +1,256 @@
+//! Named profiles of settings and bindings that can be switched with the buttons.
+
+use std::{collections::BTreeMap, fmt, str::FromStr, time::Duration};
+
+use crate::{
+    bindings::{Bindings, Trigger, TriggerDetector},
+    config::{ButtonHandler, RunConfig},
+    lights::{self, Effect},
+    macros::MacroControl,
+    plugin::Plugin,
+    ButtonState, Event, EventKind, Headset,
+};
+
+/// The settings and bindings of a profile.
+#[derive(Debug)]
+pub struct Profile {
+    /// The name of the profile.
+    pub name: String,
+    /// The effect of the side lights.
+    pub side_light: Effect,
+    /// The effect of the logo light.
+    pub logo_light: Effect,
+    /// The sidetone level in percent.
+    pub sidetone: Option<u8>,
+    /// The gain in dB of each equalizer band.
+    pub equalizer: Option<Vec<i8>>,
+    /// The bindings active while the profile is selected.
+    pub bindings: Bindings,
+    /// The macros played while the profile is selected.
+    pub macros: Option<MacroControl>,
+}
+
+impl Profile {
+    /// Applies the settings of the profile to the config.
+    pub fn apply(&self, config: &mut RunConfig) {
+        config.set_lights(lights::FullLightSettings {
+            side: self.side_light,
+            logo: self.logo_light,
+        });
+        config.set_sidetone(self.sidetone);
+        config.set_equalizer(self.equalizer.clone());
+    }
+}
+
+/// A change of the selected profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileAction {
+    /// Selects the next profile, wrapping around after the last one.
+    Next,
+    /// Selects the previous profile, wrapping around before the first one.
+    Previous,
+    /// Selects the profile with the name.
+    Select(String),
+}
+
+impl fmt::Display for ProfileAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileAction::Next => write!(f, "next"),
+            ProfileAction::Previous => write!(f, "previous"),
+            ProfileAction::Select(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl FromStr for ProfileAction {
+    type Err = std::convert::Infallible;
+
+    /// Parses `"next"` and `"previous"`, treating everything else as a profile name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "next" => ProfileAction::Next,
+            "previous" => ProfileAction::Previous,
+            name => ProfileAction::Select(name.to_string()),
+        })
+    }
+}
+
+/// A handler called with the name of the newly selected profile.
+pub type ProfileChangeHandler = Box<dyn FnMut(&str)>;
+
+/// Switches between profiles when button events occur, running the bindings of the selected one.
+pub struct Profiles {
+    /// The profiles that can be selected.
+    profiles: Vec<Profile>,
+    /// The index of the selected profile.
+    selected: usize,
+    /// The profile changes performed for each trigger.
+    actions: BTreeMap<Trigger, ProfileAction>,
+    /// The detector for the triggers of the actions and bindings.
+    detector: TriggerDetector,
+    /// The handler called when the selected profile changes.
+    change_handler: Option<ProfileChangeHandler>,
+}
+
+impl fmt::Debug for Profiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Profiles")
+            .field("profiles", &self.profiles)
+            .field("selected", &self.selected)
+            .field("actions", &self.actions)
+            .field("detector", &self.detector)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Profiles {
+    /// Creates the profiles, selecting the first one.
+    ///
+    /// Returns `None` if there are no profiles.
+    pub fn new(profiles: Vec<Profile>) -> Option<Self> {
+        if profiles.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            profiles,
+            selected: 0,
+            actions: BTreeMap::new(),
+            detector: TriggerDetector::new(),
+            change_handler: None,
+        })
+    }
+
+    /// Returns the selected profile.
+    pub fn selected(&self) -> &Profile {
+        &self.profiles[self.selected]
+    }
+
+    /// Returns the index of the profile with the name.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.name == name)
+    }
+
+    /// Selects the profile with the name without applying it, returning `false` if it does not
+    /// exist.
+    ///
+    /// This is meant for choosing the initial profile, before the profiles are used.
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.selected = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Performs the action when the trigger occurs, instead of running the bound command.
+    pub fn bind(&mut self, trigger: Trigger, action: ProfileAction) {
+        self.actions.insert(trigger, action);
+    }
+
+    /// Sets the time a key needs to be held to count as a long press.
+    ///
+    /// The default is 500 ms.
+    pub fn set_long_press_threshold(&mut self, threshold: Duration) {
+        self.detector.set_long_press_threshold(threshold);
+    }
+
+    /// Sets the time within which a second press counts as a double press.
+    ///
+    /// The default is 300 ms.
+    pub fn set_double_press_window(&mut self, window: Duration) {
+        self.detector.set_double_press_window(window);
+    }
+
+    /// Sets the time between two repeats while the wheel is scrolled.
+    ///
+    /// The default is 100 ms.
+    pub fn set_scroll_repeat_interval(&mut self, interval: Duration) {
+        self.detector.set_scroll_repeat_interval(interval);
+    }
+
+    /// Sets the time within which presses of several G keys form a chord, or `None` to not
+    /// detect chords.
+    ///
+    /// Chords are not detected by default.
+    pub fn set_chord_window(&mut self, window: Option<Duration>) {
+        self.detector.set_chord_window(window);
+    }
+
+    /// Sets the handler called with the name of the newly selected profile.
+    pub fn set_change_handler(&mut self, handler: Option<ProfileChangeHandler>) {
+        self.change_handler = handler;
+    }
+
+    /// Performs the action, applying the newly selected profile to the config.
+    fn perform(&mut self, action: &ProfileAction, config: &mut RunConfig) {
+        let count = self.profiles.len();
+        let selected = match action {
+            ProfileAction::Next => (self.selected + 1) % count,
+            ProfileAction::Previous => (self.selected + count - 1) % count,
+            ProfileAction::Select(name) => match self.position(name) {
+                Some(index) => index,
+                None => {
+                    log::warn!("cannot select the unknown profile {name:?}");
+                    return;
+                }
+            },
+        };
+        if selected == self.selected {
+            return;
+        }
+
+        // A repeated macro of the previous profile would otherwise never be stopped
+        if let Some(macros) = &mut self.profiles[self.selected].macros {
+            macros.stop();
+        }
+
+        self.selected = selected;
+        let profile = &self.profiles[selected];
+        log::info!("switched to the {} profile", profile.name);
+        profile.apply(config);
+
+        if let Some(handler) = &mut self.change_handler {
+            handler(&profile.name);
+        }
+    }
+
+    /// Switches profiles or runs the bound commands of the selected profile for the events that
+    /// led to the given button state.
+    pub fn handle(&mut self, config: &mut RunConfig, headset: &mut Headset, state: ButtonState) {
+        if let Some(macros) = &mut self.profiles[self.selected].macros {
+            macros.handle(state);
+        }
+
+        for trigger in self.detector.detect(state) {
+            match self.actions.get(&trigger).cloned() {
+                Some(action) => self.perform(&action, config),
+                None => self.profiles[self.selected].bindings.run(trigger, headset),
+            }
+        }
+    }
+
+    /// Turns the profiles into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |config, headset, state| self.handle(config, headset, state))
+    }
+}
+
+impl Plugin for Profiles {
+    fn on_event(&mut self, config: &mut RunConfig, headset: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            self.handle(config, headset, state);
+        }
+    }
+
+    fn on_disconnect(&mut self, _: &mut RunConfig, _: &mut Headset) {
+        if let Some(macros) = &mut self.profiles[self.selected].macros {
+            macros.stop();
+        }
+    }
+}
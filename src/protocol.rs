@@ -0,0 +1,200 @@
+//! The raw reports exchanged with the headset, for tools talking to it directly.
+//!
+//! The headset sends the state of its wheel, microphone arm and mute button in reports of their
+//! own. Everything else uses HID++ reports, which start with the report ID, followed by the device
+//! index, the feature index and a byte combining the function with the software ID. The
+//! parameters of the function follow.
+
+use crate::{
+    buttons::{MicArm, Wheel},
+    FromBytes,
+};
+
+pub use crate::discovery::RECEIVER_INDEX;
+
+/// The report ID of the reports of the wheel.
+pub const WHEEL_REPORT_ID: u8 = 0x01;
+
+/// The length of the reports of the wheel.
+pub const WHEEL_REPORT_LEN: usize = 5;
+
+/// The report ID of the reports of the microphone arm and the mute button.
+pub const BUTTON_REPORT_ID: u8 = 0x08;
+
+/// The length of the reports of the microphone arm and the mute button.
+pub const BUTTON_REPORT_LEN: usize = 2;
+
+/// The report ID of a HID++ short report, which has 3 bytes of parameters.
+pub const SHORT_REPORT_ID: u8 = 0x10;
+
+/// The length of a HID++ short report.
+pub const SHORT_REPORT_LEN: usize = 7;
+
+/// The report ID of a HID++ long report, which has 16 bytes of parameters.
+pub const LONG_REPORT_ID: u8 = 0x11;
+
+/// The length of a HID++ long report, which all requests of this library use.
+pub const LONG_REPORT_LEN: usize = 20;
+
+/// The length of the header preceding the parameters of a HID++ report.
+pub const HEADER_LEN: usize = 4;
+
+/// The software ID this library sends its requests with, to tell its responses apart from those
+/// to other programs.
+pub const SOFTWARE_ID: u8 = 0x01;
+
+/// The IDs of the HID++ features used by this library, as listed in the HID++ specification.
+///
+/// The headset assigns each feature an index, which the root feature resolves the ID to.
+pub mod feature {
+    /// The root feature, which always has the index 0 and resolves the other features.
+    pub const ROOT: u16 = 0x0000;
+    /// The feature listing all features of the device.
+    pub const FEATURE_SET: u16 = 0x0001;
+    /// The feature reporting the name of the device.
+    pub const DEVICE_NAME: u16 = 0x0005;
+    /// The feature reporting the battery voltage and charging status.
+    pub const BATTERY_VOLTAGE: u16 = 0x1f20;
+    /// The feature reporting the state of charge directly, if the firmware supports it.
+    pub const UNIFIED_BATTERY: u16 = 0x1004;
+    /// The feature reporting changes of the wireless link.
+    pub const WIRELESS_STATUS: u16 = 0x1d4b;
+    /// The feature handing the G keys to the host.
+    pub const GKEY: u16 = 0x8010;
+    /// The feature controlling the report rate of the receiver.
+    pub const REPORT_RATE: u16 = 0x8060;
+    /// The feature controlling the lights.
+    pub const COLOR_LED_EFFECTS: u16 = 0x8070;
+    /// The feature controlling the sidetone.
+    pub const SIDETONE: u16 = 0x8300;
+    /// The feature controlling the equalizer.
+    pub const EQUALIZER: u16 = 0x8310;
+}
+
+/// Returns the byte requesting the function of a feature, combining it with [`SOFTWARE_ID`].
+///
+/// Only the lower 4 bits of the function are used.
+pub const fn function_byte(function: u8) -> u8 {
+    (function & 0x0f) << 4 | SOFTWARE_ID
+}
+
+/// Returns the HID++ long report requesting the function of the feature with the given index.
+///
+/// Fails if the function does not fit into 4 bits or if there are more than 16 bytes of
+/// parameters. The unused parameters are 0.
+pub fn encode_request(
+    device_index: u8,
+    feature_index: u8,
+    function: u8,
+    params: &[u8],
+) -> anyhow::Result<[u8; LONG_REPORT_LEN]> {
+    anyhow::ensure!(function <= 0x0f, "there is no function {function}");
+    anyhow::ensure!(
+        params.len() <= LONG_REPORT_LEN - HEADER_LEN,
+        "a request can have at most {} bytes of parameters",
+        LONG_REPORT_LEN - HEADER_LEN
+    );
+
+    let mut report = [0; LONG_REPORT_LEN];
+    report[..HEADER_LEN].copy_from_slice(&[
+        LONG_REPORT_ID,
+        device_index,
+        feature_index,
+        function_byte(function),
+    ]);
+    report[HEADER_LEN..HEADER_LEN + params.len()].copy_from_slice(params);
+
+    Ok(report)
+}
+
+/// Returns the length of the reports with the given ID, if it is known.
+///
+/// On Windows, reports are padded to the length of the longest report of the interface, so they
+/// need to be cut to their actual length, as the messages are told apart by their length.
+pub fn report_len(id: u8) -> Option<usize> {
+    match id {
+        WHEEL_REPORT_ID => Some(WHEEL_REPORT_LEN),
+        BUTTON_REPORT_ID => Some(BUTTON_REPORT_LEN),
+        SHORT_REPORT_ID => Some(SHORT_REPORT_LEN),
+        LONG_REPORT_ID => Some(LONG_REPORT_LEN),
+        _ => None,
+    }
+}
+
+/// A report received from the headset, as told apart by [`decode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message<'a> {
+    /// The wheel started or stopped scrolling.
+    Wheel(Wheel),
+    /// The microphone arm was flipped.
+    MicArm(MicArm),
+    /// The mute button was pressed.
+    MuteButton,
+    /// A HID++ report, such as a response or a notification.
+    Hidpp(HidppReport<'a>),
+}
+
+/// Returns what the report is, if it is one of the reports the headset is known to send.
+///
+/// The report needs to be cut to its length, see [`report_len`].
+pub fn decode(bytes: &[u8]) -> Option<Message<'_>> {
+    match bytes {
+        [WHEEL_REPORT_ID, _, 0x00, 0x00, 0x00] => Some(Message::Wheel(Wheel::from_bytes(bytes))),
+        [BUTTON_REPORT_ID, 0x10 | 0x20] => Some(Message::MicArm(MicArm::from_bytes(bytes))),
+        [BUTTON_REPORT_ID, 0x01] => Some(Message::MuteButton),
+        _ => HidppReport::parse(bytes).map(Message::Hidpp),
+    }
+}
+
+/// A borrowed HID++ report, such as a response or a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HidppReport<'a> {
+    /// The bytes of the report, which contain at least the header.
+    bytes: &'a [u8],
+}
+
+impl<'a> HidppReport<'a> {
+    /// Returns the view of the bytes, if they are a HID++ report.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        match bytes {
+            [SHORT_REPORT_ID | LONG_REPORT_ID, _, _, _, ..] => Some(Self { bytes }),
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the device behind the receiver that the report belongs to.
+    pub fn device_index(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// Returns the index of the feature that the report belongs to.
+    pub fn feature_index(&self) -> u8 {
+        self.bytes[2]
+    }
+
+    /// Returns the function of the feature, which is 0 for notifications.
+    pub fn function(&self) -> u8 {
+        self.bytes[3] >> 4
+    }
+
+    /// Returns the software ID, which is set by the program making a request and 0 for
+    /// notifications.
+    pub fn software_id(&self) -> u8 {
+        self.bytes[3] & 0x0f
+    }
+
+    /// Returns whether the report is a notification of the headset rather than a response.
+    pub fn is_notification(&self) -> bool {
+        self.bytes[3] == 0x00
+    }
+
+    /// Returns all bytes of the report.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the parameters following the header.
+    pub fn params(&self) -> &'a [u8] {
+        &self.bytes[HEADER_LEN..]
+    }
+}
@@ -0,0 +1,49 @@
+//! Differences between headset models and firmware versions that the protocol does not announce.
+//!
+//! The code was written for the G935 using protocol version 4.2. Other variants, like the G933
+//! and G633, use the same features, but some of them lay out the responses differently.
+
+use crate::{info::HeadsetInfo, BatteryStatus};
+
+/// The models and protocol versions known to behave as expected, with their quirks.
+const KNOWN: &[(&str, (u8, u8), Quirks)] = &[("G935", (4, 2), Quirks { battery_offset: 0 })];
+
+/// The largest shift of the battery status that is tried for unknown variants.
+const MAX_BATTERY_OFFSET: usize = 2;
+
+/// The range of plausible battery voltages in mV, used to recognize the battery status.
+const PLAUSIBLE_VOLTAGES: std::ops::RangeInclusive<u16> = 3000..=4500;
+
+/// How a variant of the headset deviates from the G935 with protocol version 4.2.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    /// By how many bytes the battery status is shifted in the battery responses and
+    /// notifications.
+    pub battery_offset: usize,
+}
+
+impl Quirks {
+    /// Returns the quirks of the variant, if it is known.
+    pub fn known(info: &HeadsetInfo) -> Option<Quirks> {
+        KNOWN
+            .iter()
+            .find(|(model, protocol_version, _)| {
+                info.name.contains(model) && info.protocol_version == *protocol_version
+            })
+            .map(|(_, _, quirks)| *quirks)
+    }
+
+    /// Finds the quirks of an unknown variant from its response to a battery request, returning
+    /// `None` if the response does not match any known layout.
+    pub(crate) fn negotiate(battery_response: &[u8]) -> Option<Quirks> {
+        (0..=MAX_BATTERY_OFFSET)
+            .find(|&offset| {
+                battery_response
+                    .get(offset..)
+                    .and_then(BatteryStatus::parse)
+                    .is_some_and(|status| PLAUSIBLE_VOLTAGES.contains(&status.voltage))
+            })
+            .map(|battery_offset| Quirks { battery_offset })
+    }
+}
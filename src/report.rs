@@ -0,0 +1,46 @@
+//! Typed views of the HID++ responses of the headset, whose layout is described in
+//! [`crate::protocol`].
+
+use std::fmt;
+
+use crate::{
+    device::Report,
+    protocol::{HidppReport, HEADER_LEN, LONG_REPORT_LEN},
+};
+
+/// The response of the headset to a request on one of its features.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FeatureResponse {
+    /// The report of the response, which is a HID++ report.
+    report: Report,
+}
+
+impl FeatureResponse {
+    /// Wraps the response to a request, failing if it is not a complete HID++ long report.
+    ///
+    /// The parameters are indexed by the callers, so a truncated read is rejected here.
+    pub(crate) fn new(report: Report) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            HidppReport::parse(&report).is_some(),
+            "the response {report:?} is not a HID++ report"
+        );
+        anyhow::ensure!(
+            report.len() >= LONG_REPORT_LEN,
+            "the response {report:?} is cut off after {} of {LONG_REPORT_LEN} bytes",
+            report.len()
+        );
+
+        Ok(Self { report })
+    }
+
+    /// Returns the parameters of the response.
+    pub(crate) fn params(&self) -> &[u8] {
+        &self.report[HEADER_LEN..]
+    }
+}
+
+impl fmt::Debug for FeatureResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.report.fmt(f)
+    }
+}
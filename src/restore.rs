@@ -0,0 +1,60 @@
+//! Returning the headset to its defaults once it is no longer driven.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{config::RestorePolicy, Headset};
+
+/// Restores the headset when it is dropped, see [`Headset::restore_on_drop`].
+///
+/// The guard derefs to the headset, so it can be used in its place.
+#[derive(Debug)]
+pub struct RestoreOnDrop<'a> {
+    /// The headset to restore.
+    headset: &'a mut Headset,
+    /// What is restored.
+    policy: RestorePolicy,
+}
+
+impl<'a> RestoreOnDrop<'a> {
+    /// Creates the guard restoring the headset as the policy asks for.
+    pub(crate) fn new(headset: &'a mut Headset, policy: RestorePolicy) -> Self {
+        Self { headset, policy }
+    }
+
+    /// Changes what is restored.
+    pub(crate) fn set_policy(&mut self, policy: RestorePolicy) {
+        self.policy = policy;
+    }
+}
+
+impl Deref for RestoreOnDrop<'_> {
+    type Target = Headset;
+
+    fn deref(&self) -> &Self::Target {
+        self.headset
+    }
+}
+
+impl DerefMut for RestoreOnDrop<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.headset
+    }
+}
+
+impl Drop for RestoreOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.policy == RestorePolicy::Keep {
+            return;
+        }
+
+        if std::thread::panicking() {
+            log::warn!("restoring the headset after a panic");
+        } else {
+            log::debug!("restoring the headset");
+        }
+
+        if let Err(err) = self.headset.restore(self.policy) {
+            log::warn!("{err:#}");
+        }
+    }
+}
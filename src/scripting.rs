@@ -0,0 +1,242 @@
+//! Button bindings written as [Rhai](https://rhai.rs) scripts.
+//!
+//! The scripts can only use the functions registered here, which cannot access files or block:
+//!
+//! - `set_lights(light, color)` shows a static color on the `"side"` or `"logo"` light
+//! - `lights_off(light)` turns the light off
+//! - `set_sidetone(level)` sets the sidetone level in percent
+//! - `run(command)` runs the shell command without waiting for it
+//! - `battery()` returns a map with `percent`, `voltage` and `charging`, or `()` if the battery
+//!   status cannot be read
+//!
+//! The trigger that ran the script is available as the constant `trigger`, and `print` logs its
+//! argument. Changes to the lights and the sidetone are made to the [`RunConfig`], so they are
+//! restored after reconnects.
+
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
+
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{
+    battery::BATTERY_MAX_AGE,
+    bindings::{spawn_shell_command, Trigger, TriggerDetector},
+    config::RunConfig,
+    lights::{Color, Effect, Light},
+    plugin::Plugin,
+    BatteryStatus, Event, EventKind, Headset,
+};
+
+/// The maximum number of operations a script may perform, so that endless loops are stopped.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A change requested by a script, performed after it finished.
+#[derive(Debug)]
+enum Action {
+    /// Sets the effect of a light.
+    SetLights(Light, Effect),
+    /// Sets the sidetone level in percent.
+    SetSidetone(u8),
+    /// Runs a shell command.
+    Run(String),
+}
+
+/// A compiled script.
+#[derive(Debug)]
+struct Script {
+    /// The compiled script.
+    ast: AST,
+    /// Whether the script reads the battery status, which is only read if it does.
+    reads_battery: bool,
+}
+
+/// Runs scripts when button events occur.
+pub struct ScriptBindings {
+    /// The engine running the scripts.
+    engine: Engine,
+    /// The script to run for each trigger.
+    scripts: BTreeMap<Trigger, Script>,
+    /// The changes requested by the running script.
+    actions: Rc<RefCell<Vec<Action>>>,
+    /// The battery status provided to the running script.
+    battery: Rc<RefCell<Option<BatteryStatus>>>,
+    /// The detector for the triggers of the scripts.
+    detector: TriggerDetector,
+}
+
+impl fmt::Debug for ScriptBindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScriptBindings")
+            .field("scripts", &self.scripts.keys())
+            .field("detector", &self.detector)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ScriptBindings {
+    fn default() -> Self {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let battery = Rc::new(RefCell::new(None::<BatteryStatus>));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| log::info!("script: {text}"));
+        engine.on_debug(|text, _, pos| log::debug!("script at {pos}: {text}"));
+
+        let queue = Rc::clone(&actions);
+        engine.register_fn(
+            "set_lights",
+            move |light: &str, color: &str| -> Result<(), Box<EvalAltResult>> {
+                let light = light.parse::<Light>().map_err(|err| err.to_string())?;
+                let color = color.parse::<Color>().map_err(|err| err.to_string())?;
+
+                queue
+                    .borrow_mut()
+                    .push(Action::SetLights(light, Effect::Static { color }));
+
+                Ok(())
+            },
+        );
+
+        let queue = Rc::clone(&actions);
+        engine.register_fn(
+            "lights_off",
+            move |light: &str| -> Result<(), Box<EvalAltResult>> {
+                let light = light.parse::<Light>().map_err(|err| err.to_string())?;
+
+                queue
+                    .borrow_mut()
+                    .push(Action::SetLights(light, Effect::Off));
+
+                Ok(())
+            },
+        );
+
+        let queue = Rc::clone(&actions);
+        engine.register_fn(
+            "set_sidetone",
+            move |level: i64| -> Result<(), Box<EvalAltResult>> {
+                let level = u8::try_from(level)
+                    .ok()
+                    .filter(|level| *level <= 100)
+                    .ok_or_else(|| format!("invalid sidetone level {level}"))?;
+
+                queue.borrow_mut().push(Action::SetSidetone(level));
+
+                Ok(())
+            },
+        );
+
+        let queue = Rc::clone(&actions);
+        engine.register_fn("run", move |command: &str| {
+            queue.borrow_mut().push(Action::Run(command.to_string()));
+        });
+
+        let status = Rc::clone(&battery);
+        engine.register_fn("battery", move || match *status.borrow() {
+            Some(status) => {
+                let mut map = Map::new();
+                map.insert("percent".into(), Dynamic::from_float(status.charge.into()));
+                map.insert("voltage".into(), Dynamic::from_int(status.voltage.into()));
+                map.insert("charging".into(), status.charging_status.to_string().into());
+
+                Dynamic::from_map(map)
+            }
+            None => Dynamic::UNIT,
+        });
+
+        Self {
+            engine,
+            scripts: BTreeMap::new(),
+            actions,
+            battery,
+            detector: TriggerDetector::new(),
+        }
+    }
+}
+
+impl ScriptBindings {
+    /// Creates an empty set of script bindings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles the script and binds it to the trigger, replacing any previous binding of the
+    /// trigger.
+    ///
+    /// Fails if the script does not compile.
+    pub fn bind(&mut self, trigger: Trigger, source: &str) -> anyhow::Result<()> {
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|err| anyhow::anyhow!("invalid script for {trigger}: {err}"))?;
+
+        self.scripts.insert(
+            trigger,
+            Script {
+                ast,
+                reads_battery: source.contains("battery"),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Removes the binding of the trigger.
+    pub fn unbind(&mut self, trigger: Trigger) {
+        self.scripts.remove(&trigger);
+    }
+
+    /// Returns `true` if no script is bound.
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Replaces the detector for the triggers, for example to change the long press threshold.
+    pub fn set_detector(&mut self, detector: TriggerDetector) {
+        self.detector = detector;
+    }
+
+    /// Runs the script bound to the trigger, if there is one.
+    ///
+    /// The changes requested by the script are performed once it finished, even if it failed
+    /// afterwards.
+    pub fn run(&mut self, trigger: Trigger, config: &mut RunConfig, headset: &mut Headset) {
+        let Some(script) = self.scripts.get(&trigger) else {
+            return;
+        };
+
+        *self.battery.borrow_mut() = if script.reads_battery {
+            headset.battery_status_cached(BATTERY_MAX_AGE).ok()
+        } else {
+            None
+        };
+
+        let mut scope = Scope::new();
+        scope.push_constant("trigger", trigger.to_string());
+
+        if let Err(err) = self.engine.run_ast_with_scope(&mut scope, &script.ast) {
+            log::error!("the script bound to {trigger} failed: {err}");
+        }
+
+        for action in self.actions.borrow_mut().drain(..) {
+            log::debug!("performing {action:?} for the script bound to {trigger}");
+
+            match action {
+                Action::SetLights(Light::Side, effect) => config.set_side_light_effect(effect),
+                Action::SetLights(Light::Logo, effect) => config.set_logo_light_effect(effect),
+                Action::SetSidetone(level) => config.set_sidetone(Some(level)),
+                Action::Run(command) => spawn_shell_command(command),
+            }
+        }
+    }
+}
+
+impl Plugin for ScriptBindings {
+    fn on_event(&mut self, config: &mut RunConfig, headset: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            for trigger in self.detector.detect(state) {
+                self.run(trigger, config, headset);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Statistics about the communication with the headset.
+
+use std::{fmt, time::Duration};
+
+/// The upper bounds in milliseconds of the buckets of [`LatencyHistogram`], followed by a bucket
+/// for the longer latencies.
+pub const LATENCY_BUCKETS_MS: [u64; 6] = [1, 5, 10, 50, 100, 500];
+
+/// Counters of the messages exchanged with the headset since it was opened.
+///
+/// Many timeouts and unhandled messages with few failed syncs hint at a flaky wireless link,
+/// whereas failing syncs with a quiet link hint at a bug.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// The number of requests sent to the headset.
+    pub requests: u64,
+    /// The number of requests that were not answered in time.
+    pub timeouts: u64,
+    /// The number of times applying the settings was retried after it failed.
+    pub retries: u64,
+    /// The number of messages the headset sent on its own, such as button events.
+    pub unrequested_messages: u64,
+    /// The number of messages from the headset that could not be understood.
+    pub parse_failures: u64,
+    /// How long it took from receiving a report until the handlers of its event finished.
+    pub handler_latency: LatencyHistogram,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} timeouts, {} retries, {} unrequested messages, {} parse failures, \
+             handler latency: {}",
+            self.requests,
+            self.timeouts,
+            self.retries,
+            self.unrequested_messages,
+            self.parse_failures,
+            self.handler_latency
+        )
+    }
+}
+
+/// How many events took how long to handle, in the buckets of [`LATENCY_BUCKETS_MS`].
+///
+/// Handlers blocking the loop, for example by waiting for a command, delay the events following
+/// them, which shows up here. See [`RunConfig::set_latency_budget`] to be warned about it.
+///
+/// [`RunConfig::set_latency_budget`]: crate::RunConfig::set_latency_budget
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyHistogram {
+    /// The number of events in each bucket, the last one counting the events above the last
+    /// bound.
+    pub counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    /// The longest latency in microseconds.
+    pub max_us: u64,
+}
+
+impl LatencyHistogram {
+    /// Counts the latency in its bucket.
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency <= Duration::from_millis(bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.counts[bucket] += 1;
+        self.max_us = self
+            .max_us
+            .max(latency.as_micros().try_into().unwrap_or(u64::MAX));
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.counts.iter().all(|&count| count == 0) {
+            return write!(f, "no events");
+        }
+
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0);
+        for (i, (bucket, count)) in buckets.enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            match LATENCY_BUCKETS_MS.get(bucket) {
+                Some(bound) => write!(f, "{count} within {bound} ms")?,
+                None => write!(
+                    f,
+                    "{count} above {} ms",
+                    LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]
+                )?,
+            }
+        }
+
+        write!(f, " (max {:.1} ms)", self.max_us as f64 / 1000.0)
+    }
+}
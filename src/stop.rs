@@ -0,0 +1,36 @@
+//! Stopping the loop polling the headset from other threads.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::Waker;
+
+/// Stops [`Headset::run_with_config`] from other threads, for example from a signal handler.
+///
+/// The loop finishes the event it is handling, restores the headset as configured with
+/// [`RunConfig::set_restore_on_exit`] and returns.
+///
+/// [`Headset::run_with_config`]: crate::Headset::run_with_config
+/// [`RunConfig::set_restore_on_exit`]: crate::RunConfig::set_restore_on_exit
+#[derive(Debug, Clone)]
+pub struct StopToken {
+    /// Whether stopping was requested, shared with the config.
+    pub(crate) stopped: Arc<AtomicBool>,
+    /// The waker making the loop notice the request right away.
+    pub(crate) waker: Waker,
+}
+
+impl StopToken {
+    /// Asks the loop to stop.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.waker.wake();
+    }
+
+    /// Returns whether the loop was asked to stop.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
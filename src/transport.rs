@@ -0,0 +1,324 @@
+//! The exchange of HID reports with the headset, and recording and replaying it.
+//!
+//! Captures are stored as JSON lines, one per report, such as
+//! `{"time_us":1532,"direction":"read","report":"11ff0400..."}`, where the time is counted from
+//! the start of the capture. Replaying them needs the `serde` feature.
+
+#[cfg(feature = "serde")]
+use std::{
+    collections::VecDeque,
+    io::{BufRead as _, BufReader},
+    time::Duration,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::Context as _;
+
+/// The size of the buffer reports are read into.
+///
+/// HID++ reports are at most 20 bytes long, and even the padded reports on Windows fit, so longer
+/// reports are cut off.
+pub const MAX_REPORT_LEN: usize = 64;
+
+/// Sends reports to the headset and receives its reports.
+pub trait Transport: Send {
+    /// Writes the report, returning the number of bytes written.
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize>;
+
+    /// Reads one report into the buffer, returning its length.
+    ///
+    /// The buffer is [`MAX_REPORT_LEN`] bytes long, and longer reports may be cut off.
+    ///
+    /// Waits up to `timeout` milliseconds, or forever if it is negative, returning 0 if no report
+    /// arrived in time.
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize>;
+
+    /// Returns the serial number reported by the device, if there is one.
+    fn serial_number(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the product name reported by the device, if there is one.
+    fn product(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        (**self).write(report)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize> {
+        (**self).read(buf, timeout)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        (**self).serial_number()
+    }
+
+    fn product(&self) -> Option<String> {
+        (**self).product()
+    }
+}
+
+#[cfg(feature = "hidapi")]
+impl Transport for hidapi::HidDevice {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        Ok(hidapi::HidDevice::write(self, report)?)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize> {
+        Ok(self.read_timeout(buf, timeout)?)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.get_serial_number_string()
+            .ok()
+            .flatten()
+            .filter(|serial_number| !serial_number.is_empty())
+    }
+
+    fn product(&self) -> Option<String> {
+        self.get_product_string().ok().flatten()
+    }
+}
+
+/// The direction of a captured report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// The report was written to the headset.
+    Write,
+    /// The report was read from the headset.
+    Read,
+}
+
+impl Direction {
+    /// Returns the name used in captures.
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Write => "write",
+            Direction::Read => "read",
+        }
+    }
+}
+
+/// A file that reports are recorded to, shared by all connections of a headset.
+#[derive(Debug, Clone)]
+pub struct CaptureFile {
+    /// The file written to.
+    file: Arc<Mutex<BufWriter<File>>>,
+    /// When the capture started.
+    start: Instant,
+}
+
+impl CaptureFile {
+    /// Creates the file, replacing an existing one.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("could not create capture file {}", path.display()))?;
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+            start: Instant::now(),
+        })
+    }
+
+    /// Records the report.
+    fn record(&self, direction: Direction, report: &[u8]) {
+        let time_us = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX);
+
+        let mut file = self.file.lock().unwrap();
+        // None of the values need escaping, so the line is written without a JSON library.
+        // Flushed right away, so that the capture is complete even if the program crashes
+        let result = writeln!(
+            file,
+            r#"{{"time_us":{time_us},"direction":"{}","report":"{}"}}"#,
+            direction.name(),
+            hex(report)
+        )
+        .and_then(|()| file.flush());
+        if let Err(err) = result {
+            log::warn!("could not write to the capture file: {err}");
+        }
+    }
+}
+
+/// Records all reports exchanged by the inner transport.
+#[derive(Debug)]
+pub struct Capture<T> {
+    /// The transport that is recorded.
+    inner: T,
+    /// The file the reports are recorded to.
+    file: CaptureFile,
+}
+
+impl<T: Transport> Capture<T> {
+    /// Records the reports exchanged by `inner` to the file.
+    pub fn new(inner: T, file: CaptureFile) -> Self {
+        Self { inner, file }
+    }
+}
+
+impl<T: Transport> Transport for Capture<T> {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        self.file.record(Direction::Write, report);
+
+        self.inner.write(report)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize> {
+        let len = self.inner.read(buf, timeout)?;
+        if len != 0 {
+            self.file.record(Direction::Read, &buf[..len]);
+        }
+
+        Ok(len)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.inner.serial_number()
+    }
+
+    fn product(&self) -> Option<String> {
+        self.inner.product()
+    }
+}
+
+/// A transport replaying a capture instead of talking to a headset.
+///
+/// The read reports are returned in order, at the time they were captured relative to the start
+/// of the replay. Written reports are compared to the captured ones, warning about differences,
+/// as the replay does not match the capture anymore then. Once the capture ended, the transport
+/// behaves like an idle headset.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct Replay {
+    /// The captured reads that were not replayed yet, with their time.
+    reads: VecDeque<(Duration, Vec<u8>)>,
+    /// The captured writes that were not compared yet.
+    writes: VecDeque<Vec<u8>>,
+    /// When the replay started.
+    start: Instant,
+    /// Whether the end of the capture was logged already.
+    ended: bool,
+}
+
+#[cfg(feature = "serde")]
+impl Replay {
+    /// Loads the capture from the file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("could not open capture file {}", path.display()))?;
+
+        let mut reads = VecDeque::new();
+        let mut writes = VecDeque::new();
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (time, direction, report) = parse_record(&line)
+                .with_context(|| format!("invalid record in line {}", index + 1))?;
+            match direction {
+                Direction::Read => reads.push_back((time, report)),
+                Direction::Write => writes.push_back(report),
+            }
+        }
+
+        Ok(Self {
+            reads,
+            writes,
+            start: Instant::now(),
+            ended: false,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Transport for Replay {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        match self.writes.pop_front() {
+            Some(captured) if captured == report => (),
+            Some(captured) => log::warn!(
+                "the replay diverged from the capture, wrote {} instead of {}",
+                hex(report),
+                hex(&captured)
+            ),
+            None => log::warn!("wrote {} after the end of the capture", hex(report)),
+        }
+
+        Ok(report.len())
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: i32) -> anyhow::Result<usize> {
+        let timeout = u64::try_from(timeout).map_or(Duration::MAX, Duration::from_millis);
+
+        let Some((time, _)) = self.reads.front() else {
+            if !self.ended {
+                self.ended = true;
+                log::info!("the capture ended");
+            }
+
+            // Sleeping in steps, as very long sleeps are not supported everywhere
+            std::thread::sleep(timeout.min(Duration::from_secs(3600)));
+
+            return Ok(0);
+        };
+
+        let remaining = time.saturating_sub(self.start.elapsed());
+        if remaining > timeout {
+            std::thread::sleep(timeout);
+
+            return Ok(0);
+        }
+        std::thread::sleep(remaining);
+
+        let (_, report) = self.reads.pop_front().expect("the front record exists");
+        let len = report.len().min(buf.len());
+        buf[..len].copy_from_slice(&report[..len]);
+
+        Ok(len)
+    }
+}
+
+/// Parses a line of a capture.
+#[cfg(feature = "serde")]
+fn parse_record(line: &str) -> anyhow::Result<(Duration, Direction, Vec<u8>)> {
+    let record: serde_json::Value = serde_json::from_str(line)?;
+
+    let time = record["time_us"]
+        .as_u64()
+        .context("missing time_us")
+        .map(Duration::from_micros)?;
+    let direction = match record["direction"].as_str() {
+        Some("read") => Direction::Read,
+        Some("write") => Direction::Write,
+        _ => anyhow::bail!("the direction needs to be \"read\" or \"write\""),
+    };
+    let report = record["report"].as_str().context("missing report")?;
+    anyhow::ensure!(
+        report.is_ascii() && report.len() % 2 == 0,
+        "the report needs to consist of pairs of hex digits"
+    );
+    let report = (0..report.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&report[index..index + 2], 16))
+        .collect::<Result<_, _>>()
+        .context("the report needs to consist of pairs of hex digits")?;
+
+    Ok((time, direction, report))
+}
+
+/// Formats the bytes as lowercase hex digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
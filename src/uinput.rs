@@ -0,0 +1,179 @@
+//! Exposes the buttons of the headset as a virtual input device.
+//!
+//! This allows the desktop environment to bind the buttons like any other key.
+
+use evdev::{uinput::VirtualDevice, AttributeSet, InputEvent, KeyEvent};
+
+use crate::{
+    config::{ButtonHandler, RunConfig},
+    plugin::Plugin,
+    ButtonChange, ButtonState, Event, EventKind, Headset,
+};
+
+pub use evdev::KeyCode;
+
+/// The keys emitted for the buttons of the headset.
+///
+/// Buttons mapped to `None` do not emit any key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMap {
+    /// The key emitted for the G1 key.
+    pub g1: Option<KeyCode>,
+    /// The key emitted for the G2 key.
+    pub g2: Option<KeyCode>,
+    /// The key emitted for the G3 key.
+    pub g3: Option<KeyCode>,
+    /// The key emitted for each scroll up report of the wheel.
+    pub scroll_up: Option<KeyCode>,
+    /// The key emitted for each scroll down report of the wheel.
+    pub scroll_down: Option<KeyCode>,
+    /// The key emitted for the mute button.
+    pub mute: Option<KeyCode>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            g1: Some(KeyCode::KEY_PLAYPAUSE),
+            g2: Some(KeyCode::KEY_NEXTSONG),
+            g3: Some(KeyCode::KEY_PREVIOUSSONG),
+            scroll_up: Some(KeyCode::KEY_VOLUMEUP),
+            scroll_down: Some(KeyCode::KEY_VOLUMEDOWN),
+            mute: Some(KeyCode::KEY_MICMUTE),
+        }
+    }
+}
+
+impl KeyMap {
+    /// The keys that the G keys act as while their reporting is disabled, see
+    /// [`Headset::set_gkeys_reporting`], without keys for the other buttons.
+    ///
+    /// The headset can only divert all G keys at once, so this allows keeping some of them as
+    /// their default keys while the others are handled by the program.
+    pub const FIRMWARE: KeyMap = KeyMap {
+        g1: Some(KeyCode::KEY_F1),
+        g2: Some(KeyCode::KEY_F2),
+        g3: Some(KeyCode::KEY_F3),
+        scroll_up: None,
+        scroll_down: None,
+        mute: None,
+    };
+
+    /// Returns all keys that are emitted.
+    fn keys(&self) -> impl Iterator<Item = KeyCode> {
+        [
+            self.g1,
+            self.g2,
+            self.g3,
+            self.scroll_up,
+            self.scroll_down,
+            self.mute,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+/// A virtual input device emitting keys for the buttons of the headset.
+///
+/// The G keys are held down as long as the button is, while the wheel and the mute button emit a
+/// key press for every report.
+#[derive(Debug)]
+pub struct VirtualInput {
+    /// The uinput device.
+    device: VirtualDevice,
+    /// The keys emitted for the buttons.
+    keys: KeyMap,
+    /// The button state of the previous event.
+    old_state: ButtonState,
+}
+
+impl VirtualInput {
+    /// Creates the virtual input device.
+    ///
+    /// This requires write access to `/dev/uinput`.
+    pub fn new(keys: KeyMap) -> anyhow::Result<Self> {
+        let device = VirtualDevice::builder()?
+            .name("Logitech G935 Buttons")
+            .with_keys(&keys.keys().collect::<AttributeSet<_>>())?
+            .build()?;
+
+        Ok(Self {
+            device,
+            keys,
+            old_state: ButtonState::default(),
+        })
+    }
+
+    /// Emits the key events for the events that led to the given button state.
+    pub fn handle(&mut self, state: ButtonState) -> anyhow::Result<()> {
+        let changes = state.diff(&self.old_state);
+        self.old_state = state;
+
+        let events = [self.keys.g1, self.keys.g2, self.keys.g3]
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, key)| {
+                let pressed = changes.contains(ButtonChange::g_key(index, true));
+                let released = changes.contains(ButtonChange::g_key(index, false));
+
+                match (key, pressed, released) {
+                    (Some(key), true, _) => Some(*KeyEvent::new(key, 1)),
+                    (Some(key), _, true) => Some(*KeyEvent::new(key, 0)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !events.is_empty() {
+            self.emit(&events)?;
+        }
+
+        let taps = [
+            (self.keys.scroll_up, state.scroll_up()),
+            (self.keys.scroll_down, state.scroll_down()),
+            (self.keys.mute, state.mute_button_pressed()),
+        ];
+        for (key, active) in taps {
+            if let (Some(key), true) = (key, active) {
+                self.emit(&[*KeyEvent::new(key, 1)])?;
+                self.emit(&[*KeyEvent::new(key, 0)])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits the events as one batch.
+    fn emit(&mut self, events: &[InputEvent]) -> anyhow::Result<()> {
+        log::trace!("emitting {events:?}");
+
+        Ok(self.device.emit(events)?)
+    }
+
+    /// Turns the virtual input device into a handler for button events.
+    pub fn into_button_handler(mut self) -> ButtonHandler {
+        Box::new(move |_, _, state| {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to emit key events: {err}");
+            }
+        })
+    }
+}
+
+impl Plugin for VirtualInput {
+    fn on_event(&mut self, _: &mut RunConfig, _: &mut Headset, event: Event) {
+        if let EventKind::Buttons(state) = event.kind {
+            if let Err(err) = self.handle(state) {
+                log::error!("failed to emit key events: {err}");
+            }
+        }
+    }
+
+    fn on_disconnect(&mut self, _: &mut RunConfig, _: &mut Headset) {
+        // The release of held buttons is not reported anymore
+        if let Err(err) = self.handle(ButtonState::default()) {
+            log::error!("failed to release the keys: {err}");
+        }
+    }
+}
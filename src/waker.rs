@@ -0,0 +1,103 @@
+//! Waking the loop polling the headset from other threads.
+
+#[cfg(unix)]
+use std::{
+    io::{Read as _, Write as _},
+    os::unix::net::UnixStream,
+    sync::Arc,
+};
+
+/// Wakes the loop polling the headset, so that it runs the jobs of the handles and the periodic
+/// handler right away instead of waiting for the next event or the periodic interval.
+///
+/// Waking only has an effect on Linux, elsewhere the loop notices changes after at most the
+/// timeout it was polled with.
+#[derive(Debug, Clone, Default)]
+pub struct Waker {
+    /// The end of the socket pair to write to, if it could be created.
+    #[cfg(unix)]
+    stream: Option<Arc<UnixStream>>,
+}
+
+impl Waker {
+    /// Wakes the loop, or makes its next poll return right away if it is not waiting.
+    pub fn wake(&self) {
+        #[cfg(unix)]
+        if let Some(stream) = &self.stream {
+            match (&**stream).write(&[0]) {
+                Ok(_) => (),
+                // The buffer being full means that a wakeup is pending anyway
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => (),
+                Err(err) => log::debug!("could not wake the headset loop: {err}"),
+            }
+        }
+    }
+}
+
+/// The receiving side of the wakeups.
+#[derive(Debug)]
+pub(crate) struct Wakeup {
+    /// The end of the socket pair waited on.
+    #[cfg(unix)]
+    receiver: Option<UnixStream>,
+    /// The waker writing to the other end.
+    waker: Waker,
+}
+
+impl Wakeup {
+    /// Creates the wakeup, which never fires if the socket pair cannot be created.
+    pub(crate) fn new() -> Self {
+        #[cfg(unix)]
+        {
+            let pair = UnixStream::pair().and_then(|(receiver, sender)| {
+                receiver.set_nonblocking(true)?;
+                sender.set_nonblocking(true)?;
+
+                Ok((receiver, sender))
+            });
+
+            match pair {
+                Ok((receiver, sender)) => Self {
+                    receiver: Some(receiver),
+                    waker: Waker {
+                        stream: Some(Arc::new(sender)),
+                    },
+                },
+                Err(err) => {
+                    log::warn!("could not create the socket to wake the headset loop: {err}");
+
+                    Self {
+                        receiver: None,
+                        waker: Waker::default(),
+                    }
+                }
+            }
+        }
+
+        #[cfg(not(unix))]
+        Self {
+            waker: Waker::default(),
+        }
+    }
+
+    /// Returns a waker for this wakeup.
+    pub(crate) fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    /// Returns the socket that becomes readable when woken.
+    #[cfg(unix)]
+    pub(crate) fn receiver(&self) -> Option<&UnixStream> {
+        self.receiver.as_ref()
+    }
+
+    /// Forgets all pending wakeups.
+    pub(crate) fn clear(&self) {
+        #[cfg(unix)]
+        if let Some(receiver) = &self.receiver {
+            let mut buf = [0; 64];
+
+            while matches!((&*receiver).read(&mut buf), Ok(len) if len > 0) {}
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! The state of the wireless link between the receiver and the headset.
+
+use std::fmt;
+
+/// The state of the wireless link between the USB receiver and the headset.
+///
+/// The receiver stays connected to the computer while the headset is turned off, so that an open
+/// [`crate::Headset`] alone does not mean that the headset can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WirelessStatus {
+    /// Whether the headset is linked to the receiver, which means it is turned on and in range.
+    pub linked: bool,
+    /// The round-trip time of a request in microseconds while the headset is linked.
+    ///
+    /// The receiver does not report the signal strength, but a weak signal makes the headset
+    /// answer late, as the reports need to be sent again.
+    pub latency_us: Option<u64>,
+    /// Whether the headset reports reconnections using the wireless device status feature
+    /// (0x1d4b), or whether they are only noticed by the battery notifications.
+    pub reports_reconnections: bool,
+}
+
+impl fmt::Display for WirelessStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.latency_us {
+            Some(latency_us) if self.linked => write!(f, "linked ({latency_us} µs)"),
+            _ if self.linked => write!(f, "linked"),
+            _ => write!(f, "not linked"),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! A simulated headset shared by the integration tests.
+//!
+//! The mock can pad its reports like Windows does, where every report is as long as the longest
+//! report of the interface.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use g935::transport::Transport;
+
+/// The feature IDs of the simulated headset, in the order of their indices.
+const FEATURES: [u16; 10] = [
+    0x0000, 0x0001, 0x0005, 0x1f20, 0x8010, 0x8070, 0x8300, 0x1004, 0x8310, 0x1d4b,
+];
+
+/// Simulates a headset, answering the requests it receives.
+#[derive(Debug, Default)]
+pub struct Mock {
+    /// The reports that were not read yet.
+    pub reads: VecDeque<Vec<u8>>,
+    /// The reports the headset sends on its own, which are read once all responses were read.
+    pub notifications: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    /// All reports written to the headset.
+    pub writes: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// The length the reports are padded to.
+    padded_len: usize,
+    /// The name reported by the headset, which is G935 if it is empty.
+    pub name: &'static str,
+    /// By how many bytes the battery status is shifted in the battery response.
+    pub battery_offset: usize,
+    /// The state of charge reported by the headset, which lacks the feature if it is `None`.
+    pub state_of_charge: Option<u8>,
+    /// The number of equalizer bands and the largest gain, which the headset lacks if it is
+    /// `None`.
+    pub equalizer: Option<(u8, i8)>,
+    /// The stored effects of the logo and side light, in the layout of the request setting them.
+    pub stored_lights: [[u8; 13]; 2],
+    /// Whether storing effects is silently ignored, like by a faulty headset.
+    pub ignores_stored_lights: bool,
+    /// How many of the next effects shown on a light are ignored, answering that it is off.
+    pub ignored_lights: usize,
+    /// How many of the next effects shown on a light are answered with an unknown effect.
+    pub garbled_lights: usize,
+    /// Whether the responses are cut off after their header, like by a truncated read.
+    pub truncates_responses: bool,
+    /// Whether reading fails, like after the headset was unplugged.
+    pub fails_reads: Arc<AtomicBool>,
+    /// The most responses that were written but not read yet at the same time.
+    pub most_unread: Arc<AtomicUsize>,
+}
+
+impl Mock {
+    /// Creates a mock padding its reports to the given length.
+    pub fn new(padded_len: usize) -> Self {
+        Self {
+            padded_len,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the response of the simulated headset to the request.
+    fn respond(&mut self, request: &[u8]) -> Vec<u8> {
+        let name = if self.name.is_empty() {
+            "G935"
+        } else {
+            self.name
+        };
+
+        let mut response = request[..4].to_vec();
+        response.resize(20, 0);
+
+        let feature = FEATURES[usize::from(request[2])];
+        match (feature, request[3] >> 4) {
+            // Resolves a feature ID to its index
+            (0x0000, 0x0) => {
+                let id = u16::from_be_bytes([request[4], request[5]]);
+                let lacks_feature = (id == 0x1004 && self.state_of_charge.is_none())
+                    || (id == 0x8310 && self.equalizer.is_none());
+                if !lacks_feature {
+                    response[4] = FEATURES
+                        .iter()
+                        .position(|&feature| feature == id)
+                        .unwrap_or(0) as u8;
+                }
+            }
+            // Returns the protocol version, echoing the ping data
+            (0x0000, 0x1) => response[4..7].copy_from_slice(&[4, 2, request[6]]),
+            // Returns the length of the name and its characters
+            (0x0005, 0x0) => response[4] = name.len() as u8,
+            (0x0005, 0x1) => response[4..4 + name.len()].copy_from_slice(name.as_bytes()),
+            // Returns 3900 mV while charging
+            (0x1f20, 0x0) => {
+                let start = 4 + self.battery_offset;
+                response[start..start + 3].copy_from_slice(&[0x0f, 0x3c, 3]);
+            }
+            // Reports the state of charge, with all levels and the state of charge supported
+            (0x1004, 0x0) => response[4..6].copy_from_slice(&[0x0f, 0x03]),
+            (0x1004, 0x1) => response[4] = self.state_of_charge.unwrap_or(0),
+            // Reports the number of bands and the largest gain of the equalizer
+            (0x8310, 0x0) => {
+                let (bands, max_gain) = self.equalizer.unwrap_or_default();
+                response[4..6].copy_from_slice(&[bands, max_gain as u8]);
+            }
+            // Stores the effects of the permanent profile and reports them
+            (0x8070, 0x3) if request[16] == 2 => {
+                if !self.ignores_stored_lights {
+                    self.stored_lights[usize::from(request[4])].copy_from_slice(&request[4..17]);
+                }
+                response[4..].copy_from_slice(&request[4..]);
+            }
+            (0x8070, 0x3) if self.ignored_lights > 0 => {
+                self.ignored_lights -= 1;
+                response[4] = request[4];
+            }
+            (0x8070, 0x3) if self.garbled_lights > 0 => {
+                self.garbled_lights -= 1;
+                response[4..6].copy_from_slice(&[request[4], 0xff]);
+            }
+            (0x8070, 0xe) => {
+                let zone = usize::from(request[4]);
+                response[4..16].copy_from_slice(&self.stored_lights[zone][..12]);
+                response[4] = request[4];
+            }
+            // Confirms the settings
+            _ => response[4..].copy_from_slice(&request[4..]),
+        }
+
+        response
+    }
+}
+
+impl Transport for Mock {
+    fn write(&mut self, report: &[u8]) -> anyhow::Result<usize> {
+        self.writes.lock().unwrap().push(report.to_vec());
+
+        let mut response = self.respond(report);
+        if self.truncates_responses {
+            response.truncate(4);
+        }
+        response.resize(response.len().max(self.padded_len), 0);
+        self.reads.push_back(response);
+        self.most_unread
+            .fetch_max(self.reads.len(), Ordering::Relaxed);
+
+        Ok(report.len())
+    }
+
+    fn read(&mut self, buf: &mut [u8], _timeout: i32) -> anyhow::Result<usize> {
+        anyhow::ensure!(
+            !self.fails_reads.load(Ordering::Relaxed),
+            "the device is gone"
+        );
+
+        let report = self
+            .reads
+            .pop_front()
+            .or_else(|| self.notifications.lock().unwrap().pop_front());
+        let Some(report) = report else {
+            return Ok(0);
+        };
+
+        buf[..report.len()].copy_from_slice(&report);
+
+        Ok(report.len())
+    }
+}
@@ -0,0 +1,96 @@
+//! Property-based tests of the encoding of the light settings.
+//!
+//! The mock echoes the settings it receives, so the settings returned by `Headset::set_lights`
+//! are decoded from the bytes they were encoded to.
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::Mock;
+use g935::{
+    lights::{self, Color, Effect, Light, ProfileType, MAX_BRIGHTNESS, MAX_RATE},
+    Headset,
+};
+use proptest::prelude::*;
+
+/// Returns the valid colors.
+fn color() -> impl Strategy<Value = Color> {
+    any::<(u8, u8, u8)>().prop_map(|(r, g, b)| Color::new(r, g, b))
+}
+
+/// Returns the rates the protocol can encode, which are whole milliseconds.
+fn rate() -> impl Strategy<Value = Duration> {
+    (0..=MAX_RATE.as_millis() as u64).prop_map(Duration::from_millis)
+}
+
+/// Returns the valid effects.
+fn effect() -> impl Strategy<Value = Effect> {
+    prop_oneof![
+        Just(Effect::Off),
+        color().prop_map(|color| Effect::Static { color }),
+        (color(), rate(), 0..=MAX_BRIGHTNESS).prop_map(|(color, rate, brightness)| {
+            Effect::Breathing {
+                color,
+                rate,
+                brightness,
+            }
+        }),
+        (rate(), 0..=MAX_BRIGHTNESS)
+            .prop_map(|(rate, brightness)| Effect::ColorCycle { rate, brightness }),
+    ]
+}
+
+/// Returns the valid light settings.
+fn config() -> impl Strategy<Value = lights::LightSettings> {
+    (
+        prop_oneof![Just(Light::Logo), Just(Light::Side)],
+        effect(),
+        prop_oneof![Just(ProfileType::Temporary), Just(ProfileType::Permanent)],
+    )
+        .prop_map(|(light, effect, profile_type)| lights::LightSettings {
+            light,
+            effect,
+            profile_type,
+        })
+}
+
+proptest! {
+    #[test]
+    fn lights_round_trip(config in config()) {
+        let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+        prop_assert_eq!(headset.set_lights(&config).unwrap(), config);
+    }
+
+    #[test]
+    fn lights_use_the_protocol_offsets(config in config()) {
+        let mock = Mock::new(0);
+        let writes = Arc::clone(&mock.writes);
+        let mut headset = Headset::open_with_transport(mock).unwrap();
+
+        headset.set_lights(&config).unwrap();
+
+        let writes = writes.lock().unwrap();
+        // The settings follow the report ID, device index, feature index and function
+        let params = &writes.last().unwrap()[4..];
+        match config.effect {
+            Effect::Off => prop_assert_eq!(params[1], 0x00),
+            Effect::Static { color } => {
+                prop_assert_eq!(params[1], 0x01);
+                prop_assert_eq!(&params[2..5], &[color.r, color.g, color.b]);
+            }
+            Effect::Breathing { color, rate, brightness } => {
+                prop_assert_eq!(params[1], 0x02);
+                prop_assert_eq!(&params[2..5], &[color.r, color.g, color.b]);
+                prop_assert_eq!(&params[5..7], &(rate.as_millis() as u16).to_be_bytes());
+                prop_assert_eq!(params[8], brightness);
+            }
+            Effect::ColorCycle { rate, brightness } => {
+                prop_assert_eq!(params[1], 0x03);
+                prop_assert_eq!(&params[7..9], &(rate.as_millis() as u16).to_be_bytes());
+                prop_assert_eq!(params[9], brightness);
+            }
+        }
+    }
+}
@@ -0,0 +1,1007 @@
+//! Tests talking to a simulated headset through a mock transport.
+
+mod common;
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use common::Mock;
+use g935::{
+    config::{ErrorPolicy, RestorePolicy, RunConfig},
+    lights::{self, Color, Effect, Light, ProfileType},
+    BatteryModel, Capabilities, ChargingStatus, EventKind, Headset, MicArm, PowerState, RawReport,
+    RECEIVER_INDEX,
+};
+
+#[test]
+fn opens_headset() {
+    let headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    assert_eq!(headset.info().name, "G935");
+    assert_eq!(headset.info().protocol_version, (4, 2));
+}
+
+#[test]
+fn waits_after_failed_reads() {
+    let mock = Mock::new(0);
+    let fails_reads = Arc::clone(&mock.fails_reads);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let mut config = RunConfig::default();
+    headset.apply_config(&mut config).unwrap();
+
+    fails_reads.store(true, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..3 {
+        headset
+            .poll_once(&mut config, Duration::from_millis(20))
+            .unwrap();
+    }
+
+    // Each failed read waits for the timeout instead of returning right away
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    assert_eq!(headset.consecutive_failures(), 3);
+}
+
+#[test]
+fn rejects_truncated_responses() {
+    let mut mock = Mock::new(0);
+    mock.truncates_responses = true;
+
+    // Fails instead of panicking while reading the parameters
+    let err = Headset::open_with_transport(mock).unwrap_err();
+    assert!(format!("{err:#}").contains("cut off after 4 of 20 bytes"));
+}
+
+#[test]
+fn detects_capabilities() {
+    let headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    assert_eq!(
+        headset.capabilities(),
+        Capabilities {
+            has_rgb_logo: true,
+            has_gkeys: true,
+            battery_event_support: true,
+            eq_bands: None,
+            sidetone_range: Some(0..=100),
+        }
+    );
+}
+
+#[test]
+fn rejects_out_of_range_equalizer_gains() {
+    let mut mock = Mock::new(0);
+    mock.equalizer = Some((5, 12));
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    assert_eq!(headset.capabilities().eq_bands, Some(5));
+    headset.set_equalizer(&[-12, -6, 0, 6, 12]).unwrap();
+    // The magnitude of -128 does not fit into an i8
+    for gains in [[-128, 0, 0, 0, 0], [0, 0, 0, 0, 13]] {
+        let err = headset.set_equalizer(&gains).unwrap_err();
+        assert!(err.to_string().contains("between -12 and 12 dB"));
+    }
+}
+
+#[test]
+fn reads_battery() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    let status = headset.get_battery_status().unwrap();
+
+    assert_eq!(status.voltage, 3900);
+    assert_eq!(status.charging_status, ChargingStatus::Charging);
+    assert_eq!(
+        status.to_string(),
+        format!("{}% (3900 mV, charging)", status.percent())
+    );
+    assert!(!status.is_low(100));
+}
+
+#[test]
+fn estimates_the_charge_with_the_battery_model() {
+    /// Estimates the charge linearly between 3500 and 4000 mV.
+    #[derive(Debug)]
+    struct Linear;
+
+    impl BatteryModel for Linear {
+        fn estimate(&self, voltage: u16, _: ChargingStatus) -> f32 {
+            (f32::from(voltage) - 3500.0) / 5.0
+        }
+    }
+
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+    headset.set_battery_model(Linear);
+
+    assert_eq!(headset.get_battery_status().unwrap().charge, 80.0);
+}
+
+#[test]
+fn prefers_the_reported_state_of_charge() {
+    let mut mock = Mock::new(0);
+    mock.state_of_charge = Some(87);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let status = headset.get_battery_status().unwrap();
+
+    assert_eq!(status.voltage, 3900);
+    assert_eq!(status.charge, 87.0);
+}
+
+#[test]
+fn caches_battery() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let status = headset
+        .battery_status_cached(Duration::from_secs(60))
+        .unwrap();
+    let requests = writes.lock().unwrap().len();
+
+    assert_eq!(
+        headset
+            .battery_status_cached(Duration::from_secs(60))
+            .unwrap(),
+        status
+    );
+    assert_eq!(writes.lock().unwrap().len(), requests);
+
+    std::thread::sleep(Duration::from_millis(1));
+    headset.battery_status_cached(Duration::ZERO).unwrap();
+    assert_eq!(writes.lock().unwrap().len(), requests + 1);
+}
+
+#[test]
+fn detects_shifted_battery() {
+    let mut mock = Mock::new(0);
+    mock.name = "G933";
+    mock.battery_offset = 1;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    assert_eq!(headset.quirks().battery_offset, 1);
+    assert_eq!(headset.get_battery_status().unwrap().voltage, 3900);
+    headset.set_sidetone(30).unwrap();
+}
+
+#[test]
+fn refuses_changes_to_unknown_variants_unless_forced() {
+    let mut mock = Mock::new(0);
+    mock.name = "G933";
+    mock.battery_offset = 5;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    assert!(headset.set_sidetone(30).is_err());
+
+    headset.set_force(true);
+    headset.set_sidetone(30).unwrap();
+}
+
+#[test]
+fn reports_suspend_and_resume() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+    let mut config = RunConfig::default();
+
+    headset.suspend(&mut config);
+    headset.resume(&mut config);
+
+    let mut power_states = Vec::new();
+    while let Some(event) = headset.poll_once(&mut config, Duration::ZERO).unwrap() {
+        if let EventKind::PowerState(power_state) = event.kind {
+            power_states.push(power_state);
+        }
+    }
+    assert_eq!(
+        power_states,
+        [
+            PowerState::Suspended,
+            PowerState::Reconnecting,
+            PowerState::Connected
+        ]
+    );
+}
+
+#[test]
+fn passes_unknown_reports_to_the_handler() {
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let reports2 = Rc::clone(&reports);
+    let mut config = RunConfig::default();
+    config.set_raw_report_handler(Some(Box::new(move |_, _, report| {
+        reports2.borrow_mut().push(report.clone());
+    })));
+
+    // An unknown function of the lights feature, which has the index 5
+    let mut lights_report = vec![0x11, 0xff, 0x05, 0x70];
+    lights_report.resize(20, 0);
+    notifications
+        .lock()
+        .unwrap()
+        .push_back(lights_report.clone());
+    notifications.lock().unwrap().push_back(vec![0x20, 0x01]);
+    for _ in 0..2 {
+        assert_eq!(
+            headset.poll_once(&mut config, Duration::ZERO).unwrap(),
+            None
+        );
+    }
+
+    assert_eq!(
+        *reports.borrow(),
+        [
+            RawReport {
+                bytes: lights_report,
+                feature_id: Some(0x8070),
+            },
+            RawReport {
+                bytes: vec![0x20, 0x01],
+                feature_id: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn passes_short_notifications_to_the_handler() {
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let reports2 = Rc::clone(&reports);
+    let mut config = RunConfig::default();
+    config.set_raw_report_handler(Some(Box::new(move |_, _, report| {
+        reports2.borrow_mut().push(report.bytes.clone());
+    })));
+
+    // Notifications of the G keys and the wireless status, which have the indices 4 and 9, but
+    // lack their parameters
+    let short_reports = [
+        vec![0x11, 0xff, 0x04, 0x00],
+        vec![0x11, 0xff, 0x09, 0x00, 0x01],
+    ];
+    for report in &short_reports {
+        notifications.lock().unwrap().push_back(report.clone());
+        assert_eq!(
+            headset.poll_once(&mut config, Duration::ZERO).unwrap(),
+            None
+        );
+    }
+
+    assert_eq!(*reports.borrow(), short_reports);
+}
+
+#[test]
+fn reads_padded_mic_arm() {
+    let mut mock = Mock::new(64);
+    let mut report = vec![0x08, 0x20];
+    report.resize(64, 0);
+    mock.reads.push_back(report);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    assert_eq!(headset.mic_arm().unwrap(), Some(MicArm::Down));
+}
+
+#[test]
+fn sets_lights() {
+    let mock = Mock::new(64);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let config = lights::LightSettings {
+        light: Light::Logo,
+        effect: Effect::static_color(Color {
+            r: 0xff,
+            g: 0x80,
+            b: 0x00,
+        }),
+        profile_type: ProfileType::Temporary,
+    };
+    assert_eq!(headset.set_lights(&config).unwrap(), config);
+
+    let writes = writes.lock().unwrap();
+    let request = writes.last().unwrap();
+    assert_eq!(request.len(), 20);
+    assert_eq!(
+        request[..9],
+        [0x11, 0xff, 0x05, 0x31, 0x00, 0x01, 0xff, 0x80, 0x00]
+    );
+}
+
+#[test]
+fn sends_both_lights_before_waiting() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let most_unread = Arc::clone(&mock.most_unread);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let opening_writes = writes.lock().unwrap().len();
+
+    let orange = Effect::static_color(Color::new(0xff, 0x80, 0x00));
+    let mut config = RunConfig::default();
+    config.set_side_light_effect(orange);
+    config.set_logo_light_effect(Effect::Off);
+    headset.apply_config(&mut config).unwrap();
+
+    let writes = writes.lock().unwrap();
+    let lights = writes[opening_writes..]
+        .iter()
+        .filter(|request| request[3] == 0x31)
+        .map(|request| request[4])
+        .collect::<Vec<_>>();
+    assert_eq!(lights, [0x01, 0x00]);
+    assert_eq!(most_unread.load(std::sync::atomic::Ordering::Relaxed), 2);
+}
+
+#[test]
+fn skips_lights_the_headset_already_shows() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let light_writes = || {
+        writes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request[3] == 0x31)
+            .map(|request| request[4])
+            .collect::<Vec<_>>()
+    };
+
+    let mut config = RunConfig::default();
+    config.set_side_light_effect(Effect::static_color(Color::new(0xff, 0x80, 0x00)));
+    headset.apply_config(&mut config).unwrap();
+    assert_eq!(light_writes(), [0x01, 0x00]);
+
+    // Only the logo light changes
+    config.push_overlay(
+        Light::Logo,
+        Effect::static_color(Color::new(0xff, 0x00, 0x00)),
+    );
+    headset.poll_once(&mut config, Duration::ZERO).unwrap();
+    assert_eq!(light_writes(), [0x01, 0x00, 0x00]);
+
+    // Resyncing writes both lights again
+    config.set_dirty();
+    headset.poll_once(&mut config, Duration::ZERO).unwrap();
+    assert_eq!(light_writes(), [0x01, 0x00, 0x00, 0x01, 0x00]);
+}
+
+#[test]
+fn retries_ignored_lights_once() {
+    let orange = Effect::static_color(Color::new(0xff, 0x80, 0x00));
+    let settings = lights::LightSettings {
+        light: Light::Side,
+        effect: orange,
+        profile_type: ProfileType::Temporary,
+    };
+
+    let mut mock = Mock::new(0);
+    mock.ignored_lights = 1;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    assert_eq!(headset.set_lights(&settings).unwrap().effect, orange);
+    assert_eq!(headset.stats().retries, 1);
+
+    let mut mock = Mock::new(0);
+    mock.ignored_lights = 2;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let err = headset.set_lights(&settings).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<g935::Error>(),
+        Some(g935::Error::SettingRejected { .. })
+    ));
+}
+
+#[test]
+fn rejects_lights_answered_with_unknown_effects() {
+    let orange = Effect::static_color(Color::new(0xff, 0x80, 0x00));
+    let settings = lights::LightSettings {
+        light: Light::Side,
+        effect: orange,
+        profile_type: ProfileType::Temporary,
+    };
+
+    let mut mock = Mock::new(0);
+    mock.garbled_lights = 1;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    assert_eq!(headset.set_lights(&settings).unwrap().effect, orange);
+    assert_eq!(headset.stats().retries, 1);
+
+    let mut mock = Mock::new(0);
+    mock.garbled_lights = 2;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let err = headset.set_lights(&settings).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<g935::Error>(),
+        Some(g935::Error::SettingRejected { .. })
+    ));
+}
+
+#[test]
+fn stores_lights_only_when_confirmed() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let effect = Effect::static_color(Color::new(0xff, 0x80, 0x00));
+
+    let opening_writes = writes.lock().unwrap().len();
+    assert!(headset
+        .set_permanent_light(Light::Side, effect, false)
+        .is_err());
+    // Only the stored effect was read
+    assert_eq!(writes.lock().unwrap().len(), opening_writes + 1);
+
+    assert_eq!(
+        headset
+            .set_permanent_light(Light::Side, effect, true)
+            .unwrap(),
+        Effect::Off
+    );
+    assert_eq!(headset.get_permanent_light(Light::Side).unwrap(), effect);
+    assert_eq!(
+        headset.get_permanent_light(Light::Logo).unwrap(),
+        Effect::Off
+    );
+}
+
+#[test]
+fn verifies_stored_lights() {
+    let mut mock = Mock::new(0);
+    mock.ignores_stored_lights = true;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    assert!(headset
+        .set_permanent_light(Light::Logo, Effect::static_color(Color::BLACK), true)
+        .is_err());
+}
+
+#[test]
+fn sets_both_lights_together() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let most_unread = Arc::clone(&mock.most_unread);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let opening_writes = writes.lock().unwrap().len();
+
+    let lights = lights::FullLightSettings {
+        side: Effect::static_color(Color::new(0xff, 0x80, 0x00)),
+        logo: Effect::color_cycle().build(),
+    };
+    headset
+        .set_full_lights(&lights, ProfileType::Temporary)
+        .unwrap();
+
+    // Both requests were written before either response was read
+    let light_writes = writes.lock().unwrap()[opening_writes..]
+        .iter()
+        .map(|request| request[4])
+        .collect::<Vec<_>>();
+    assert_eq!(light_writes, [0x01, 0x00]);
+    assert_eq!(most_unread.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn overlays_restore_the_configured_effect() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let orange = Effect::static_color(Color::new(0xff, 0x80, 0x00));
+    let red = Effect::static_color(Color::new(0xff, 0x00, 0x00));
+    let blue = Effect::static_color(Color::new(0x00, 0x00, 0xff));
+    let mut config = RunConfig::default();
+    config.set_side_light_effect(orange);
+
+    let battery = config.push_overlay(Light::Side, red);
+    let mute = config.push_overlay(Light::Side, blue);
+    assert_eq!(config.shown_lights().side, blue);
+
+    // Popping an overlay below the top one keeps showing the top one
+    assert!(config.pop_overlay(battery));
+    assert_eq!(config.shown_lights().side, blue);
+    headset.apply_config(&mut config).unwrap();
+    let side_request = writes
+        .lock()
+        .unwrap()
+        .iter()
+        .rfind(|request| request[3] == 0x31 && request[4] == 0x01)
+        .cloned()
+        .unwrap();
+    assert_eq!(side_request[6..9], [0x00, 0x00, 0xff]);
+
+    assert!(config.pop_overlay(mute));
+    assert!(!config.pop_overlay(mute));
+    assert_eq!(config.shown_lights().side, orange);
+    assert_eq!(config.light_effect(Light::Side), orange);
+}
+
+#[test]
+fn sets_brightness_of_the_shown_effect() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    assert!(headset.set_brightness(Light::Side, 50).is_err());
+
+    let color = Color::new(0xff, 0x80, 0x00);
+    headset
+        .set_lights(&lights::LightSettings {
+            light: Light::Side,
+            effect: Effect::static_color(color),
+            profile_type: ProfileType::Temporary,
+        })
+        .unwrap();
+
+    let dimmed = headset.set_brightness(Light::Side, 50).unwrap();
+    assert_eq!(
+        dimmed.effect,
+        Effect::static_color(Color::new(0x7f, 0x40, 0x00))
+    );
+
+    // The brightness applies to the effect as it was set, not to the dimmed one
+    let restored = headset.set_brightness(Light::Side, 100).unwrap();
+    assert_eq!(restored.effect, Effect::static_color(color));
+}
+
+#[test]
+fn reports_missing_features() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    let err = headset.get_report_rate().unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<g935::Error>(),
+        Some(g935::Error::Unsupported { .. })
+    ));
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let opening_writes = writes.lock().unwrap().len();
+
+    headset.set_dry_run(true);
+    headset.set_sidetone(30).unwrap();
+
+    assert_eq!(writes.lock().unwrap().len(), opening_writes);
+}
+
+#[test]
+fn sets_gkeys_reporting() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    headset.set_gkeys_reporting(true).unwrap();
+    assert_eq!(
+        writes.lock().unwrap().last().unwrap()[2..5],
+        [0x04, 0x21, 0x01]
+    );
+
+    headset.set_gkeys_reporting(false).unwrap();
+    assert_eq!(
+        writes.lock().unwrap().last().unwrap()[2..5],
+        [0x04, 0x21, 0x00]
+    );
+}
+
+#[test]
+fn survives_panicking_handlers() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    for remove in [false, true] {
+        let calls = Rc::new(RefCell::new(0));
+        let calls2 = Rc::clone(&calls);
+        let mut config = RunConfig::default();
+        config.set_remove_panicking_handlers(remove);
+        config.set_periodic_handler(Some(Box::new(move |_, _| {
+            *calls2.borrow_mut() += 1;
+            panic!("the handler failed");
+        })));
+
+        for _ in 0..2 {
+            headset.poll_once(&mut config, Duration::ZERO).unwrap();
+        }
+
+        assert_eq!(*calls.borrow(), if remove { 1 } else { 2 });
+    }
+}
+
+#[test]
+fn restores_defaults_on_drop() {
+    let mut mock = Mock::new(0);
+    mock.stored_lights[1] = [0x01, 0x01, 0xff, 0x80, 0x00, 0, 0, 0, 0, 0, 0, 0, 0x02];
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    headset.restore_on_drop().set_gkeys_reporting(true).unwrap();
+
+    let writes = writes.lock().unwrap();
+    assert_eq!(
+        writes[writes.len() - 5][2..5],
+        [0x04, 0x21, 0x00],
+        "the G key reporting is disabled first"
+    );
+    // The stored effect is shown on the side light
+    assert!(writes[writes.len() - 2..]
+        .iter()
+        .any(|request| request[2..9] == [0x05, 0x31, 0x01, 0x01, 0xff, 0x80, 0x00]));
+}
+
+#[test]
+fn restores_the_buttons_when_the_loop_stops() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let mut config = RunConfig::default();
+    config.set_restore_on_exit(RestorePolicy::Buttons);
+    config.set_button_handler(Some(Box::new(|_, _, _| ())));
+    // Applying the config fails, which stops the loop
+    config.set_sidetone(Some(200));
+    config.set_error_handler(Some(Box::new(|_, _| ErrorPolicy::Stop)));
+    headset.run_with_config(config);
+
+    let writes = writes.lock().unwrap();
+    assert!(writes
+        .iter()
+        .any(|request| request[2..5] == [0x04, 0x21, 0x01]));
+    assert_eq!(writes.last().unwrap()[2..5], [0x04, 0x21, 0x00]);
+}
+
+#[test]
+fn keeps_the_periodic_handler_that_sets_the_config_dirty() {
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let calls = Rc::new(RefCell::new(0));
+    let mut config = RunConfig::default();
+    config.set_periodic_handler(Some(Box::new({
+        let calls = Rc::clone(&calls);
+        move |config, _| {
+            *calls.borrow_mut() += 1;
+            config.set_dirty();
+        }
+    })));
+    for _ in 0..3 {
+        writes.lock().unwrap().clear();
+        headset.poll_once(&mut config, Duration::ZERO).unwrap();
+        // The G key reporting is sent again, like after a reconnection
+        assert!(writes
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|write| write[2..4] == [0x04, 0x21]));
+    }
+
+    assert_eq!(*calls.borrow(), 3);
+}
+
+#[test]
+fn keeps_the_periodic_handler_that_resumes() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    let calls = Rc::new(RefCell::new(0));
+    let mut config = RunConfig::default();
+    config.set_periodic_handler(Some(Box::new({
+        let calls = Rc::clone(&calls);
+        // Like the resume watcher of the daemon, after every suspend
+        move |config, headset| {
+            *calls.borrow_mut() += 1;
+            headset.suspend(config);
+            headset.resume(config);
+        }
+    })));
+    for _ in 0..3 {
+        headset.poll_once(&mut config, Duration::ZERO).unwrap();
+    }
+
+    assert_eq!(*calls.borrow(), 3);
+}
+
+#[test]
+fn stops_with_the_stop_token() {
+    let mut headset = Headset::open_with_transport(Mock::new(0)).unwrap();
+
+    let mut config = RunConfig::default();
+    let stop_token = config.stop_token();
+    config.set_periodic_handler_with_interval(
+        Duration::from_millis(1),
+        Box::new(move |_, _| stop_token.stop()),
+    );
+    // Returns instead of running forever
+    headset.run_with_config(config);
+}
+
+#[test]
+fn reconnects_when_the_error_handler_asks_to() {
+    let mut mock = Mock::new(0);
+    // Applying the config fails, as both lights are answered wrongly twice
+    mock.garbled_lights = 4;
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let power_states = Rc::new(RefCell::new(Vec::new()));
+    let mut config = RunConfig::default();
+    config.set_side_light_effect(Effect::static_color(Color::new(0xff, 0x80, 0x00)));
+    config.set_error_handler(Some(Box::new(|_, _| ErrorPolicy::Reconnect)));
+    config.set_power_state_change_handler(Some(Box::new({
+        let power_states = Rc::clone(&power_states);
+        move |_, _, state| power_states.borrow_mut().push(state)
+    })));
+    let stop_token = config.stop_token();
+    config.add_timer(
+        Duration::from_millis(50),
+        Box::new(move |_, _| stop_token.stop()),
+    );
+    headset.run_with_config(config);
+
+    // The reconnection is reported like any other
+    assert_eq!(
+        *power_states.borrow(),
+        [PowerState::Reconnecting, PowerState::Connected]
+    );
+}
+
+#[test]
+fn records_the_handler_latency() {
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let mut config = RunConfig::default();
+    config.set_latency_budget(Some(Duration::from_millis(1)));
+    config.set_button_handler(Some(Box::new(|_, _, _| {
+        std::thread::sleep(Duration::from_millis(6))
+    })));
+    notifications.lock().unwrap().push_back(vec![0x08, 0x01]);
+    headset.poll_once(&mut config, Duration::ZERO).unwrap();
+
+    let latency = headset.stats().handler_latency;
+    // Not within 1 or 5 ms
+    assert_eq!(latency.counts[..2], [0, 0]);
+    assert_eq!(latency.counts.iter().sum::<u64>(), 1);
+    assert!(latency.max_us >= 6000);
+}
+
+#[test]
+fn drops_repeated_reports_within_the_debounce_window() {
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let presses = Rc::new(RefCell::new(Vec::new()));
+    let mut config = RunConfig::default();
+    config.set_mic_arm_debounce(Some(Duration::from_secs(10)));
+    config.set_mute_debounce(Some(Duration::from_secs(10)));
+    config.set_button_handler(Some(Box::new({
+        let presses = Rc::clone(&presses);
+        move |_, _, state| {
+            presses
+                .borrow_mut()
+                .push((state.mic_arm(), state.mute_button_pressed()))
+        }
+    })));
+    for report in [
+        [0x08, 0x01],
+        [0x08, 0x01],
+        [0x08, 0x10],
+        [0x08, 0x10],
+        [0x08, 0x20],
+    ] {
+        notifications.lock().unwrap().push_back(report.to_vec());
+        headset.poll_once(&mut config, Duration::ZERO).unwrap();
+    }
+
+    // The other position of the mic arm is never dropped
+    assert_eq!(
+        *presses.borrow(),
+        [
+            (None, true),
+            (Some(MicArm::Up), false),
+            (Some(MicArm::Down), false),
+        ]
+    );
+}
+
+#[test]
+fn accepts_mute_button_presses_after_other_reports() {
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+
+    let presses = Rc::new(RefCell::new(Vec::new()));
+    let mut config = RunConfig::default();
+    config.set_mute_debounce(Some(Duration::from_secs(10)));
+    config.set_button_handler(Some(Box::new({
+        let presses = Rc::clone(&presses);
+        move |_, _, state| {
+            presses
+                .borrow_mut()
+                .push((state.mic_arm(), state.mute_button_pressed()))
+        }
+    })));
+    for report in [[0x08, 0x01], [0x08, 0x10], [0x08, 0x01]] {
+        notifications.lock().unwrap().push_back(report.to_vec());
+        headset.poll_once(&mut config, Duration::ZERO).unwrap();
+    }
+
+    // The second press does not repeat the report right before it
+    assert_eq!(
+        *presses.borrow(),
+        [
+            (None, true),
+            (Some(MicArm::Up), false),
+            (Some(MicArm::Up), true),
+        ]
+    );
+}
+
+#[test]
+fn encodes_and_decodes_raw_reports() {
+    use g935::protocol::{self, feature, Message};
+
+    // Asks the root feature for the index of the battery feature, like the headset does itself
+    let request = protocol::encode_request(
+        RECEIVER_INDEX,
+        0,
+        0,
+        &feature::BATTERY_VOLTAGE.to_be_bytes(),
+    )
+    .unwrap();
+    let mock = Mock::new(0);
+    let writes = Arc::clone(&mock.writes);
+    Headset::open_with_transport(mock).unwrap();
+    assert!(writes.lock().unwrap().contains(&request.to_vec()));
+
+    let Some(Message::Hidpp(report)) = protocol::decode(&request) else {
+        panic!("the request is not a HID++ report");
+    };
+    assert_eq!(report.feature_index(), 0);
+    assert_eq!(report.function(), 0);
+    assert_eq!(report.software_id(), protocol::SOFTWARE_ID);
+    assert_eq!(report.params()[..2], [0x1f, 0x20]);
+
+    assert_eq!(
+        protocol::decode(&[0x08, 0x20]),
+        Some(Message::MicArm(MicArm::Down))
+    );
+    assert_eq!(protocol::decode(&[0x08, 0x01]), Some(Message::MuteButton));
+    assert_eq!(protocol::decode(&[0x08, 0x02]), None);
+    assert!(protocol::encode_request(RECEIVER_INDEX, 0, 0x10, &[]).is_err());
+    assert!(protocol::encode_request(RECEIVER_INDEX, 0, 0, &[0; 17]).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_commands_in_the_background() {
+    use std::process::Command;
+
+    use g935::bindings::spawn_command;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut echo = Command::new("sh");
+    echo.args(["-c", "echo out; echo err >&2"]);
+    spawn_command(echo, Duration::from_secs(10), {
+        let sender = sender.clone();
+        move |output| sender.send(output.unwrap()).unwrap()
+    });
+    let output = receiver.recv().unwrap();
+    assert!(output.status.success());
+    assert!(!output.timed_out);
+    assert_eq!(output.stdout, b"out\n");
+    assert_eq!(output.stderr, b"err\n");
+
+    // The processes started by the command are killed as well, closing the output
+    let mut sleep = Command::new("sh");
+    sleep.args(["-c", "sleep 10; true"]);
+    spawn_command(sleep, Duration::from_millis(20), move |output| {
+        sender.send(output.unwrap()).unwrap()
+    });
+    let output = receiver.recv().unwrap();
+    assert!(output.timed_out);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn does_not_wait_for_the_background_processes_of_commands() {
+    use std::process::Command;
+
+    use g935::bindings::spawn_command;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut command = Command::new("sh");
+    // The backgrounded sleep inherits the pipes, keeping them open after the shell exited
+    command.args(["-c", "echo out; sleep 5 &"]);
+    spawn_command(command, Duration::from_secs(10), move |output| {
+        sender.send(output.unwrap()).unwrap()
+    });
+    let output = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert!(output.status.success());
+    assert!(!output.timed_out);
+    assert_eq!(output.stdout, b"out\n");
+}
+
+#[cfg(feature = "obs")]
+#[test]
+fn switches_obs_scenes_and_shows_streaming() {
+    use std::{net::TcpListener, time::Instant};
+
+    use g935::obs::{MicArmAction, OnAir};
+    use serde_json::{json, Value};
+    use tungstenite::Message;
+
+    // A fake OBS, which is streaming and reports the requested scene
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (sender, scenes) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut socket = tungstenite::accept(listener.accept().unwrap().0).unwrap();
+        let receive = |socket: &mut tungstenite::WebSocket<_>| -> Value {
+            serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap()
+        };
+
+        let hello = json!({ "op": 0, "d": { "rpcVersion": 1 } });
+        socket.send(Message::text(hello.to_string())).unwrap();
+        assert_eq!(receive(&mut socket)["op"], 1);
+        socket
+            .send(Message::text(json!({ "op": 2, "d": {} }).to_string()))
+            .unwrap();
+
+        loop {
+            let request = receive(&mut socket)["d"].clone();
+            let response = json!({
+                "op": 7,
+                "d": {
+                    "requestType": request["requestType"],
+                    "requestId": request["requestId"],
+                    "requestStatus": { "result": true, "code": 100 },
+                    "responseData": { "outputActive": true },
+                },
+            });
+            socket.send(Message::text(response.to_string())).unwrap();
+            if request["requestType"] == "SetCurrentProgramScene" {
+                sender
+                    .send(request["requestData"]["sceneName"].clone())
+                    .unwrap();
+            }
+        }
+    });
+
+    let mock = Mock::new(0);
+    let notifications = Arc::clone(&mock.notifications);
+    let mut headset = Headset::open_with_transport(mock).unwrap();
+    let red = Effect::static_color(Color::new(0xff, 0x00, 0x00));
+    let mut config = RunConfig::default();
+    config.add_plugin(Box::new(
+        OnAir::connect(url, None)
+            .on_mic_arm(MicArmAction::SwitchScene {
+                up: "Muted".to_string(),
+                down: "Live".to_string(),
+            })
+            .show_streaming(Light::Logo, red),
+    ));
+
+    notifications.lock().unwrap().push_back(vec![0x08, 0x10]);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while config.shown_lights().logo != red {
+        assert!(
+            Instant::now() < deadline,
+            "the streaming state is not shown"
+        );
+        headset.poll_once(&mut config, Duration::ZERO).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(
+        scenes.recv_timeout(Duration::from_secs(10)).unwrap(),
+        "Muted"
+    );
+}